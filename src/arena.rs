@@ -14,7 +14,10 @@
 
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::pin::Pin;
 
+use ast::MacroSource;
+use error::SourceFileLocator;
 use heap;
 use heap::{PoolPtr, RootPtr};
 use util::{is_numeric, simplify_numeric};
@@ -32,6 +35,19 @@ pub struct Arena {
     roots: Vec<RootPtr>,
     symbol_map: RefCell<HashMap<String, RootPtr>>,
     gensym_counter: Cell<usize>,
+    /// Where each value produced by the reader came from in its source file, keyed by pointer
+    /// identity. Populated by `Reader::insert_positioned` and consulted when building a
+    /// `ast::ParseError`, so a parse error can point back at the code that caused it - not
+    /// populated for values synthesized later (macro expansion, `gensym`, ...), which is why
+    /// lookups return `Option`.
+    locations: RefCell<HashMap<PoolPtr, SourceFileLocator>>,
+    /// Which macro use-site produced a given macro-expanded value, keyed by the root pointer of
+    /// that expansion step. Populated by `ast::expand_macro_full` as it runs, so a later error
+    /// about a fully macro-expanded form (one with no entry in `locations`) can still be
+    /// attributed to the macro call that generated it rather than shown as bare synthetic code.
+    /// Only the root of each step is tagged, not every sub-form the expansion produced - see
+    /// `ast::record_expansion_origin`.
+    expansions: RefCell<HashMap<PoolPtr, MacroSource>>,
     pub undefined: PoolPtr,
     pub unspecific: PoolPtr,
     pub eof: PoolPtr,
@@ -39,12 +55,28 @@ pub struct Arena {
     pub t: PoolPtr,
     pub f: PoolPtr,
     heap: heap::RHeap,
+    /// Allocations recorded since the arena was created or last [`Arena::reset_allocation_count`]
+    /// - see [`vm::ExecutionLimits::max_allocations`]. Bumped on every [`Arena::try_insert`] call,
+    /// including ones that resolve to an already-interned symbol or a singleton like `#t`, so
+    /// it's a cheap upper bound on allocation pressure rather than an exact count of new heap
+    /// cells.
+    allocations: Cell<usize>,
 }
 
 impl Arena {
     /// Moves a value into the arena, and returns a pointer to its new position.
+    ///
+    /// Panics if the heap is out of memory; see [`Arena::try_insert`] for a fallible version.
     pub fn insert(&self, v: Value) -> PoolPtr {
-        match v {
+        self.try_insert(v).expect("out of memory")
+    }
+
+    /// Same as [`Arena::insert`], but returns [`heap::AllocError`] instead of panicking when the
+    /// heap can't satisfy the allocation (only possible when the arena was built with a
+    /// `max_pools` ceiling - see [`Arena::with_gc_mode_and_max_heap`]).
+    pub fn try_insert(&self, v: Value) -> Result<PoolPtr, heap::AllocError> {
+        self.allocations.set(self.allocations.get() + 1);
+        Ok(match v {
             Value::Undefined => self.undefined,
             Value::Unspecific => self.unspecific,
             Value::EofObject => self.eof,
@@ -57,22 +89,65 @@ impl Arena {
                     Some(u) => u.pp(),
                     None => {
                         let label = s.clone();
-                        let pos = self.heap.allocate_rooted(Value::Symbol(s));
+                        let pos = self.heap.try_allocate_rooted(Value::Symbol(s))?;
                         let ptr = pos.pp();
                         self.symbol_map.borrow_mut().insert(label, pos);
                         ptr
                     }
                 }
             }
-            _ if is_numeric(&v) => self.heap.allocate(simplify_numeric(v)),
-            _ => self.heap.allocate(v),
-        }
+            _ if is_numeric(&v) => self.heap.try_allocate(simplify_numeric(v))?,
+            _ => self.heap.try_allocate(v)?,
+        })
     }
 
     pub fn root(&self, at: PoolPtr) -> RootPtr {
         self.heap.root(at)
     }
 
+    /// Allocations recorded since the last reset - see the `allocations` field.
+    pub fn allocation_count(&self) -> usize {
+        self.allocations.get()
+    }
+
+    /// Zeroes the allocation counter without touching anything else - called by [`vm::run`] at
+    /// the start of every run, nested ones (e.g. from `run_thunk` or `eval`) included, so
+    /// `ExecutionLimits::max_allocations` budgets a single `vm::run` rather than the
+    /// interpreter's whole lifetime.
+    pub fn reset_allocation_count(&self) {
+        self.allocations.set(0);
+    }
+
+    /// Records where `ptr` came from in its source file. See the `locations` field.
+    pub fn set_location(&self, ptr: PoolPtr, locator: SourceFileLocator) {
+        self.locations.borrow_mut().insert(ptr, locator);
+    }
+
+    /// Looks up where `ptr` came from in its source file, if it was read from one. `None` for
+    /// anything synthesized rather than read (macro expansions, `gensym`, quasiquote splices).
+    pub fn location_of(&self, ptr: PoolPtr) -> Option<SourceFileLocator> {
+        self.locations.borrow().get(&ptr).cloned()
+    }
+
+    /// Records that `ptr` is the root of one macro-expansion step, produced by the macro use at
+    /// `origin`. See the `expansions` field.
+    pub fn set_expansion_origin(&self, ptr: PoolPtr, origin: MacroSource) {
+        self.expansions.borrow_mut().insert(ptr, origin);
+    }
+
+    /// Looks up which macro use-site produced `ptr`, if any. `None` for values read directly
+    /// from source, or for sub-forms of an expansion other than its root - see the `expansions`
+    /// field.
+    pub fn expansion_origin_of(&self, ptr: PoolPtr) -> Option<MacroSource> {
+        self.expansions.borrow().get(&ptr).cloned()
+    }
+
+    /// Every symbol ever interned into this arena (see `symbol_map`). Used to build completion
+    /// candidates for the REPL - see `Interpreter::completion_candidates`.
+    pub fn known_symbols(&self) -> Vec<String> {
+        self.symbol_map.borrow().keys().cloned().collect()
+    }
+
     pub fn root_vm(&self, vm: &Vm) {
         self.heap.root_vm(vm);
     }
@@ -85,6 +160,15 @@ impl Arena {
         self.root(self.insert(v))
     }
 
+    /// Returns a fresh, monotonically increasing id, suitable for distinguishing otherwise
+    /// identical instances of something from one another (see `value::RecordType::new`). Shares
+    /// its counter with [`Arena::gensym`], so marks and gensym suffixes never collide.
+    pub fn new_mark(&self) -> usize {
+        let mark = self.gensym_counter.get();
+        self.gensym_counter.set(mark + 1);
+        mark
+    }
+
     pub fn gensym(&self, base: Option<&str>) -> PoolPtr {
         let base_str = base.map(|s| format!("{}-", s)).unwrap_or_else(|| "".into());
         loop {
@@ -97,8 +181,26 @@ impl Arena {
     }
 
     pub fn with_gc_mode(gc_mode: heap::GcMode) -> Arena {
+        Self::with_gc_mode_and_max_heap(gc_mode, None)
+    }
+
+    /// Same as [`Arena::with_gc_mode`], but caps the underlying heap at `max_pools` pools, after
+    /// which allocations fail with [`heap::AllocError`] (surfaced by [`Arena::try_insert`])
+    /// instead of growing without bound. Useful for sandboxing untrusted Scheme.
+    pub fn with_gc_mode_and_max_heap(gc_mode: heap::GcMode, max_pools: Option<usize>) -> Arena {
+        Self::from_rheap(heap::RHeap::with_gc_mode_and_max_pools(gc_mode, max_pools))
+    }
+
+    /// Same as [`Arena::with_gc_mode`], but the heap's entire pool budget is the caller-supplied
+    /// `pools` rather than pools grown lazily from the global allocator - see
+    /// [`heap::RHeap::with_preallocated_pools`]. Useful for embedding peroxide with a
+    /// deterministic, fixed-at-startup memory ceiling.
+    pub fn with_preallocated_pools(pools: Vec<Pin<Box<heap::Pool>>>, gc_mode: heap::GcMode) -> Arena {
+        Self::from_rheap(heap::RHeap::with_preallocated_pools(pools, gc_mode))
+    }
+
+    fn from_rheap(values: heap::RHeap) -> Arena {
         let mut roots = Vec::new();
-        let values = heap::RHeap::with_gc_mode(gc_mode);
 
         macro_rules! root {
             ($i: ident, $x: expr) => {
@@ -118,6 +220,8 @@ impl Arena {
             heap: values,
             symbol_map: RefCell::new(HashMap::new()),
             gensym_counter: Cell::new(0),
+            locations: RefCell::new(HashMap::new()),
+            expansions: RefCell::new(HashMap::new()),
             undefined,
             unspecific,
             eof,
@@ -125,6 +229,7 @@ impl Arena {
             f,
             t,
             roots,
+            allocations: Cell::new(0),
         }
     }
 }
@@ -31,20 +31,161 @@ use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::rc::Rc;
 
+use num_bigint::BigInt;
+
 use arena::Arena;
 use environment::{
-    get_toplevel_afi, ActivationFrame, Environment, EnvironmentValue, Macro, RcAfi, RcEnv,
+    get_toplevel_afi, ActivationFrame, CustomSyntax, Environment, EnvironmentValue, Macro,
+    MacroTransformer, RcAfi, RcEnv,
 };
+use error::{Source, SourceFileLocator};
 use heap::{PoolPtr, RootPtr};
 use primitives::SyntacticClosure;
+use syntax_rules;
 use util::check_len;
-use value::{list_from_vec, pretty_print, vec_from_list, Value};
+use value::{list_from_vec, pretty_print, vec_from_list, RecordType, Value};
 use VmState;
 use {compile, vm};
 use {compile_run, environment};
 
+/// Default value of `vms.macro_expansion_limit`, the cap on how many times in a row
+/// [`expand_macro_full`] will re-expand a macro call's result before giving up with
+/// [`ParseErrorKind::MacroExpansionLimit`]. Embedders/REPL users can raise or lower the bound by
+/// setting `vms.macro_expansion_limit` directly; this constant only supplies its starting value.
 const MAX_MACRO_EXPANSION: usize = 1000;
 
+/// Identifies one macro-expansion site, so an error in the code a macro expanded to can point
+/// back at the `define-syntax`'d macro that generated it (see `error::Source::Macro`).
+#[derive(Debug, Clone)]
+pub struct MacroSource {
+    pub name: String,
+    pub invocation: SourceFileLocator,
+}
+
+/// One step of a macro expansion: `before` rewritten to `after` by the macro named
+/// `macro_name`. Recorded on `VmState::macro_trace` by [`expand_macro_full`] as it runs, so the
+/// whole chain - not just the final result - is still there to inspect even if parsing later
+/// fails (e.g. [`ParseErrorKind::MacroExpansionLimit`]). Also how `(macroexpand expr)` /
+/// `(macroexpand-1 expr)` report what they did.
+#[derive(Debug, Clone)]
+pub struct MacroExpansionStep {
+    pub macro_name: String,
+    pub before: RootPtr,
+    pub after: RootPtr,
+}
+
+/// Broad classification of a [`ParseError`], so callers that want to react programmatically
+/// (rather than just display `msg`) don't have to match on message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A special form or macro use was given the wrong number of arguments.
+    WrongArgCount,
+    /// A name was used in a context that required something it wasn't (e.g. referencing a
+    /// `define-syntax` macro, or `set!`-ing something that was never defined).
+    IllegalReference,
+    /// A lambda/`define` formals list wasn't a proper `(a b . c)` shape.
+    MalformedFormals,
+    /// A macro (or chain of macros) expanded more than `vms.macro_expansion_limit` times in a row.
+    MacroExpansionLimit,
+    /// A name resolved to something other than a variable where a variable was required.
+    NotAVariable,
+    /// Doesn't fit one of the more specific buckets above.
+    Other,
+}
+
+/// An error encountered while turning a read s-expression into an AST. Carries a [`Source`] when
+/// one is available (the code came from a file, or a macro expansion of code that did) so the
+/// error can be rendered as a span, e.g. via `error::locate_message`; `source` is `None` for
+/// errors about synthesized code (a `gensym`, a value built by `make-syntactic-closure`, ...)
+/// that has no span to point at.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub msg: String,
+    pub source: Option<Source>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Builds a [`ParseError`], looking up `origin`'s source span (if it has one) to attach to it.
+fn err(arena: &Arena, kind: ParseErrorKind, origin: PoolPtr, msg: impl Into<String>) -> ParseError {
+    ParseError {
+        kind,
+        msg: msg.into(),
+        source: source_of(arena, origin),
+    }
+}
+
+/// Looks up where `origin` came from: directly from source if it was read from a file, or
+/// (falling back to the `Arena`'s expansion side table) from the macro use-site that generated
+/// it, if it's the untouched root of a macro expansion step. `None` if `origin` is synthesized
+/// code with no recorded provenance at all (e.g. a sub-form built by a `syntax-rules` template
+/// that isn't itself an expansion root).
+fn source_of(arena: &Arena, origin: PoolPtr) -> Option<Source> {
+    arena.location_of(origin).map(Source::Code).or_else(|| {
+        arena.expansion_origin_of(origin).map(|macro_source| Source::Macro {
+            code_source: Box::new(Source::Code(macro_source.invocation.clone())),
+            macro_source: Rc::new(macro_source),
+        })
+    })
+}
+
+/// Records, in the `Arena`'s expansion side table, that `after` is the root of the expansion
+/// step that rewrote `before` via the macro named `name` - so a later [`err`] anchored on
+/// `after` (e.g. from [`collect_internal_defines`] parsing deeper into the expanded code) is
+/// automatically attributed to this macro use via [`source_of`], without every such call site
+/// needing to wrap its own errors in a [`Source::Macro`] by hand. A no-op if `before` wasn't
+/// read directly from a source file - e.g. it's itself the product of an earlier expansion step,
+/// whether or not that step tagged it as a root - in which case there's no file location to
+/// anchor the new entry to.
+fn record_expansion_origin(arena: &Arena, name: &str, before: PoolPtr, after: PoolPtr) {
+    if let Some(invocation) = arena.location_of(before) {
+        arena.set_expansion_origin(
+            after,
+            MacroSource {
+                name: name.to_string(),
+                invocation,
+            },
+        );
+    }
+}
+
+/// Wraps `e`'s source, if any, in a [`Source::Macro`] attributing it to the expansion of macro
+/// `name` invoked at `invocation` - so an error in macro-generated code also shows "in this
+/// expansion of macro `name`", the way `error::locate_message` already knows how to render a
+/// `Source::Macro` chain. A no-op if `invocation` itself has no known source (there's then
+/// nothing useful to attribute the error to).
+fn wrap_macro_source(arena: &Arena, name: &str, invocation: PoolPtr, mut e: ParseError) -> ParseError {
+    if let Some(invocation_locator) = arena.location_of(invocation) {
+        let code_source = e
+            .source
+            .unwrap_or_else(|| Source::Code(invocation_locator.clone()));
+        e.source = Some(Source::Macro {
+            macro_source: Rc::new(MacroSource {
+                name: name.to_string(),
+                invocation: invocation_locator,
+            }),
+            code_source: Box::new(code_source),
+        });
+    }
+    e
+}
+
+/// A [`SyntaxElement`] together with the source span it was parsed from, when known. Compiled
+/// code (see `compile::compile`) only cares about `element`; `source` exists purely so a
+/// [`ParseError`] encountered while parsing a parent form can point at the right child.
+#[derive(Debug)]
+pub struct LocatedSyntaxElement {
+    pub element: SyntaxElement,
+    pub source: Option<Source>,
+}
+
 #[derive(Debug)]
 pub enum SyntaxElement {
     Reference(Box<Reference>),
@@ -70,14 +211,14 @@ pub struct Quote {
 
 #[derive(Debug)]
 pub struct If {
-    pub cond: SyntaxElement,
-    pub t: SyntaxElement,
-    pub f: Option<SyntaxElement>,
+    pub cond: LocatedSyntaxElement,
+    pub t: LocatedSyntaxElement,
+    pub f: Option<LocatedSyntaxElement>,
 }
 
 #[derive(Debug)]
 pub struct Begin {
-    pub expressions: Vec<SyntaxElement>,
+    pub expressions: Vec<LocatedSyntaxElement>,
 }
 
 // The activation frame in a lambda has the formals, then all inner defines. In other words there
@@ -86,8 +227,8 @@ pub struct Lambda {
     pub env: RcEnv,
     pub arity: usize,
     pub dotted: bool,
-    pub defines: Vec<SyntaxElement>,
-    pub expressions: Vec<SyntaxElement>,
+    pub defines: Vec<LocatedSyntaxElement>,
+    pub expressions: Vec<LocatedSyntaxElement>,
     pub name: Option<String>,
 }
 
@@ -106,13 +247,13 @@ pub struct Set {
     pub altitude: usize,
     pub depth: usize,
     pub index: usize,
-    pub value: SyntaxElement,
+    pub value: LocatedSyntaxElement,
 }
 
 #[derive(Debug)]
 pub struct Application {
-    pub function: SyntaxElement,
-    pub args: Vec<SyntaxElement>,
+    pub function: LocatedSyntaxElement,
+    pub args: Vec<LocatedSyntaxElement>,
 }
 
 #[derive(Debug)]
@@ -145,26 +286,43 @@ pub fn parse(
     env: &RcEnv,
     af_info: &RcAfi,
     value: PoolPtr,
-) -> Result<SyntaxElement, String> {
+) -> Result<LocatedSyntaxElement, ParseError> {
     let _value_hold = arena.root(value);
     let (env, value) = resolve_syntactic_closure(arena, env, value)?;
     match arena.get(value) {
-        Value::Symbol(s) => Ok(SyntaxElement::Reference(Box::new(construct_reference(
-            &env, af_info, s,
-        )?))),
-        Value::EmptyList => Err("Cannot evaluate empty list".into()),
+        Value::Symbol(s) => Ok(LocatedSyntaxElement {
+            element: SyntaxElement::Reference(Box::new(construct_reference(
+                arena, &env, af_info, s, value,
+            )?)),
+            source: source_of(arena, value),
+        }),
+        Value::EmptyList => Err(err(
+            arena,
+            ParseErrorKind::Other,
+            value,
+            "Cannot evaluate empty list",
+        )),
         Value::Pair(car, cdr) => {
             let car = car.get();
             let cdr = cdr.get();
-            parse_pair(arena, vms, &env, af_info, car, cdr)
+            parse_pair(arena, vms, &env, af_info, car, cdr, value)
         }
-        _ => Ok(SyntaxElement::Quote(Box::new(Quote {
-            quoted: arena.root(value),
-        }))),
+        _ => Ok(LocatedSyntaxElement {
+            element: SyntaxElement::Quote(Box::new(Quote {
+                quoted: arena.root(value),
+            })),
+            source: source_of(arena, value),
+        }),
     }
 }
 
-fn construct_reference(env: &RcEnv, afi: &RcAfi, name: &str) -> Result<Reference, String> {
+fn construct_reference(
+    arena: &Arena,
+    env: &RcEnv,
+    afi: &RcAfi,
+    name: &str,
+    origin: PoolPtr,
+) -> Result<Reference, ParseError> {
     let mut env = env.borrow_mut();
     match env.get(name) {
         Some(EnvironmentValue::Variable(v)) => Ok(Reference {
@@ -172,9 +330,11 @@ fn construct_reference(env: &RcEnv, afi: &RcAfi, name: &str) -> Result<Reference
             depth: afi.borrow().altitude - v.altitude,
             index: v.index,
         }),
-        Some(_) => Err(format!(
-            "Illegal reference to {}, which is not a variable.",
-            name
+        Some(_) => Err(err(
+            arena,
+            ParseErrorKind::NotAVariable,
+            origin,
+            format!("Illegal reference to {}, which is not a variable.", name),
         )),
         None => {
             // TODO: remove this, or find a better way to surface it.
@@ -199,27 +359,41 @@ fn parse_pair(
     af_info: &RcAfi,
     car: PoolPtr,
     cdr: PoolPtr,
-) -> Result<SyntaxElement, String> {
-    let rest = vec_from_list(arena, cdr)?;
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    let rest = vec_from_list(arena, cdr).map_err(|e| err(arena, ParseErrorKind::Other, form, e))?;
     let (car_env, resolved_car) = resolve_syntactic_closure(arena, env, car)?;
     match arena.get(resolved_car) {
         Value::Symbol(s) => match match_symbol(&car_env, s) {
-            Symbol::Quote => parse_quote(arena, &env, &rest, false),
-            Symbol::SyntaxQuote => parse_quote(arena, &env, &rest, true),
-            Symbol::If => parse_if(arena, vms, &env, af_info, &rest),
-            Symbol::Begin => parse_begin(arena, vms, &env, af_info, &rest),
-            Symbol::Lambda => parse_lambda(arena, vms, &env, af_info, &rest),
-            Symbol::Set => parse_set(arena, vms, &env, af_info, &rest),
-            Symbol::Define => parse_define(arena, vms, &env, af_info, &rest),
-            Symbol::DefineSyntax => parse_define_syntax(arena, vms, &env, af_info, &rest),
-            Symbol::LetSyntax => parse_let_syntax(arena, vms, &env, af_info, &rest, false),
-            Symbol::LetrecSyntax => parse_let_syntax(arena, vms, &env, af_info, &rest, true),
+            Symbol::Quote => parse_quote(arena, &env, &rest, false, form),
+            Symbol::SyntaxQuote => parse_quote(arena, &env, &rest, true, form),
+            Symbol::If => parse_if(arena, vms, &env, af_info, &rest, form),
+            Symbol::Begin => parse_begin(arena, vms, &env, af_info, &rest, form),
+            Symbol::Lambda => parse_lambda(arena, vms, &env, af_info, &rest, form),
+            Symbol::Set => parse_set(arena, vms, &env, af_info, &rest, form),
+            Symbol::Define => parse_define(arena, vms, &env, af_info, &rest, form),
+            Symbol::DefineRecordType => {
+                parse_define_record_type(arena, vms, &env, af_info, &rest, form)
+            }
+            Symbol::Guard => parse_guard(arena, vms, &env, af_info, &rest, form),
+            Symbol::DefineSyntax => parse_define_syntax(arena, vms, &env, af_info, &rest, form),
+            Symbol::LetSyntax => parse_let_syntax(arena, vms, &env, af_info, &rest, false, form),
+            Symbol::LetrecSyntax => parse_let_syntax(arena, vms, &env, af_info, &rest, true, form),
             Symbol::Macro(m) => {
                 // TODO fix this to avoid reconstructing the pair
                 let expr = arena.insert(Value::Pair(Cell::new(car), Cell::new(cdr)));
-                let expanded = expand_macro_full(arena, vms, &env, m, expr)?;
+                let expanded = expand_macro_full(arena, vms, &env, s, m, expr)
+                    .map_err(|e| wrap_macro_source(arena, s, form, e))?;
                 parse(arena, vms, &env, af_info, expanded)
+                    .map_err(|e| wrap_macro_source(arena, s, form, e))
             }
+            Symbol::MacroExpand(full) => {
+                parse_macroexpand(arena, vms, &env, &rest, form, full)
+            }
+            Symbol::MacroExpansionTrace => {
+                parse_macro_expansion_trace(arena, vms, &env, &rest, form)
+            }
+            Symbol::CustomSyntax(cs) => (cs.handler)(arena, vms, &env, af_info, &rest),
             _ => parse_application(arena, vms, &env, af_info, car, &rest),
         },
         _ => parse_application(arena, vms, &env, af_info, car, &rest),
@@ -231,27 +405,118 @@ fn parse_quote(
     env: &RcEnv,
     rest: &[PoolPtr],
     syntax: bool,
-) -> Result<SyntaxElement, String> {
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
     if rest.len() != 1 {
-        Err(format!("quote expected 1 argument, got {}.", rest.len()))
+        Err(err(
+            arena,
+            ParseErrorKind::WrongArgCount,
+            form,
+            format!("quote expected 1 argument, got {}.", rest.len()),
+        ))
     } else if syntax {
-        Ok(SyntaxElement::Quote(Box::new(Quote {
-            quoted: arena.root(rest[0]),
-        })))
+        Ok(LocatedSyntaxElement {
+            element: SyntaxElement::Quote(Box::new(Quote {
+                quoted: arena.root(rest[0]),
+            })),
+            source: source_of(arena, form),
+        })
     } else {
         let quoted = arena.root(strip_syntactic_closure(arena, env, rest[0]));
-        Ok(SyntaxElement::Quote(Box::new(Quote { quoted })))
+        Ok(LocatedSyntaxElement {
+            element: SyntaxElement::Quote(Box::new(Quote { quoted })),
+            source: source_of(arena, form),
+        })
     }
 }
 
+/// Parses `(macroexpand expr)` / `(macroexpand-1 expr)`. `expr` is never evaluated or compiled:
+/// if it's a macro call, the transformer is run (once for `macroexpand-1`, repeatedly for
+/// `macroexpand`, exactly as [`expand_macro_full`]/[`expand_macro`] do for a real parse) and the
+/// resulting s-expression is handed back to the caller as quoted data, not as a `SyntaxElement`
+/// to compile. If `expr` isn't a macro call, it's returned unchanged.
+fn parse_macroexpand(
+    arena: &Arena,
+    vms: &mut VmState,
+    env: &RcEnv,
+    rest: &[PoolPtr],
+    form: PoolPtr,
+    full: bool,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(1), Some(1))
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
+    let expr = rest[0];
+    let expanded = match get_macro(arena, env, expr) {
+        Some((name, m)) => {
+            if full {
+                expand_macro_full(arena, vms, env, &name, m, expr)?
+            } else {
+                let before = arena.root(expr);
+                let after = expand_macro(arena, vms, env, m, before.clone())?;
+                vms.macro_trace.push(MacroExpansionStep {
+                    macro_name: name,
+                    before,
+                    after: after.clone(),
+                });
+                after.pp()
+            }
+        }
+        None => expr,
+    };
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Quote(Box::new(Quote {
+            quoted: arena.root(expanded),
+        })),
+        source: source_of(arena, form),
+    })
+}
+
+/// Parses `(macro-expansion-trace expr)`. Unlike `macroexpand`, which only hands back the final
+/// expansion, this returns every intermediate step `expr` went through to get there: a list of
+/// `(macro-name before after)` triples, in the order [`expand_macro_full`] produced them, so a
+/// REPL user can see a multi-step rewrite stage by stage instead of just its end state - handy
+/// for diagnosing where in a chain of macro calls something went wrong. If `expr` isn't a macro
+/// call, the result is the empty list.
+fn parse_macro_expansion_trace(
+    arena: &Arena,
+    vms: &mut VmState,
+    env: &RcEnv,
+    rest: &[PoolPtr],
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(1), Some(1))
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
+    let expr = rest[0];
+    let trace_start = vms.macro_trace.len();
+    if let Some((name, m)) = get_macro(arena, env, expr) {
+        expand_macro_full(arena, vms, env, &name, m, expr)?;
+    }
+    let entries: Vec<PoolPtr> = vms.macro_trace[trace_start..]
+        .iter()
+        .map(|step| {
+            let name = arena.insert(Value::Symbol(step.macro_name.clone()));
+            list_from_vec(arena, &[name, step.before.pp(), step.after.pp()])
+        })
+        .collect();
+    let result = list_from_vec(arena, &entries);
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Quote(Box::new(Quote {
+            quoted: arena.root(result),
+        })),
+        source: source_of(arena, form),
+    })
+}
+
 fn parse_if(
     arena: &Arena,
     vms: &mut VmState,
     env: &RcEnv,
     af_info: &RcAfi,
     rest: &[PoolPtr],
-) -> Result<SyntaxElement, String> {
-    check_len(rest, Some(2), Some(3))?;
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(2), Some(3))
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
     let cond = parse(arena, vms, env, af_info, rest[0])?;
     let t = parse(arena, vms, env, af_info, rest[1])?;
     let f_s: Option<Result<_, _>> = rest.get(2).map(|e| parse(arena, vms, env, af_info, *e));
@@ -259,7 +524,10 @@ fn parse_if(
     // This dark magic swaps the option and the result (then `?`s the result)
     // https://doc.rust-lang.org/rust-by-example/error/multiple_error_types/option_result.html
     let f: Option<_> = f_s.map_or(Ok(None), |r| r.map(Some))?;
-    Ok(SyntaxElement::If(Box::new(If { cond, t, f })))
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::If(Box::new(If { cond, t, f })),
+        source: source_of(arena, form),
+    })
 }
 
 fn parse_begin(
@@ -268,13 +536,17 @@ fn parse_begin(
     env: &RcEnv,
     af_info: &RcAfi,
     rest: &[PoolPtr],
-) -> Result<SyntaxElement, String> {
-    check_len(rest, Some(1), None)?;
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(1), None).map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
     let expressions = rest
         .iter()
         .map(|e| parse(arena, vms, env, af_info, *e))
         .collect::<Result<Vec<_>, _>>()?;
-    Ok(SyntaxElement::Begin(Box::new(Begin { expressions })))
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Begin(Box::new(Begin { expressions })),
+        source: source_of(arena, form),
+    })
 }
 
 fn parse_lambda(
@@ -283,8 +555,9 @@ fn parse_lambda(
     env: &RcEnv,
     af_info: &RcAfi,
     rest: &[PoolPtr],
-) -> Result<SyntaxElement, String> {
-    check_len(rest, Some(2), None)?;
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(2), None).map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
     parse_split_lambda(
         arena,
         vms,
@@ -293,6 +566,7 @@ fn parse_lambda(
         rest[0],
         &rest[1..rest.len()],
         None,
+        form,
     )
 }
 
@@ -304,7 +578,8 @@ fn parse_split_lambda(
     formals: PoolPtr,
     body: &[PoolPtr],
     name: Option<String>,
-) -> Result<SyntaxElement, String> {
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
     let formals = parse_formals(arena, formals)?;
     let inner_afi = environment::extend_af_info(af_info);
     let raw_env = Environment::new(Some(outer_env.clone()));
@@ -320,7 +595,8 @@ fn parse_split_lambda(
         define_in_env(arena, &inner_env, &inner_afi, define_target, true);
         targets.push(define_target.clone());
     }
-    let (unparsed_defines, rest) = collect_internal_defines(arena, vms, &inner_env, body)?;
+    let (unparsed_defines, rest) =
+        collect_internal_defines(arena, vms, &inner_env, &inner_afi, body)?;
     for define_data in unparsed_defines.iter() {
         define_in_env(arena, &inner_env, &inner_afi, &define_data.target, false);
         targets.push(define_data.target.clone());
@@ -339,12 +615,16 @@ fn parse_split_lambda(
             if let Some(EnvironmentValue::Variable(v)) =
                 get_in_env(arena, &inner_env, &define_data.target)
             {
-                Ok(SyntaxElement::Set(Box::new(Set {
-                    altitude: v.altitude,
-                    depth: inner_afi.borrow().altitude - v.altitude,
-                    index: v.index,
-                    value,
-                })))
+                let source = value.source.clone();
+                Ok(LocatedSyntaxElement {
+                    element: SyntaxElement::Set(Box::new(Set {
+                        altitude: v.altitude,
+                        depth: inner_afi.borrow().altitude - v.altitude,
+                        index: v.index,
+                        value,
+                    })),
+                    source,
+                })
             } else {
                 panic!(
                     "Expected {} in {:?} to be a variable, was {:?}.",
@@ -354,7 +634,7 @@ fn parse_split_lambda(
                 );
             }
         })
-        .collect::<Result<Vec<SyntaxElement>, String>>()?;
+        .collect::<Result<Vec<LocatedSyntaxElement>, ParseError>>()?;
 
     let expressions = rest
         .iter()
@@ -363,16 +643,24 @@ fn parse_split_lambda(
 
     pop_envs(arena, &targets);
     if expressions.is_empty() {
-        return Err("Lambda cannot have empty body".into());
+        return Err(err(
+            arena,
+            ParseErrorKind::Other,
+            form,
+            "Lambda cannot have empty body",
+        ));
     }
-    Ok(SyntaxElement::Lambda(Box::new(Lambda {
-        env: inner_env,
-        arity: formals.values.len(),
-        dotted: formals.rest.is_some(),
-        defines,
-        expressions,
-        name,
-    })))
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Lambda(Box::new(Lambda {
+            env: inner_env,
+            arity: formals.values.len(),
+            dotted: formals.rest.is_some(),
+            defines,
+            expressions,
+            name,
+        })),
+        source: source_of(arena, form),
+    })
 }
 
 fn parse_set(
@@ -381,30 +669,44 @@ fn parse_set(
     env: &RcEnv,
     af_info: &RcAfi,
     rest: &[PoolPtr],
-) -> Result<SyntaxElement, String> {
-    check_len(rest, Some(2), Some(2))?;
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(2), Some(2))
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
     if let Some(dt) = get_define_target(arena, rest[0]) {
         let value = parse(arena, vms, env, af_info, rest[1])?;
         match get_in_env(arena, env, &dt) {
-            Some(EnvironmentValue::Variable(v)) => Ok(SyntaxElement::Set(Box::new(Set {
-                altitude: v.altitude,
-                depth: af_info.borrow().altitude - v.altitude,
-                index: v.index,
-                value,
-            }))),
-            Some(_) => Err(format!(
-                "Trying to set non-variable `{}`",
-                dt.get_name(arena)
+            Some(EnvironmentValue::Variable(v)) => Ok(LocatedSyntaxElement {
+                element: SyntaxElement::Set(Box::new(Set {
+                    altitude: v.altitude,
+                    depth: af_info.borrow().altitude - v.altitude,
+                    index: v.index,
+                    value,
+                })),
+                source: source_of(arena, form),
+            }),
+            Some(_) => Err(err(
+                arena,
+                ParseErrorKind::NotAVariable,
+                rest[0],
+                format!("Trying to set non-variable `{}`", dt.get_name(arena)),
             )),
-            None => Err(format!(
-                "Trying to set undefined value `{}`",
-                dt.get_name(arena)
+            None => Err(err(
+                arena,
+                ParseErrorKind::IllegalReference,
+                rest[0],
+                format!("Trying to set undefined value `{}`", dt.get_name(arena)),
             )),
         }
     } else {
-        Err(format!(
-            "Expected symbol as target of set!, got `{}`",
-            pretty_print(arena, rest[0])
+        Err(err(
+            arena,
+            ParseErrorKind::Other,
+            rest[0],
+            format!(
+                "Expected symbol as target of set!, got `{}`",
+                pretty_print(arena, rest[0])
+            ),
         ))
     }
 }
@@ -417,27 +719,365 @@ fn parse_define(
     env: &RcEnv,
     af_info: &RcAfi,
     rest: &[PoolPtr],
-) -> Result<SyntaxElement, String> {
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
     // TODO the actual check should not be on activation frame altitude, but on syntactic
     //      toplevelness. (eg `(define x (define y 1))` should not work).
     if af_info.borrow().altitude != 0 {
-        return Err(format!(
-            "Define in illegal position: {}",
-            pretty_print(arena, list_from_vec(arena, rest))
+        return Err(err(
+            arena,
+            ParseErrorKind::Other,
+            form,
+            format!(
+                "Define in illegal position: {}",
+                pretty_print(arena, list_from_vec(arena, rest))
+            ),
         ));
     }
-    let define_data = get_define_data(arena, rest)?;
+    let define_data = get_define_data(arena, rest, form)?;
 
     // TODO: don't do this and instead allow defining syncloses at top level?
     let symbol = define_data.target.coerce_symbol();
     let index = env.borrow_mut().define_if_absent(&symbol, af_info, false);
     let value = define_data.value.parse(arena, vms, env, af_info, symbol)?;
-    Ok(SyntaxElement::Set(Box::new(Set {
-        altitude: 0,
-        depth: af_info.borrow().altitude,
-        index,
-        value,
-    })))
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Set(Box::new(Set {
+            altitude: 0,
+            depth: af_info.borrow().altitude,
+            index,
+            value,
+        })),
+        source: source_of(arena, form),
+    })
+}
+
+/// Parses `(define-record-type <type-name> (<ctor-name> <field> ...) <predicate-name>
+/// (<field> <accessor> [<mutator>]) ...)`.
+///
+/// There's no dedicated `SyntaxElement` for records - instead, the form is desugared into an
+/// ordinary `begin` of `define`s built out of `make-record`/`record?`/`record-type`/`record-ref`/
+/// `record-set!` calls (see `primitives/record.rs`), exactly the shape a `syntax-rules` macro
+/// would expand to, and handed to [`parse_begin`]. Like `define`, it's only legal at toplevel.
+fn parse_define_record_type(
+    arena: &Arena,
+    vms: &mut VmState,
+    env: &RcEnv,
+    af_info: &RcAfi,
+    rest: &[PoolPtr],
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(3), None)
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
+
+    let type_name = expect_record_symbol(arena, rest[0], "type name")?;
+
+    let ctor_spec = vec_from_list(arena, rest[1])
+        .map_err(|e| err(arena, ParseErrorKind::Other, rest[1], e))?;
+    check_len(&ctor_spec, Some(1), None)
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, rest[1], e))?;
+    let ctor_name = expect_record_symbol(arena, ctor_spec[0], "constructor name")?;
+    let ctor_fields = ctor_spec[1..]
+        .iter()
+        .map(|v| expect_record_symbol(arena, *v, "constructor field"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let predicate_name = expect_record_symbol(arena, rest[2], "predicate name")?;
+
+    struct FieldSpec {
+        name: String,
+        accessor: String,
+        mutator: Option<String>,
+    }
+    let fields = rest[3..]
+        .iter()
+        .map(|spec| {
+            let spec = vec_from_list(arena, *spec)
+                .map_err(|e| err(arena, ParseErrorKind::Other, *spec, e))?;
+            check_len(&spec, Some(2), Some(3))
+                .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, *spec, e))?;
+            Ok(FieldSpec {
+                name: expect_record_symbol(arena, spec[0], "field name")?,
+                accessor: expect_record_symbol(arena, spec[1], "accessor name")?,
+                mutator: spec
+                    .get(2)
+                    .map(|v| expect_record_symbol(arena, *v, "mutator name"))
+                    .transpose()?,
+            })
+        })
+        .collect::<Result<Vec<_>, ParseError>>()?;
+
+    for f in ctor_fields.iter() {
+        if !fields.iter().any(|spec| &spec.name == f) {
+            return Err(err(
+                arena,
+                ParseErrorKind::Other,
+                rest[1],
+                format!(
+                    "define-record-type: constructor field `{}` is not a field of `{}`",
+                    f, type_name
+                ),
+            ));
+        }
+    }
+
+    let field_names: Vec<String> = fields.iter().map(|f| f.name.clone()).collect();
+    let rtype = arena.insert(Value::RecordType(RecordType::new(
+        arena,
+        type_name.clone(),
+        field_names,
+    )));
+
+    let define_sym = arena.insert(Value::Symbol("define".to_string()));
+    let lambda_sym = arena.insert(Value::Symbol("lambda".to_string()));
+    let if_sym = arena.insert(Value::Symbol("if".to_string()));
+    let make_record_sym = arena.insert(Value::Symbol("make-record".to_string()));
+    let record_p_sym = arena.insert(Value::Symbol("record?".to_string()));
+    let record_type_sym = arena.insert(Value::Symbol("record-type".to_string()));
+    let record_ref_sym = arena.insert(Value::Symbol("record-ref".to_string()));
+    let record_set_sym = arena.insert(Value::Symbol("record-set!".to_string()));
+    let eq_p_sym = arena.insert(Value::Symbol("eq?".to_string()));
+
+    let mut defines = Vec::new();
+
+    // `(define <type-name> <rtype>)`, so the type descriptor itself is a first-class value users
+    // can compare against `record-type`.
+    defines.push(list_from_vec(
+        arena,
+        &[define_sym, arena.insert(Value::Symbol(type_name)), rtype],
+    ));
+
+    // Constructor: `(define (ctor field...) (make-record <rtype> field-or-undefined...))`.
+    let ctor_args: Vec<PoolPtr> = ctor_fields
+        .iter()
+        .map(|f| arena.insert(Value::Symbol(f.clone())))
+        .collect();
+    let make_record_args: Vec<PoolPtr> = fields
+        .iter()
+        .map(|f| {
+            ctor_fields
+                .iter()
+                .position(|cf| cf == &f.name)
+                .map(|idx| ctor_args[idx])
+                .unwrap_or(arena.undefined)
+        })
+        .collect();
+    let mut make_record_call = vec![make_record_sym, rtype];
+    make_record_call.extend(make_record_args);
+    let ctor_lambda = list_from_vec(
+        arena,
+        &[
+            lambda_sym,
+            list_from_vec(arena, &ctor_args),
+            list_from_vec(arena, &make_record_call),
+        ],
+    );
+    defines.push(list_from_vec(
+        arena,
+        &[
+            define_sym,
+            arena.insert(Value::Symbol(ctor_name)),
+            ctor_lambda,
+        ],
+    ));
+
+    // Predicate: `(define (pred? x) (if (record? x) (eq? (record-type x) <rtype>) #f))`.
+    let pred_arg = arena.gensym(Some("record"));
+    let pred_lambda = list_from_vec(
+        arena,
+        &[
+            lambda_sym,
+            list_from_vec(arena, &[pred_arg]),
+            list_from_vec(
+                arena,
+                &[
+                    if_sym,
+                    list_from_vec(arena, &[record_p_sym, pred_arg]),
+                    list_from_vec(
+                        arena,
+                        &[
+                            eq_p_sym,
+                            list_from_vec(arena, &[record_type_sym, pred_arg]),
+                            rtype,
+                        ],
+                    ),
+                    arena.f,
+                ],
+            ),
+        ],
+    );
+    defines.push(list_from_vec(
+        arena,
+        &[
+            define_sym,
+            arena.insert(Value::Symbol(predicate_name)),
+            pred_lambda,
+        ],
+    ));
+
+    // Accessors/mutators: `(define (accessor r) (record-ref r <index>))`, and symmetrically
+    // `(define (mutator r v) (record-set! r <index> v))` for fields that declared one.
+    for (idx, f) in fields.iter().enumerate() {
+        let index = arena.insert(Value::Integer(BigInt::from(idx)));
+
+        let r_arg = arena.gensym(Some("record"));
+        let accessor_lambda = list_from_vec(
+            arena,
+            &[
+                lambda_sym,
+                list_from_vec(arena, &[r_arg]),
+                list_from_vec(arena, &[record_ref_sym, r_arg, index]),
+            ],
+        );
+        defines.push(list_from_vec(
+            arena,
+            &[
+                define_sym,
+                arena.insert(Value::Symbol(f.accessor.clone())),
+                accessor_lambda,
+            ],
+        ));
+
+        if let Some(mutator) = &f.mutator {
+            let r_arg = arena.gensym(Some("record"));
+            let v_arg = arena.gensym(Some("value"));
+            let mutator_lambda = list_from_vec(
+                arena,
+                &[
+                    lambda_sym,
+                    list_from_vec(arena, &[r_arg, v_arg]),
+                    list_from_vec(arena, &[record_set_sym, r_arg, index, v_arg]),
+                ],
+            );
+            defines.push(list_from_vec(
+                arena,
+                &[
+                    define_sym,
+                    arena.insert(Value::Symbol(mutator.clone())),
+                    mutator_lambda,
+                ],
+            ));
+        }
+    }
+
+    parse_begin(arena, vms, env, af_info, &defines, form)
+}
+
+/// Desugars `(guard (var clause...) body...)` into `with-exception-handler` plus a `cond`-style
+/// if-chain over `clause`, built entirely from forms already handled natively (`if`, `lambda`,
+/// `begin`) so this doesn't depend on `cond`/`let` existing as library macros.
+///
+/// Each `clause` is `(test expr...)`, `(test => proc)`, or `(else expr...)`, exactly like `cond`.
+/// If no clause's test is true and there's no `else`, the condition is re-raised with `raise`, so
+/// a `guard` that doesn't handle a given condition lets it propagate to an outer handler.
+///
+/// This only catches what `raise` (and any error that unwinds through `with-exception-handler`)
+/// can express here: since `raise` always unwinds the innermost `vm::run` down to the matching
+/// `with-exception-handler` (see that function's doc comment), a clause that returns normally
+/// simply becomes `guard`'s result - there's no way, nor need, to resume at the `raise` site.
+fn parse_guard(
+    arena: &Arena,
+    vms: &mut VmState,
+    env: &RcEnv,
+    af_info: &RcAfi,
+    rest: &[PoolPtr],
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(2), None)
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
+
+    let spec = vec_from_list(arena, rest[0])
+        .map_err(|e| err(arena, ParseErrorKind::Other, rest[0], e))?;
+    check_len(&spec, Some(1), None)
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, rest[0], e))?;
+    let var = spec[0];
+    let clauses = &spec[1..];
+    let body = &rest[1..];
+
+    let lambda_sym = arena.insert(Value::Symbol("lambda".to_string()));
+    let if_sym = arena.insert(Value::Symbol("if".to_string()));
+    let begin_sym = arena.insert(Value::Symbol("begin".to_string()));
+    let raise_sym = arena.insert(Value::Symbol("raise".to_string()));
+    let with_exception_handler_sym =
+        arena.insert(Value::Symbol("with-exception-handler".to_string()));
+
+    // Built innermost-out: start from "no clause matched" and wrap one more `if` per clause,
+    // working backwards so earlier clauses end up testing first.
+    let mut dispatch = list_from_vec(arena, &[raise_sym, var]);
+
+    for clause in clauses.iter().rev() {
+        let parts = vec_from_list(arena, *clause)
+            .map_err(|e| err(arena, ParseErrorKind::Other, *clause, e))?;
+        check_len(&parts, Some(1), None)
+            .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, *clause, e))?;
+
+        let test = parts[0];
+        let is_else = matches!(arena.get(test), Value::Symbol(s) if s == "else");
+        let is_arrow = parts.len() == 3 && matches!(arena.get(parts[1]), Value::Symbol(s) if s == "=>");
+
+        if is_else {
+            check_len(&parts[1..], Some(1), None)
+                .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, *clause, e))?;
+            let mut begin_form = vec![begin_sym];
+            begin_form.extend(&parts[1..]);
+            dispatch = list_from_vec(arena, &begin_form);
+        } else if is_arrow {
+            let proc = parts[2];
+            let test_var = arena.gensym(Some("guard-test"));
+            let consequent = list_from_vec(arena, &[proc, test_var]);
+            let inner_if = list_from_vec(arena, &[if_sym, test_var, consequent, dispatch]);
+            let test_lambda = list_from_vec(
+                arena,
+                &[lambda_sym, list_from_vec(arena, &[test_var]), inner_if],
+            );
+            dispatch = list_from_vec(arena, &[test_lambda, test]);
+        } else if parts.len() == 1 {
+            let test_var = arena.gensym(Some("guard-test"));
+            let inner_if = list_from_vec(arena, &[if_sym, test_var, test_var, dispatch]);
+            let test_lambda = list_from_vec(
+                arena,
+                &[lambda_sym, list_from_vec(arena, &[test_var]), inner_if],
+            );
+            dispatch = list_from_vec(arena, &[test_lambda, test]);
+        } else {
+            let mut begin_form = vec![begin_sym];
+            begin_form.extend(&parts[1..]);
+            let consequent = list_from_vec(arena, &begin_form);
+            dispatch = list_from_vec(arena, &[if_sym, test, consequent, dispatch]);
+        }
+    }
+
+    let handler = list_from_vec(
+        arena,
+        &[lambda_sym, list_from_vec(arena, &[var]), dispatch],
+    );
+
+    let mut thunk_body = vec![begin_sym];
+    thunk_body.extend(body);
+    let thunk = list_from_vec(
+        arena,
+        &[lambda_sym, arena.empty_list, list_from_vec(arena, &thunk_body)],
+    );
+
+    let expanded = list_from_vec(arena, &[with_exception_handler_sym, handler, thunk]);
+    parse(arena, vms, env, af_info, expanded)
+}
+
+/// Pulls a plain (non-syntactic-closure) symbol name out of a `define-record-type` sub-form,
+/// erroring with `role` describing what was expected there.
+fn expect_record_symbol(arena: &Arena, value: PoolPtr, role: &str) -> Result<String, ParseError> {
+    match arena.get(value) {
+        Value::Symbol(s) => Ok(s.clone()),
+        _ => Err(err(
+            arena,
+            ParseErrorKind::Other,
+            value,
+            format!(
+                "define-record-type: expected a symbol for {}, got `{}`",
+                role,
+                pretty_print(arena, value)
+            ),
+        )),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -490,11 +1130,11 @@ impl DefineValue {
         env: &RcEnv,
         af_info: &RcAfi,
         name: String,
-    ) -> Result<SyntaxElement, String> {
+    ) -> Result<LocatedSyntaxElement, ParseError> {
         match self {
             DefineValue::Value(v) => parse(arena, vms, env, af_info, *v),
             DefineValue::Lambda { formals, body } => {
-                parse_split_lambda(arena, vms, env, af_info, *formals, &body, Some(name))
+                parse_split_lambda(arena, vms, env, af_info, *formals, &body, Some(name), *formals)
             }
         }
     }
@@ -506,22 +1146,27 @@ struct DefineData {
     pub value: DefineValue,
 }
 
-fn get_define_data(arena: &Arena, rest: &[PoolPtr]) -> Result<DefineData, String> {
+fn get_define_data(arena: &Arena, rest: &[PoolPtr], form: PoolPtr) -> Result<DefineData, ParseError> {
     let res = if let Some(target) = get_define_target(arena, rest[0]) {
-        check_len(rest, Some(2), Some(2))?;
+        check_len(rest, Some(2), Some(2))
+            .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
         DefineData {
             target,
             value: DefineValue::Value(rest[1]),
         }
     } else {
-        get_lambda_define_value(arena, rest)?
+        get_lambda_define_value(arena, rest, form)?
     };
     Ok(res)
 }
 
 /// Helper method to parse direct lambda defines `(define (x y z) y z)`.
-fn get_lambda_define_value(arena: &Arena, rest: &[PoolPtr]) -> Result<DefineData, String> {
-    check_len(rest, Some(2), None)?;
+fn get_lambda_define_value(
+    arena: &Arena,
+    rest: &[PoolPtr],
+    form: PoolPtr,
+) -> Result<DefineData, ParseError> {
+    check_len(rest, Some(2), None).map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
     if let Value::Pair(car, cdr) = arena.get(rest[0]) {
         if let Value::Symbol(s) = arena.get(car.get()) {
             let variable = s.clone();
@@ -533,15 +1178,25 @@ fn get_lambda_define_value(arena: &Arena, rest: &[PoolPtr]) -> Result<DefineData
                 },
             })
         } else {
-            Err(format!(
-                "Expected symbol for method name in define method, got `{}`.",
-                pretty_print(arena, car.get())
+            Err(err(
+                arena,
+                ParseErrorKind::Other,
+                car.get(),
+                format!(
+                    "Expected symbol for method name in define method, got `{}`.",
+                    pretty_print(arena, car.get())
+                ),
             ))
         }
     } else {
-        Err(format!(
-            "Expected symbol or formals as target of define, got `{}`.",
-            pretty_print(arena, rest[0])
+        Err(err(
+            arena,
+            ParseErrorKind::Other,
+            rest[0],
+            format!(
+                "Expected symbol or formals as target of define, got `{}`.",
+                pretty_print(arena, rest[0])
+            ),
         ))
     }
 }
@@ -553,19 +1208,20 @@ fn parse_application(
     af_info: &RcAfi,
     fun: PoolPtr,
     rest: &[PoolPtr],
-) -> Result<SyntaxElement, String> {
+) -> Result<LocatedSyntaxElement, ParseError> {
     let function = parse(arena, vms, env, af_info, fun)?;
     let args = rest
         .iter()
         .map(|e| parse(arena, vms, env, af_info, *e))
         .collect::<Result<Vec<_>, _>>()?;
-    Ok(SyntaxElement::Application(Box::new(Application {
-        function,
-        args,
-    })))
+    let source = function.source.clone();
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Application(Box::new(Application { function, args })),
+        source,
+    })
 }
 
-fn parse_formals(arena: &Arena, formals: PoolPtr) -> Result<Formals, String> {
+fn parse_formals(arena: &Arena, formals: PoolPtr) -> Result<Formals, ParseError> {
     let mut values = Vec::new();
     let mut formal = formals;
     loop {
@@ -582,16 +1238,26 @@ fn parse_formals(arena: &Arena, formals: PoolPtr) -> Result<Formals, String> {
                         values.push(dt);
                         formal = cdr.get();
                     } else {
-                        return Err(format!(
-                            "Malformed formals: {}.",
-                            arena.get(formals).pretty_print(arena)
+                        return Err(err(
+                            arena,
+                            ParseErrorKind::MalformedFormals,
+                            formals,
+                            format!(
+                                "Malformed formals: {}.",
+                                arena.get(formals).pretty_print(arena)
+                            ),
                         ));
                     }
                 }
                 _ => {
-                    return Err(format!(
-                        "Malformed formals: {}.",
-                        arena.get(formals).pretty_print(arena)
+                    return Err(err(
+                        arena,
+                        ParseErrorKind::MalformedFormals,
+                        formals,
+                        format!(
+                            "Malformed formals: {}.",
+                            arena.get(formals).pretty_print(arena)
+                        ),
                     ));
                 }
             }
@@ -599,36 +1265,68 @@ fn parse_formals(arena: &Arena, formals: PoolPtr) -> Result<Formals, String> {
     }
 }
 
-fn parse_define_syntax(
+/// Parses a `(name transformer-spec)` pair shared by `define-syntax` and each binding of
+/// `let-syntax`/`letrec-syntax`, returning the bound name and the [`Macro`] its transformer
+/// compiles to. `what` names the calling form, used only to word the error message if `name`
+/// isn't a symbol.
+fn parse_macro_binding(
     arena: &Arena,
-    vms: &mut VmState,
     env: &RcEnv,
     af_info: &RcAfi,
+    vms: &mut VmState,
     rest: &[PoolPtr],
-) -> Result<SyntaxElement, String> {
-    // TODO the actual check should not be on activation frame altitude, but on syntactic
-    //      toplevelness. (eg `(define x (define y 1))` should not work).
-    if af_info.borrow().altitude != 0 {
-        return Err("Illegally placed define-syntax.".into());
-    }
-    check_len(rest, Some(2), Some(2))?;
-
+    what: &str,
+) -> Result<(String, MacroTransformer), ParseError> {
     let symbol = arena
         .try_get_symbol(rest[0])
         .ok_or_else(|| {
-            format!(
-                "define-syntax: target must be symbol, not {}.",
-                pretty_print(arena, rest[0])
+            err(
+                arena,
+                ParseErrorKind::Other,
+                rest[0],
+                format!(
+                    "{}: target must be symbol, not {}.",
+                    what,
+                    pretty_print(arena, rest[0])
+                ),
             )
         })?
         .to_string();
     let mac = make_macro(arena, env, af_info, vms, rest[1])?;
+    Ok((symbol, mac))
+}
+
+fn parse_define_syntax(
+    arena: &Arena,
+    vms: &mut VmState,
+    env: &RcEnv,
+    af_info: &RcAfi,
+    rest: &[PoolPtr],
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    // TODO the actual check should not be on activation frame altitude, but on syntactic
+    //      toplevelness. (eg `(define x (define y 1))` should not work).
+    if af_info.borrow().altitude != 0 {
+        return Err(err(
+            arena,
+            ParseErrorKind::Other,
+            form,
+            "Illegally placed define-syntax.",
+        ));
+    }
+    check_len(rest, Some(2), Some(2))
+        .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
+
+    let (symbol, mac) = parse_macro_binding(arena, env, af_info, vms, rest, "define-syntax")?;
     env.borrow_mut().define_macro(&symbol, mac, env.clone());
 
     // TODO remove this somehow
-    Ok(SyntaxElement::Quote(Box::new(Quote {
-        quoted: arena.root(arena.unspecific),
-    })))
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Quote(Box::new(Quote {
+            quoted: arena.root(arena.unspecific),
+        })),
+        source: source_of(arena, form),
+    })
 }
 
 fn parse_let_syntax(
@@ -638,25 +1336,18 @@ fn parse_let_syntax(
     af_info: &RcAfi,
     rest: &[PoolPtr],
     rec: bool,
-) -> Result<SyntaxElement, String> {
-    check_len(rest, Some(2), None)?;
-    let bindings = vec_from_list(arena, rest[0])?;
+    form: PoolPtr,
+) -> Result<LocatedSyntaxElement, ParseError> {
+    check_len(rest, Some(2), None).map_err(|e| err(arena, ParseErrorKind::WrongArgCount, form, e))?;
+    let bindings = vec_from_list(arena, rest[0]).map_err(|e| err(arena, ParseErrorKind::Other, form, e))?;
     let inner_env = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
     let definition_env = if rec { env } else { &inner_env };
     for b in bindings.iter() {
-        let binding = vec_from_list(arena, *b)?;
-        check_len(&binding, Some(2), Some(2))?;
+        let binding = vec_from_list(arena, *b).map_err(|e| err(arena, ParseErrorKind::Other, *b, e))?;
+        check_len(&binding, Some(2), Some(2))
+            .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, *b, e))?;
 
-        let symbol = arena
-            .try_get_symbol(binding[0])
-            .ok_or_else(|| {
-                format!(
-                    "let-syntax: target must be symbol, not {}.",
-                    pretty_print(arena, rest[0])
-                )
-            })?
-            .to_string();
-        let mac = make_macro(arena, env, af_info, vms, binding[1])?;
+        let (symbol, mac) = parse_macro_binding(arena, env, af_info, vms, &binding, "let-syntax")?;
         inner_env
             .borrow_mut()
             .define_macro(&symbol, mac, definition_env.clone());
@@ -672,11 +1363,15 @@ fn parse_let_syntax(
         arena.empty_list,
         &rest[1..],
         Some("[let-syntax inner lambda]".into()),
+        form,
     )?;
-    Ok(SyntaxElement::Application(Box::new(Application {
-        function: lambda,
-        args: vec![],
-    })))
+    Ok(LocatedSyntaxElement {
+        element: SyntaxElement::Application(Box::new(Application {
+            function: lambda,
+            args: vec![],
+        })),
+        source: source_of(arena, form),
+    })
 }
 
 fn make_macro(
@@ -685,14 +1380,24 @@ fn make_macro(
     af_info: &RcAfi,
     vms: &mut VmState,
     val: PoolPtr,
-) -> Result<RootPtr, String> {
+) -> Result<MacroTransformer, ParseError> {
+    // `(syntax-rules ...)` is a literal transformer spec, not an expression to compile and
+    // run - check for it before falling back to the procedural (lambda-producing) path.
+    if let Some(rules) = syntax_rules::try_parse(arena, "define-syntax", val)
+        .map_err(|e| err(arena, ParseErrorKind::Other, val, e))?
+    {
+        return Ok(MacroTransformer::SyntaxRules(Rc::new(rules)));
+    }
+
     let mac = parse_compile_run_macro(arena, env, af_info, vms, val)?;
     let mac = arena.root(mac);
     match arena.get(mac.pp()) {
-        Value::Lambda { .. } => Ok(mac), // TODO check the lambda takes 3 args
-        _ => Err(format!(
-            "macro must be a lambda, is {}",
-            pretty_print(arena, mac.pp())
+        Value::Lambda { .. } => Ok(MacroTransformer::Procedural(mac)), // TODO check the lambda takes 3 args
+        _ => Err(err(
+            arena,
+            ParseErrorKind::Other,
+            val,
+            format!("macro must be a lambda, is {}", pretty_print(arena, mac.pp())),
         )),
     }
 }
@@ -705,9 +1410,8 @@ fn parse_compile_run_macro(
     af_info: &RcAfi,
     vms: &mut VmState,
     val: PoolPtr,
-) -> Result<PoolPtr, String> {
-    let syntax_tree =
-        parse(arena, vms, env, af_info, val).map_err(|e| format!("syntax error: {}", e))?;
+) -> Result<PoolPtr, ParseError> {
+    let syntax_tree = parse(arena, vms, env, af_info, val)?;
     arena
         .get_activation_frame(vms.global_frame.pp())
         .borrow_mut()
@@ -715,12 +1419,19 @@ fn parse_compile_run_macro(
 
     let frame = make_frame(arena, vms.global_frame.pp(), af_info);
 
-    let code = compile::compile_toplevel(arena, &syntax_tree, vms.global_environment.clone());
+    let code = compile::compile_toplevel(arena, &syntax_tree.element, vms.global_environment.clone());
     // println!(" => {:?}", &state.code[start_pc..state.code.len()]);
     let code = arena.root(code);
     vm::run(arena, code, 0, vms.global_frame.pp(), frame)
         .map(|v| v.pp())
-        .map_err(|e| format!("runtime error: {}", pretty_print(arena, e.pp())))
+        .map_err(|e| {
+            err(
+                arena,
+                ParseErrorKind::Other,
+                val,
+                format!("runtime error: {}", pretty_print(arena, e.pp())),
+            )
+        })
 }
 
 fn make_frame(arena: &Arena, global_frame: PoolPtr, af_info: &RcAfi) -> PoolPtr {
@@ -738,22 +1449,73 @@ fn make_frame(arena: &Arena, global_frame: PoolPtr, af_info: &RcAfi) -> PoolPtr
     arena.insert(Value::ActivationFrame(RefCell::new(frame)))
 }
 
+/// Builds the message for [`ParseErrorKind::MacroExpansionLimit`]: names the macro whose
+/// expansion was about to exceed `vms.macro_expansion_limit` (`culprit`), and shows the last few
+/// steps already recorded in `vms.macro_trace` for this chain, so the user can see the runaway
+/// rewrite rule in action instead of just a bare "too much recursion" message.
+fn macro_expansion_limit_message(arena: &Arena, vms: &VmState, culprit: &str) -> String {
+    const CONTEXT_STEPS: usize = 5;
+    let trace = &vms.macro_trace;
+    let context = trace[trace.len().saturating_sub(CONTEXT_STEPS)..]
+        .iter()
+        .map(|step| {
+            format!(
+                "  {}: {} => {}",
+                step.macro_name,
+                pretty_print(arena, step.before.pp()),
+                pretty_print(arena, step.after.pp())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "Maximum macro expansion depth ({}) reached while expanding `{}`. Last steps:\n{}",
+        vms.macro_expansion_limit, culprit, context
+    )
+}
+
+/// Expands `expr` (an invocation of macro `name`) and keeps re-expanding the result for as long
+/// as it's itself a macro call, up to `vms.macro_expansion_limit` steps (defaulting to
+/// [`MAX_MACRO_EXPANSION`] - see that constant's doc comment). Every individual rewrite - not
+/// just the final result - is appended to `vms.macro_trace`, so a caller that hits the depth
+/// limit (or just wants to see how a macro expands) can inspect the whole chain afterwards; see
+/// `(macroexpand expr)` / `(macroexpand-1 expr)`, which surface this trace as data.
 fn expand_macro_full(
     arena: &Arena,
     vms: &mut VmState,
     env: &RcEnv,
+    name: &str,
     mac: Macro,
     expr: PoolPtr,
-) -> Result<PoolPtr, String> {
+) -> Result<PoolPtr, ParseError> {
+    let origin = expr;
     let expr = arena.root(expr);
-    let mut expanded = expand_macro(arena, vms, env, mac, expr)?;
+    let mut expanded = expand_macro(arena, vms, env, mac, expr.clone())?;
+    record_expansion_origin(arena, name, expr.pp(), expanded.pp());
+    vms.macro_trace.push(MacroExpansionStep {
+        macro_name: name.to_string(),
+        before: expr,
+        after: expanded.clone(),
+    });
     let mut macro_count = 0;
-    while let Some(m) = get_macro(arena, env, expanded.pp()) {
+    while let Some((next_name, m)) = get_macro(arena, env, expanded.pp()) {
         macro_count += 1;
-        if macro_count > MAX_MACRO_EXPANSION {
-            return Err("Maximum macro expansion depth reached.".into());
+        if macro_count > vms.macro_expansion_limit {
+            return Err(err(
+                arena,
+                ParseErrorKind::MacroExpansionLimit,
+                origin,
+                macro_expansion_limit_message(arena, vms, &next_name),
+            ));
         }
+        let before = expanded.clone();
         expanded = expand_macro(arena, vms, env, m, expanded)?;
+        record_expansion_origin(arena, &next_name, before.pp(), expanded.pp());
+        vms.macro_trace.push(MacroExpansionStep {
+            macro_name: next_name,
+            before,
+            after: expanded.clone(),
+        });
     }
     Ok(expanded.pp())
 }
@@ -764,32 +1526,51 @@ fn expand_macro(
     env: &RcEnv,
     mac: Macro,
     expr: RootPtr,
-) -> Result<RootPtr, String> {
-    let definition_environment = Value::Environment(mac.definition_environment.clone());
+) -> Result<RootPtr, ParseError> {
+    match mac.transformer {
+        MacroTransformer::Procedural(lambda) => {
+            expand_procedural_macro(arena, vms, env, lambda, mac.definition_environment, expr)
+        }
+        MacroTransformer::SyntaxRules(rules) => {
+            let expanded = syntax_rules::expand(arena, &rules, &mac.definition_environment, expr.pp())
+                .map_err(|e| err(arena, ParseErrorKind::Other, expr.pp(), e))?;
+            Ok(arena.root(expanded))
+        }
+    }
+}
+
+fn expand_procedural_macro(
+    arena: &Arena,
+    vms: &mut VmState,
+    env: &RcEnv,
+    lambda: RootPtr,
+    definition_environment: RcEnv,
+    expr: RootPtr,
+) -> Result<RootPtr, ParseError> {
+    let definition_environment = Value::Environment(definition_environment);
     let usage_environment = Value::Environment(env.clone());
-    arena.insert(Value::Integer(100.into()));
+    let quote = |quoted| LocatedSyntaxElement {
+        element: SyntaxElement::Quote(Box::new(Quote { quoted })),
+        source: None,
+    };
     let syntax_tree = SyntaxElement::Application(Box::new(Application {
-        function: SyntaxElement::Quote(Box::new(Quote { quoted: mac.lambda })),
+        function: quote(lambda),
         args: vec![
-            SyntaxElement::Quote(Box::new(Quote { quoted: expr })),
-            SyntaxElement::Quote(Box::new(Quote {
-                quoted: arena.insert_rooted(usage_environment),
-            })),
-            SyntaxElement::Quote(Box::new(Quote {
-                quoted: arena.insert_rooted(definition_environment),
-            })),
+            quote(expr.clone()),
+            quote(arena.insert_rooted(usage_environment)),
+            quote(arena.insert_rooted(definition_environment)),
         ],
     }));
-    compile_run(arena, vms, &syntax_tree)
+    compile_run(arena, vms, &syntax_tree).map_err(|e| err(arena, ParseErrorKind::Other, expr.pp(), e))
 }
 
-fn get_macro(arena: &Arena, env: &RcEnv, expr: PoolPtr) -> Option<Macro> {
+fn get_macro(arena: &Arena, env: &RcEnv, expr: PoolPtr) -> Option<(String, Macro)> {
     match arena.get(expr) {
         Value::Pair(car, _cdr) => {
             let (res_env, res_car) = resolve_syntactic_closure(arena, env, car.get()).unwrap();
             match arena.get(res_car) {
                 Value::Symbol(s) => match match_symbol(&res_env, &s) {
-                    Symbol::Macro(m) => Some(m),
+                    Symbol::Macro(m) => Some((s.clone(), m)),
                     _ => None,
                 },
                 _ => None,
@@ -807,10 +1588,18 @@ enum Symbol {
     Lambda,
     Set,
     Define,
+    DefineRecordType,
+    /// `(guard (var clause...) body...)`; see `parse_guard`.
+    Guard,
     DefineSyntax,
     LetSyntax,
     LetrecSyntax,
     Macro(Macro),
+    /// `(macroexpand expr)` (`true`) or `(macroexpand-1 expr)` (`false`).
+    MacroExpand(bool),
+    /// `(macro-expansion-trace expr)`.
+    MacroExpansionTrace,
+    CustomSyntax(CustomSyntax),
     Variable,
 }
 
@@ -824,34 +1613,48 @@ fn match_symbol(env: &RcEnv, sym: &str) -> Symbol {
             "lambda" => Symbol::Lambda,
             "set!" => Symbol::Set,
             "define" => Symbol::Define,
+            "define-record-type" => Symbol::DefineRecordType,
+            "guard" => Symbol::Guard,
             "define-syntax" => Symbol::DefineSyntax,
             "let-syntax" => Symbol::LetSyntax,
             "letrec-syntax" => Symbol::LetrecSyntax,
+            "macroexpand" => Symbol::MacroExpand(true),
+            "macroexpand-1" => Symbol::MacroExpand(false),
+            "macro-expansion-trace" => Symbol::MacroExpansionTrace,
             _ => Symbol::Variable,
         },
         Some(EnvironmentValue::Macro(m)) => Symbol::Macro(m),
+        Some(EnvironmentValue::CustomSyntax(cs)) => Symbol::CustomSyntax(cs),
         Some(EnvironmentValue::Variable(_)) => Symbol::Variable,
     }
 }
 
+/// Collects the leading run of internal definitions in a lambda/let body, stopping at the first
+/// statement that isn't a `define`, `begin`, `define-syntax`, `let-syntax`, or `letrec-syntax`.
+/// `define-syntax`/`let-syntax`/`letrec-syntax` install macro bindings straight into `env` - the
+/// body's own environment, shared by every statement in this scan - so that, per R7RS, later
+/// statements in the same body can use a macro defined earlier in it. `let-syntax`/
+/// `letrec-syntax` additionally recurse into their own body, splicing any definitions they
+/// contain into the result exactly like `begin` does; their bindings aren't given a narrower
+/// scope than the rest of the body, matching how `begin` doesn't introduce one either.
 #[allow(clippy::type_complexity)]
 fn collect_internal_defines(
     arena: &Arena,
     vms: &mut VmState,
     env: &RcEnv,
+    af_info: &RcAfi,
     body: &[PoolPtr],
-) -> Result<(Vec<DefineData>, Vec<PoolPtr>), String> {
+) -> Result<(Vec<DefineData>, Vec<PoolPtr>), ParseError> {
     // TODO figure out a nice way to push macro expanded, non-define values. Right know
     //      we'll perform macro expansion both here and at the actual parse site.
-    // TODO support internal macro definitions
 
     let mut defines = Vec::new();
     let mut rest = Vec::new();
     let mut i = 0 as usize;
 
     for statement in body.iter() {
-        let expanded_statement = if let Some(m) = get_macro(arena, env, *statement) {
-            expand_macro_full(arena, vms, env, m, *statement)?
+        let expanded_statement = if let Some((name, m)) = get_macro(arena, env, *statement) {
+            expand_macro_full(arena, vms, env, &name, m, *statement)?
         } else {
             *statement
         };
@@ -860,18 +1663,74 @@ fn collect_internal_defines(
             if let Value::Symbol(s) = arena.get(res_car) {
                 match match_symbol(&res_env, s) {
                     Symbol::Define => {
-                        let rest = vec_from_list(arena, cdr.get())?;
-                        let dv = get_define_data(arena, &rest)?;
+                        let rest = vec_from_list(arena, cdr.get())
+                            .map_err(|e| err(arena, ParseErrorKind::Other, expanded_statement, e))?;
+                        let dv = get_define_data(arena, &rest, expanded_statement)?;
                         defines.push(dv);
                     }
                     Symbol::Begin => {
-                        let expressions = vec_from_list(arena, cdr.get())?;
-                        let (d, rest) = collect_internal_defines(arena, vms, env, &expressions)?;
+                        let expressions = vec_from_list(arena, cdr.get())
+                            .map_err(|e| err(arena, ParseErrorKind::Other, expanded_statement, e))?;
+                        let (d, rest) =
+                            collect_internal_defines(arena, vms, env, af_info, &expressions)?;
                         if !rest.is_empty() {
-                            return Err(
-                                "Inner begin in define section may only contain definitions."
-                                    .into(),
-                            );
+                            return Err(err(
+                                arena,
+                                ParseErrorKind::Other,
+                                expanded_statement,
+                                "Inner begin in define section may only contain definitions.",
+                            ));
+                        }
+                        defines.extend(d.into_iter());
+                    }
+                    Symbol::DefineSyntax => {
+                        let define_rest = vec_from_list(arena, cdr.get())
+                            .map_err(|e| err(arena, ParseErrorKind::Other, expanded_statement, e))?;
+                        check_len(&define_rest, Some(2), Some(2)).map_err(|e| {
+                            err(arena, ParseErrorKind::WrongArgCount, expanded_statement, e)
+                        })?;
+                        let (symbol, mac) = parse_macro_binding(
+                            arena,
+                            env,
+                            af_info,
+                            vms,
+                            &define_rest,
+                            "define-syntax",
+                        )?;
+                        env.borrow_mut().define_macro(&symbol, mac, env.clone());
+                    }
+                    Symbol::LetSyntax | Symbol::LetrecSyntax => {
+                        let let_rest = vec_from_list(arena, cdr.get())
+                            .map_err(|e| err(arena, ParseErrorKind::Other, expanded_statement, e))?;
+                        check_len(&let_rest, Some(2), None).map_err(|e| {
+                            err(arena, ParseErrorKind::WrongArgCount, expanded_statement, e)
+                        })?;
+                        let bindings = vec_from_list(arena, let_rest[0])
+                            .map_err(|e| err(arena, ParseErrorKind::Other, expanded_statement, e))?;
+                        for b in bindings.iter() {
+                            let binding = vec_from_list(arena, *b)
+                                .map_err(|e| err(arena, ParseErrorKind::Other, *b, e))?;
+                            check_len(&binding, Some(2), Some(2))
+                                .map_err(|e| err(arena, ParseErrorKind::WrongArgCount, *b, e))?;
+                            let (symbol, mac) = parse_macro_binding(
+                                arena, env, af_info, vms, &binding, "let-syntax",
+                            )?;
+                            env.borrow_mut().define_macro(&symbol, mac, env.clone());
+                        }
+                        let (d, inner_rest) = collect_internal_defines(
+                            arena,
+                            vms,
+                            env,
+                            af_info,
+                            &let_rest[1..],
+                        )?;
+                        if !inner_rest.is_empty() {
+                            return Err(err(
+                                arena,
+                                ParseErrorKind::Other,
+                                expanded_statement,
+                                "Inner let-syntax in define section may only contain definitions.",
+                            ));
                         }
                         defines.extend(d.into_iter());
                     }
@@ -896,7 +1755,7 @@ fn resolve_syntactic_closure(
     arena: &Arena,
     env: &RcEnv,
     value: PoolPtr,
-) -> Result<(RcEnv, PoolPtr), String> {
+) -> Result<(RcEnv, PoolPtr), ParseError> {
     if let Value::SyntacticClosure(SyntacticClosure {
         closed_env,
         free_variables,
@@ -906,7 +1765,8 @@ fn resolve_syntactic_closure(
         let closed_env = arena
             .try_get_environment(*closed_env.borrow())
             .expect("Syntactic closure created with non-environment argument.");
-        let inner_env = environment::filter(closed_env, env, free_variables)?;
+        let inner_env = environment::filter(closed_env, env, free_variables)
+            .map_err(|e| err(arena, ParseErrorKind::Other, value, e))?;
         resolve_syntactic_closure(arena, &inner_env, *expr)
     } else {
         Ok((env.clone(), value))
@@ -21,9 +21,10 @@ use std::env;
 use std::str::FromStr;
 
 use clap::{App, Arg};
+use peroxide::error::{format_error, ColorChoice};
 use peroxide::heap::GcMode;
 use peroxide::lex::{SegmentationResult, Token};
-use peroxide::repl::{FileRepl, GetLineError, ReadlineRepl, Repl, StdIoRepl};
+use peroxide::repl::{FileRepl, GetLineError, ReadlineRepl, Repl, StdIoRepl, StdinRepl};
 use peroxide::Interpreter;
 
 fn main() {
@@ -41,17 +42,8 @@ fn do_main(args: Vec<String>) -> Result<(), String> {
     let options = parse_args(&args.iter().map(|x| &**x).collect::<Vec<_>>())
         .map_err(|e| format!("could not parse arguments: {}", e))?;
 
-    let silent = options.input_file.is_some();
-    let mut repl: Box<dyn Repl> = match options.input_file {
-        Some(f) => Box::new(FileRepl::new(&f)?),
-        None => {
-            if options.enable_readline {
-                Box::new(ReadlineRepl::new(Some("history.txt".to_string())))
-            } else {
-                Box::new(StdIoRepl {})
-            }
-        }
-    };
+    let silent = options.silent || options.input_file.is_some();
+    let color = resolve_color(options.color);
 
     let interpreter = Interpreter::new(options.gc_mode);
     let interruptor_clone = interpreter.interruptor();
@@ -64,8 +56,29 @@ fn do_main(args: Vec<String>) -> Result<(), String> {
     if !options.no_std {
         interpreter.initialize("src/scheme-lib/init.scm")?;
     }
+
+    if !options.eval.is_empty() {
+        let mut last_result = Ok(());
+        for expr in &options.eval {
+            last_result = eval_str(&interpreter, expr, silent, color);
+        }
+        return last_result;
+    }
+
+    let mut repl: Box<dyn Repl> = match options.input_file.as_deref() {
+        Some("-") => Box::new(StdinRepl::new()),
+        Some(f) => Box::new(FileRepl::new(f)?),
+        None => {
+            if options.enable_readline {
+                Box::new(ReadlineRepl::new(Some("history.txt".to_string())))
+            } else {
+                Box::new(StdIoRepl {})
+            }
+        }
+    };
+
     loop {
-        if !handle_one_expr_wrap(&mut *repl, &interpreter, silent) {
+        if !handle_one_expr_wrap(&mut *repl, &interpreter, silent, color) {
             break;
         }
     }
@@ -74,10 +87,70 @@ fn do_main(args: Vec<String>) -> Result<(), String> {
     Ok(())
 }
 
+/// Resolves the `--color` CLI choice against whether stdout is actually a terminal, so `auto`
+/// behaves like `always` when output goes to an interactive shell and like `never` when it's
+/// redirected to a file or piped into another program.
+fn resolve_color(choice: CliColorChoice) -> ColorChoice {
+    let colored = match choice {
+        CliColorChoice::Always => true,
+        CliColorChoice::Never => false,
+        CliColorChoice::Auto => atty::is(atty::Stream::Stdout),
+    };
+    if colored {
+        ColorChoice::Colored
+    } else {
+        ColorChoice::Plain
+    }
+}
+
+/// Lexes, parses, compiles and runs every top-level form in `expr`, in order, printing each
+/// result unless `silent`. Returns the outcome of the last form only, so an earlier form's
+/// failure doesn't carry over once a later form succeeds; a lex, segmentation or read error
+/// stops at once and is returned as is. Used by `--eval` to set the process's exit status.
+fn eval_str(
+    vm_state: &Interpreter,
+    expr: &str,
+    silent: bool,
+    color: ColorChoice,
+) -> Result<(), String> {
+    let tokens = peroxide::lex::lex(expr)?;
+    let SegmentationResult {
+        segments, remainder, ..
+    } = peroxide::lex::segment(tokens, 0)?;
+    if !remainder.is_empty() {
+        return Err(format!("incomplete expression: {}", expr));
+    }
+
+    let mut result = Ok(());
+    for token_vector in segments {
+        let parse_value = peroxide::read::read_tokens(&vm_state.arena, &token_vector)
+            .map_err(|e| format!("parse error: {:?}", e))?;
+
+        result = match vm_state.parse_compile_run(parse_value) {
+            Ok(v) => {
+                if !silent {
+                    println!(" => {}", v.pp().write_shared())
+                }
+                Ok(())
+            }
+            Err(e) => {
+                println!("{}", format_error(color, &e));
+                Err(e)
+            }
+        };
+    }
+    result
+}
+
 // Returns true if the REPL loop should continue, false otherwise.
-fn handle_one_expr_wrap(repl: &mut dyn Repl, vm_state: &Interpreter, silent: bool) -> bool {
-    handle_one_expr(repl, vm_state, silent)
-        .map_err(|e| println!("Error: {}", e))
+fn handle_one_expr_wrap(
+    repl: &mut dyn Repl,
+    vm_state: &Interpreter,
+    silent: bool,
+    color: ColorChoice,
+) -> bool {
+    handle_one_expr(repl, vm_state, silent, color)
+        .map_err(|e| println!("{}", format_error(color, &e)))
         .unwrap_or(true)
 }
 
@@ -85,6 +158,7 @@ fn handle_one_expr(
     repl: &mut dyn Repl,
     vm_state: &Interpreter,
     silent: bool,
+    color: ColorChoice,
 ) -> Result<bool, String> {
     let mut current_expr_string: Vec<String> = Vec::new();
     let mut exprs: Vec<Vec<Token>> = Vec::new();
@@ -129,11 +203,16 @@ fn handle_one_expr(
     }
 
     repl.add_to_history(&current_expr_string.join("\n"));
-    let _ = rep(vm_state, exprs, silent);
+    let _ = rep(vm_state, exprs, silent, color);
     Ok(true)
 }
 
-fn rep(vm_state: &Interpreter, toks: Vec<Vec<Token>>, silent: bool) -> Result<(), ()> {
+fn rep(
+    vm_state: &Interpreter,
+    toks: Vec<Vec<Token>>,
+    silent: bool,
+    color: ColorChoice,
+) -> Result<(), ()> {
     for token_vector in toks {
         let parse_value = peroxide::read::read_tokens(&vm_state.arena, &token_vector)
             .map_err(|e| println!("parse error: {:?}", e))?;
@@ -141,21 +220,46 @@ fn rep(vm_state: &Interpreter, toks: Vec<Vec<Token>>, silent: bool) -> Result<()
         match vm_state.parse_compile_run(parse_value) {
             Ok(v) => {
                 if !silent {
-                    println!(" => {}", v.pp().pretty_print())
+                    println!(" => {}", v.pp().write_shared())
                 }
             }
-            Err(e) => println!("{}", e),
+            Err(e) => println!("{}", format_error(color, &e)),
         }
     }
     Ok(())
 }
 
+/// The raw `--color` choice as given on the command line, before being resolved against whether
+/// stdout is a terminal (see `resolve_color`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CliColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for CliColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "auto" => Ok(CliColorChoice::Auto),
+            "always" => Ok(CliColorChoice::Always),
+            "never" => Ok(CliColorChoice::Never),
+            _ => Err(format!("invalid color mode: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Options {
     pub enable_readline: bool,
     pub no_std: bool,
     pub input_file: Option<String>,
     pub gc_mode: GcMode,
+    pub eval: Vec<String>,
+    pub silent: bool,
+    pub color: CliColorChoice,
 }
 
 fn parse_args(args: &[&str]) -> Result<Options, String> {
@@ -178,7 +282,31 @@ fn parse_args(args: &[&str]) -> Result<Options, String> {
                 .possible_values(&["off", "normal", "debug", "debug-heavy"])
                 .default_value("normal"),
         )
-        .arg(Arg::with_name("input-file").help("Sets the input file to use"))
+        .arg(
+            Arg::with_name("eval")
+                .short("e")
+                .long("eval")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true)
+                .help("Evaluates an expression given on the command line; can be repeated"),
+        )
+        .arg(
+            Arg::with_name("silent")
+                .long("silent")
+                .help("Does not print the result of evaluated expressions"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .help("Controls colored diagnostic output"),
+        )
+        .arg(
+            Arg::with_name("input-file")
+                .help("Sets the input file to use; `-` reads a full program from stdin"),
+        )
         .get_matches_from(args);
 
     Ok(Options {
@@ -186,5 +314,10 @@ fn parse_args(args: &[&str]) -> Result<Options, String> {
         no_std: matches.is_present("no-std"),
         input_file: matches.value_of("input-file").map(|v| v.to_string()),
         gc_mode: GcMode::from_str(matches.value_of("gc-mode").unwrap()).unwrap(),
+        eval: matches
+            .values_of("eval")
+            .map_or_else(Vec::new, |v| v.map(|s| s.to_string()).collect()),
+        silent: matches.is_present("silent"),
+        color: CliColorChoice::from_str(matches.value_of("color").unwrap()).unwrap(),
     })
 }
@@ -24,11 +24,12 @@ use std::rc::Rc;
 use std::str::FromStr;
 
 use clap::{App, Arg};
+use peroxide::diagnostic::Diagnostics;
 use peroxide::heap::GcMode;
-use peroxide::lex::{PositionedToken, SegmentationResult};
+use peroxide::lex::{LexError, PositionedToken, SegmentationResult};
 use peroxide::read::Reader;
 use peroxide::repl::{FileRepl, GetLineError, ReadlineRepl, Repl, StdIoRepl};
-use peroxide::Interpreter;
+use peroxide::{File, Interpreter};
 
 fn main() {
     pretty_env_logger::init();
@@ -47,20 +48,23 @@ fn do_main(args: Vec<String>) -> Result<(), String> {
         .map_err(|e| format!("could not parse arguments: {}", e))?;
 
     let silent = options.input_file.is_some();
+    let interpreter = Rc::new(Interpreter::new(options.gc_mode));
+    let interruptor_clone = interpreter.interruptor();
+
     let mut repl: Box<dyn Repl> = match options.input_file {
         Some(f) => Box::new(FileRepl::new(&f)?),
         None => {
             if options.enable_readline {
-                Box::new(ReadlineRepl::new(Some("history.txt".to_string())))
+                Box::new(ReadlineRepl::new(
+                    Some("history.txt".to_string()),
+                    Rc::clone(&interpreter),
+                ))
             } else {
                 Box::new(StdIoRepl {})
             }
         }
     };
 
-    let interpreter = Interpreter::new(options.gc_mode);
-    let interruptor_clone = interpreter.interruptor();
-
     ctrlc::set_handler(move || {
         interruptor_clone.interrupt();
     })
@@ -69,6 +73,19 @@ fn do_main(args: Vec<String>) -> Result<(), String> {
     if let Some(path) = options.stdlib_file {
         interpreter.initialize(&path)?;
     }
+
+    if options.check {
+        let path = options
+            .input_file
+            .as_ref()
+            .expect("--check requires an input file (enforced by clap's `requires`)");
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        for diagnostic in interpreter.lint(&contents) {
+            println!("{}", diagnostic.render());
+        }
+        return Ok(());
+    }
+
     loop {
         if !handle_one_expr_wrap(&mut *repl, &interpreter, silent) {
             break;
@@ -95,6 +112,7 @@ fn handle_one_expr(
     let mut exprs: Vec<Vec<PositionedToken>> = Vec::new();
     let mut pending_expr: Vec<PositionedToken> = Vec::new();
     let mut depth: u64 = 0;
+    let mut comment_depth: u32 = 0;
 
     loop {
         let line_opt = if pending_expr.is_empty() {
@@ -114,18 +132,23 @@ fn handle_one_expr(
         };
 
         let line = line_opt.unwrap();
-        let mut tokenize_result = peroxide::lex::lex(&line)?;
+        let (mut tokenize_result, new_comment_depth) =
+            peroxide::lex::lex_resumable(&line, comment_depth)
+                .map_err(|e| render_lex_error(&line, &e))?;
+        comment_depth = new_comment_depth;
         current_expr_string.push(line);
         pending_expr.append(&mut tokenize_result);
 
+        let source_so_far = current_expr_string.join("\n");
         let SegmentationResult {
             mut segments,
             remainder,
             depth: new_depth,
-        } = peroxide::lex::segment(pending_expr)?;
+        } = peroxide::lex::segment(pending_expr, comment_depth)
+            .map_err(|e| render_lex_error(&source_so_far, &e))?;
         exprs.append(&mut segments);
 
-        if remainder.is_empty() {
+        if remainder.is_empty() && comment_depth == 0 {
             break;
         }
 
@@ -133,16 +156,39 @@ fn handle_one_expr(
         pending_expr = remainder;
     }
 
-    repl.add_to_history(&current_expr_string.join("\n"));
-    let _ = rep(vm_state, exprs, silent);
+    let source = current_expr_string.join("\n");
+    repl.add_to_history(&source);
+    let _ = rep(vm_state, exprs, &source, silent);
     Ok(true)
 }
 
-fn rep(vm_state: &Interpreter, toks: Vec<Vec<PositionedToken>>, silent: bool) -> Result<(), ()> {
+/// Renders a `LexError` as a rustc-style report against the REPL input it came from.
+fn render_lex_error(source: &str, err: &LexError) -> String {
+    let file = File::new("<repl>", source.to_string());
+    Diagnostics::new(&*file).render_lex_error(err)
+}
+
+fn rep(
+    vm_state: &Interpreter,
+    toks: Vec<Vec<PositionedToken>>,
+    source: &str,
+    silent: bool,
+) -> Result<(), ()> {
+    let file = File::new("<repl>", source.to_string());
+    let diagnostics = Diagnostics::new(&*file);
     for token_vector in toks {
-        let parse_value = Reader::new(&vm_state.arena, true, Rc::new("<repl>".to_string()))
-            .read_tokens(&token_vector)
-            .map_err(|e| println!("parse error: {:?}", e))?;
+        let (parse_value, errors) =
+            Reader::new(&vm_state.arena, true, Rc::clone(&file)).read_tokens_recovering(&token_vector);
+
+        // A malformed expression can contain more than one mistake (e.g. several bracket
+        // errors); report all of them, but - per `read_tokens_recovering`'s contract - never
+        // evaluate the partially-recovered datum they were found in.
+        if !errors.is_empty() {
+            for e in &errors {
+                println!("{}", diagnostics.render(e));
+            }
+            continue;
+        }
 
         match vm_state.parse_compile_run(parse_value.ptr) {
             Ok(v) => {
@@ -162,6 +208,7 @@ struct Options {
     pub stdlib_file: Option<String>,
     pub input_file: Option<String>,
     pub gc_mode: GcMode,
+    pub check: bool,
 }
 
 fn parse_args(args: &[&str]) -> Result<Options, String> {
@@ -192,6 +239,15 @@ fn parse_args(args: &[&str]) -> Result<Options, String> {
                 .default_value("normal"),
         )
         .arg(Arg::with_name("input-file").help("Sets the input file to use"))
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .requires("input-file")
+                .help(
+                    "Statically check the input file for unbound references and arity \
+                     mismatches, without running it",
+                ),
+        )
         .get_matches_from(args);
 
     let stdlib_file = if matches.is_present("no-std") {
@@ -208,5 +264,6 @@ fn parse_args(args: &[&str]) -> Result<Options, String> {
         stdlib_file,
         input_file: matches.value_of("input-file").map(|v| v.to_string()),
         gc_mode: GcMode::from_str(matches.value_of("gc-mode").unwrap()).unwrap(),
+        check: matches.is_present("check"),
     })
 }
@@ -0,0 +1,490 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Caches the compiled top-level bytecode of a Scheme source file, so a later run can skip
+//! `ast::parse` (and with it, all macro expansion) and go straight to running the result - see
+//! `Interpreter::compile_to_file` and `Interpreter::load_compiled`.
+//!
+//! There's no serde/bincode dependency in this crate, so the format is hand-rolled in the same
+//! style as `snapshot`: a small tag-based encoding for `vm::Instruction`, and a recursive encoding
+//! for `compile::CodeBlock` that reuses `snapshot`'s own codec for the plain-data values in a
+//! block's `constants`. A `CodeBlock`'s `environment` field only matters for debug/REPL
+//! introspection (see `Environment::get_name`'s graceful fallback for an unnamed slot), so it
+//! isn't serialized at all - a loaded block gets a fresh, empty one.
+//!
+//! Running cached bytecode skips the `ast::parse` step that would otherwise register each
+//! top-level `define` in `Interpreter::global_environment`, so the image also carries every
+//! global binding the source file introduced, in assignment order. `Interpreter::load_compiled`
+//! replays these into the environment before running anything, so that `(altitude, index)`
+//! allocation for any code compiled afterwards - e.g. a REPL line - lines up with what the cached
+//! bytecode expects, and so those names are resolvable by later code at all.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use arena::Arena;
+use compile::CodeBlock;
+use environment::{Environment, RcEnv};
+use heap::PoolPtr;
+use snapshot;
+use value::Value;
+use vm::Instruction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheError(&'static str);
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<snapshot::SnapshotError> for CacheError {
+    fn from(_: snapshot::SnapshotError) -> Self {
+        CacheError("failed to encode/decode a cached constant")
+    }
+}
+
+const MAGIC: &[u8; 4] = b"PRXC";
+/// Bumped whenever the on-disk layout below, or the set of primitives a fresh `Interpreter`
+/// starts with, changes in a way that would make an old cache unsafe to replay.
+const FORMAT_VERSION: u32 = 1;
+
+/// A validated, deserialized cache, ready for `Interpreter::load_compiled` to replay.
+pub struct Image {
+    pub source_hash: u64,
+    /// Length of the global frame a fresh `Interpreter` has before this source file is loaded -
+    /// checked against the live interpreter's global frame length before replaying `bindings`, so
+    /// a cache built against a different primitive set is rejected rather than silently
+    /// misallocating indices.
+    pub base_globals: usize,
+    /// Every global binding the source file introduced, in the order it was defined.
+    pub bindings: Vec<(String, bool)>,
+    /// One compiled `[toplevel]` code block per top-level form in the source file, to be run in
+    /// order.
+    pub blocks: Vec<PoolPtr>,
+}
+
+/// Cheap, non-cryptographic hash used only to notice that a source file has changed since its
+/// cache was built - FNV-1a, chosen because it needs no dependency and fits in a few lines.
+pub fn hash_source(source: &str) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    source
+        .bytes()
+        .fold(OFFSET, |h, b| (h ^ u64::from(b)).wrapping_mul(PRIME))
+}
+
+fn write_u32(n: u32, out: &mut Vec<u8>) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_u64(n: u64, out: &mut Vec<u8>) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_usize(n: usize, out: &mut Vec<u8>) {
+    write_u32(n as u32, out);
+}
+
+fn write_str(s: &str, out: &mut Vec<u8>) {
+    write_u32(s.len() as u32, out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_bytes(b: &[u8], out: &mut Vec<u8>) {
+    write_u32(b.len() as u32, out);
+    out.extend_from_slice(b);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], CacheError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .ok_or(CacheError("unexpected end of cache file"))?;
+        let slice = self
+            .data
+            .get(self.pos..end)
+            .ok_or(CacheError("unexpected end of cache file"))?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, CacheError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, CacheError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, CacheError> {
+        let b = self.bytes(8)?;
+        let mut arr = [0u8; 8];
+        arr.copy_from_slice(b);
+        Ok(u64::from_le_bytes(arr))
+    }
+
+    fn usize(&mut self) -> Result<usize, CacheError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn bool(&mut self) -> Result<bool, CacheError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn string(&mut self) -> Result<String, CacheError> {
+        let len = self.usize()?;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| CacheError("invalid utf-8 in cache file"))
+    }
+
+    fn owned_bytes(&mut self) -> Result<Vec<u8>, CacheError> {
+        let len = self.usize()?;
+        Ok(self.bytes(len)?.to_vec())
+    }
+}
+
+// Instruction tags. Kept in declaration order, but - unlike `Instruction` itself - these are a
+// stable on-disk format, so an entry must never be renumbered once shipped.
+const I_CONSTANT: u8 = 0;
+const I_JUMP_FALSE: u8 = 1;
+const I_JUMP: u8 = 2;
+const I_GLOBAL_ARGUMENT_SET: u8 = 3;
+const I_GLOBAL_ARGUMENT_GET: u8 = 4;
+const I_CHECKED_GLOBAL_ARGUMENT_GET: u8 = 5;
+const I_DEEP_ARGUMENT_SET: u8 = 6;
+const I_LOCAL_ARGUMENT_GET: u8 = 7;
+const I_CHECKED_LOCAL_ARGUMENT_GET: u8 = 8;
+const I_CHECK_ARITY: u8 = 9;
+const I_EXTEND_ENV: u8 = 10;
+const I_RETURN: u8 = 11;
+const I_CREATE_CLOSURE: u8 = 12;
+const I_PACK_FRAME: u8 = 13;
+const I_EXTEND_FRAME: u8 = 14;
+const I_PRESERVE_ENV: u8 = 15;
+const I_RESTORE_ENV: u8 = 16;
+const I_PUSH_VALUE: u8 = 17;
+const I_POP_FUNCTION: u8 = 18;
+const I_FUNCTION_INVOKE: u8 = 19;
+const I_CREATE_FRAME: u8 = 20;
+const I_NO_OP: u8 = 21;
+const I_FINISH: u8 = 22;
+
+fn encode_instruction(i: &Instruction, out: &mut Vec<u8>) {
+    match *i {
+        Instruction::Constant(idx) => {
+            out.push(I_CONSTANT);
+            write_usize(idx, out);
+        }
+        Instruction::JumpFalse(offset) => {
+            out.push(I_JUMP_FALSE);
+            write_usize(offset, out);
+        }
+        Instruction::Jump(offset) => {
+            out.push(I_JUMP);
+            write_usize(offset, out);
+        }
+        Instruction::GlobalArgumentSet { index } => {
+            out.push(I_GLOBAL_ARGUMENT_SET);
+            write_usize(index, out);
+        }
+        Instruction::GlobalArgumentGet { index } => {
+            out.push(I_GLOBAL_ARGUMENT_GET);
+            write_usize(index, out);
+        }
+        Instruction::CheckedGlobalArgumentGet { index } => {
+            out.push(I_CHECKED_GLOBAL_ARGUMENT_GET);
+            write_usize(index, out);
+        }
+        Instruction::DeepArgumentSet { depth, index } => {
+            out.push(I_DEEP_ARGUMENT_SET);
+            write_usize(depth, out);
+            write_usize(index, out);
+        }
+        Instruction::LocalArgumentGet { depth, index } => {
+            out.push(I_LOCAL_ARGUMENT_GET);
+            write_usize(depth, out);
+            write_usize(index, out);
+        }
+        Instruction::CheckedLocalArgumentGet { depth, index } => {
+            out.push(I_CHECKED_LOCAL_ARGUMENT_GET);
+            write_usize(depth, out);
+            write_usize(index, out);
+        }
+        Instruction::CheckArity { arity, dotted } => {
+            out.push(I_CHECK_ARITY);
+            write_usize(arity, out);
+            out.push(dotted as u8);
+        }
+        Instruction::ExtendEnv => out.push(I_EXTEND_ENV),
+        Instruction::Return => out.push(I_RETURN),
+        Instruction::CreateClosure(idx) => {
+            out.push(I_CREATE_CLOSURE);
+            write_usize(idx, out);
+        }
+        Instruction::PackFrame(arity) => {
+            out.push(I_PACK_FRAME);
+            write_usize(arity, out);
+        }
+        Instruction::ExtendFrame(n) => {
+            out.push(I_EXTEND_FRAME);
+            write_usize(n, out);
+        }
+        Instruction::PreserveEnv => out.push(I_PRESERVE_ENV),
+        Instruction::RestoreEnv => out.push(I_RESTORE_ENV),
+        Instruction::PushValue => out.push(I_PUSH_VALUE),
+        Instruction::PopFunction => out.push(I_POP_FUNCTION),
+        Instruction::FunctionInvoke { tail } => {
+            out.push(I_FUNCTION_INVOKE);
+            out.push(tail as u8);
+        }
+        Instruction::CreateFrame(n) => {
+            out.push(I_CREATE_FRAME);
+            write_usize(n, out);
+        }
+        Instruction::NoOp => out.push(I_NO_OP),
+        Instruction::Finish => out.push(I_FINISH),
+    }
+}
+
+fn decode_instruction(r: &mut Reader<'_>) -> Result<Instruction, CacheError> {
+    Ok(match r.u8()? {
+        I_CONSTANT => Instruction::Constant(r.usize()?),
+        I_JUMP_FALSE => Instruction::JumpFalse(r.usize()?),
+        I_JUMP => Instruction::Jump(r.usize()?),
+        I_GLOBAL_ARGUMENT_SET => Instruction::GlobalArgumentSet { index: r.usize()? },
+        I_GLOBAL_ARGUMENT_GET => Instruction::GlobalArgumentGet { index: r.usize()? },
+        I_CHECKED_GLOBAL_ARGUMENT_GET => {
+            Instruction::CheckedGlobalArgumentGet { index: r.usize()? }
+        }
+        I_DEEP_ARGUMENT_SET => {
+            let depth = r.usize()?;
+            let index = r.usize()?;
+            Instruction::DeepArgumentSet { depth, index }
+        }
+        I_LOCAL_ARGUMENT_GET => {
+            let depth = r.usize()?;
+            let index = r.usize()?;
+            Instruction::LocalArgumentGet { depth, index }
+        }
+        I_CHECKED_LOCAL_ARGUMENT_GET => {
+            let depth = r.usize()?;
+            let index = r.usize()?;
+            Instruction::CheckedLocalArgumentGet { depth, index }
+        }
+        I_CHECK_ARITY => {
+            let arity = r.usize()?;
+            let dotted = r.bool()?;
+            Instruction::CheckArity { arity, dotted }
+        }
+        I_EXTEND_ENV => Instruction::ExtendEnv,
+        I_RETURN => Instruction::Return,
+        I_CREATE_CLOSURE => Instruction::CreateClosure(r.usize()?),
+        I_PACK_FRAME => Instruction::PackFrame(r.usize()?),
+        I_EXTEND_FRAME => Instruction::ExtendFrame(r.usize()?),
+        I_PRESERVE_ENV => Instruction::PreserveEnv,
+        I_RESTORE_ENV => Instruction::RestoreEnv,
+        I_PUSH_VALUE => Instruction::PushValue,
+        I_POP_FUNCTION => Instruction::PopFunction,
+        I_FUNCTION_INVOKE => Instruction::FunctionInvoke { tail: r.bool()? },
+        I_CREATE_FRAME => Instruction::CreateFrame(r.usize()?),
+        I_NO_OP => Instruction::NoOp,
+        I_FINISH => Instruction::Finish,
+        _ => return Err(CacheError("unknown instruction tag in cache file")),
+    })
+}
+
+fn encode_code_block(
+    arena: &Arena,
+    block: &CodeBlock,
+    out: &mut Vec<u8>,
+) -> Result<(), CacheError> {
+    match &block.name {
+        Some(name) => {
+            out.push(1);
+            write_str(name, out);
+        }
+        None => out.push(0),
+    }
+    write_usize(block.arity, out);
+    out.push(block.dotted as u8);
+
+    write_usize(block.instructions.len(), out);
+    for i in &block.instructions {
+        encode_instruction(i, out);
+    }
+
+    write_usize(block.constants.len(), out);
+    for &c in &block.constants {
+        let bytes = snapshot::serialize_reachable(&arena.root(c))?;
+        write_bytes(&bytes, out);
+    }
+
+    write_usize(block.code_blocks.len(), out);
+    for &c in &block.code_blocks {
+        encode_code_block(arena, c.long_lived().get_code_block(), out)?;
+    }
+    Ok(())
+}
+
+fn decode_code_block(arena: &Arena, r: &mut Reader<'_>) -> Result<PoolPtr, CacheError> {
+    // Only consulted for debug/REPL introspection (`Environment::get_name`,
+    // `environment::dump_frames`), which degrades gracefully to "unnamed variable" for an
+    // environment with no names recorded - not worth reconstructing here for nested blocks. See
+    // `decode_code_block_with_env` for the one case (a block decoded standalone, outside an
+    // `Image`) where the caller does have a real environment to attach.
+    decode_code_block_with_env(arena, r, Rc::new(RefCell::new(Environment::new(None))))
+}
+
+fn decode_code_block_with_env(
+    arena: &Arena,
+    r: &mut Reader<'_>,
+    environment: RcEnv,
+) -> Result<PoolPtr, CacheError> {
+    let name = if r.bool()? { Some(r.string()?) } else { None };
+    let arity = r.usize()?;
+    let dotted = r.bool()?;
+
+    let instruction_count = r.usize()?;
+    let mut instructions = Vec::with_capacity(instruction_count);
+    for _ in 0..instruction_count {
+        instructions.push(decode_instruction(r)?);
+    }
+
+    let constant_count = r.usize()?;
+    let mut constants = Vec::with_capacity(constant_count);
+    for _ in 0..constant_count {
+        let bytes = r.owned_bytes()?;
+        constants.push(snapshot::deserialize(arena, &bytes)?.pp());
+    }
+
+    let nested_count = r.usize()?;
+    let mut code_blocks = Vec::with_capacity(nested_count);
+    for _ in 0..nested_count {
+        code_blocks.push(decode_code_block(arena, r)?);
+    }
+
+    let mut code_block = CodeBlock::new(name, arity, dotted, environment);
+    code_block.instructions = instructions;
+    code_block.constants = constants;
+    code_block.code_blocks = code_blocks;
+    Ok(arena.insert(Value::CodeBlock(Box::new(code_block))))
+}
+
+const BLOCK_MAGIC: &[u8; 4] = b"PRXB";
+
+/// Encodes a single compiled `CodeBlock` (and everything it transitively references) to bytes -
+/// the backing implementation of [`crate::compile::CodeBlock::serialize`]. Distinct from
+/// [`encode_image`]'s container, which wraps a whole compiled source file: a source hash and the
+/// list of global bindings it introduced, alongside one or more of these blocks.
+pub fn encode_block(arena: &Arena, block: &CodeBlock) -> Result<Vec<u8>, CacheError> {
+    let mut out = Vec::new();
+    out.extend_from_slice(BLOCK_MAGIC);
+    write_u32(FORMAT_VERSION, &mut out);
+    encode_code_block(arena, block, &mut out)?;
+    Ok(out)
+}
+
+/// Decodes a single `CodeBlock` written by [`encode_block`] - the backing implementation of
+/// [`crate::compile::CodeBlock::load`]. `environment` becomes the decoded block's lexical
+/// environment, in place of serializing (and blindly trusting) whatever the writer's looked like;
+/// this is what lets the caller reattach it to its own live global environment.
+pub fn decode_block(arena: &Arena, bytes: &[u8], environment: RcEnv) -> Result<PoolPtr, CacheError> {
+    let mut r = Reader::new(bytes);
+    if r.bytes(4)? != BLOCK_MAGIC {
+        return Err(CacheError("not a peroxide code block"));
+    }
+    if r.u32()? != FORMAT_VERSION {
+        return Err(CacheError("code block was built by an incompatible version"));
+    }
+    decode_code_block_with_env(arena, &mut r, environment)
+}
+
+/// Encodes a whole cache image: the header (magic, format version, source hash, base global frame
+/// length), the global bindings the source introduced, and the compiled top-level code blocks.
+pub fn encode_image(
+    source: &str,
+    base_globals: usize,
+    bindings: &[(String, bool)],
+    blocks: &[PoolPtr],
+    arena: &Arena,
+) -> Result<Vec<u8>, CacheError> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    write_u32(FORMAT_VERSION, &mut out);
+    write_u64(hash_source(source), &mut out);
+    write_usize(base_globals, &mut out);
+
+    write_usize(bindings.len(), &mut out);
+    for (name, initialized) in bindings {
+        write_str(name, &mut out);
+        out.push(*initialized as u8);
+    }
+
+    write_usize(blocks.len(), &mut out);
+    for &block in blocks {
+        encode_code_block(arena, block.long_lived().get_code_block(), &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Decodes a cache image written by [`encode_image`]. Does not by itself validate
+/// `source_hash`/`base_globals` against the live interpreter - see `Interpreter::load_compiled`.
+pub fn decode_image(arena: &Arena, bytes: &[u8]) -> Result<Image, CacheError> {
+    let mut r = Reader::new(bytes);
+    if r.bytes(4)? != MAGIC {
+        return Err(CacheError("not a peroxide bytecode cache"));
+    }
+    if r.u32()? != FORMAT_VERSION {
+        return Err(CacheError("cache was built by an incompatible version"));
+    }
+    let source_hash = r.u64()?;
+    let base_globals = r.usize()?;
+
+    let binding_count = r.usize()?;
+    let mut bindings = Vec::with_capacity(binding_count);
+    for _ in 0..binding_count {
+        let name = r.string()?;
+        let initialized = r.bool()?;
+        bindings.push((name, initialized));
+    }
+
+    let block_count = r.usize()?;
+    let mut blocks = Vec::with_capacity(block_count);
+    for _ in 0..block_count {
+        blocks.push(decode_code_block(arena, &mut r)?);
+    }
+
+    Ok(Image {
+        source_hash,
+        base_globals,
+        bindings,
+        blocks,
+    })
+}
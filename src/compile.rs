@@ -16,6 +16,7 @@ use std::cell::RefCell;
 
 use arena::Arena;
 use ast::{Lambda, LocatedSyntaxElement, SyntaxElement};
+use bytecode_cache;
 use environment::RcEnv;
 use heap::{Inventory, PoolPtr, PtrVec};
 use value::Value;
@@ -31,6 +32,24 @@ pub struct CodeBlock {
     pub constants: Vec<PoolPtr>,
     pub code_blocks: Vec<PoolPtr>,
     pub environment: RcEnv,
+    /// One entry per `new_label()` call; `None` until the label is `bind_label`'d, at which point
+    /// it holds the instruction address the label was bound to.
+    labels: Vec<Option<usize>>,
+    /// Pending `push_jump`/`push_jump_false` calls not yet resolved by `finalize_labels`: the
+    /// index of the placeholder instruction, the label it targets, and which kind of jump to emit.
+    fixups: Vec<(usize, Label, JumpKind)>,
+}
+
+/// An opaque forward-jump target created by [`CodeBlock::new_label`]. Stays unresolved until
+/// [`CodeBlock::bind_label`] records where it points; [`CodeBlock::finalize_labels`] then turns
+/// every `push_jump`/`push_jump_false` that referenced it into a real relative-offset jump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JumpKind {
+    Jump,
+    JumpFalse,
 }
 
 impl Inventory for CodeBlock {
@@ -54,6 +73,8 @@ impl CodeBlock {
             constants: vec![],
             code_blocks: vec![],
             environment,
+            labels: vec![],
+            fixups: vec![],
         }
     }
 
@@ -73,6 +94,69 @@ impl CodeBlock {
         self.constants.push(c);
         self.constants.len() - 1
     }
+
+    /// Creates a new, as-yet-unbound jump target. Emit provisional jumps to it with `push_jump`/
+    /// `push_jump_false`, then fix its address with `bind_label` once the code it should point to
+    /// has been compiled.
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Binds `label` to the current end of the instruction stream - i.e. the address the next
+    /// instruction pushed will have.
+    pub fn bind_label(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code_size());
+    }
+
+    /// Emits a provisional unconditional jump to `label`, to be resolved by `finalize_labels`.
+    pub fn push_jump(&mut self, label: Label) {
+        self.fixups.push((self.code_size(), label, JumpKind::Jump));
+        self.push(Instruction::NoOp);
+    }
+
+    /// Emits a provisional conditional jump to `label`, to be resolved by `finalize_labels`.
+    pub fn push_jump_false(&mut self, label: Label) {
+        self.fixups
+            .push((self.code_size(), label, JumpKind::JumpFalse));
+        self.push(Instruction::NoOp);
+    }
+
+    /// Resolves every `push_jump`/`push_jump_false` placeholder into a real relative-offset
+    /// `Jump`/`JumpFalse` instruction, now that every label referenced should have been bound.
+    /// Called once per block, right before it's inserted into the arena. A label that was never
+    /// bound is a bug in the compiler itself, not something user code can trigger, so this panics
+    /// rather than threading a `Result` through every caller of `compile`/`compile_lambda`.
+    pub(crate) fn finalize_labels(&mut self) {
+        let fixups = std::mem::replace(&mut self.fixups, vec![]);
+        for (instr_idx, label, kind) in fixups {
+            let target = self.labels[label.0]
+                .unwrap_or_else(|| panic!("compiler bug: {:?} was never bound", label));
+            let offset = target - (instr_idx + 1);
+            self.instructions[instr_idx] = match kind {
+                JumpKind::Jump => Instruction::Jump(offset),
+                JumpKind::JumpFalse => Instruction::JumpFalse(offset),
+            };
+        }
+    }
+
+    /// Encodes this block - its instructions, and every constant and nested code block it
+    /// transitively references - to a versioned binary blob that [`CodeBlock::load`] can turn
+    /// back into a runnable `PoolPtr` in a later process, without re-parsing or re-compiling.
+    /// Built on the same instruction/constant codec `bytecode_cache` uses to cache a whole
+    /// compiled source file; this just serializes a single block on its own.
+    pub fn serialize(&self, arena: &Arena) -> Result<Vec<u8>, String> {
+        bytecode_cache::encode_block(arena, self).map_err(|e| e.to_string())
+    }
+
+    /// Decodes a block written by [`CodeBlock::serialize`]. `environment` is attached as the
+    /// loaded block's lexical environment in place of whatever the original had - the original is
+    /// never serialized, since it only makes sense relative to the interpreter that compiled it.
+    /// Pass the caller's live global environment here to make the loaded block's globals resolve
+    /// against it.
+    pub fn load(arena: &Arena, bytes: &[u8], environment: RcEnv) -> Result<PoolPtr, String> {
+        bytecode_cache::decode_block(arena, bytes, environment).map_err(|e| e.to_string())
+    }
 }
 
 pub fn compile_toplevel(arena: &Arena, tree: &SyntaxElement, environment: RcEnv) -> PoolPtr {
@@ -88,6 +172,7 @@ pub fn compile_toplevel(arena: &Arena, tree: &SyntaxElement, environment: RcEnv)
 
     compile(arena, tree, &mut code_block, false, rooted_vec.pp());
     code_block.push(Instruction::Finish);
+    code_block.finalize_labels();
     arena.insert(Value::CodeBlock(Box::new(code_block)))
 }
 
@@ -99,18 +184,18 @@ pub fn compile(arena: &Arena, tree: &SyntaxElement, code: &mut CodeBlock, tail:
         }
         SyntaxElement::If(i) => {
             compile(arena, &i.cond.element, code, false, rv);
-            let cond_jump = code.code_size();
-            code.push(Instruction::NoOp); // Is rewritten as a conditional jump below
+            let false_label = code.new_label();
+            code.push_jump_false(false_label);
             compile(arena, &i.t.element, code, tail, rv);
-            let mut true_end = code.code_size();
             if let Some(ref f) = i.f {
-                code.push(Instruction::NoOp);
-                true_end += 1;
+                let end_label = code.new_label();
+                code.push_jump(end_label);
+                code.bind_label(false_label);
                 compile(arena, &f.element, code, tail, rv);
-                let jump_offset = code.code_size() - true_end;
-                code.replace(true_end - 1, Instruction::Jump(jump_offset));
+                code.bind_label(end_label);
+            } else {
+                code.bind_label(false_label);
             }
-            code.replace(cond_jump, Instruction::JumpFalse(true_end - cond_jump - 1));
         }
         SyntaxElement::Begin(b) => {
             compile_sequence(arena, &b.expressions, code, tail, rv);
@@ -187,6 +272,7 @@ fn compile_lambda(arena: &Arena, l: &Lambda, rv: PoolPtr) -> PoolPtr {
     compile_sequence(arena, &l.expressions, &mut code, true, rooted_vec.pp());
 
     code.push(Instruction::Return);
+    code.finalize_labels();
 
     let code_block_ptr = arena.insert(Value::CodeBlock(Box::new(code)));
     rv.try_get_vector()
@@ -0,0 +1,184 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Renders reader errors ([`read::NoReadResult`]) as rustc/miette-style reports: the file
+//! name and line/column of the error, followed by the offending source line(s) with a
+//! caret/underline spanning the `CodeRange`.
+
+use std::fmt::Write;
+
+use lex::{CodeRange, LexError};
+use read::NoReadResult;
+use value::Locator;
+use File;
+
+/// Renders [`NoReadResult`]s and [`LexError`]s against a single source file.
+pub struct Diagnostics<'a> {
+    file: &'a File,
+}
+
+impl<'a> Diagnostics<'a> {
+    pub fn new(file: &'a File) -> Self {
+        Diagnostics { file }
+    }
+
+    /// Renders `err` as a multi-line report. For a [`NoReadResult::ReadError`], this includes
+    /// the source line(s) spanned by the error's `CodeRange`, underlined with carets. For a
+    /// [`NoReadResult::DuplicateLabel`], both the original and conflicting spans are rendered,
+    /// each with their own caret run and a label explaining which is which.
+    pub fn render(&self, err: &NoReadResult) -> String {
+        match err {
+            NoReadResult::Nothing => "error: no expression to read".to_string(),
+            NoReadResult::ReadError { msg, locator } => self.render_located(msg, locator),
+            NoReadResult::DuplicateLabel {
+                label,
+                first,
+                second,
+            } => self.render_labeled(
+                &format!("duplicate datum label: #{}=", label),
+                &[
+                    (first.range, "first defined here"),
+                    (second.range, "redefined here"),
+                ],
+            ),
+        }
+    }
+
+    /// Renders a raw lexer error the same way: source file/position header followed by the
+    /// offending line(s), underlined with carets at `err.location`.
+    pub fn render_lex_error(&self, err: &LexError) -> String {
+        self.render_range(&err.msg, err.location)
+    }
+
+    fn render_located(&self, msg: &str, locator: &Locator) -> String {
+        self.render_range(msg, locator.range)
+    }
+
+    fn render_range(&self, msg: &str, range: CodeRange) -> String {
+        let mut out = String::new();
+        writeln!(out, "error: {}", msg).unwrap();
+        self.render_span(&mut out, range, None);
+        out
+    }
+
+    /// Renders `msg` as the report header, followed by one `-->`/gutter/caret block per label,
+    /// in order - for errors that point at more than one place at once (e.g. a conflicting prior
+    /// definition), where a single [`render_range`](Self::render_range) block isn't enough.
+    fn render_labeled(&self, msg: &str, labels: &[(CodeRange, &str)]) -> String {
+        let mut out = String::new();
+        writeln!(out, "error: {}", msg).unwrap();
+        for (range, label) in labels {
+            self.render_span(&mut out, *range, Some(label));
+        }
+        out
+    }
+
+    /// Appends a `-->` file/position line, the source line(s) spanned by `range`, and a caret
+    /// run underneath, to `out`. If `label` is given, it's appended after the carets on the last
+    /// underlined line.
+    fn render_span(&self, out: &mut String, range: CodeRange, label: Option<&str>) {
+        let (start_line, start_col) = range.start.linecol_in(&self.file.source);
+        let (end_line, end_col) = range.end.linecol_in(&self.file.source);
+
+        writeln!(
+            out,
+            "  --> {}:{}",
+            self.file.name,
+            range.display_in(&self.file.source)
+        )
+        .unwrap();
+
+        for line_no in start_line..=end_line {
+            let text = match self.file.line(line_no) {
+                Some(t) => t,
+                None => continue,
+            };
+            writeln!(out, "{:>4} | {}", line_no, text).unwrap();
+
+            let underline_start = if line_no == start_line { start_col } else { 1 };
+            let underline_end = if line_no == end_line {
+                end_col.max(underline_start)
+            } else {
+                text.chars().count() as u32
+            };
+            let padding = " ".repeat((underline_start - 1) as usize);
+            let carets = "^".repeat((underline_end - underline_start + 1) as usize);
+            let suffix = if line_no == end_line {
+                label.map(|l| format!(" {}", l)).unwrap_or_default()
+            } else {
+                String::new()
+            };
+            writeln!(out, "     | {}{}{}", padding, carets, suffix).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read::Reader;
+    use std::rc::Rc;
+
+    #[test]
+    fn renders_unterminated_list() {
+        let arena = ::arena::Arena::default();
+        let file = File::new("test.scm", "(foo (bar\n");
+        let reader = Reader::new(&arena, false, Rc::clone(&file));
+        let err = reader.read_many(&file.source).unwrap_err();
+
+        let diagnostics = Diagnostics::new(&file);
+        let rendered = diagnostics.render(&err);
+        assert!(rendered.starts_with("error: "));
+        assert!(rendered.contains("(foo (bar"));
+        assert!(rendered.contains('^'));
+    }
+
+    #[test]
+    fn renders_duplicate_label() {
+        let arena = ::arena::Arena::default();
+        let file = File::new("test.scm", "(#0=1 #0=2)");
+        let reader = Reader::new(&arena, false, Rc::clone(&file));
+        let err = reader.read_many(&file.source).unwrap_err();
+
+        let diagnostics = Diagnostics::new(&file);
+        let rendered = diagnostics.render(&err);
+        assert!(rendered.starts_with("error: duplicate datum label: #0="));
+        assert!(rendered.contains("first defined here"));
+        assert!(rendered.contains("redefined here"));
+        // Two caret runs, one under each three-character `#0=`.
+        assert_eq!(rendered.matches('^').count(), 6);
+    }
+
+    #[test]
+    fn renders_nothing_to_read() {
+        let file = File::new("test.scm", "");
+        let diagnostics = Diagnostics::new(&file);
+        assert_eq!(
+            diagnostics.render(&NoReadResult::Nothing),
+            "error: no expression to read"
+        );
+    }
+
+    #[test]
+    fn renders_lex_error() {
+        let file = File::new("test.scm", "\"abc");
+        let err = ::lex::lex(&file.source).unwrap_err();
+
+        let diagnostics = Diagnostics::new(&file);
+        let rendered = diagnostics.render_lex_error(&err);
+        assert!(rendered.starts_with("error: "));
+        assert!(rendered.contains("\"abc"));
+        assert!(rendered.contains('^'));
+    }
+}
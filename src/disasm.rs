@@ -0,0 +1,438 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Human-readable disassembly and reverse assembly for a single `CodeBlock`, exposed to Scheme as
+//! `(disassemble proc)`/`(assemble text)`. This is a different axis from `bytecode_cache`'s
+//! `CodeBlock::serialize`/`load`: that one round-trips a block through a compact binary blob for
+//! machines, this one round-trips it through text meant for a person to read or hand-edit.
+//!
+//! Jump targets are rendered as symbolic labels (`L0:`, `L1:`, ...) instead of raw relative
+//! offsets, built on the same `CodeBlock::new_label`/`push_jump`/`push_jump_false`/`bind_label`
+//! fixup machinery `compile`'s `If` arm uses. A `create-closure` instruction is immediately
+//! followed by the disassembly of the nested block it creates rather than a bare numeric index,
+//! since that nested block's position in `code_blocks` is always "the next block compiled", and
+//! the assembler reconstructs that same index by just appending blocks in the order it parses
+//! them.
+//!
+//! Global/local/deep variable references are annotated with the name they resolve to - via the
+//! same `Environment::get_name` lookup `vm::resolve_variable` uses for error backtraces - as a
+//! trailing `; name` comment, since the raw `(depth, index)` pair alone isn't enough to tell what
+//! a reference was to. The comment is purely informational: `assemble` strips it back off before
+//! parsing the numeric fields, so it plays no part in the round trip.
+
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::iter::Peekable;
+
+use arena::Arena;
+use compile::{CodeBlock, Label};
+use environment::{Environment, RcEnv};
+use heap::PoolPtr;
+use read::Reader;
+use value::Value;
+use vm::Instruction;
+use File;
+
+/// Distance from `env` to the global environment, counting `Environment::parent` hops - the same
+/// quantity `vm::resolve_variable` gets at runtime via `ActivationFrame::depth`, just computed
+/// over the compile-time environment tree a `CodeBlock` carries instead of a live frame chain.
+fn environment_altitude(env: &RcEnv) -> usize {
+    match env.borrow().parent() {
+        Some(p) => environment_altitude(p) + 1,
+        None => 0,
+    }
+}
+
+/// Resolves a variable reference compiled into `code` back to a name, the same way `error_stack`
+/// and `CheckedLocalArgumentGet` do at runtime (see `vm::resolve_variable`) - `altitude` is
+/// either `0` for a global reference, or `environment_altitude(&code.environment) - depth` for a
+/// local/deep one.
+fn resolve_name(code: &CodeBlock, altitude: usize, index: usize) -> String {
+    code.environment.borrow().get_name(altitude, index)
+}
+
+pub fn disassemble(code: &CodeBlock) -> String {
+    let mut out = String::new();
+    write_block(code, 0, &mut out);
+    out
+}
+
+fn write_block(code: &CodeBlock, depth: usize, out: &mut String) {
+    let pad = "  ".repeat(depth);
+    let body_pad = "  ".repeat(depth + 1);
+
+    let name = match &code.name {
+        Some(n) => format!("{:?}", n),
+        None => "#f".to_string(),
+    };
+    writeln!(
+        out,
+        "{}.code name={} arity={} dotted={}",
+        pad, name, code.arity, code.dotted
+    )
+    .unwrap();
+
+    // Every instruction address targeted by some Jump/JumpFalse gets a label, numbered in
+    // address order so the same block always disassembles to the same text.
+    let mut label_addrs: Vec<usize> = Vec::new();
+    for (i, instr) in code.instructions.iter().enumerate() {
+        let target = match instr {
+            Instruction::Jump(offset) | Instruction::JumpFalse(offset) => Some(i + 1 + offset),
+            _ => None,
+        };
+        if let Some(t) = target {
+            if !label_addrs.contains(&t) {
+                label_addrs.push(t);
+            }
+        }
+    }
+    label_addrs.sort_unstable();
+    let label_of = |addr: usize| label_addrs.iter().position(|&a| a == addr).unwrap();
+
+    for (i, instr) in code.instructions.iter().enumerate() {
+        if label_addrs.contains(&i) {
+            writeln!(out, "{}L{}:", body_pad, label_of(i)).unwrap();
+        }
+        match instr {
+            Instruction::Constant(idx) => {
+                writeln!(out, "{}constant {}", body_pad, code.constants[*idx].pretty_print()).unwrap();
+            }
+            Instruction::JumpFalse(offset) => {
+                writeln!(out, "{}jump-false L{}", body_pad, label_of(i + 1 + offset)).unwrap();
+            }
+            Instruction::Jump(offset) => {
+                writeln!(out, "{}jump L{}", body_pad, label_of(i + 1 + offset)).unwrap();
+            }
+            Instruction::GlobalArgumentSet { index } => {
+                let name = resolve_name(code, 0, *index);
+                writeln!(out, "{}global-set {} ; {}", body_pad, index, name).unwrap();
+            }
+            Instruction::GlobalArgumentGet { index } => {
+                let name = resolve_name(code, 0, *index);
+                writeln!(out, "{}global-get {} ; {}", body_pad, index, name).unwrap();
+            }
+            Instruction::CheckedGlobalArgumentGet { index } => {
+                let name = resolve_name(code, 0, *index);
+                writeln!(out, "{}checked-global-get {} ; {}", body_pad, index, name).unwrap();
+            }
+            Instruction::DeepArgumentSet { depth: d, index } => {
+                let altitude = environment_altitude(&code.environment).saturating_sub(*d);
+                let name = resolve_name(code, altitude, *index);
+                writeln!(out, "{}deep-set {} {} ; {}", body_pad, d, index, name).unwrap();
+            }
+            Instruction::LocalArgumentGet { depth: d, index } => {
+                let altitude = environment_altitude(&code.environment).saturating_sub(*d);
+                let name = resolve_name(code, altitude, *index);
+                writeln!(out, "{}local-get {} {} ; {}", body_pad, d, index, name).unwrap();
+            }
+            Instruction::CheckedLocalArgumentGet { depth: d, index } => {
+                let altitude = environment_altitude(&code.environment).saturating_sub(*d);
+                let name = resolve_name(code, altitude, *index);
+                writeln!(out, "{}checked-local-get {} {} ; {}", body_pad, d, index, name).unwrap();
+            }
+            Instruction::CheckArity { arity, dotted } => {
+                writeln!(out, "{}check-arity {} {}", body_pad, arity, dotted).unwrap();
+            }
+            Instruction::ExtendEnv => {
+                writeln!(out, "{}extend-env", body_pad).unwrap();
+            }
+            Instruction::Return => {
+                writeln!(out, "{}return", body_pad).unwrap();
+            }
+            Instruction::CreateClosure(idx) => {
+                writeln!(out, "{}create-closure", body_pad).unwrap();
+                write_block(
+                    code.code_blocks[*idx]
+                        .try_get_code_block()
+                        .expect("code_blocks entries are always code blocks"),
+                    depth + 1,
+                    out,
+                );
+            }
+            Instruction::PackFrame(arity) => {
+                writeln!(out, "{}pack-frame {}", body_pad, arity).unwrap();
+            }
+            Instruction::ExtendFrame(count) => {
+                writeln!(out, "{}extend-frame {}", body_pad, count).unwrap();
+            }
+            Instruction::PreserveEnv => {
+                writeln!(out, "{}preserve-env", body_pad).unwrap();
+            }
+            Instruction::RestoreEnv => {
+                writeln!(out, "{}restore-env", body_pad).unwrap();
+            }
+            Instruction::PushValue => {
+                writeln!(out, "{}push-value", body_pad).unwrap();
+            }
+            Instruction::PopFunction => {
+                writeln!(out, "{}pop-function", body_pad).unwrap();
+            }
+            Instruction::FunctionInvoke { tail } => {
+                writeln!(out, "{}function-invoke {}", body_pad, tail).unwrap();
+            }
+            Instruction::CreateFrame(count) => {
+                writeln!(out, "{}create-frame {}", body_pad, count).unwrap();
+            }
+            Instruction::NoOp => {
+                writeln!(out, "{}no-op", body_pad).unwrap();
+            }
+            Instruction::Finish => {
+                writeln!(out, "{}finish", body_pad).unwrap();
+            }
+        }
+    }
+    if label_addrs.contains(&code.instructions.len()) {
+        writeln!(out, "{}L{}:", body_pad, label_of(code.instructions.len())).unwrap();
+    }
+    writeln!(out, "{}.end", pad).unwrap();
+}
+
+/// Parses the text produced by [`disassemble`] (or hand-written in the same form) back into a
+/// runnable `CodeBlock`, inserted fresh into `arena`.
+pub fn assemble(arena: &Arena, text: &str) -> Result<PoolPtr, String> {
+    let mut lines = text.lines().peekable();
+    let block = parse_block(arena, &mut lines)?;
+    while let Some(line) = lines.next() {
+        if !line.trim().is_empty() {
+            return Err("assemble: trailing content after final `.end`".to_string());
+        }
+    }
+    Ok(block)
+}
+
+fn parse_block<'a, I: Iterator<Item = &'a str>>(
+    arena: &Arena,
+    lines: &mut Peekable<I>,
+) -> Result<PoolPtr, String> {
+    let header = next_nonblank(lines)
+        .ok_or_else(|| "assemble: expected a `.code` header".to_string())?;
+    let (name, arity, dotted) = parse_header(header)?;
+
+    let environment = Environment::new(None);
+    let mut code = CodeBlock::new(
+        name,
+        arity,
+        dotted,
+        std::rc::Rc::new(std::cell::RefCell::new(environment)),
+    );
+    let mut labels: HashMap<String, Label> = HashMap::new();
+
+    loop {
+        let line = next_nonblank(lines)
+            .ok_or_else(|| "assemble: unterminated `.code` block".to_string())?;
+        if line == ".end" {
+            break;
+        }
+
+        if line.ends_with(':') {
+            let label = label_for(&mut code, &mut labels, &line[..line.len() - 1]);
+            code.bind_label(label);
+            continue;
+        }
+
+        let mut parts = line.splitn(2, ' ');
+        let mnemonic = parts.next().unwrap();
+        let rest = parts.next().unwrap_or("").trim();
+        match mnemonic {
+            "constant" => {
+                let value = read_datum(arena, rest)?;
+                let idx = code.push_constant(value);
+                code.push(Instruction::Constant(idx));
+            }
+            "jump-false" => {
+                let label = label_for(&mut code, &mut labels, rest);
+                code.push_jump_false(label);
+            }
+            "jump" => {
+                let label = label_for(&mut code, &mut labels, rest);
+                code.push_jump(label);
+            }
+            "global-set" => code.push(Instruction::GlobalArgumentSet {
+                index: parse_usize(strip_comment(rest))?,
+            }),
+            "global-get" => code.push(Instruction::GlobalArgumentGet {
+                index: parse_usize(strip_comment(rest))?,
+            }),
+            "checked-global-get" => code.push(Instruction::CheckedGlobalArgumentGet {
+                index: parse_usize(strip_comment(rest))?,
+            }),
+            "deep-set" => {
+                let (depth, index) = parse_two_usize(strip_comment(rest))?;
+                code.push(Instruction::DeepArgumentSet { depth, index });
+            }
+            "local-get" => {
+                let (depth, index) = parse_two_usize(strip_comment(rest))?;
+                code.push(Instruction::LocalArgumentGet { depth, index });
+            }
+            "checked-local-get" => {
+                let (depth, index) = parse_two_usize(strip_comment(rest))?;
+                code.push(Instruction::CheckedLocalArgumentGet { depth, index });
+            }
+            "check-arity" => {
+                let mut p = rest.splitn(2, ' ');
+                let arity = parse_usize(p.next().unwrap_or(""))?;
+                let dotted = parse_bool(p.next().unwrap_or("").trim())?;
+                code.push(Instruction::CheckArity { arity, dotted });
+            }
+            "extend-env" => code.push(Instruction::ExtendEnv),
+            "return" => code.push(Instruction::Return),
+            "create-closure" => {
+                let nested = parse_block(arena, lines)?;
+                let idx = code.code_blocks.len();
+                code.code_blocks.push(nested);
+                code.push(Instruction::CreateClosure(idx));
+            }
+            "pack-frame" => code.push(Instruction::PackFrame(parse_usize(rest)?)),
+            "extend-frame" => code.push(Instruction::ExtendFrame(parse_usize(rest)?)),
+            "preserve-env" => code.push(Instruction::PreserveEnv),
+            "restore-env" => code.push(Instruction::RestoreEnv),
+            "push-value" => code.push(Instruction::PushValue),
+            "pop-function" => code.push(Instruction::PopFunction),
+            "function-invoke" => code.push(Instruction::FunctionInvoke {
+                tail: parse_bool(rest)?,
+            }),
+            "create-frame" => code.push(Instruction::CreateFrame(parse_usize(rest)?)),
+            "no-op" => code.push(Instruction::NoOp),
+            "finish" => code.push(Instruction::Finish),
+            other => return Err(format!("assemble: unknown instruction `{}`", other)),
+        }
+    }
+
+    code.finalize_labels();
+    Ok(arena.insert(Value::CodeBlock(Box::new(code))))
+}
+
+fn next_nonblank<'a, I: Iterator<Item = &'a str>>(lines: &mut Peekable<I>) -> Option<&'a str> {
+    loop {
+        let line = lines.next()?.trim();
+        if !line.is_empty() {
+            return Some(line);
+        }
+    }
+}
+
+fn label_for(code: &mut CodeBlock, labels: &mut HashMap<String, Label>, name: &str) -> Label {
+    if let Some(l) = labels.get(name) {
+        return *l;
+    }
+    let l = code.new_label();
+    labels.insert(name.to_string(), l);
+    l
+}
+
+fn parse_header(header: &str) -> Result<(Option<String>, usize, bool), String> {
+    let rest = header
+        .strip_prefix_compat(".code ")
+        .ok_or_else(|| format!("assemble: expected `.code ...`, got `{}`", header))?;
+
+    let mut name = None;
+    let mut arity = None;
+    let mut dotted = None;
+    for field in rest.split_whitespace() {
+        let mut kv = field.splitn(2, '=');
+        let key = kv.next().unwrap_or("");
+        let value = kv
+            .next()
+            .ok_or_else(|| format!("assemble: malformed `.code` field `{}`", field))?;
+        match key {
+            "name" => {
+                name = if value == "#f" {
+                    None
+                } else {
+                    Some(
+                        value
+                            .strip_prefix_compat("\"")
+                            .and_then(|v| v.strip_suffix_compat("\""))
+                            .ok_or_else(|| format!("assemble: malformed name `{}`", value))?
+                            .to_string(),
+                    )
+                };
+            }
+            "arity" => arity = Some(parse_usize(value)?),
+            "dotted" => dotted = Some(parse_bool(value)?),
+            other => return Err(format!("assemble: unknown `.code` field `{}`", other)),
+        }
+    }
+    Ok((
+        name,
+        arity.ok_or_else(|| "assemble: `.code` header is missing `arity`".to_string())?,
+        dotted.ok_or_else(|| "assemble: `.code` header is missing `dotted`".to_string())?,
+    ))
+}
+
+fn parse_usize(s: &str) -> Result<usize, String> {
+    s.parse::<usize>()
+        .map_err(|_| format!("assemble: expected a number, got `{}`", s))
+}
+
+fn parse_two_usize(s: &str) -> Result<(usize, usize), String> {
+    let mut parts = s.splitn(2, ' ');
+    let a = parse_usize(parts.next().unwrap_or(""))?;
+    let b = parse_usize(parts.next().unwrap_or("").trim())?;
+    Ok((a, b))
+}
+
+/// Drops a trailing `` ; name `` comment - written by `write_block` to annotate variable
+/// references with the name `resolve_name` found for them - before the remaining numeric fields
+/// are parsed. Only instructions that can carry one of these comments call this.
+fn strip_comment(s: &str) -> &str {
+    s.splitn(2, " ;").next().unwrap_or(s).trim()
+}
+
+fn parse_bool(s: &str) -> Result<bool, String> {
+    match s {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(format!("assemble: expected `true`/`false`, got `{}`", other)),
+    }
+}
+
+/// Reads the single Scheme datum written by `constant`'s `pretty_print` back into a `PoolPtr`,
+/// via the same reader used for source files - a constant's text is just ordinary Scheme data.
+fn read_datum(arena: &Arena, text: &str) -> Result<PoolPtr, String> {
+    let file = File::new("<disassembly>", text.to_string());
+    let mut values = Reader::new(arena, false, file)
+        .read_many(text)
+        .map_err(|e| format!("assemble: invalid constant `{}`: {:?}", text, e))?;
+    if values.len() != 1 {
+        return Err(format!(
+            "assemble: expected exactly one constant datum, got {}",
+            values.len()
+        ));
+    }
+    Ok(values.remove(0).ptr.pp())
+}
+
+trait StrCompatExt {
+    fn strip_prefix_compat(&self, prefix: &str) -> Option<&str>;
+    fn strip_suffix_compat(&self, suffix: &str) -> Option<&str>;
+}
+
+impl StrCompatExt for str {
+    fn strip_prefix_compat(&self, prefix: &str) -> Option<&str> {
+        if self.starts_with(prefix) {
+            Some(&self[prefix.len()..])
+        } else {
+            None
+        }
+    }
+
+    fn strip_suffix_compat(&self, suffix: &str) -> Option<&str> {
+        if self.ends_with(suffix) {
+            Some(&self[..self.len() - suffix.len()])
+        } else {
+            None
+        }
+    }
+}
@@ -23,21 +23,68 @@
 //! the global environment).
 
 use arena::Arena;
+use ast::{LocatedSyntaxElement, ParseError};
+use heap::{PoolPtr, RootPtr};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::{Debug, Error, Formatter};
 use std::option::Option;
 use std::rc::Rc;
+use syntax_rules::SyntaxRules;
 use value::Value;
+use VmState;
+
+/// A crate-wide-style interner for variable names, scoped to a single `Environment` tree (shared
+/// by every `Environment` in that tree - see `Environment::new`). Lets `Environment`'s internal
+/// maps key on a small `Copy` `SymbolId` instead of hashing and cloning `String`s on every
+/// `get`/`define`/`mark_initialized` call along the parent-chain walk.
+pub type SymbolId = u32;
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    names: Vec<String>,
+    ids: HashMap<String, SymbolId>,
+}
+
+impl SymbolTable {
+    fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// Interns `name`, allocating a new `SymbolId` if it hasn't been seen before.
+    fn intern(&mut self, name: &str) -> SymbolId {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as SymbolId;
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Looks up `name`'s `SymbolId` without interning it - used on lookup paths, where a name
+    /// that was never defined anywhere should not grow the table.
+    fn try_get(&self, name: &str) -> Option<SymbolId> {
+        self.ids.get(name).copied()
+    }
+
+    /// O(1) reverse lookup, backing `Environment::get_name`.
+    fn resolve(&self, id: SymbolId) -> &str {
+        &self.names[id as usize]
+    }
+}
 
 pub struct Environment {
     parent: Option<Rc<RefCell<Environment>>>,
 
+    /// Shared with every other `Environment` in this tree - see `Environment::new`.
+    interner: Rc<RefCell<SymbolTable>>,
+
     // The value can be a none to hide a value defined in a parent environment.
-    values: HashMap<String, Option<EnvironmentValue>>,
+    values: HashMap<SymbolId, Option<EnvironmentValue>>,
 
     /// Map of (altitude, index) to variable name.
-    variable_names: HashMap<(usize, usize), String>,
+    variable_names: HashMap<(usize, usize), SymbolId>,
 }
 
 impl PartialEq for Environment {
@@ -49,7 +96,9 @@ impl PartialEq for Environment {
 impl Debug for Environment {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         if let Some(ref p) = self.parent {
-            write!(f, "{:?} ← {:?}", p.borrow(), self.values.keys())
+            let interner = self.interner.borrow();
+            let names = self.values.keys().map(|id| interner.resolve(*id));
+            write!(f, "{:?} ← {:?}", p.borrow(), names.collect::<Vec<_>>())
         } else {
             write!(f, "<toplevel>")
         }
@@ -60,6 +109,32 @@ impl Debug for Environment {
 pub enum EnvironmentValue {
     Macro(Macro),
     Variable(Variable),
+    CustomSyntax(CustomSyntax),
+}
+
+/// A handler for an embedder-registered special form: given the form's unevaluated `rest`
+/// (mirroring the built-in forms in `ast::parse_pair`), it produces the `SyntaxElement` that form
+/// should compile to. Lets a host program extend the parser with its own syntax (e.g. a `(matrix
+/// ...)` literal or a `(with-transaction ...)` block) without round-tripping through
+/// `syntax-rules`.
+pub type CustomSyntaxFn = dyn Fn(
+    &Arena,
+    &mut VmState,
+    &RcEnv,
+    &RcAfi,
+    &[PoolPtr],
+) -> Result<LocatedSyntaxElement, ParseError>;
+
+#[derive(Clone)]
+pub struct CustomSyntax {
+    pub name: String,
+    pub handler: Rc<CustomSyntaxFn>,
+}
+
+impl Debug for CustomSyntax {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "CustomSyntax{{{}}}", self.name)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -69,23 +144,40 @@ pub struct Variable {
     pub initialized: bool,
 }
 
+/// What a macro expands a use site into. A macro is either procedural - a lambda of 3 args
+/// (expr, usage_env, definition_env), compiled and run at `define-syntax` time - or a
+/// `syntax-rules` pattern-matching transformer, matched and expanded without being run as code.
+#[derive(Clone)]
+pub enum MacroTransformer {
+    Procedural(RootPtr),
+    SyntaxRules(Rc<SyntaxRules>),
+}
+
 #[derive(Clone)]
 pub struct Macro {
-    pub lambda: usize,
+    pub transformer: MacroTransformer,
     pub definition_environment: RcEnv,
 }
 
 impl std::fmt::Debug for Macro {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         // hide the environment field to avoid environment -> macro -> environment reference loops
-        write!(f, "Macro{{Lambda={}}}", self.lambda)
+        match &self.transformer {
+            MacroTransformer::Procedural(lambda) => write!(f, "Macro{{Lambda={}}}", lambda.pp()),
+            MacroTransformer::SyntaxRules(_) => write!(f, "Macro{{SyntaxRules}}"),
+        }
     }
 }
 
 impl Environment {
     pub fn new(parent: Option<Rc<RefCell<Environment>>>) -> Self {
+        let interner = match &parent {
+            Some(p) => Rc::clone(&p.borrow().interner),
+            None => Rc::new(RefCell::new(SymbolTable::new())),
+        };
         Environment {
             parent,
+            interner,
             values: HashMap::new(),
             variable_names: HashMap::new(),
         }
@@ -97,6 +189,8 @@ impl Environment {
         bindings: &[T],
     ) -> Self {
         let mut env = Environment::new(parent);
+        env.values.reserve(bindings.len());
+        env.variable_names.reserve(bindings.len());
         for identifier in bindings.iter() {
             env.define(identifier.as_ref(), af_info, true);
         }
@@ -144,10 +238,10 @@ impl Environment {
         index: usize,
         initialized: bool,
     ) -> usize {
-        self.variable_names
-            .insert((altitude, index), name.to_string());
+        let id = self.interner.borrow_mut().intern(name);
+        self.variable_names.insert((altitude, index), id);
         self.values.insert(
-            name.to_string(),
+            id,
             Some(EnvironmentValue::Variable(Variable {
                 altitude,
                 index,
@@ -163,20 +257,46 @@ impl Environment {
     /// case, the macro will be replaced.
     ///
     /// TODO: definition environment should be a weak ref to avoid cycles?
-    pub fn define_macro(&mut self, name: &str, lambda: usize, definition_environment: RcEnv) {
+    pub fn define_macro(
+        &mut self,
+        name: &str,
+        transformer: MacroTransformer,
+        definition_environment: RcEnv,
+    ) {
+        let id = self.interner.borrow_mut().intern(name);
         self.values.insert(
-            name.to_string(),
+            id,
             Some(EnvironmentValue::Macro(Macro {
-                lambda,
+                transformer,
                 definition_environment,
             })),
         );
     }
 
+    /// Registers an embedder-defined special form in the current environment (topmost frame).
+    /// Looked up the same way as a macro - via the ordinary name lookup chain - so it can shadow,
+    /// and be shadowed by, a variable or macro of the same name defined in a more local scope.
+    ///
+    /// It is legal to call [define_custom_syntax] with a name that already has a handler; the
+    /// handler is replaced.
+    pub fn define_custom_syntax(&mut self, name: &str, handler: Rc<CustomSyntaxFn>) {
+        let id = self.interner.borrow_mut().intern(name);
+        self.values.insert(
+            id,
+            Some(EnvironmentValue::CustomSyntax(CustomSyntax {
+                name: name.to_string(),
+                handler,
+            })),
+        );
+    }
+
     pub fn get(&self, name: &str) -> Option<EnvironmentValue> {
-        if self.values.contains_key(name) {
-            self.values.get(name).and_then(Clone::clone)
-        } else if let Some(ref e) = self.parent {
+        if let Some(id) = self.interner.borrow().try_get(name) {
+            if self.values.contains_key(&id) {
+                return self.values.get(&id).and_then(Clone::clone);
+            }
+        }
+        if let Some(ref e) = self.parent {
             e.borrow().get(name)
         } else {
             None
@@ -184,8 +304,8 @@ impl Environment {
     }
 
     pub fn get_name(&self, altitude: usize, index: usize) -> String {
-        if let Some(s) = self.variable_names.get(&(altitude, index)) {
-            s.clone()
+        if let Some(id) = self.variable_names.get(&(altitude, index)) {
+            self.interner.borrow().resolve(*id).to_string()
         } else if let Some(ref e) = self.parent {
             e.borrow().get_name(altitude, index)
         } else {
@@ -193,12 +313,42 @@ impl Environment {
         }
     }
 
+    /// Looks up the [`Variable`] most recently defined at `(altitude, index)`, for debugger/REPL
+    /// introspection - see [`dump_frames`]. Returns `None` if nothing was ever defined there in
+    /// this environment tree, or if the name bound there has since been redefined as something
+    /// other than a plain variable (e.g. a macro).
+    pub fn get_variable(&self, altitude: usize, index: usize) -> Option<Variable> {
+        if let Some(id) = self.variable_names.get(&(altitude, index)) {
+            match self.values.get(id) {
+                Some(Some(EnvironmentValue::Variable(v))) => Some(v.clone()),
+                _ => None,
+            }
+        } else if let Some(ref e) = self.parent {
+            e.borrow().get_variable(altitude, index)
+        } else {
+            None
+        }
+    }
+
     pub fn parent(&self) -> Option<&RcEnv> {
         (&self.parent).as_ref()
     }
 
+    /// Names bound in this environment, not counting those shadowed away by a later `None`
+    /// entry (see the `values` field) or names bound only in a parent environment. Used to
+    /// build completion candidates for the REPL - see `Interpreter::completion_candidates`.
+    pub fn bound_names(&self) -> Vec<String> {
+        let interner = self.interner.borrow();
+        self.values
+            .iter()
+            .filter(|(_, v)| v.is_some())
+            .map(|(id, _)| interner.resolve(*id).to_string())
+            .collect()
+    }
+
     pub fn mark_initialized(&mut self, name: &str) {
-        match self.values.get_mut(name) {
+        let id = self.interner.borrow().try_get(name);
+        match id.and_then(|id| self.values.get_mut(&id)) {
             Some(Some(EnvironmentValue::Variable(v))) => v.initialized = true,
             Some(_) => panic!("Tried to mark non-variable as initialized"),
             None => match self.parent {
@@ -221,11 +371,10 @@ pub fn filter(closed_env: &RcEnv, free_env: &RcEnv, free_vars: &[String]) -> Res
     let mut filtered = Environment::new(Some(closed_env.clone()));
     for free_var in free_vars.iter() {
         let var = free_env.borrow().get(free_var);
-        filtered.values.insert(free_var.clone(), var.clone());
+        let id = filtered.interner.borrow_mut().intern(free_var);
+        filtered.values.insert(id, var.clone());
         if let Some(EnvironmentValue::Variable(v)) = var {
-            filtered
-                .variable_names
-                .insert((v.altitude, v.index), free_var.clone());
+            filtered.variable_names.insert((v.altitude, v.index), id);
         }
     }
 
@@ -235,34 +384,34 @@ pub fn filter(closed_env: &RcEnv, free_env: &RcEnv, free_vars: &[String]) -> Res
 // TODO make these fields private and have proper accessors
 #[derive(Debug, PartialEq, Clone)]
 pub struct ActivationFrame {
-    pub parent: Option<usize>,
-    pub values: Vec<usize>,
+    pub parent: Option<PoolPtr>,
+    pub values: Vec<PoolPtr>,
 }
 
 impl ActivationFrame {
-    pub fn get_parent<'a>(&self, arena: &'a Arena) -> Option<&'a RefCell<Self>> {
+    pub fn get_parent(&self) -> Option<&RefCell<Self>> {
         self.parent.map(|p| {
-            if let Value::ActivationFrame(af) = arena.get(p) {
+            if let Value::ActivationFrame(af) = p.long_lived() {
                 af
             } else {
-                panic!("Parent of ActivationFrame is {:?}", arena.get(p))
+                panic!("Parent of ActivationFrame is {:?}", p.long_lived())
             }
         })
     }
 
-    pub fn get(&self, arena: &Arena, depth: usize, index: usize) -> usize {
+    pub fn get(&self, arena: &Arena, depth: usize, index: usize) -> PoolPtr {
         if depth == 0 {
             self.values[index]
-        } else if let Some(p) = self.get_parent(arena) {
+        } else if let Some(p) = self.get_parent() {
             p.borrow().get(arena, depth - 1, index)
         } else {
             panic!("Accessing depth with no parent.")
         }
     }
 
-    pub fn depth(&self, arena: &Arena) -> usize {
-        if let Some(p) = self.get_parent(arena) {
-            p.borrow().depth(arena) + 1
+    pub fn depth(&self) -> usize {
+        if let Some(p) = self.get_parent() {
+            p.borrow().depth() + 1
         } else {
             0
         }
@@ -281,10 +430,10 @@ impl ActivationFrame {
         }
     }
 
-    pub fn set(&mut self, arena: &Arena, depth: usize, index: usize, value: usize) {
+    pub fn set(&mut self, arena: &Arena, depth: usize, index: usize, value: PoolPtr) {
         if depth == 0 {
             self.values[index] = value;
-        } else if let Some(p) = self.get_parent(arena) {
+        } else if let Some(p) = self.get_parent() {
             p.borrow_mut().set(arena, depth - 1, index, value);
         } else {
             panic!("Accessing depth with no parent.");
@@ -292,6 +441,66 @@ impl ActivationFrame {
     }
 }
 
+/// One live slot inside a [`FrameDump`] - see [`dump_frames`].
+#[derive(Debug, Clone)]
+pub struct FrameBinding {
+    pub name: String,
+    pub initialized: bool,
+    /// The slot's current value, already pretty-printed, or `"unbound"` for a slot that has been
+    /// popped or never assigned (still holding `arena.undefined`).
+    pub value: String,
+}
+
+/// A single activation frame's live bindings, as reconstructed by [`dump_frames`].
+#[derive(Debug, Clone)]
+pub struct FrameDump {
+    pub altitude: usize,
+    pub bindings: Vec<FrameBinding>,
+}
+
+/// Walks `frame`'s parent chain, pairing each slot up with the variable name and `initialized`
+/// flag `env`'s `variable_names` map has on file for it, for a REPL or error handler to print
+/// local bindings at a breakpoint or on error.
+///
+/// Unlike [`ActivationFrame::get`], this never panics: a slot with
+/// no matching [`Variable`] (e.g. popped, per the TODO on [`filter`]) or still holding
+/// `arena.undefined` is rendered as unbound rather than causing an out-of-bounds access or a
+/// lookup failure.
+pub fn dump_frames(arena: &Arena, frame: &ActivationFrame, env: &RcEnv) -> Vec<FrameDump> {
+    let altitude = frame.depth();
+    let bindings = (0..frame.values.len())
+        .map(|index| frame_binding(arena, frame, env, altitude, index))
+        .collect();
+    let mut dumps = vec![FrameDump { altitude, bindings }];
+    if let Some(parent) = frame.get_parent() {
+        dumps.extend(dump_frames(arena, &parent.borrow(), env));
+    }
+    dumps
+}
+
+fn frame_binding(
+    arena: &Arena,
+    frame: &ActivationFrame,
+    env: &RcEnv,
+    altitude: usize,
+    index: usize,
+) -> FrameBinding {
+    let name = env.borrow().get_name(altitude, index);
+    let value = frame.values[index];
+    match env.borrow().get_variable(altitude, index) {
+        Some(var) if value != arena.undefined => FrameBinding {
+            name,
+            initialized: var.initialized,
+            value: value.pretty_print(),
+        },
+        _ => FrameBinding {
+            name,
+            initialized: false,
+            value: "unbound".to_string(),
+        },
+    }
+}
+
 #[derive(Debug)]
 pub struct ActivationFrameInfo {
     pub parent: Option<Rc<RefCell<ActivationFrameInfo>>>,
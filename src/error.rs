@@ -1,9 +1,22 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
 use std::fmt::Write;
 use std::rc::Rc;
 
 use ast::MacroSource;
 use lex::CodeRange;
-use value::Locator;
 use File;
 
 /// An error which can provide a code location
@@ -11,6 +24,9 @@ trait SourcedError: std::error::Error {
     fn code_source(&self) -> Option<&Source>;
 }
 
+/// Where a span of code came from: either straight from a source file, or generated by expanding
+/// a macro, in which case `code_source` says where *that* code came from in turn - another macro
+/// expansion, for a macro that expands into a call to another macro, or ultimately a `Code`.
 #[derive(Debug, Clone)]
 pub enum Source {
     /// Directly from source code
@@ -28,63 +44,284 @@ pub struct SourceFileLocator {
     pub range: CodeRange,
 }
 
-pub fn locate_message(locator: &Locator, msg: &str) -> String {
+/// Whether a rendered diagnostic should be wrapped in ANSI color codes. Resolving the CLI's
+/// `--color=auto|always|never` against whether stdout is actually a terminal is the caller's job
+/// (see `bin/main.rs`); by the time a `ColorChoice` reaches this module, it's already a plain
+/// yes-or-no.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Colored,
+    Plain,
+}
+
+fn colorize(color: ColorChoice, code: &str, text: &str) -> String {
+    match color {
+        ColorChoice::Plain => text.to_string(),
+        ColorChoice::Colored => format!("\x1b[{}m{}\x1b[0m", code, text),
+    }
+}
+
+/// Renders a bare `error: msg` line, coloring the `error:` tag red under `color`. Shared by
+/// [`locate_message`] (for its first frame's header) and by callers that have a message but no
+/// [`Source`] to show a code span for.
+pub fn format_error(color: ColorChoice, msg: &str) -> String {
+    format!("{} {}", colorize(color, "31", "error:"), msg)
+}
+
+/// Renders an error that may or may not have a known [`Source`]: delegates to [`locate_message`]
+/// when one is available, and falls back to a bare `prefix: error: msg` line (`prefix` followed
+/// by [`format_error`]'s output) when it isn't - e.g. an error about code synthesized by a macro expansion
+/// rather than read from a file.
+pub fn error_with_source(source: &Option<Source>, prefix: &str, msg: &str) -> String {
+    match source {
+        Some(source) => locate_message(source, msg, ColorChoice::Plain),
+        None => format!("{}: {}", prefix, format_error(ColorChoice::Plain, msg)),
+    }
+}
+
+/// Renders `source` as a rustc-style diagnostic: the innermost code span with its caret
+/// underline, followed by one "in this expansion" note per enclosing [`MacroSource`], in
+/// innermost-to-outermost order. A macro that expands into a call to another macro therefore
+/// shows its whole expansion chain rather than just the outermost invocation.
+///
+/// Under [`ColorChoice::Colored`], the `error:` tag is red, the line gutters and `-->` markers
+/// are blue, and the underline carets are yellow. Under [`ColorChoice::Plain`] the output is
+/// unchanged from the pre-color rendering.
+pub fn locate_message(source: &Source, msg: &str, color: ColorChoice) -> String {
+    render_chain(source, format_error(color, msg), color)
+}
+
+/// Renders a bare `warning: msg` line, coloring the `warning:` tag yellow under `color` - the
+/// non-fatal counterpart to [`format_error`].
+pub fn format_warning(color: ColorChoice, msg: &str) -> String {
+    format!("{} {}", colorize(color, "33", "warning:"), msg)
+}
+
+/// Same as [`locate_message`], but with a `warning:` header instead of `error:`. Used by
+/// [`crate::lint::Diagnostic`] so lint findings go through the same span-rendering machinery as
+/// runtime errors.
+pub fn locate_warning(source: &Source, msg: &str, color: ColorChoice) -> String {
+    render_chain(source, format_warning(color, msg), color)
+}
+
+/// Shared by [`locate_message`] and [`locate_warning`]: renders the innermost code span under
+/// `header`, followed by one "in this expansion" note per enclosing [`MacroSource`].
+fn render_chain(source: &Source, header: String, color: ColorChoice) -> String {
+    let mut output = render_frame(innermost_locator(source), &header, color);
+
+    // `source` nests outermost-first, so collect the chain and render it back to front.
+    let mut macro_sources = Vec::new();
+    let mut frame = source;
+    while let Source::Macro {
+        macro_source,
+        code_source,
+    } = frame
+    {
+        macro_sources.push(macro_source);
+        frame = code_source;
+    }
+    for macro_source in macro_sources.iter().rev() {
+        output.push_str(&render_frame(
+            &macro_source.invocation,
+            &format!("note: in this expansion of macro `{}`", macro_source.name),
+            color,
+        ));
+    }
+
+    output
+}
+
+fn innermost_locator(source: &Source) -> &SourceFileLocator {
+    match source {
+        Source::Code(locator) => locator,
+        Source::Macro { code_source, .. } => innermost_locator(code_source),
+    }
+}
+
+fn render_frame(locator: &SourceFileLocator, header: &str, color: ColorChoice) -> String {
     let mut output = String::new();
 
-    let max_num_line_width = locator.range.end.0.to_string().chars().count();
+    let (start_line, start_col) = locator.range.start.linecol_in(&locator.file.source);
+    let (end_line, end_col) = locator.range.end.linecol_in(&locator.file.source);
+
+    let max_num_line_width = end_line.to_string().chars().count();
     let prefix: String = " ".repeat(max_num_line_width);
 
-    writeln!(output, "error: {}", msg).unwrap();
-    writeln!(output, "{}--> {}", prefix, locator).unwrap();
-    writeln!(output, "{} |", prefix).unwrap();
+    writeln!(output, "{}", header).unwrap();
+    writeln!(
+        output,
+        "{}",
+        colorize(
+            color,
+            "34",
+            &format!(
+                "{}--> {}:{}",
+                prefix,
+                locator.file.name,
+                locator.range.display_in(&locator.file.source)
+            )
+        )
+    )
+    .unwrap();
+    writeln!(output, "{}", colorize(color, "34", &format!("{} |", prefix))).unwrap();
 
     for (i_line, line) in locator.file.source.lines().enumerate() {
-        if i_line + 1 < locator.range.start.0 as usize {
+        if i_line + 1 < start_line as usize {
             continue;
-        } else if i_line + 1 > locator.range.end.0 as usize {
+        } else if i_line + 1 > end_line as usize {
             break;
         }
-        if i_line + 1 == locator.range.start.0 as usize {
-            writeln!(
-                output,
-                "{:width$} |   {}",
-                i_line + 1,
-                line,
-                width = max_num_line_width
-            )
-            .unwrap();
-            let marker = if i_line + 1 == locator.range.end.0 as usize {
-                let underline =
-                    "^".repeat((locator.range.end.1 - locator.range.start.1 + 1) as usize);
-                let prefix = " ".repeat((locator.range.start.1 + 1) as usize);
+        if i_line + 1 == start_line as usize {
+            let gutter = colorize(
+                color,
+                "34",
+                &format!("{:width$} |", i_line + 1, width = max_num_line_width),
+            );
+            writeln!(output, "{}   {}", gutter, line).unwrap();
+            let marker = if i_line + 1 == end_line as usize {
+                let underline = "^".repeat((end_col - start_col + 1) as usize);
+                let prefix = " ".repeat((start_col + 1) as usize);
                 prefix + &underline
             } else {
-                "-".repeat((locator.range.start.1 + 2) as usize) + "^"
+                "-".repeat((start_col + 2) as usize) + "^"
             };
-            writeln!(output, "{} | {}", prefix, marker).unwrap();
-        } else if i_line + 1 == locator.range.end.0 as usize {
-            writeln!(
-                output,
-                "{:width$} | | {}",
-                i_line + 1,
-                line,
-                width = max_num_line_width
-            )
-            .unwrap();
-            let marker = "-".repeat((locator.range.end.1 + 2) as usize) + "^";
-            writeln!(output, "{} | {}", prefix, marker).unwrap();
+            let gutter = colorize(color, "34", &format!("{} |", prefix));
+            writeln!(output, "{} {}", gutter, colorize(color, "33", &marker)).unwrap();
+        } else if i_line + 1 == end_line as usize {
+            let gutter = colorize(
+                color,
+                "34",
+                &format!("{:width$} | |", i_line + 1, width = max_num_line_width),
+            );
+            writeln!(output, "{} {}", gutter, line).unwrap();
+            let marker = "-".repeat((end_col + 2) as usize) + "^";
+            let gutter = colorize(color, "34", &format!("{} |", prefix));
+            writeln!(output, "{} {}", gutter, colorize(color, "33", &marker)).unwrap();
         } else {
-            writeln!(
-                output,
-                "{:width$} | | {}",
-                i_line + 1,
-                line,
-                width = max_num_line_width
-            )
-            .unwrap();
+            let gutter = colorize(
+                color,
+                "34",
+                &format!("{:width$} | |", i_line + 1, width = max_num_line_width),
+            );
+            writeln!(output, "{} {}", gutter, line).unwrap();
         }
     }
 
-    writeln!(output, "{} |", prefix).unwrap();
+    writeln!(output, "{}", colorize(color, "34", &format!("{} |", prefix))).unwrap();
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lex::Span;
+
+    fn locator(file: &Rc<File>, start: usize, end: usize) -> SourceFileLocator {
+        SourceFileLocator {
+            file: Rc::clone(file),
+            range: CodeRange {
+                start: Span(start),
+                end: Span(end),
+            },
+        }
+    }
+
+    #[test]
+    fn renders_direct_code_error() {
+        let file = File::new("test.scm", "(+ 1 foo)");
+        let source = Source::Code(locator(&file, 5, 8));
+
+        let rendered = locate_message(&source, "unbound variable: foo", ColorChoice::Plain);
+        assert!(rendered.starts_with("error: unbound variable: foo"));
+        assert!(rendered.contains("(+ 1 foo)"));
+        assert!(rendered.contains('^'));
+        assert!(!rendered.contains("in this expansion"));
+    }
+
+    #[test]
+    fn colored_output_wraps_error_tag_and_carets_but_keeps_plain_bytes_unchanged() {
+        let file = File::new("test.scm", "(+ 1 foo)");
+        let source = Source::Code(locator(&file, 5, 8));
+
+        let plain = locate_message(&source, "unbound variable: foo", ColorChoice::Plain);
+        let colored = locate_message(&source, "unbound variable: foo", ColorChoice::Colored);
+
+        assert!(!plain.contains('\x1b'));
+        assert!(colored.contains("\x1b[31merror:\x1b[0m"));
+        assert!(colored.contains("\x1b[34m"));
+        assert!(colored.contains("\x1b[33m"));
+        // Stripping the color codes back out reproduces the plain rendering byte-for-byte.
+        let stripped = strip_ansi(&colored);
+        assert_eq!(stripped, plain);
+    }
+
+    fn strip_ansi(s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                for c in chars.by_ref() {
+                    if c == 'm' {
+                        break;
+                    }
+                }
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn renders_one_level_macro_expansion() {
+        let macro_file = File::new("macros.scm", "(define-syntax my-macro (foo))");
+        let code_file = File::new("test.scm", "(my-macro)");
+
+        let source = Source::Macro {
+            macro_source: Rc::new(MacroSource {
+                name: "my-macro".to_string(),
+                invocation: locator(&code_file, 1, 9),
+            }),
+            code_source: Box::new(Source::Code(locator(&macro_file, 25, 28))),
+        };
+
+        let rendered = locate_message(&source, "unbound variable: foo", ColorChoice::Plain);
+        // Innermost frame (the generated code) comes first...
+        assert!(rendered.starts_with("error: unbound variable: foo"));
+        assert!(rendered.contains("macros.scm"));
+        // ...followed by a note pointing back at the macro invocation site.
+        assert!(rendered.contains("in this expansion of macro `my-macro`"));
+        assert!(rendered.contains("test.scm"));
+        assert!(rendered.find("macros.scm").unwrap() < rendered.find("test.scm").unwrap());
+    }
+
+    #[test]
+    fn renders_nested_macro_expansion() {
+        let inner_macro_file = File::new("inner.scm", "(define-syntax inner (foo))");
+        let outer_macro_file = File::new("outer.scm", "(define-syntax outer (inner))");
+        let code_file = File::new("test.scm", "(outer)");
+
+        let source = Source::Macro {
+            macro_source: Rc::new(MacroSource {
+                name: "outer".to_string(),
+                invocation: locator(&code_file, 1, 6),
+            }),
+            code_source: Box::new(Source::Macro {
+                macro_source: Rc::new(MacroSource {
+                    name: "inner".to_string(),
+                    invocation: locator(&outer_macro_file, 22, 27),
+                }),
+                code_source: Box::new(Source::Code(locator(&inner_macro_file, 22, 25))),
+            }),
+        };
+
+        let rendered = locate_message(&source, "unbound variable: foo", ColorChoice::Plain);
+        let inner_note = rendered.find("in this expansion of macro `inner`").unwrap();
+        let outer_note = rendered.find("in this expansion of macro `outer`").unwrap();
+        let code_pos = rendered.find("inner.scm").unwrap();
+
+        // Innermost code frame first, then the `inner` expansion note, then the `outer` one.
+        assert!(code_pos < inner_note);
+        assert!(inner_note < outer_note);
+    }
+}
@@ -27,6 +27,49 @@
 
 use std::cell::{RefCell, UnsafeCell};
 
+/// Memcheck client-request annotations for the arena, gated behind the `valgrind` feature so
+/// default builds never pay for (or depend on) them. When compiled in and run under Valgrind,
+/// these let Memcheck report use-after-free and leaks in terms of individual GC cells rather
+/// than one giant live allocation covering the whole backing `Vec`.
+///
+/// Thin wrappers over the `crabgrind` crate's `memcheck`/`memcheck::mempool` client requests.
+#[cfg(feature = "valgrind")]
+mod valgrind_support {
+    /// Registers `vec`'s backing buffer as a Memcheck memory pool, so that cells handed out by
+    /// `Gc::insert`/reclaimed by `Gc::remove` can be individually marked allocated/freed within
+    /// it. Must be re-run whenever the `Vec` reallocates (its base address moves).
+    pub fn register_pool<T>(vec: &[Option<Box<T>>]) {
+        crabgrind::memcheck::mempool::create_mempool(
+            vec.as_ptr() as usize,
+            0,
+            false,
+            crabgrind::memcheck::mempool::Kind::Auto,
+        );
+    }
+
+    pub fn deregister_pool<T>(vec: &[Option<Box<T>>]) {
+        deregister_pool_at(vec.as_ptr() as usize);
+    }
+
+    pub fn deregister_pool_at(base: usize) {
+        crabgrind::memcheck::mempool::destroy_mempool(base);
+    }
+
+    /// Marks the logical cell at `slot` as freshly allocated and defined.
+    pub fn mark_allocated<T>(slot: &Option<Box<T>>) {
+        let addr = slot as *const _ as usize;
+        let size = std::mem::size_of::<Option<Box<T>>>();
+        crabgrind::memcheck::mark_mem(addr, size, crabgrind::memcheck::MemState::Defined);
+    }
+
+    /// Marks the logical cell at `slot` as freed: no longer accessible until reused.
+    pub fn mark_freed<T>(slot: &Option<Box<T>>) {
+        let addr = slot as *const _ as usize;
+        let size = std::mem::size_of::<Option<Box<T>>>();
+        crabgrind::memcheck::mark_mem(addr, size, crabgrind::memcheck::MemState::NoAccess);
+    }
+}
+
 pub struct PushOnlyVec<T> {
     underlying: Vec<T>,
 }
@@ -50,17 +93,63 @@ pub struct Gc<T: Inventory> {
     free_cells: RefCell<Vec<usize>>,
 }
 
+/// Below this fraction of reusable (free) cells, [`Gc::collect_if_needed`] runs a collection
+/// pass instead of waiting for an explicit external `collect()` call.
+const MIN_FREE_RATIO: f64 = 0.25;
+
 impl<T: Inventory> Gc<T> {
+    /// Creates a `Gc` with its arena and free list pre-reserved for `n` cells, to avoid
+    /// reallocating the backing storage while warming up a long-running session.
+    pub fn with_capacity(n: usize) -> Self {
+        let arena = Vec::with_capacity(n);
+        #[cfg(feature = "valgrind")]
+        valgrind_support::register_pool(&arena);
+        Gc {
+            arena: UnsafeCell::new(arena),
+            free_cells: RefCell::new(Vec::with_capacity(n)),
+        }
+    }
+
+    /// The number of live cells: the arena's total slots minus the ones sitting on the free list.
+    pub fn len(&self) -> usize {
+        self.capacity() - self.free_cells.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The arena's total number of slots, live or free.
+    pub fn capacity(&self) -> usize {
+        unsafe { (*self.arena.get()).len() }
+    }
+
     pub fn insert(&self, val: T) -> usize {
         let boxed = Some(Box::new(val));
         if let Some(insert_pos) = self.free_cells.borrow_mut().pop() {
             unsafe {
                 (*self.arena.get())[insert_pos] = boxed;
+                #[cfg(feature = "valgrind")]
+                valgrind_support::mark_allocated(&(*self.arena.get())[insert_pos]);
             }
             insert_pos
         } else {
+            #[cfg(feature = "valgrind")]
+            let old_base = unsafe { (*self.arena.get()).as_ptr() };
             unsafe {
                 (*self.arena.get()).push(boxed);
+                #[cfg(feature = "valgrind")]
+                {
+                    // The `Vec` may have just reallocated, moving the whole pool: Valgrind has no
+                    // way to know the old allocation and the new one are "the same" pool, so we
+                    // must deregister under the stale base address before registering the new one.
+                    if (*self.arena.get()).as_ptr() != old_base {
+                        valgrind_support::deregister_pool_at(old_base as usize);
+                        valgrind_support::register_pool(&(*self.arena.get()));
+                    }
+                    let last = (*self.arena.get()).len() - 1;
+                    valgrind_support::mark_allocated(&(*self.arena.get())[last]);
+                }
                 (*self.arena.get()).len() - 1
             }
         }
@@ -80,13 +169,34 @@ impl<T: Inventory> Gc<T> {
         self.maybe_get(pos).expect("get() on invalid GC value")
     }
 
-    fn remove(&mut self, pos: usize) {
+    fn remove(&self, pos: usize) {
         if unsafe { std::mem::replace(&mut (*self.arena.get())[pos], None) }.is_some() {
+            #[cfg(feature = "valgrind")]
+            unsafe {
+                valgrind_support::mark_freed(&(*self.arena.get())[pos]);
+            }
             self.free_cells.borrow_mut().push(pos);
         }
     }
 
-    pub fn collect(&mut self, roots: &[usize]) {
+    /// Runs [`Gc::collect`] if the free-cell ratio has dropped below [`MIN_FREE_RATIO`],
+    /// otherwise does nothing. Callers that `insert` in a loop (e.g. a REPL driving
+    /// `GcMode::Normal`) should call this with the current root set after each insertion instead
+    /// of waiting on an external, manually-triggered `collect()`, so the arena doesn't bloat over
+    /// a long-running session. Collection itself is non-moving: indices of surviving cells are
+    /// never changed, matching [`tests::no_readress`].
+    pub fn collect_if_needed(&self, roots: &[usize]) {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return;
+        }
+        let free_ratio = self.free_cells.borrow().len() as f64 / capacity as f64;
+        if free_ratio < MIN_FREE_RATIO {
+            self.collect(roots);
+        }
+    }
+
+    pub fn collect(&self, roots: &[usize]) {
         let current_len = unsafe { (*self.arena.get()).len() };
 
         let mut marks = vec![false; current_len];
@@ -120,6 +230,16 @@ impl<T: Inventory> Default for Gc<T> {
     }
 }
 
+#[cfg(feature = "valgrind")]
+impl<T: Inventory> Drop for Gc<T> {
+    fn drop(&mut self) {
+        let vec = unsafe { &*self.arena.get() };
+        if vec.capacity() > 0 {
+            valgrind_support::deregister_pool(vec);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -230,4 +350,51 @@ mod tests {
         }
         assert_eq!(val.id, "Label");
     }
+
+    #[test]
+    fn capacity_accounting() {
+        let gc: Gc<Node> = Default::default();
+        assert_eq!(gc.capacity(), 0);
+        assert_eq!(gc.len(), 0);
+        gc.insert(Default::default());
+        gc.insert(Default::default());
+        assert_eq!(gc.capacity(), 2);
+        assert_eq!(gc.len(), 2);
+        gc.collect(&vec![]);
+        assert_eq!(gc.capacity(), 2);
+        assert_eq!(gc.len(), 0);
+    }
+
+    #[test]
+    fn collect_if_needed_no_op_above_threshold() {
+        let gc: Gc<Node> = Default::default();
+        let root = gc.insert(Node::new("Root".into(), vec![]));
+        for _ in 0..3 {
+            gc.insert(Default::default());
+        }
+        gc.collect(&[root]);
+        // One live cell (the root) plus three now-free ones: a 75% free ratio, comfortably
+        // above the threshold, so collect_if_needed should leave the arena untouched.
+        assert_eq!(gc.len(), 1);
+        assert_eq!(gc.capacity(), 4);
+        gc.collect_if_needed(&[root]);
+        assert_eq!(gc.get(root).id, "Root");
+        assert_eq!(gc.len(), 1);
+        assert_eq!(gc.capacity(), 4);
+    }
+
+    #[test]
+    fn collect_if_needed_reclaims_when_sparse() {
+        let gc: Gc<Node> = Default::default();
+        let root = gc.insert(Node::new("Root".into(), vec![]));
+        for _ in 0..7 {
+            gc.insert(Default::default());
+        }
+        // Nothing has been freed yet, so the free ratio is 0: well below the threshold.
+        // collect_if_needed should run a real (non-moving) collection pass.
+        gc.collect_if_needed(&[root]);
+        assert_eq!(gc.get(root).id, "Root");
+        assert_eq!(gc.len(), 1);
+        assert_eq!(gc.capacity(), 8);
+    }
 }
@@ -15,11 +15,12 @@
 /// General strategy
 ///
 /// We maintain a set of pools, each of which contains some number (256 currently) of entries.
-/// Each entry is either empty, or contains a value. Empty entries are part of a linked list.
+/// Each entry is either empty, or contains a value. A pool tracks which entries are occupied
+/// with a `reserved` bitset (and a separate `live` bitset distinguishing real values from
+/// debug-poisoned slots - see `Pool::free_ref`).
 ///
 /// A Heap object manages pools. When we need to perform an allocation, we take the first pool
-/// we find with at least one free entry, and make that entry occupied. We edit the free entry
-/// linked list accordingly.
+/// we find with at least one free entry (a zero bit in `reserved`), and claim it.
 ///
 /// Each pool also has a bitvec for the mark phase of GC.
 ///
@@ -31,12 +32,13 @@
 /// been dropped.
 use std::cell::UnsafeCell;
 use std::convert::{From, TryFrom};
-use std::fmt::{self, Debug, Error, Formatter};
+use std::fmt::{self, Error, Formatter};
 use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::rc::{Rc, Weak};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use bitvec::prelude::{BitBox, BitVec};
 use value::Value;
@@ -46,6 +48,74 @@ const POOL_ENTRIES: u16 = 1 << 8;
 const FIRST_GC: usize = 1024 * 1024;
 const GC_GROWTH: f32 = 2.0;
 
+/// A fixed-size bitset backed by `AtomicU32` words, word-at-a-time CAS'd so a slot can be claimed
+/// without a lock - the prerequisite for [`Pool`] to eventually live behind an `Arc` instead of an
+/// `Rc`. `Pool::allocate` only ever sees `&mut self` today (this heap is still single-threaded),
+/// so nothing currently contends on these CASes, but the bitset itself doesn't assume that.
+struct AtomicBitset {
+    words: Box<[AtomicU32]>,
+    len: usize,
+}
+
+impl AtomicBitset {
+    fn new(len: usize) -> Self {
+        let num_words = (len + 31) / 32;
+        AtomicBitset {
+            words: (0..num_words).map(|_| AtomicU32::new(0)).collect(),
+            len,
+        }
+    }
+
+    /// Scans word-at-a-time for a zero bit and CASes it to one, returning the claimed index, or
+    /// `None` if every bit is already set.
+    fn try_claim(&self) -> Option<usize> {
+        for (word_idx, word) in self.words.iter().enumerate() {
+            let mut current = word.load(Ordering::Relaxed);
+            loop {
+                if current == u32::MAX {
+                    break;
+                }
+                let bit = (!current).trailing_zeros() as usize;
+                let idx = word_idx * 32 + bit;
+                if idx >= self.len {
+                    break;
+                }
+                match word.compare_exchange_weak(
+                    current,
+                    current | (1 << bit),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return Some(idx),
+                    Err(observed) => current = observed,
+                }
+            }
+        }
+        None
+    }
+
+    fn clear(&self, idx: usize) {
+        debug_assert!(idx < self.len);
+        self.words[idx / 32].fetch_and(!(1 << (idx % 32)), Ordering::AcqRel);
+    }
+}
+
+/// Returned by [`Heap::try_allocate`] (and the `RHeap`/`Arena` wrappers around it) when an
+/// allocation cannot be satisfied even after a forced [`Heap::gc`] - i.e. every pool is full and
+/// a configured `max_pools` ceiling keeps the heap from growing to make room. Lets callers (like
+/// the `cons`/`car`/`cdr` builtins) surface a Scheme-level `out-of-memory` condition instead of
+/// the interpreter aborting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "out of memory")
+    }
+}
+
+impl std::error::Error for AllocError {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GcMode {
     Off,
@@ -78,47 +148,20 @@ impl FromStr for GcMode {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-struct FreePoolEntry {
-    prev: Option<u16>,
-    next: Option<u16>,
-}
-
-struct UsedPoolEntry(Value);
-
-impl Debug for UsedPoolEntry {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.0)
-    }
-}
-
-#[derive(Debug)]
-enum PoolEntry {
-    Free(FreePoolEntry),
-    Used(UsedPoolEntry),
-}
-
-impl PoolEntry {
-    fn is_free(&self) -> bool {
-        match self {
-            PoolEntry::Free(_) => true,
-            PoolEntry::Used(_) => false,
-        }
-    }
-}
-
-impl Default for PoolEntry {
-    fn default() -> Self {
-        PoolEntry::Free(FreePoolEntry {
-            prev: None,
-            next: None,
-        })
-    }
-}
-
-struct Pool {
-    data: [PoolEntry; POOL_ENTRIES as usize],
-    free_block: Option<u16>,
+pub struct Pool {
+    data: [MaybeUninit<Value>; POOL_ENTRIES as usize],
+    /// `reserved[i]` is set the moment `allocate` claims slot `i`, via [`AtomicBitset::try_claim`]
+    /// CASing a zero bit to one word-at-a-time - this replaces the old intrusive prev/next free
+    /// list with a flat occupied-bitset, atomic so it stays correct once `Pool` lives behind an
+    /// `Arc` instead of an `Rc`.
+    reserved: AtomicBitset,
+    /// `live[i]` is set while slot `i` holds a valid, not-yet-dropped `Value`; this is what
+    /// `Deref`/`sweep` consult to tell a real value apart from a free (or debug-poisoned) slot.
+    /// In non-debug mode `reserved` and `live` are always cleared together by `free_ref`; in
+    /// debug mode `live` is cleared but `reserved` is left set forever, so the slot is dropped
+    /// exactly once but never reused, and later dereferences keep panicking instead of reading
+    /// a reallocated value.
+    live: BitBox,
     allocated: u16,
     marked: BitBox,
 }
@@ -127,48 +170,37 @@ impl std::fmt::Debug for Pool {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         let mut data_string = "[".to_string();
         data_string.push_str(
-            &self.data[..]
-                .iter()
-                .map(|pe| format!("{:?}", pe))
+            &(0..POOL_ENTRIES as usize)
+                .map(|i| {
+                    if self.live[i] {
+                        format!("{:?}", unsafe { &*self.data[i].as_ptr() })
+                    } else {
+                        "Free".to_string()
+                    }
+                })
                 .collect::<Vec<_>>()
                 .join(", "),
         );
         data_string.push(']');
         f.debug_struct("Pool")
             .field("data", &data_string)
-            .field("free", &self.free_block)
             .field("allocated", &self.allocated)
             .finish()
     }
 }
 
 impl Pool {
-    fn new() -> Pin<Box<Self>> {
-        let data = {
-            let mut data: [MaybeUninit<PoolEntry>; POOL_ENTRIES as usize] =
-                unsafe { MaybeUninit::uninit().assume_init() };
-
-            for (i_block, item) in data.iter_mut().enumerate() {
-                let i_block = u16::try_from(i_block).expect("wat");
-                *item = MaybeUninit::new(PoolEntry::Free(FreePoolEntry {
-                    prev: if i_block == 0 {
-                        None
-                    } else {
-                        Some(i_block - 1)
-                    },
-                    next: if i_block == POOL_ENTRIES - 1 {
-                        None
-                    } else {
-                        Some(i_block + 1)
-                    },
-                }));
-            }
-
-            unsafe { std::mem::transmute::<_, [PoolEntry; POOL_ENTRIES as usize]>(data) }
-        };
+    /// Allocates one fresh, empty `Pool` from the global allocator. Build several of these and
+    /// hand them to [`RHeap::with_preallocated_pools`] to fix a heap's pool budget up front.
+    pub fn new() -> Pin<Box<Self>> {
+        // An array of `MaybeUninit<Value>` needs no initialization of its own - leaving it
+        // uninitialized is exactly what `reserved`/`live` being all-zero says it is.
+        let data: [MaybeUninit<Value>; POOL_ENTRIES as usize] =
+            unsafe { MaybeUninit::uninit().assume_init() };
         let pool = Pool {
             data,
-            free_block: Some(0),
+            reserved: AtomicBitset::new(POOL_ENTRIES as usize),
+            live: BitVec::from(&[false; POOL_ENTRIES as usize][..]).into_boxed_bitslice(),
             allocated: 0,
             marked: BitVec::from(&[false; POOL_ENTRIES as usize][..]).into_boxed_bitslice(),
         };
@@ -176,34 +208,27 @@ impl Pool {
     }
 }
 
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for i in 0..POOL_ENTRIES as usize {
+            if self.live[i] {
+                unsafe { std::ptr::drop_in_place(self.data[i].as_mut_ptr()) };
+            }
+        }
+    }
+}
+
 impl Pool {
     fn allocate(self: Pin<&mut Self>, value: Value) -> Option<PoolPtr> {
         let selr = unsafe { self.get_unchecked_mut() };
-        if let Some(old_free_index) = selr.free_block {
-            // println!("allocating {:?} at {}", &value, old_free_index);
-            let next = if let PoolEntry::Free(ref e) = selr.data[usize::from(old_free_index)] {
-                e.next
-            } else {
-                panic!("free not pointing to free entry")
-            };
-            selr.data[usize::from(old_free_index)] = PoolEntry::Used(UsedPoolEntry(value));
-            if let Some(next) = next {
-                if let PoolEntry::Free(ref mut e) = selr.data[usize::from(next)] {
-                    e.prev = None
-                } else {
-                    panic!("free->next not pointing to free entry")
-                }
-            }
-            selr.free_block = next;
-            selr.allocated += 1;
-            Some(PoolPtr {
-                pool: selr as *mut Pool,
-                idx: old_free_index,
-            })
-        } else {
-            debug_assert_eq!(selr.allocated, POOL_ENTRIES);
-            None
-        }
+        let free_idx = selr.reserved.try_claim()?;
+        selr.live.set(free_idx, true);
+        selr.data[free_idx] = MaybeUninit::new(value);
+        selr.allocated += 1;
+        Some(PoolPtr {
+            pool: selr as *mut Pool,
+            idx: free_idx as u16,
+        })
     }
 
     #[cfg(test)]
@@ -212,49 +237,40 @@ impl Pool {
         selr.free_ref(idx, debug);
     }
 
-    /// Frees the memory at the specified address by returning the memory to the free list.
+    /// Frees the memory at the specified address by clearing its occupied bit.
+    ///
+    /// If the GC is in debug mode, the value is still dropped and `live` cleared (so a later
+    /// dereference panics instead of reading garbage), but `reserved` is left set forever -
+    /// `allocate` will never see this slot as free again. This gives us a nice error, instead of
+    /// a segmentation fault or garbage data, when freed memory is accessed again.
     ///
-    /// If the GC is in debug mode, the memory will be marked free, but not returned to the free
-    /// list. This allows us to get a nice error, instead of a segmentation fault or garbage data,
-    /// when freed memory is accessed again.
+    /// This `drop_in_place` is also this heap's entire finalizer mechanism: there's no separate
+    /// registry of "values that need a hook run before they're freed", because every slot already
+    /// goes through ordinary Rust drop glue here, unconditionally, whether it's being reclaimed by
+    /// `sweep` or by `Pool`'s own `Drop` impl at heap teardown. A `Value::Port` wraps its
+    /// `Box<dyn TextInputPort>`/`Box<dyn OutputPort>` with no manual `Drop`, so dropping it here
+    /// drops the underlying `std::fs::File` (closing the descriptor) exactly once - and a no-op if
+    /// `close-port` already took it, since the concrete port types track that with an
+    /// `Option`-valued reader/writer field (see e.g. `FileTextInputPort::close`). Order among
+    /// mutually-unreachable ports freed in the same sweep falls out of slot iteration order, which
+    /// is unspecified on purpose.
     fn free_ref(&mut self, idx: u16, debug: bool) {
-        debug_assert!(
-            !self.data[usize::from(idx)].is_free(),
-            "freeing free entry!"
-        );
-        // println!("freeing {:?} at {:?} {}", self.data[usize::from(idx)], self as *const Self, idx);
-        self.data[usize::from(idx)] = PoolEntry::Free(FreePoolEntry {
-            prev: None,
-            next: if debug { None } else { self.free_block },
-        });
-
-        if let Some(free_index) = self.free_block {
-            if let PoolEntry::Free(ref mut f) = self.data[usize::from(free_index)] {
-                debug_assert_eq!(f.prev, None);
-                if !debug {
-                    f.prev = Some(idx);
-                }
-            } else {
-                panic!("free_block not pointing at free entry");
-            }
-        }
+        let i = usize::from(idx);
+        debug_assert!(self.live[i], "freeing free entry!");
+        unsafe { std::ptr::drop_in_place(self.data[i].as_mut_ptr()) };
+        self.live.set(i, false);
         if !debug {
-            self.free_block = Some(idx);
+            self.reserved.clear(i);
             self.allocated -= 1;
         }
     }
 
     /// Returns the number of freed entries
     fn sweep(self: Pin<&mut Self>, debug: bool) -> u16 {
-        let mut selr = unsafe { self.get_unchecked_mut() };
+        let selr = unsafe { self.get_unchecked_mut() };
         let init = selr.allocated;
-        for (i_mark, mark) in selr.marked.clone().iter().enumerate() {
-            if !mark && !selr.data[i_mark].is_free() {
-                /*
-                if let PoolEntry::Used(UsedPoolEntry(Value::CodeBlock(_))) = &selr.data[i_mark] {
-                    println!("Freeing code block at {:?} / {}", selr as *const Pool, i_mark);
-                }
-                */
+        for i_mark in 0..POOL_ENTRIES as usize {
+            if !selr.marked[i_mark] && selr.live[i_mark] {
                 selr.free_ref(u16::try_from(i_mark).unwrap(), debug)
             }
         }
@@ -263,7 +279,7 @@ impl Pool {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Hash)]
 pub struct PoolPtr {
     pool: *mut Pool,
     idx: u16,
@@ -285,9 +301,11 @@ impl Deref for PoolPtr {
 
     fn deref(&self) -> &Self::Target {
         let pool = unsafe { &*self.pool };
-        match &pool.data[usize::from(self.idx)] {
-            PoolEntry::Used(u) => &u.0,
-            PoolEntry::Free(_) => panic!("dereferencing freed value at {:?}", self),
+        let idx = usize::from(self.idx);
+        if pool.live[idx] {
+            unsafe { &*pool.data[idx].as_ptr() }
+        } else {
+            panic!("dereferencing freed value at {:?}", self)
         }
     }
 }
@@ -308,9 +326,9 @@ impl PoolPtr {
 
 #[cfg(any(debug_assertions, test))]
 impl PoolPtr {
-    fn maybe_deref(&self) -> &PoolEntry {
+    fn is_free(&self) -> bool {
         let pool = unsafe { &*self.pool };
-        &pool.data[usize::from(self.idx)]
+        !pool.live[usize::from(self.idx)]
     }
 
     pub fn ok(&self) -> bool {
@@ -338,10 +356,64 @@ pub trait Inventory {
     fn inventory(&self, v: &mut PtrVec);
 }
 
-#[derive(Debug)]
-struct Heap {
+/// Which pool family an allocation is routed to. Every entry still stores a full `Value` (see
+/// the "Not done" note on [`PoolClass::of`]), so this buys locality, not smaller entries: pair
+/// traversal - the hot path in `gc`'s mark phase, which walks `Pair` chains one `inventory` call
+/// at a time - stays within pools that are never interleaved with bulkier values like strings or
+/// vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PoolClass {
+    /// Small, frequently-traced values: pairs, closures (`Lambda`) and primitives, and the
+    /// numeric/constant leaf types that terminate most list walks.
+    Compact,
+    /// Everything else - strings, symbols, vectors, records, and other comparatively large or
+    /// rarely-walked variants.
+    Bulky,
+}
+
+impl PoolClass {
+    /// Picks the family a value's pool entry should live in.
+    ///
+    /// Not done: this only decides which `Vec<Pin<Box<Pool>>>` a `Value` is placed into: `Pool`'s
+    /// entries are still `MaybeUninit<Value>`, i.e. sized for the largest variant regardless of
+    /// family, since `Value` is one enum matched on throughout the crate (`pretty_print`, `eqv`,
+    /// `equal_seen`, every primitive...). Actually shrinking `Compact` entries would mean giving
+    /// `Pool` a second, narrower storage type and duplicating (or generalizing) all of that
+    /// matching code - out of scope for this chunk, which only delivers the locality half.
+    fn of(v: &Value) -> PoolClass {
+        match v {
+            Value::Undefined
+            | Value::Unspecific
+            | Value::EofObject
+            | Value::EmptyList
+            | Value::Real(_)
+            | Value::Integer(_)
+            | Value::Boolean(_)
+            | Value::Character(_)
+            | Value::Pair(_, _)
+            | Value::Lambda { .. }
+            | Value::Primitive(_) => PoolClass::Compact,
+            _ => PoolClass::Bulky,
+        }
+    }
+
+    fn idx(self) -> usize {
+        match self {
+            PoolClass::Compact => 0,
+            PoolClass::Bulky => 1,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolFamily {
     pools: Vec<Pin<Box<Pool>>>,
     full_pools: Vec<Pin<Box<Pool>>>,
+}
+
+#[derive(Debug)]
+struct Heap {
+    families: [PoolFamily; 2],
     roots: Vec<Option<PoolPtr>>,
     allocated_values: usize,
     next_gc: usize,
@@ -349,24 +421,40 @@ struct Heap {
     // `vms` basically acts as additional roots. There can be several rooted VMs at the same
     // time when `eval` is used.
     vms: Vec<*const Vm>,
+    // A hard ceiling on the number of pools this heap may hold across both families, or `None`
+    // for no limit. Once every existing pool is full and a forced `gc()` can't free any of them,
+    // `try_allocate` fails with `AllocError` rather than growing the heap further - useful for
+    // sandboxing untrusted Scheme with a deterministic memory bound.
+    max_pools: Option<usize>,
 }
 
 impl Default for Heap {
     fn default() -> Self {
         Heap {
-            pools: Vec::new(),
-            full_pools: Vec::new(),
+            families: [PoolFamily::default(), PoolFamily::default()],
             roots: Vec::new(),
             allocated_values: 0,
             next_gc: FIRST_GC,
             gc_mode: GcMode::Off,
             vms: Vec::new(),
+            max_pools: None,
         }
     }
 }
 
 impl Heap {
-    fn allocate(&mut self, v: Value) -> PoolPtr {
+    fn pool_count(&self) -> usize {
+        self.families
+            .iter()
+            .map(|f| f.pools.len() + f.full_pools.len())
+            .sum()
+    }
+
+    fn at_pool_cap(&self) -> bool {
+        self.max_pools.map_or(false, |max| self.pool_count() >= max)
+    }
+
+    fn try_allocate(&mut self, v: Value) -> Result<PoolPtr, AllocError> {
         if self.gc_mode == GcMode::DebugHeavy {
             self.gc();
         } else if self.gc_mode.is_normal() && self.allocated_values > self.next_gc {
@@ -376,29 +464,45 @@ impl Heap {
             self.next_gc = (self.allocated_values as f32 * GC_GROWTH) as usize;
         }
 
-        if self.pools.is_empty() {
-            self.pools.push(Pool::new())
+        let idx = PoolClass::of(&v).idx();
+
+        if self.families[idx].pools.is_empty() {
+            if self.at_pool_cap() {
+                // Try to scavenge a free pool before giving up.
+                self.gc();
+            }
+            if self.families[idx].pools.is_empty() {
+                if self.at_pool_cap() {
+                    return Err(AllocError);
+                }
+                self.families[idx].pools.push(Pool::new())
+            }
         }
 
-        let last_pool = self.pools.last_mut().expect("no free pools");
+        let family = &mut self.families[idx];
+        let last_pool = family.pools.last_mut().expect("no free pools");
         let ptr = last_pool
             .as_mut()
             .allocate(v)
             .expect("full pool in non-full list");
         let last_pool = &*last_pool;
         if last_pool.allocated == POOL_ENTRIES {
-            let pool = self.pools.pop().unwrap();
-            self.full_pools.push(pool);
+            let pool = family.pools.pop().unwrap();
+            family.full_pools.push(pool);
         }
         self.allocated_values += 1;
         // println!("Allocated {:?} for {:?}", ptr, *ptr);
-        ptr
+        Ok(ptr)
+    }
+
+    fn allocate(&mut self, v: Value) -> PoolPtr {
+        self.try_allocate(v).expect("out of memory")
     }
 
     fn root(&mut self, p: PoolPtr) -> usize {
         #[cfg(debug_assertions)]
         {
-            debug_assert!(!p.maybe_deref().is_free(), "rooting freed pointer {:?}", p,);
+            debug_assert!(!p.is_free(), "rooting freed pointer {:?}", p,);
         }
         let empty = self
             .roots
@@ -445,19 +549,22 @@ impl Heap {
                 (*root).inventory(&mut stack);
             }
         }
-        for pool in self.pools.iter_mut() {
-            self.allocated_values -= usize::from(pool.as_mut().sweep(self.gc_mode.is_debug()));
-        }
-        for pool in self.full_pools.iter_mut() {
-            self.allocated_values -= usize::from(pool.as_mut().sweep(self.gc_mode.is_debug()));
-        }
-        for i_pool in (0..self.full_pools.len()).rev() {
-            if self.full_pools[i_pool].allocated != POOL_ENTRIES {
-                let pool = self.full_pools.swap_remove(i_pool);
-                self.pools.push(pool);
+        let debug = self.gc_mode.is_debug();
+        for family in self.families.iter_mut() {
+            for pool in family.pools.iter_mut() {
+                self.allocated_values -= usize::from(pool.as_mut().sweep(debug));
+            }
+            for pool in family.full_pools.iter_mut() {
+                self.allocated_values -= usize::from(pool.as_mut().sweep(debug));
+            }
+            for i_pool in (0..family.full_pools.len()).rev() {
+                if family.full_pools[i_pool].allocated != POOL_ENTRIES {
+                    let pool = family.full_pools.swap_remove(i_pool);
+                    family.pools.push(pool);
+                }
             }
+            family.pools.sort_by_key(|p| p.allocated)
         }
-        self.pools.sort_by_key(|p| p.allocated)
     }
 }
 
@@ -471,14 +578,58 @@ impl Default for RHeap {
 
 impl RHeap {
     pub fn with_gc_mode(gc_mode: GcMode) -> RHeap {
+        Self::with_gc_mode_and_max_pools(gc_mode, None)
+    }
+
+    /// Same as [`RHeap::with_gc_mode`], but caps the heap at `max_pools` pools (each holding
+    /// `POOL_ENTRIES` values). Once every pool is full and a forced `gc()` can't free any of
+    /// them, [`RHeap::try_allocate`] fails with [`AllocError`] instead of growing the heap
+    /// without bound - useful for sandboxing untrusted Scheme.
+    pub fn with_gc_mode_and_max_pools(gc_mode: GcMode, max_pools: Option<usize>) -> RHeap {
         RHeap(Rc::new(UnsafeCell::new(Heap {
-            pools: vec![],
-            full_pools: vec![],
+            families: [PoolFamily::default(), PoolFamily::default()],
             roots: vec![],
             allocated_values: 0,
             next_gc: FIRST_GC,
             gc_mode,
             vms: vec![],
+            max_pools,
+        })))
+    }
+
+    /// Builds a heap whose entire pool budget is supplied up front as `pools`, rather than grown
+    /// lazily from the global allocator one `Pool::new()` at a time. `try_allocate` never creates
+    /// pools beyond the ones given here - once they're all full and a forced `gc()` can't free
+    /// any of them, allocation fails with [`AllocError`] instead of calling into the allocator
+    /// again, giving a hard, deterministic memory ceiling fixed at construction time.
+    ///
+    /// This is the capacity-bounding half of true static/`no_std` storage, not the whole thing:
+    /// `Pool` still reaches the global allocator for its `data`/`reserved`/`live`/`marked`
+    /// storage (the `MaybeUninit` array is stack-sized, but `BitBox` is heap-backed), and `Heap`
+    /// still keeps `pools`/`full_pools` in ordinary `Vec`s. Accepting a single caller-supplied
+    /// `&'static mut [MaybeUninit<Pool>]` slab and eliminating `Vec`/`BitBox` from `Pool` itself
+    /// would need `Pool`'s storage layout redesigned around fixed-size arrays, which is a larger
+    /// change than this constructor - left as follow-up work toward genuine `no_std` support.
+    ///
+    /// All of `pools` seed the `Compact` family (see [`PoolClass`]), since that's the
+    /// high-volume path pairs and numbers go through; the `Bulky` family still grows lazily from
+    /// the global allocator, subject to the same overall `max_pools` ceiling.
+    pub fn with_preallocated_pools(pools: Vec<Pin<Box<Pool>>>, gc_mode: GcMode) -> RHeap {
+        let max_pools = Some(pools.len());
+        RHeap(Rc::new(UnsafeCell::new(Heap {
+            families: [
+                PoolFamily {
+                    pools,
+                    full_pools: vec![],
+                },
+                PoolFamily::default(),
+            ],
+            roots: vec![],
+            allocated_values: 0,
+            next_gc: FIRST_GC,
+            gc_mode,
+            vms: vec![],
+            max_pools,
         })))
     }
 
@@ -486,6 +637,10 @@ impl RHeap {
         unsafe { &mut *self.0.get() }.allocate(v)
     }
 
+    pub fn try_allocate(&self, v: Value) -> Result<PoolPtr, AllocError> {
+        unsafe { &mut *self.0.get() }.try_allocate(v)
+    }
+
     pub fn root(&self, v: PoolPtr) -> RootPtr {
         let s = unsafe { &mut *self.0.get() };
         let idx = s.root(v);
@@ -506,12 +661,122 @@ impl RHeap {
         self.root(ptr)
     }
 
-    #[cfg(test)]
-    fn gc(&self) {
+    pub fn try_allocate_rooted(&self, v: Value) -> Result<RootPtr, AllocError> {
+        let ptr = self.try_allocate(v)?;
+        Ok(self.root(ptr))
+    }
+
+    pub fn gc(&self) {
         unsafe { &mut *self.0.get() }.gc()
     }
 }
 
+/// Abstracts the allocator operations `Arena` needs from its backing heap, so an alternative
+/// allocator can be swapped in without touching callers (the `cons`/`car`/`cdr` builtins and
+/// friends only ever go through `Arena`, never `RHeap` directly). [`RHeap`] is the normal
+/// mark-and-sweep implementation; [`RegionHeap`] is a bump allocator for callers that would
+/// rather pay for one bulk deallocation than have the GC repeatedly trace thousands of
+/// short-lived values.
+pub trait PoolProvider {
+    fn allocate(&self, v: Value) -> PoolPtr;
+    fn try_allocate(&self, v: Value) -> Result<PoolPtr, AllocError>;
+    fn allocate_rooted(&self, v: Value) -> RootPtr;
+    fn try_allocate_rooted(&self, v: Value) -> Result<RootPtr, AllocError>;
+    fn root(&self, v: PoolPtr) -> RootPtr;
+    fn root_vm(&self, vm: &Vm);
+    fn unroot_vm(&self);
+    fn gc(&self);
+}
+
+impl PoolProvider for RHeap {
+    fn allocate(&self, v: Value) -> PoolPtr {
+        RHeap::allocate(self, v)
+    }
+
+    fn try_allocate(&self, v: Value) -> Result<PoolPtr, AllocError> {
+        RHeap::try_allocate(self, v)
+    }
+
+    fn allocate_rooted(&self, v: Value) -> RootPtr {
+        RHeap::allocate_rooted(self, v)
+    }
+
+    fn try_allocate_rooted(&self, v: Value) -> Result<RootPtr, AllocError> {
+        RHeap::try_allocate_rooted(self, v)
+    }
+
+    fn root(&self, v: PoolPtr) -> RootPtr {
+        RHeap::root(self, v)
+    }
+
+    fn root_vm(&self, vm: &Vm) {
+        RHeap::root_vm(self, vm)
+    }
+
+    fn unroot_vm(&self) {
+        RHeap::unroot_vm(self)
+    }
+
+    fn gc(&self) {
+        RHeap::gc(self)
+    }
+}
+
+/// A bump/region allocator built on the same `Pool`/`RootPtr` machinery as [`RHeap`], except
+/// [`gc`](PoolProvider::gc) is a no-op: nothing is ever freed one value at a time, so callers
+/// that allocate a large, short-lived batch (macro expansion, a single `eval` call) can skip the
+/// cost of a mark-and-sweep pass that would just re-trace values they're about to throw away
+/// wholesale anyway. Memory is reclaimed all at once, when the `RegionHeap` (and every pool it
+/// holds) is dropped.
+///
+/// This is deliberately *not* wired into `Arena` yet - `Arena` stores its heap as a concrete
+/// `RHeap` field, and every primitive in `src/primitives/` takes `&Arena` directly, so making
+/// `Arena` generic over `PoolProvider` would ripple through dozens of call sites for a change
+/// that can't be verified without a compiler in this environment. This type is the allocator
+/// half of that future change, ready to be plugged in once `Arena<P>` lands.
+pub struct RegionHeap(RHeap);
+
+impl Default for RegionHeap {
+    fn default() -> Self {
+        RegionHeap(RHeap::with_gc_mode(GcMode::Off))
+    }
+}
+
+impl PoolProvider for RegionHeap {
+    fn allocate(&self, v: Value) -> PoolPtr {
+        self.0.allocate(v)
+    }
+
+    fn try_allocate(&self, v: Value) -> Result<PoolPtr, AllocError> {
+        self.0.try_allocate(v)
+    }
+
+    fn allocate_rooted(&self, v: Value) -> RootPtr {
+        self.0.allocate_rooted(v)
+    }
+
+    fn try_allocate_rooted(&self, v: Value) -> Result<RootPtr, AllocError> {
+        self.0.try_allocate_rooted(v)
+    }
+
+    fn root(&self, v: PoolPtr) -> RootPtr {
+        self.0.root(v)
+    }
+
+    fn root_vm(&self, vm: &Vm) {
+        self.0.root_vm(vm)
+    }
+
+    fn unroot_vm(&self) {
+        self.0.unroot_vm()
+    }
+
+    fn gc(&self) {
+        // A region never frees values individually - see this type's doc comment. Everything is
+        // reclaimed in one shot when `self`, and the pools it owns, are dropped.
+    }
+}
+
 /// A rooted pointer. Will unroot itself when dropped.
 #[derive(Debug)]
 pub struct RootPtr {
@@ -624,7 +889,7 @@ mod test {
         let val_ptr = heap.allocate(val.clone());
         assert_eq!(*val_ptr, val);
         heap.gc();
-        assert!(val_ptr.maybe_deref().is_free());
+        assert!(val_ptr.is_free());
     }
 
     #[test]
@@ -640,6 +905,6 @@ mod test {
         assert_eq!(*val_ptr, val);
         std::mem::drop(rooted_ptr);
         heap.gc();
-        assert!(val_ptr.maybe_deref().is_free());
+        assert!(val_ptr.is_free());
     }
 }
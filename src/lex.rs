@@ -12,7 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fmt::{Display, Formatter};
 use std::str::Chars;
 
 use num_bigint::{BigInt, Sign};
@@ -45,6 +44,12 @@ pub enum Token {
     QuasiQuote,
     Unquote,
     UnquoteSplicing,
+    /// `#n=`, introducing a datum label for shared/cyclic structure.
+    DatumLabelDefinition(u32),
+    /// `#n#`, referring back to a previously-defined datum label.
+    DatumLabelReference(u32),
+    /// `#;`, a datum comment: causes [`segment`] to drop the following complete datum.
+    DatumComment,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -54,7 +59,7 @@ pub struct PositionedToken {
 }
 
 impl PositionedToken {
-    fn single_char(pos: CodePosition, token: Token) -> Self {
+    fn single_char(pos: Span, token: Token) -> Self {
         Self {
             range: CodeRange {
                 start: pos,
@@ -64,7 +69,7 @@ impl PositionedToken {
         }
     }
 
-    fn new(start: CodePosition, end: CodePosition, token: Token) -> Self {
+    fn new(start: Span, end: Span, token: Token) -> Self {
         Self {
             range: CodeRange { start, end },
             token,
@@ -72,16 +77,40 @@ impl PositionedToken {
     }
 }
 
-/// (line, char)
-pub type CodePosition = (u32, u32);
+/// A byte offset into a source text.
+///
+/// Lexing tracks only this raw offset; a (line, column) pair is resolved lazily, on demand, via
+/// [`Span::linecol_in`]. This avoids paying for line/column bookkeeping on every character lexed
+/// when most of them are never part of a reported error.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Span(pub usize);
+
+impl Span {
+    /// Resolves this offset into a 1-indexed (line, column) pair within `text`, by replaying
+    /// `text` from the start and counting characters and newlines up to the offset. `text` must
+    /// be the same source text the offset was produced from.
+    pub fn linecol_in(self, text: &str) -> (u32, u32) {
+        let mut line = 1u32;
+        let mut column = 0u32;
+        for c in text[..self.0.min(text.len())].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 0;
+            } else {
+                column += 1;
+            }
+        }
+        (line, column + 1)
+    }
+}
 
 /// Represents a range in source code.
 ///
 /// Start and end are inclusive
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct CodeRange {
-    pub start: CodePosition,
-    pub end: CodePosition,
+    pub start: Span,
+    pub end: Span,
 }
 
 impl CodeRange {
@@ -93,28 +122,27 @@ impl CodeRange {
         }
     }
 
-    fn from_pos(pos: CodePosition) -> CodeRange {
+    fn from_pos(pos: Span) -> CodeRange {
         CodeRange {
             start: pos,
             end: pos,
         }
     }
 
-    fn new(start: CodePosition, end: CodePosition) -> CodeRange {
+    fn new(start: Span, end: Span) -> CodeRange {
         CodeRange { start, end }
     }
-}
 
-impl Display for CodeRange {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    /// Renders this range as `line:col` (or `line:col->line:col` if it spans more than one
+    /// position), resolving positions against `text`. `text` must be the same source text the
+    /// range was produced from.
+    pub fn display_in(self, text: &str) -> String {
+        let (start_line, start_col) = self.start.linecol_in(text);
+        let (end_line, end_col) = self.end.linecol_in(text);
         if self.start == self.end {
-            write!(f, "{}:{}", self.start.0, self.start.1)
+            format!("{}:{}", start_line, start_col)
         } else {
-            write!(
-                f,
-                "{}:{}->{}:{}",
-                self.start.0, self.start.1, self.end.0, self.end.1
-            )
+            format!("{}:{}->{}:{}", start_line, start_col, end_line, end_col)
         }
     }
 }
@@ -164,8 +192,53 @@ impl NumValue {
 
 /// Turns an str slice into a vector of tokens, or fails with an error message.
 pub fn lex(input: &str) -> Result<Vec<PositionedToken>, LexError> {
-    let mut it = positioned_chars(input);
+    lex_at(input, 0)
+}
+
+/// Like [`lex`], but every token's [`Span`] is offset by `base`. This is how
+/// [`source_map::SourceMap`] lexes a registered file into tokens whose spans land inside the
+/// global range it reserved for that file, so they can later be resolved back to it.
+pub fn lex_at(input: &str, base: usize) -> Result<Vec<PositionedToken>, LexError> {
+    let (tokens, depth) = lex_resumable_at(input, base, 0)?;
+    if depth > 0 {
+        return LexError::new(
+            "unterminated block comment",
+            CodeRange::from_pos(Span(base + input.len())),
+        );
+    }
+    Ok(tokens)
+}
+
+/// Like [`lex`], but resumes lexing inside a `#| ... |#` block comment already `starting_depth`
+/// levels deep (0 if not inside one). Unlike [`lex`], running out of input while still inside a
+/// block comment is not an error here: it returns the tokens lexed so far together with the
+/// remaining nesting depth, so a REPL reading one line at a time can treat an open block comment
+/// like an open bracket -- prompting for a continuation line and resuming from that depth on the
+/// next call -- instead of failing outright.
+pub fn lex_resumable(
+    input: &str,
+    starting_depth: u32,
+) -> Result<(Vec<PositionedToken>, u32), LexError> {
+    lex_resumable_at(input, 0, starting_depth)
+}
+
+/// Combines [`lex_at`]'s offset and [`lex_resumable`]'s comment-depth resumption.
+pub fn lex_resumable_at(
+    input: &str,
+    base: usize,
+    starting_depth: u32,
+) -> Result<(Vec<PositionedToken>, u32), LexError> {
+    let mut it = positioned_chars_at(input, base);
     let mut tokens: Vec<PositionedToken> = Vec::new();
+
+    let mut depth = starting_depth;
+    if depth > 0 {
+        depth = consume_block_comment_body(&mut it, depth);
+        if depth > 0 {
+            return Ok((tokens, depth));
+        }
+    }
+
     loop {
         consume_leading_spaces(&mut it);
         if let Some(&(pos, c)) = it.peek() {
@@ -173,12 +246,18 @@ pub fn lex(input: &str) -> Result<Vec<PositionedToken>, LexError> {
                 consume_to_newline(&mut it);
                 continue;
             }
+            if c == '#' {
+                match consume_hash(&mut it)? {
+                    HashOutcome::Token(token) => tokens.push(token),
+                    HashOutcome::None => {}
+                    HashOutcome::OpenComment(depth) => return Ok((tokens, depth)),
+                }
+                continue;
+            }
             let token: PositionedToken = if c.is_digit(10) {
                 consume_number(&mut it)?
             } else if c == '.' || c == '-' || c == '+' {
                 consume_sign_or_dot(&mut it)?
-            } else if c == '#' {
-                consume_hash(&mut it)?
             } else if c == '"' {
                 consume_string(&mut it)?
             } else if c == '\'' {
@@ -212,21 +291,20 @@ pub fn lex(input: &str) -> Result<Vec<PositionedToken>, LexError> {
         }
     }
 
-    Ok(tokens)
+    Ok((tokens, 0))
 }
 
-/// An iterator over chars in a string + their position.
+/// An iterator over chars in a string + their byte offset.
 /// Effectively reimplements [`std::iter::Peekable`], but it's a pain to access the original
 /// iterator with `Peekable`, and `last_position` is a convenient little feature.
 struct PositionedChars<'a> {
-    line: u32,
-    column: u32,
+    offset: usize,
     characters: Chars<'a>,
     next_item: Option<PositionedChar>,
-    last_position: CodePosition,
+    last_position: Span,
 }
 
-type PositionedChar = (CodePosition, char);
+type PositionedChar = (Span, char);
 
 impl<'a> Iterator for PositionedChars<'a> {
     type Item = PositionedChar;
@@ -247,16 +325,10 @@ impl<'a> PositionedChars<'a> {
                 Some(nxt)
             }
             None => {
+                let pos = Span(self.offset);
                 let next_char = self.characters.next()?;
-                if next_char == '\n' {
-                    let (line, column) = (self.line, self.column + 1);
-                    self.line += 1;
-                    self.column = 0;
-                    Some(((line, column), next_char))
-                } else {
-                    self.column += 1;
-                    Some(((self.line, self.column), next_char))
-                }
+                self.offset += next_char.len_utf8();
+                Some((pos, next_char))
             }
         }
     }
@@ -272,18 +344,21 @@ impl<'a> PositionedChars<'a> {
     }
 
     /// Returns the position returned in the last call to `next`.
-    fn last_position(&self) -> CodePosition {
+    fn last_position(&self) -> Span {
         self.last_position
     }
 }
 
 fn positioned_chars(s: &str) -> PositionedChars {
+    positioned_chars_at(s, 0)
+}
+
+fn positioned_chars_at(s: &str, base: usize) -> PositionedChars {
     PositionedChars {
-        line: 1,
-        column: 0,
+        offset: base,
         characters: s.chars(),
         next_item: None,
-        last_position: (1, 0),
+        last_position: Span(base),
     }
 }
 
@@ -313,14 +388,15 @@ fn consume_leading_spaces(it: &mut PositionedChars) {
 fn take_delimited_token(
     it: &mut PositionedChars,
     min: usize,
-) -> (CodePosition, CodePosition, Vec<char>) {
+) -> (Span, Span, Vec<char>) {
     let mut result: Vec<char> = Vec::new();
     let start_pos = if let Some(&(pos, _c)) = it.peek() {
         pos
     } else {
         // TODO this is kind of gross -- if we've reached the end of the stream, there's no code
         //      position to collect, so we should return Nones instead?
-        return ((0, 0), (0, 0), result);
+        let pos = it.last_position();
+        return (pos, pos, result);
     };
     while let Some(&(_pos, c)) = it.peek() {
         if result.len() < min || (c != '(' && c != ')' && !c.is_whitespace()) {
@@ -398,9 +474,74 @@ fn consume_sign_or_dot(it: &mut PositionedChars) -> Result<PositionedToken, LexE
     Ok(PositionedToken::new(start, end, Token::Symbol(token_s)))
 }
 
-fn consume_hash(it: &mut PositionedChars) -> Result<PositionedToken, LexError> {
+/// The outcome of lexing a `#`-prefixed construct.
+enum HashOutcome {
+    /// A regular token, e.g. `#t` or `#;`.
+    Token(PositionedToken),
+    /// A closed `#| ... |#` block comment, which produces no token.
+    None,
+    /// A `#| ... |#` block comment still `depth` levels deep when the input ran out. Not an
+    /// error: see [`lex_resumable_at`].
+    OpenComment(u32),
+}
+
+/// Handles a `#`-prefixed construct.
+fn consume_hash(it: &mut PositionedChars) -> Result<HashOutcome, LexError> {
     let (start_pos, first) = it.next().unwrap();
     debug_assert_eq!(first, '#');
+    match it.peek() {
+        Some(&(_, '|')) => {
+            it.next();
+            let depth = consume_block_comment_body(it, 1);
+            if depth > 0 {
+                Ok(HashOutcome::OpenComment(depth))
+            } else {
+                Ok(HashOutcome::None)
+            }
+        }
+        Some(&(end_pos, ';')) => {
+            it.next();
+            Ok(HashOutcome::Token(PositionedToken::new(
+                start_pos,
+                end_pos,
+                Token::DatumComment,
+            )))
+        }
+        _ => consume_hash_token(start_pos, it).map(HashOutcome::Token),
+    }
+}
+
+/// Consumes as much of a (possibly nested) `#|`-delimited block comment as is available, starting
+/// `depth` levels deep (the opening `#|` that produced the first level has already been
+/// consumed). Returns the remaining nesting depth: 0 once the comment fully closes, or positive if
+/// `it` runs out first -- the latter is not an error here, since the caller may be lexing a single
+/// line of a longer, still-incomplete REPL input; see [`lex_resumable_at`].
+fn consume_block_comment_body(it: &mut PositionedChars, mut depth: u32) -> u32 {
+    while depth > 0 {
+        match it.next() {
+            Some((_, '#')) => {
+                if let Some(&(_, '|')) = it.peek() {
+                    it.next();
+                    depth += 1;
+                }
+            }
+            Some((_, '|')) => {
+                if let Some(&(_, '#')) = it.peek() {
+                    it.next();
+                    depth -= 1;
+                }
+            }
+            Some(_) => {}
+            None => return depth,
+        }
+    }
+    depth
+}
+
+fn consume_hash_token(
+    start_pos: Span,
+    it: &mut PositionedChars,
+) -> Result<PositionedToken, LexError> {
     if let Some((pos, c)) = it.next() {
         match c {
             '\\' => {
@@ -410,9 +551,29 @@ fn consume_hash(it: &mut PositionedChars) -> Result<PositionedToken, LexError> {
                     1 => Ok(Token::Character(seq[0])),
                     _ => {
                         let descriptor: String = seq.into_iter().collect();
-                        match descriptor.to_lowercase().as_ref() {
+                        let lower = descriptor.to_lowercase();
+                        match lower.as_str() {
                             "newline" => Ok(Token::Character('\n')),
                             "space" => Ok(Token::Character(' ')),
+                            "alarm" => Ok(Token::Character('\x07')),
+                            "backspace" => Ok(Token::Character('\x08')),
+                            "delete" | "rubout" => Ok(Token::Character('\x7f')),
+                            "escape" | "altmode" => Ok(Token::Character('\x1b')),
+                            "null" | "nul" => Ok(Token::Character('\0')),
+                            "return" => Ok(Token::Character('\r')),
+                            "tab" => Ok(Token::Character('\t')),
+                            _ if lower.len() > 1
+                                && lower.starts_with('x')
+                                && lower[1..].chars().all(|c| c.is_ascii_hexdigit()) =>
+                            {
+                                u32::from_str_radix(&lower[1..], 16)
+                                    .ok()
+                                    .and_then(std::char::from_u32)
+                                    .map(Token::Character)
+                                    .ok_or_else(|| {
+                                        format!("invalid character code: `#\\{}`", descriptor)
+                                    })
+                            }
                             _ => Err(format!("unknown character descriptor: `{}`.", descriptor)),
                         }
                     }
@@ -453,6 +614,42 @@ fn consume_hash(it: &mut PositionedChars) -> Result<PositionedToken, LexError> {
                 })?;
                 Ok(PositionedToken::new(start_pos, end, Token::Num(n)))
             }
+            '0'..='9' => {
+                let mut digits = vec![c];
+                while let Some(&(_, d)) = it.peek() {
+                    if d.is_ascii_digit() {
+                        digits.push(d);
+                        it.next();
+                    } else {
+                        break;
+                    }
+                }
+                let digit_s: String = digits.into_iter().collect();
+                let label: u32 = digit_s.parse().map_err(|_| LexError {
+                    msg: format!("invalid datum label: `#{}`", digit_s),
+                    location: CodeRange::new(start_pos, it.last_position()),
+                })?;
+                match it.next() {
+                    Some((end, '=')) => Ok(PositionedToken::new(
+                        start_pos,
+                        end,
+                        Token::DatumLabelDefinition(label),
+                    )),
+                    Some((end, '#')) => Ok(PositionedToken::new(
+                        start_pos,
+                        end,
+                        Token::DatumLabelReference(label),
+                    )),
+                    Some((pos, c)) => LexError::new(
+                        format!("unexpected token form: `#{}{}...`", digit_s, c),
+                        CodeRange::new(start_pos, pos),
+                    ),
+                    None => LexError::new(
+                        "unexpected end of datum label".to_string(),
+                        CodeRange::from_pos(start_pos),
+                    ),
+                }
+            }
             _ => LexError::new(
                 format!("unknown token form: `#{}...`.", c),
                 CodeRange::new(start_pos, pos),
@@ -652,29 +849,51 @@ fn consume_string(it: &mut PositionedChars) -> Result<PositionedToken, LexError>
     debug_assert_eq!(first, '"');
 
     let mut found_end: bool = false;
-    let mut escaped: bool = false;
     let mut result: String = String::new();
-    for (pos, c) in &mut *it {
-        if escaped {
-            let r = match c {
-                'n' => '\n',
-                '"' => '"',
-                '\\' => '\\',
-                _ => {
+    loop {
+        let (pos, c) = match it.next() {
+            Some(x) => x,
+            None => break,
+        };
+        if c == '"' {
+            found_end = true;
+            break;
+        } else if c == '\\' {
+            match it.next() {
+                Some((_, 'a')) => result.push('\x07'),
+                Some((_, 'b')) => result.push('\x08'),
+                Some((_, 't')) => result.push('\t'),
+                Some((_, 'n')) => result.push('\n'),
+                Some((_, 'r')) => result.push('\r'),
+                Some((_, '"')) => result.push('"'),
+                Some((_, '\\')) => result.push('\\'),
+                Some((hex_pos, 'x')) | Some((hex_pos, 'X')) => {
+                    let (_, code) = consume_hex_escape(it, hex_pos)?;
+                    let ch = std::char::from_u32(code).ok_or_else(|| LexError {
+                        msg: format!("invalid character code in string escape: `{:x}`", code),
+                        location: CodeRange::from_pos(hex_pos),
+                    })?;
+                    result.push(ch);
+                }
+                Some((nl_pos, c)) if c == '\n' || c == '\r' || c == ' ' || c == '\t' => {
+                    consume_line_continuation(it, c, nl_pos)?
+                }
+                Some((escape_pos, c)) => {
                     return LexError::new(
                         format!("Invalid escape `\\{}`", c),
+                        CodeRange::from_pos(escape_pos),
+                    )
+                }
+                None => {
+                    return LexError::new(
+                        "unterminated string: trailing backslash".to_string(),
                         CodeRange::from_pos(pos),
                     )
                 }
-            };
-            result.push(r);
-        } else if c == '"' {
-            found_end = true;
-            break;
-        } else if c != '\\' {
+            }
+        } else {
             result.push(c);
         }
-        escaped = !escaped && c == '\\';
     }
 
     if found_end {
@@ -686,11 +905,82 @@ fn consume_string(it: &mut PositionedChars) -> Result<PositionedToken, LexError>
     } else {
         LexError::new(
             format!("unterminated string `\"{}`", result),
-            CodeRange::from_pos(it.last_position),
+            CodeRange::from_pos(it.last_position()),
         )
     }
 }
 
+/// Consumes the `<hex digits>;` tail of a `\x<hex>;` string escape, given the position of the `x`
+/// that was already consumed. Returns the position of the terminating `;` and the parsed scalar
+/// value.
+fn consume_hex_escape(
+    it: &mut PositionedChars,
+    start: Span,
+) -> Result<(Span, u32), LexError> {
+    let mut digits = String::new();
+    loop {
+        match it.next() {
+            Some((pos, ';')) if !digits.is_empty() => {
+                let code = u32::from_str_radix(&digits, 16).map_err(|_| LexError {
+                    msg: format!("invalid hex escape: `\\x{};`", digits),
+                    location: CodeRange::new(start, pos),
+                })?;
+                return Ok((pos, code));
+            }
+            Some((_, c)) if c.is_ascii_hexdigit() => digits.push(c),
+            Some((pos, c)) => {
+                return LexError::new(
+                    format!("invalid character in hex escape: `{}`", c),
+                    CodeRange::new(start, pos),
+                )
+            }
+            None => return LexError::new("unterminated hex escape", CodeRange::from_pos(start)),
+        }
+    }
+}
+
+/// Consumes a string's line-continuation escape (a backslash followed by optional intraline
+/// whitespace, a line ending, and more optional intraline whitespace), which is elided entirely
+/// rather than contributing any characters to the string. `first`/`first_pos` are the character
+/// and position already read immediately after the backslash.
+fn consume_line_continuation(
+    it: &mut PositionedChars,
+    first: char,
+    first_pos: Span,
+) -> Result<(), LexError> {
+    let mut c = first;
+    let mut pos = first_pos;
+    while c == ' ' || c == '\t' {
+        match it.next() {
+            Some((p, next_c)) => {
+                pos = p;
+                c = next_c;
+            }
+            None => {
+                return LexError::new(
+                    "unterminated string: trailing line continuation",
+                    CodeRange::from_pos(pos),
+                )
+            }
+        }
+    }
+    if c == '\r' {
+        // Allow a `\r\n` line ending, not just a lone `\n` or `\r`.
+        if let Some(&(_, '\n')) = it.peek() {
+            it.next();
+        }
+    } else if c != '\n' {
+        return LexError::new(
+            format!("invalid whitespace before line ending in string: `{}`", c),
+            CodeRange::from_pos(pos),
+        );
+    }
+    while let Some(&(_, ' ')) | Some(&(_, '\t')) = it.peek() {
+        it.next();
+    }
+    Ok(())
+}
+
 // This can be extended to support R7RS comments and other stuff
 pub enum BracketType {
     List,
@@ -704,9 +994,94 @@ pub struct SegmentationResult {
     pub depth: u64,
 }
 
-/// Splits a vector of token into a vector of vector of tokens, each of which represents a single
-/// expression that can be read.
-pub fn segment(toks: Vec<PositionedToken>) -> Result<SegmentationResult, LexError> {
+/// Drops every complete `#;` datum comment along with the datum it targets, so that later
+/// segmentation and reading never see either of them (`(a #;(b c) d)` lexes as if it had been
+/// written `(a d)`). If a trailing `#;` targets a datum that hasn't arrived yet -- the token
+/// stream ran out while looking for it -- stripping stops there, and everything from that `#;`
+/// onward (including whatever was already consumed while looking for its datum) is returned as an
+/// incomplete tail instead of failing, mirroring how [`segment`] leaves an unbalanced bracket's
+/// tail in `SegmentationResult::remainder`.
+fn strip_datum_comments(
+    toks: Vec<PositionedToken>,
+) -> (Vec<PositionedToken>, Vec<PositionedToken>) {
+    let mut iter = toks.into_iter();
+    let mut result = Vec::new();
+    while let Some(tok) = iter.next() {
+        if let Token::DatumComment = tok.token {
+            let mut consumed = vec![tok];
+            if !skip_one_datum(&mut iter, &mut consumed) {
+                consumed.extend(iter);
+                return (result, consumed);
+            }
+        } else {
+            result.push(tok);
+        }
+    }
+    (result, Vec::new())
+}
+
+/// Consumes the next complete datum from `iter` into `consumed`: an atom, a quote-like prefix
+/// together with its own target, or a balanced `(`/`#(`/`#u8(` group (itself possibly containing
+/// further datum comments). Returns `true` if a full datum was found, or `false` if `iter` ran out
+/// first -- see [`strip_datum_comments`].
+fn skip_one_datum<I: Iterator<Item = PositionedToken>>(
+    iter: &mut I,
+    consumed: &mut Vec<PositionedToken>,
+) -> bool {
+    let tok = match iter.next() {
+        Some(t) => t,
+        None => return false,
+    };
+    let token = tok.token.clone();
+    consumed.push(tok);
+
+    match token {
+        Token::DatumComment => {
+            // The nested comment discards its own target first; we still owe one more datum.
+            skip_one_datum(iter, consumed) && skip_one_datum(iter, consumed)
+        }
+        Token::Quote | Token::QuasiQuote | Token::Unquote | Token::UnquoteSplicing => {
+            skip_one_datum(iter, consumed)
+        }
+        Token::OpenParen | Token::OpenVector | Token::OpenByteVector => {
+            let mut depth = 1u32;
+            while depth > 0 {
+                match iter.next() {
+                    Some(t) => {
+                        let inner = t.token.clone();
+                        consumed.push(t);
+                        match inner {
+                            Token::OpenParen | Token::OpenVector | Token::OpenByteVector => {
+                                depth += 1
+                            }
+                            Token::ClosingParen => depth -= 1,
+                            Token::DatumComment => {
+                                if !skip_one_datum(iter, consumed) {
+                                    return false;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    None => return false,
+                }
+            }
+            true
+        }
+        _ => true,
+    }
+}
+
+/// Splits a vector of tokens into a vector of vector of tokens, each of which represents a single
+/// expression that can be read. `open_block_comment_depth` is the nesting depth of a `#| ... |#`
+/// block comment still open when `toks` was lexed (0 if none, see [`lex_resumable_at`]); it folds
+/// into `depth` the same way unbalanced brackets do, so a REPL that fed it in mid-comment knows to
+/// keep prompting for input. A trailing, not-yet-resolved `#;` does the same.
+pub fn segment(
+    toks: Vec<PositionedToken>,
+    open_block_comment_depth: u32,
+) -> Result<SegmentationResult, LexError> {
+    let (toks, pending_comment_toks) = strip_datum_comments(toks);
     let mut segments = Vec::new();
     let mut current_segment = Vec::new();
     let mut brackets = Vec::new();
@@ -748,10 +1123,16 @@ pub fn segment(toks: Vec<PositionedToken>) -> Result<SegmentationResult, LexErro
         }
     }
 
+    let mut depth = brackets.len() as u64 + u64::from(open_block_comment_depth);
+    if !pending_comment_toks.is_empty() {
+        current_segment.extend(pending_comment_toks);
+        depth += 1;
+    }
+
     Ok(SegmentationResult {
         segments,
         remainder: current_segment,
-        depth: brackets.len() as u64,
+        depth,
     })
 }
 
@@ -797,6 +1178,69 @@ mod tests {
         assert!(lex("#\\").is_err());
     }
 
+    #[test]
+    fn lex_char_named() {
+        assert_eq!(
+            unposition(lex("#\\alarm").unwrap()),
+            vec![Token::Character('\x07')]
+        );
+        assert_eq!(
+            unposition(lex("#\\backspace").unwrap()),
+            vec![Token::Character('\x08')]
+        );
+        assert_eq!(
+            unposition(lex("#\\delete").unwrap()),
+            vec![Token::Character('\x7f')]
+        );
+        assert_eq!(
+            unposition(lex("#\\Escape").unwrap()),
+            vec![Token::Character('\x1b')]
+        );
+        assert_eq!(
+            unposition(lex("#\\null").unwrap()),
+            vec![Token::Character('\0')]
+        );
+        assert_eq!(
+            unposition(lex("#\\return").unwrap()),
+            vec![Token::Character('\r')]
+        );
+        assert_eq!(
+            unposition(lex("#\\tab").unwrap()),
+            vec![Token::Character('\t')]
+        );
+        // Alternate names for the same control characters.
+        assert_eq!(
+            unposition(lex("#\\nul").unwrap()),
+            vec![Token::Character('\0')]
+        );
+        assert_eq!(
+            unposition(lex("#\\rubout").unwrap()),
+            vec![Token::Character('\x7f')]
+        );
+        assert_eq!(
+            unposition(lex("#\\altmode").unwrap()),
+            vec![Token::Character('\x1b')]
+        );
+    }
+
+    #[test]
+    fn lex_char_hex() {
+        assert_eq!(
+            unposition(lex("#\\x41").unwrap()),
+            vec![Token::Character('A')]
+        );
+        assert_eq!(
+            unposition(lex("#\\x3bb").unwrap()),
+            vec![Token::Character('\u{3bb}')]
+        );
+        assert_eq!(
+            unposition(lex("#\\x").unwrap()),
+            vec![Token::Character('x')]
+        );
+        assert!(lex("#\\xd800").is_err());
+        assert!(lex("#\\xfffffff").is_err());
+    }
+
     #[test]
     fn lex_int() {
         assert_eq!(unposition(lex("123").unwrap()), vec![int_tok(123)]);
@@ -808,6 +1252,34 @@ mod tests {
         assert!(lex("123x").is_err());
     }
 
+    #[test]
+    fn lex_numeric_prefixes() {
+        // Radix prefixes alone.
+        assert_eq!(unposition(lex("#b101").unwrap()), vec![int_tok(5)]);
+        assert_eq!(unposition(lex("#o17").unwrap()), vec![int_tok(15)]);
+        assert_eq!(unposition(lex("#d42").unwrap()), vec![int_tok(42)]);
+        assert_eq!(unposition(lex("#x2a").unwrap()), vec![int_tok(42)]);
+        // Exactness prefixes alone.
+        assert_eq!(unposition(lex("#e12").unwrap()), vec![int_tok(12)]);
+        assert_eq!(unposition(lex("#i12").unwrap()), vec![real_tok(12.0)]);
+        // Radix and exactness combine in either order.
+        assert_eq!(unposition(lex("#e#xff").unwrap()), vec![int_tok(255)]);
+        assert_eq!(unposition(lex("#x#eff").unwrap()), vec![int_tok(255)]);
+        assert_eq!(unposition(lex("#i#b101").unwrap()), vec![real_tok(5.0)]);
+        assert_eq!(unposition(lex("#b#i101").unwrap()), vec![real_tok(5.0)]);
+        // Rationals are accepted in non-decimal radices too.
+        assert_eq!(
+            unposition(lex("#x1/a").unwrap()),
+            vec![Token::Num(NumValue::Rational(BigRational::new(
+                1.into(),
+                10.into()
+            )))]
+        );
+        // Digits outside the radix are rejected, same as `lex("12x3")` above.
+        assert!(lex("#b2").is_err());
+        assert!(lex("#o8").is_err());
+    }
+
     #[test]
     fn lex_float() {
         assert_eq!(
@@ -852,20 +1324,42 @@ mod tests {
         assert_eq!(unposition(lex("#t").unwrap()), vec![Token::Boolean(true)]);
     }
 
+    #[test]
+    fn lex_datum_label() {
+        assert_eq!(
+            unposition(lex("#0=(a . #0#)").unwrap()),
+            vec![
+                Token::DatumLabelDefinition(0),
+                Token::OpenParen,
+                Token::Symbol("a".to_string()),
+                Token::Dot,
+                Token::DatumLabelReference(0),
+                Token::ClosingParen,
+            ]
+        );
+        // Multi-digit labels are parsed as a single number, not just the first digit.
+        assert_eq!(
+            unposition(lex("#12=#t").unwrap()),
+            vec![Token::DatumLabelDefinition(12), Token::Boolean(true)]
+        );
+        assert!(lex("#1").is_err());
+        assert!(lex("#1x").is_err());
+    }
+
     #[test]
     fn lex_parens() {
         assert_eq!(
             lex("()").unwrap(),
             vec![
-                PositionedToken::new((1, 1), (1, 1), Token::OpenParen),
-                PositionedToken::new((1, 2), (1, 2), Token::ClosingParen),
+                PositionedToken::new(Span(0), Span(0), Token::OpenParen),
+                PositionedToken::new(Span(1), Span(1), Token::ClosingParen),
             ]
         );
         assert_eq!(
             lex(" (  ) ").unwrap(),
             vec![
-                PositionedToken::new((1, 2), (1, 2), Token::OpenParen),
-                PositionedToken::new((1, 5), (1, 5), Token::ClosingParen),
+                PositionedToken::new(Span(1), Span(1), Token::OpenParen),
+                PositionedToken::new(Span(4), Span(4), Token::ClosingParen),
             ]
         );
     }
@@ -883,16 +1377,16 @@ mod tests {
         assert_eq!(
             lex("  123   #f   ").unwrap(),
             vec![
-                PositionedToken::new((1, 3), (1, 5), int_tok(123)),
-                PositionedToken::new((1, 9), (1, 10), Token::Boolean(false)),
+                PositionedToken::new(Span(2), Span(4), int_tok(123)),
+                PositionedToken::new(Span(8), Span(9), Token::Boolean(false)),
             ]
         );
         assert_eq!(
             lex("123)456").unwrap(),
             vec![
-                PositionedToken::new((1, 1), (1, 3), int_tok(123)),
-                PositionedToken::single_char((1, 4), Token::ClosingParen),
-                PositionedToken::new((1, 5), (1, 7), int_tok(456)),
+                PositionedToken::new(Span(0), Span(2), int_tok(123)),
+                PositionedToken::single_char(Span(3), Token::ClosingParen),
+                PositionedToken::new(Span(4), Span(6), int_tok(456)),
             ]
         );
     }
@@ -902,35 +1396,35 @@ mod tests {
         assert_eq!(
             lex("abc").unwrap(),
             vec![PositionedToken::new(
-                (1, 1),
-                (1, 3),
+                Span(0),
+                Span(2),
                 Token::Symbol("abc".to_string())
             )]
         );
         assert_eq!(
             lex("<=").unwrap(),
             vec![PositionedToken::new(
-                (1, 1),
-                (1, 2),
+                Span(0),
+                Span(1),
                 Token::Symbol("<=".to_string())
             )]
         );
         assert_eq!(
             lex("+").unwrap(),
             vec![PositionedToken::single_char(
-                (1, 1),
+                Span(0),
                 Token::Symbol("+".to_string())
             )]
         );
         assert_eq!(
             lex(".").unwrap(),
-            vec![PositionedToken::single_char((1, 1), Token::Dot)]
+            vec![PositionedToken::single_char(Span(0), Token::Dot)]
         );
         assert_eq!(
             lex("...").unwrap(),
             vec![PositionedToken::new(
-                (1, 1),
-                (1, 3),
+                Span(0),
+                Span(2),
                 Token::Symbol("...".to_string())
             )]
         );
@@ -941,42 +1435,84 @@ mod tests {
         assert_eq!(
             lex("\"abcdef\"").unwrap(),
             vec![PositionedToken::new(
-                (1, 1),
-                (1, 8),
+                Span(0),
+                Span(7),
                 Token::String("abcdef".to_string())
             )]
         );
         assert_eq!(
             lex("\"abc\\\"def\"").unwrap(),
             vec![PositionedToken::new(
-                (1, 1),
-                (1, 10),
+                Span(0),
+                Span(9),
                 Token::String("abc\"def".to_string())
             )]
         );
         assert_eq!(
             lex("\"abc\\\\def\"").unwrap(),
             vec![PositionedToken::new(
-                (1, 1),
-                (1, 10),
+                Span(0),
+                Span(9),
                 Token::String("abc\\def".to_string())
             )]
         );
         assert_eq!(
             lex("\"abc\\ndef\"").unwrap(),
             vec![PositionedToken::new(
-                (1, 1),
-                (1, 10),
+                Span(0),
+                Span(9),
                 Token::String("abc\ndef".to_string())
             )]
         );
     }
 
+    #[test]
+    fn lex_string_escapes() {
+        assert_eq!(
+            unposition(lex("\"\\a\\b\\t\\r\"").unwrap()),
+            vec![Token::String("\x07\x08\t\r".to_string())]
+        );
+        assert_eq!(
+            unposition(lex("\"\\x41;bc\"").unwrap()),
+            vec![Token::String("Abc".to_string())]
+        );
+        assert_eq!(
+            unposition(lex("\"\\x3bb;\"").unwrap()),
+            vec![Token::String("\u{3bb}".to_string())]
+        );
+        assert!(lex("\"\\x;\"").is_err());
+        assert!(lex("\"\\xzz;\"").is_err());
+        assert!(lex("\"\\xffffffff;\"").is_err());
+        assert_eq!(
+            unposition(lex("\"abc\\\n   def\"").unwrap()),
+            vec![Token::String("abcdef".to_string())]
+        );
+        assert_eq!(
+            unposition(lex("\"abc\\   \n   def\"").unwrap()),
+            vec![Token::String("abcdef".to_string())]
+        );
+        assert!(lex("\"\\q\"").is_err());
+    }
+
+    #[test]
+    fn lex_string_line_continuation_crlf() {
+        // A `\r\n` (or lone `\r`) line ending in a line continuation is folded away just like
+        // `\n`, not just the Unix convention.
+        assert_eq!(
+            unposition(lex("\"abc\\\r\n   def\"").unwrap()),
+            vec![Token::String("abcdef".to_string())]
+        );
+        assert_eq!(
+            unposition(lex("\"abc\\\rdef\"").unwrap()),
+            vec![Token::String("abcdef".to_string())]
+        );
+    }
+
     #[test]
     fn lex_spaces() {
         assert_eq!(
             lex("  123  ").unwrap(),
-            vec![PositionedToken::new((1, 3), (1, 5), int_tok(123))]
+            vec![PositionedToken::new(Span(2), Span(4), int_tok(123))]
         );
     }
 
@@ -998,20 +1534,165 @@ mod tests {
         )
     }
 
+    #[test]
+    fn lex_block_comment() {
+        assert_eq!(
+            unposition(lex("1 #| a comment |# 2").unwrap()),
+            vec![int_tok(1), int_tok(2)]
+        );
+        assert_eq!(
+            unposition(lex("1 #| outer #| inner |# still outer |# 2").unwrap()),
+            vec![int_tok(1), int_tok(2)]
+        );
+        assert!(lex("#| unterminated").is_err());
+        assert!(lex("#| outer #| inner |# still unterminated").is_err());
+    }
+
+    #[test]
+    fn lex_resumable_block_comment() {
+        // A comment left open at the end of one line resumes, rather than erroring, when the
+        // caller (e.g. the REPL) feeds the next line in with the returned depth.
+        let (tokens, depth) = lex_resumable("1 #| unfinished", 0).unwrap();
+        assert_eq!(unposition(tokens), vec![int_tok(1)]);
+        assert_eq!(depth, 1);
+
+        let (tokens, depth) = lex_resumable(" still going", depth).unwrap();
+        assert!(tokens.is_empty());
+        assert_eq!(depth, 1);
+
+        let (tokens, depth) = lex_resumable(" |# 2", depth).unwrap();
+        assert_eq!(unposition(tokens), vec![int_tok(2)]);
+        assert_eq!(depth, 0);
+
+        // Nesting depth survives a resume too.
+        let (tokens, depth) = lex_resumable("#| outer #| inner", 0).unwrap();
+        assert!(tokens.is_empty());
+        assert_eq!(depth, 2);
+        let (tokens, depth) = lex_resumable("|# still outer |#", depth).unwrap();
+        assert!(tokens.is_empty());
+        assert_eq!(depth, 0);
+    }
+
+    #[test]
+    fn lex_datum_comment() {
+        assert_eq!(
+            unposition(lex("#;1 2").unwrap()),
+            vec![Token::DatumComment, int_tok(1), int_tok(2)]
+        );
+    }
+
+    #[test]
+    fn segment_datum_comment() {
+        let drop_comments = |s: &str| {
+            unposition(
+                segment(lex(s).unwrap(), 0)
+                    .unwrap()
+                    .segments
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            drop_comments("(a #;(b c) d)"),
+            vec![
+                Token::OpenParen,
+                Token::Symbol("a".into()),
+                Token::Symbol("d".into()),
+                Token::ClosingParen,
+            ]
+        );
+        assert_eq!(
+            drop_comments("(a #;'b c)"),
+            vec![
+                Token::OpenParen,
+                Token::Symbol("a".into()),
+                Token::Symbol("c".into()),
+                Token::ClosingParen,
+            ]
+        );
+        assert_eq!(drop_comments("#;1 2"), vec![int_tok(2)]);
+    }
+
+    #[test]
+    fn segment_incomplete_datum_comment() {
+        // A trailing `#;` with no datum yet is incomplete, not an error -- like an unclosed
+        // bracket, it comes back whole in `remainder` so the REPL can ask for more input and
+        // retry once the datum arrives.
+        let result = segment(lex("#;").unwrap(), 0).unwrap();
+        assert!(result.segments.is_empty());
+        assert_eq!(unposition(result.remainder), vec![Token::DatumComment]);
+        assert_eq!(result.depth, 1);
+
+        let result = segment(lex("(a #;(b").unwrap(), 0).unwrap();
+        assert!(result.segments.is_empty());
+        assert_eq!(
+            unposition(result.remainder),
+            vec![
+                Token::OpenParen,
+                Token::Symbol("a".into()),
+                Token::DatumComment,
+                Token::OpenParen,
+                Token::Symbol("b".into()),
+            ]
+        );
+
+        // Once the rest arrives, re-segmenting the same (now complete) tokens resolves cleanly.
+        let result = segment(lex("(a #;(b c) d)").unwrap(), 0).unwrap();
+        assert_eq!(result.remainder, Vec::new());
+        assert_eq!(result.depth, 0);
+    }
+
+    #[test]
+    fn segment_open_block_comment_depth() {
+        // An open block comment folds into `depth` the same way an unclosed bracket does, even
+        // though the comment itself produced no tokens -- here the bracket is also still open, so
+        // the two add up.
+        let result = segment(lex("(a b").unwrap(), 1).unwrap();
+        assert!(result.segments.is_empty());
+        assert_eq!(result.depth, 2);
+        assert_eq!(
+            unposition(result.remainder),
+            vec![
+                Token::OpenParen,
+                Token::Symbol("a".into()),
+                Token::Symbol("b".into()),
+            ]
+        );
+    }
+
     #[test]
     fn test_char_iterator() {
         let mut it = positioned_chars("ab\ncdefghijklm");
-        assert_eq!(it.peek().cloned(), Some(((1, 1), 'a')));
-        assert_eq!(it.peek().cloned(), Some(((1, 1), 'a')));
-        assert_eq!(it.last_position(), (1, 0));
-        assert_eq!(it.next(), Some(((1, 1), 'a')));
-        assert_eq!(it.last_position(), (1, 1));
-        assert_eq!(it.peek().cloned(), Some(((1, 2), 'b')));
-        assert_eq!(it.last_position(), (1, 1));
-        assert_eq!(it.next(), Some(((1, 2), 'b')));
-        assert_eq!(it.next(), Some(((1, 3), '\n')));
-        assert_eq!(it.next(), Some(((2, 1), 'c')));
-        assert_eq!(it.peek().cloned(), Some(((2, 2), 'd')));
-        assert_eq!(it.last_position(), (2, 1));
+        assert_eq!(it.peek().cloned(), Some((Span(0), 'a')));
+        assert_eq!(it.peek().cloned(), Some((Span(0), 'a')));
+        assert_eq!(it.last_position(), Span(0));
+        assert_eq!(it.next(), Some((Span(0), 'a')));
+        assert_eq!(it.last_position(), Span(0));
+        assert_eq!(it.peek().cloned(), Some((Span(1), 'b')));
+        assert_eq!(it.last_position(), Span(0));
+        assert_eq!(it.next(), Some((Span(1), 'b')));
+        assert_eq!(it.next(), Some((Span(2), '\n')));
+        assert_eq!(it.next(), Some((Span(3), 'c')));
+        assert_eq!(it.peek().cloned(), Some((Span(4), 'd')));
+        assert_eq!(it.last_position(), Span(3));
+    }
+
+    #[test]
+    fn span_linecol_in() {
+        let text = "(foo\n  bar)\nbaz";
+        assert_eq!(Span(0).linecol_in(text), (1, 1));
+        assert_eq!(Span(4).linecol_in(text), (1, 5));
+        assert_eq!(Span(5).linecol_in(text), (2, 1));
+        assert_eq!(Span(7).linecol_in(text), (2, 3));
+        assert_eq!(Span(12).linecol_in(text), (3, 1));
+    }
+
+    #[test]
+    fn lex_at_offsets_spans() {
+        assert_eq!(
+            lex_at("123", 10).unwrap(),
+            vec![PositionedToken::new(Span(10), Span(12), int_tok(123))]
+        );
     }
 }
@@ -32,22 +32,35 @@ use std::sync::Arc;
 
 use arena::Arena;
 use ast::SyntaxElement;
+use diagnostic::Diagnostics;
 use environment::{ActivationFrame, ActivationFrameInfo, Environment, RcEnv};
-use error::{error_with_source, locate_message};
-use heap::{GcMode, RootPtr};
+use error::error_with_source;
+use heap::{GcMode, PoolPtr, RootPtr};
+use observer::{NullObserver, Observer};
+use primitives::{Port, Primitive, PrimitiveImplementation};
 use read::{NoReadResult, Reader};
+use util;
 use value::Value;
+use vm::ExecutionLimits;
 
 pub mod arena;
 pub mod ast;
+pub mod bytecode_cache;
 pub mod compile;
+pub mod diagnostic;
+pub mod disasm;
 pub mod environment;
 pub mod error;
 pub mod heap;
 pub mod lex;
+pub mod lint;
+pub mod observer;
 pub mod primitives;
 pub mod read;
 pub mod repl;
+pub mod snapshot;
+pub mod source_map;
+pub mod syntax_rules;
 pub mod util;
 pub mod value;
 pub mod vm;
@@ -74,8 +87,22 @@ pub struct Interpreter {
     // This is kept in an `Rc` because `File`s are referenced from errors, and errors are supposed
     // to have a permanent lifetime.
     files: Vec<Rc<File>>,
-    // Keep arena last! It must not be dropped before the RootPtr above.
+    /// The `with-exception-handler` stack, innermost last. Lives here rather than on `vm::Vm`
+    /// because a handler's dynamic extent (its `thunk`) runs as its own nested `vm::run` - see
+    /// `vm::run_thunk` - so anything a raise inside that nested run needs to see has to be shared
+    /// across `Vm` instances rather than local to one. See `vm::with_exception_handler` and
+    /// `vm::handle_error`.
+    //
+    // Must stay above `arena`, like every other RootPtr-holding field - see the note there.
+    handlers: RefCell<Vec<RootPtr>>,
+    // Keep arena last! It must not be dropped before the RootPtr fields above.
     pub arena: Arena,
+    /// Resource ceilings checked by `vm::run` and `Arena::try_insert` - see
+    /// `vm::ExecutionLimits` and `Interpreter::new_sandboxed`. Defaults to no limits at all.
+    limits: ExecutionLimits,
+    /// Hook called by `vm::run`'s instruction loop - see `observer::Observer`. Defaults to
+    /// `observer::NullObserver`, a no-op; install a different one with `Interpreter::set_observer`.
+    observer: RefCell<Box<dyn Observer>>,
 }
 
 // Okay this is another dirty hack. This serves to convince the Rust compiler not to automatically
@@ -88,12 +115,26 @@ impl Drop for Interpreter {
 
 impl Interpreter {
     pub fn new(gc_mode: GcMode) -> Self {
+        Self::new_internal(gc_mode, ExecutionLimits::default(), false)
+    }
+
+    /// Builds an interpreter suitable for evaluating untrusted Scheme, e.g. as a configuration or
+    /// expression language: `limits` caps instructions, allocations, and recursion depth (see
+    /// `vm::ExecutionLimits`), and every primitive with an ambient side effect - console/file I/O,
+    /// `load`, reading the wall clock - is left unregistered, so it's simply unbound rather than
+    /// merely discouraged.
+    pub fn new_sandboxed(gc_mode: GcMode, limits: ExecutionLimits) -> Self {
+        Self::new_internal(gc_mode, limits, true)
+    }
+
+    fn new_internal(gc_mode: GcMode, limits: ExecutionLimits, pure_only: bool) -> Self {
         let arena = Arena::with_gc_mode(gc_mode);
         let global_environment = Rc::new(RefCell::new(Environment::new(None)));
+        let stdout_port = arena.insert(Value::Port(Box::new(Port::Stdout)));
         let global_frame =
             arena.insert_rooted(Value::ActivationFrame(RefCell::new(ActivationFrame {
                 parent: None,
-                values: vec![arena.f, arena.f, arena.f],
+                values: vec![arena.f, arena.f, stdout_port],
             })));
         let afi = Rc::new(RefCell::new(ActivationFrameInfo {
             parent: None,
@@ -120,7 +161,13 @@ impl Interpreter {
                 .define("%current-output-port", &afi, true),
             OUTPUT_PORT_INDEX
         );
-        primitives::register_primitives(&arena, &global_environment, &afi, &global_frame);
+        primitives::register_primitives(
+            &arena,
+            &global_environment,
+            &afi,
+            &global_frame,
+            pure_only,
+        );
 
         Self {
             arena,
@@ -128,6 +175,9 @@ impl Interpreter {
             global_frame,
             interruptor: Arc::new(AtomicBool::new(false)),
             files: vec![],
+            limits,
+            handlers: RefCell::new(vec![]),
+            observer: RefCell::new(Box::new(NullObserver)),
         }
     }
 
@@ -135,16 +185,33 @@ impl Interpreter {
         Interruptor(self.interruptor.clone())
     }
 
+    /// Installs `observer` as the hook `vm::run` calls into for every instruction dispatched,
+    /// procedure call entered/left, and value raised - see `observer::Observer`. Replaces
+    /// whatever observer was previously installed; there is only ever one at a time.
+    pub fn set_observer(&self, observer: Box<dyn Observer>) {
+        *self.observer.borrow_mut() = observer;
+    }
+
+    /// Every identifier this interpreter knows about: names bound in the global environment,
+    /// plus every other symbol ever interned into the arena (locals, quoted symbols, etc). Used
+    /// to drive completion in `repl::PeroxideHelper` - not meant for anything that needs exactly
+    /// the set of currently-callable globals.
+    pub fn completion_candidates(&self) -> Vec<String> {
+        let mut candidates: Vec<String> = self.global_environment.borrow().bound_names();
+        candidates.extend(self.arena.known_symbols());
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
     pub fn initialize(&self, fname: &str) -> Result<(), String> {
         let contents = fs::read_to_string(fname).map_err(|e| e.to_string())?;
         let file = File::new(fname.to_string(), contents);
         let values = Reader::new(&self.arena, true, file.clone())
             .read_many(&file.source)
-            .map_err(|e| match e {
+            .map_err(|e| match &e {
                 NoReadResult::Nothing => "standard library: empty file".to_string(),
-                NoReadResult::ReadError { msg, locator } => {
-                    locate_message(&locator, "syntax", &msg)
-                }
+                _ => Diagnostics::new(&file).render(&e),
             })?;
         //println!("Values: {:?}", values);
         for v in values.into_iter() {
@@ -160,11 +227,9 @@ impl Interpreter {
         let file = self.files.last().unwrap();
         let values = Reader::new(&self.arena, true, file.clone())
             .read_many(&file.source)
-            .map_err(|e| match e {
+            .map_err(|e| match &e {
                 NoReadResult::Nothing => format!("{}: empty file", &file.name),
-                NoReadResult::ReadError { msg, locator } => {
-                    locate_message(&locator, "syntax", &msg)
-                }
+                _ => Diagnostics::new(&file).render(&e),
             })?;
         values
             .into_iter()
@@ -172,8 +237,36 @@ impl Interpreter {
             .collect()
     }
 
-    /// High-level interface to parse, compile, and run a value that's been read.
-    pub fn parse_compile_run(&self, read: RootPtr) -> Result<RootPtr, String> {
+    /// Statically checks `code` for unbound references and arity mismatches, without running (or
+    /// even parsing/compiling) any of it - see the `lint` module. Reads `code` as a full
+    /// compilation unit under the synthetic file name `<lint>`, the way `run` treats REPL input
+    /// as `<repl>`; a read error (unbalanced parens, a bad literal, ...) is reported as a single
+    /// diagnostic with no source span rather than failing outright, so a syntactically broken
+    /// file still gets *some* feedback instead of none.
+    pub fn lint(&self, code: &str) -> Vec<lint::Diagnostic> {
+        let file = File::new("<lint>", code.to_string());
+        let values = match Reader::new(&self.arena, true, file.clone()).read_many(&file.source) {
+            Ok(values) => values,
+            Err(e) => {
+                let msg = match &e {
+                    NoReadResult::Nothing => "empty file".to_string(),
+                    _ => Diagnostics::new(&file).render(&e),
+                };
+                return vec![lint::Diagnostic {
+                    kind: lint::DiagnosticKind::Unreadable,
+                    msg,
+                    source: None,
+                }];
+            }
+        };
+        let forms: Vec<PoolPtr> = values.iter().map(|v| v.ptr).collect();
+        lint::lint(&self.arena, &self.global_environment, &forms)
+    }
+
+    /// Parses one already-read form, registering any top-level `define`s it introduces in
+    /// `global_environment` and growing the global frame to match. The first half of
+    /// `parse_compile_run`, split out so `compile_to_file` can compile a form without running it.
+    fn parse_toplevel(&self, read: RootPtr) -> Result<SyntaxElement, String> {
         let global_af_info = Rc::new(RefCell::new(ActivationFrameInfo {
             parent: None,
             altitude: 0,
@@ -192,16 +285,194 @@ impl Interpreter {
             .get_activation_frame()
             .borrow_mut()
             .ensure_index(&self.arena, global_af_info.borrow().entries);
-        self.compile_run(&syntax_tree.element)
+        Ok(syntax_tree.element)
+    }
+
+    /// Compiles an already-parsed form into a `[toplevel]` code block, without running it. The
+    /// first half of `compile_run`, split out so `compile_to_file` can serialize the result
+    /// instead of running it immediately.
+    fn compile_toplevel(&self, syntax_tree: &SyntaxElement) -> PoolPtr {
+        compile::compile_toplevel(&self.arena, syntax_tree, self.global_environment.clone())
+    }
+
+    /// High-level interface to parse, compile, and run a value that's been read.
+    pub fn parse_compile_run(&self, read: RootPtr) -> Result<RootPtr, String> {
+        let syntax_tree = self.parse_toplevel(read)?;
+        self.compile_run(&syntax_tree)
     }
 
     pub fn compile_run(&self, syntax_tree: &SyntaxElement) -> Result<RootPtr, String> {
-        let code =
-            compile::compile_toplevel(&self.arena, syntax_tree, self.global_environment.clone());
-        let code = self.arena.root(code);
+        let code = self.arena.root(self.compile_toplevel(syntax_tree));
         vm::run(code, 0, self.global_frame.pp(), self)
             .map_err(|e| format!("runtime error: {}", e.pp().pretty_print()))
     }
+
+    /// Parses and compiles every form in `src_path`, without running any of it, and writes the
+    /// result to `cache_path` for a later `load_compiled` to replay - see `bytecode_cache`. Meant
+    /// for a build step that pre-compiles the standard library, so startup doesn't pay for
+    /// `ast::parse` (and the macro expansion it drives) on every run.
+    pub fn compile_to_file(&self, src_path: &str, cache_path: &str) -> Result<(), String> {
+        let contents = fs::read_to_string(src_path).map_err(|e| e.to_string())?;
+        let file = File::new(src_path.to_string(), contents.clone());
+        let values = Reader::new(&self.arena, true, file.clone())
+            .read_many(&file.source)
+            .map_err(|e| match &e {
+                NoReadResult::Nothing => format!("{}: empty file", src_path),
+                _ => Diagnostics::new(&file).render(&e),
+            })?;
+
+        let base_globals = self
+            .global_frame
+            .pp()
+            .get_activation_frame()
+            .borrow()
+            .values
+            .len();
+        let mut blocks = Vec::with_capacity(values.len());
+        for v in values.into_iter() {
+            let syntax_tree = self.parse_toplevel(v.ptr)?;
+            blocks.push(self.compile_toplevel(&syntax_tree));
+        }
+
+        let new_globals = self
+            .global_frame
+            .pp()
+            .get_activation_frame()
+            .borrow()
+            .values
+            .len();
+        let global_environment = self.global_environment.borrow();
+        let bindings = (base_globals..new_globals)
+            .map(|index| {
+                let name = global_environment.get_name(0, index);
+                let initialized = global_environment
+                    .get_variable(0, index)
+                    .map_or(true, |v| v.initialized);
+                (name, initialized)
+            })
+            .collect::<Vec<_>>();
+        drop(global_environment);
+
+        let bytes =
+            bytecode_cache::encode_image(&contents, base_globals, &bindings, &blocks, &self.arena)
+                .map_err(|e| e.to_string())?;
+        fs::write(cache_path, bytes).map_err(|e| e.to_string())
+    }
+
+    /// Loads the standard library from a bytecode cache previously written by `compile_to_file`,
+    /// falling back to parsing `src_path` from scratch via `initialize` if the cache is missing,
+    /// corrupt, was built by a different format version, or no longer matches `src_path` (its
+    /// source hash) or this interpreter's starting global frame (its set of primitives).
+    pub fn load_compiled(&mut self, cache_path: &str, src_path: &str) -> Result<(), String> {
+        let contents = match fs::read_to_string(src_path) {
+            Ok(c) => c,
+            Err(e) => return Err(e.to_string()),
+        };
+        if let Ok(bytes) = fs::read(cache_path) {
+            if self.try_load_compiled(&bytes, &contents).is_ok() {
+                return Ok(());
+            }
+        }
+        self.initialize(src_path)
+    }
+
+    /// Attempts to replay a decoded cache image; any failure (decode error, stale hash, mismatched
+    /// global frame) is the caller's cue to fall back to `initialize` instead.
+    fn try_load_compiled(&mut self, bytes: &[u8], contents: &str) -> Result<(), String> {
+        let image = bytecode_cache::decode_image(&self.arena, bytes).map_err(|e| e.to_string())?;
+
+        let base_globals = self
+            .global_frame
+            .pp()
+            .get_activation_frame()
+            .borrow()
+            .values
+            .len();
+        if image.source_hash != bytecode_cache::hash_source(contents)
+            || image.base_globals != base_globals
+        {
+            return Err("cache is stale or was built against a different interpreter".to_string());
+        }
+
+        if !image.bindings.is_empty() {
+            let afi = Rc::new(RefCell::new(ActivationFrameInfo {
+                parent: None,
+                altitude: 0,
+                entries: base_globals,
+            }));
+            for (name, initialized) in &image.bindings {
+                self.global_environment
+                    .borrow_mut()
+                    .define(name, &afi, *initialized);
+            }
+            self.global_frame
+                .pp()
+                .get_activation_frame()
+                .borrow_mut()
+                .ensure_index(&self.arena, afi.borrow().entries - 1);
+        }
+
+        for block in image.blocks {
+            let code = self.arena.root(block);
+            vm::run(code, 0, self.global_frame.pp(), self)
+                .map_err(|e| format!("runtime error: {}", e.pp().pretty_print()))?;
+        }
+        self.global_environment.borrow_mut().remove_special();
+        Ok(())
+    }
+
+    /// Registers a host-provided closure as a new global primitive, so an application embedding
+    /// `Interpreter` can add its own builtins - callbacks, bindings onto application state via
+    /// `Value::Foreign` - without forking this crate. `min_arity`/`max_arity` are enforced the
+    /// same way every compiled-in primitive enforces its own (see `util::check_len`); `None`
+    /// means "no bound" on that end. `f` only has to implement the ordinary primitive contract
+    /// (arguments in, a value or an error string out) - it never has to touch the arena.
+    ///
+    /// `Value::Primitive` stores a `&'static Primitive`, the same as every entry in
+    /// `primitives::PRIMITIVES`, so registering a host function leaks the small, fixed amount of
+    /// memory needed to give it that lifetime. Fine for the handful of builtins an embedder
+    /// registers at startup; not meant to be called in a loop.
+    pub fn register_fn(
+        &self,
+        name: &str,
+        min_arity: Option<usize>,
+        max_arity: Option<usize>,
+        f: impl Fn(&[RootPtr]) -> Result<RootPtr, String> + 'static,
+    ) {
+        let arena = &self.arena;
+        let implementation = move |arena: &Arena, args: &[PoolPtr]| -> Result<PoolPtr, String> {
+            util::check_len(args, min_arity, max_arity)?;
+            let rooted: Vec<RootPtr> = args.iter().map(|&p| arena.root(p)).collect();
+            f(&rooted).map(|r| r.ptr)
+        };
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        let primitive: &'static Primitive = Box::leak(Box::new(Primitive {
+            name,
+            implementation: PrimitiveImplementation::Host(Rc::new(implementation)),
+        }));
+
+        let afi = Rc::new(RefCell::new(ActivationFrameInfo {
+            parent: None,
+            altitude: 0,
+            entries: self
+                .global_frame
+                .pp()
+                .get_activation_frame()
+                .borrow()
+                .values
+                .len(),
+        }));
+        self.global_environment
+            .borrow_mut()
+            .define(primitive.name, &afi, true);
+        let ptr = arena.insert(Value::Primitive(primitive));
+        self.global_frame
+            .pp()
+            .get_activation_frame()
+            .borrow_mut()
+            .values
+            .push(ptr);
+    }
 }
 
 /// Represents an input file or other textual input source, such as a REPL segment.
@@ -218,4 +489,12 @@ impl File {
             source: source.into(),
         })
     }
+
+    /// Returns the text of the 1-indexed line `n`, or `None` if the file has fewer lines.
+    pub fn line(&self, n: u32) -> Option<&str> {
+        if n == 0 {
+            return None;
+        }
+        self.source.lines().nth((n - 1) as usize)
+    }
 }
@@ -0,0 +1,514 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A static-analysis pass over already-read source, run ahead of `ast::parse`/`vm::run`. Flags
+//! references to names that are neither lexically bound, defined anywhere in the compilation
+//! unit, nor a known global (primitive or standard-library) binding, as well as call sites whose
+//! argument count provably can't satisfy the callee's arity.
+//!
+//! This walks the raw datum tree directly rather than reusing `ast::parse`/`construct_reference`:
+//! those resolve a reference straight to an `(altitude, depth, index)` triple with no name kept
+//! around, which is exactly the information a diagnostic needs to report. The tradeoff is that
+//! this pass doesn't expand macros - a name bound by `define-syntax`/`let-syntax` is tracked by
+//! name only (see `Linter::macros`), and any call to it is left unchecked rather than guessing at
+//! what it expands to.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use arena::Arena;
+use environment::{self, ActivationFrameInfo, Environment, EnvironmentValue, RcAfi, RcEnv};
+use error::{self, Source};
+use heap::PoolPtr;
+use value::Value;
+
+/// One finding produced by [`lint`] (surfaced to callers via `Interpreter::lint`).
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub msg: String,
+    pub source: Option<Source>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A reference to a name that's neither lexically bound, defined anywhere in the linted code,
+    /// nor a known primitive/standard-library binding.
+    UnboundReference,
+    /// A call site whose argument count provably can't satisfy the callee's arity.
+    ArityMismatch,
+    /// `code` couldn't even be read as a full compilation unit - see `Interpreter::lint`.
+    Unreadable,
+}
+
+impl Diagnostic {
+    fn new(kind: DiagnosticKind, arena: &Arena, origin: PoolPtr, msg: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            kind,
+            msg: msg.into(),
+            source: arena.location_of(origin).map(Source::Code),
+        }
+    }
+
+    /// Renders this finding the same way a runtime error would be, but with a `warning:` header -
+    /// see `error::locate_warning`.
+    pub fn render(&self) -> String {
+        match &self.source {
+            Some(source) => error::locate_warning(source, &self.msg, error::ColorChoice::Plain),
+            None => error::format_warning(error::ColorChoice::Plain, &self.msg),
+        }
+    }
+}
+
+/// Walks `forms` (the already-read top-level forms of one compilation unit), reporting unbound
+/// references and arity mismatches found along the way. `global_environment` is consulted (but
+/// never mutated) so names already bound by the standard library or `register_primitives` count
+/// as known - see `Interpreter::lint`.
+pub fn lint(arena: &Arena, global_environment: &RcEnv, forms: &[PoolPtr]) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        arena,
+        macros: HashSet::new(),
+        arities: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    let scope = Scope {
+        env: Rc::new(RefCell::new(Environment::new(Some(global_environment.clone())))),
+        afi: Rc::new(RefCell::new(ActivationFrameInfo::default())),
+    };
+    linter.lint_toplevel_forms(&scope, forms);
+    linter.diagnostics
+}
+
+/// A lexical scope being walked: `env` mirrors `ast::parse`'s `RcEnv` (so `get_define_target`-
+/// style lookups Just Work through nested lambdas), `afi` its matching `ActivationFrameInfo`.
+struct Scope {
+    env: RcEnv,
+    afi: RcAfi,
+}
+
+struct Linter<'a> {
+    arena: &'a Arena,
+    /// Names bound via `define-syntax`/`let-syntax`/`letrec-syntax` anywhere in the unit being
+    /// linted. Not scoped to the body that introduced them (a deliberate simplification - see the
+    /// module doc comment): a macro name is visible everywhere once seen, and its call sites are
+    /// never arity-checked.
+    macros: HashSet<String>,
+    /// Statically-known arity for a variable, keyed by its `(altitude, index)` slot - populated
+    /// whenever a `define` (or `define`-with-formals sugar) is seen binding a literal `(lambda
+    /// formals ...)` shape.
+    arities: HashMap<(usize, usize), (usize, Option<usize>)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    /// Top-level forms get forward visibility into every `define` in the whole unit before any of
+    /// them are walked - not just a leading contiguous run, as `lint_body` enforces for a lambda's
+    /// internal defines. This matches real Scheme semantics: a top-level `(define (a) (b))` is
+    /// only ever *called* after the rest of the file - including a later `(define (b) ...)` - has
+    /// run, so referencing `b` from inside `a`'s body is legal regardless of definition order.
+    fn lint_toplevel_forms(&mut self, scope: &Scope, forms: &[PoolPtr]) {
+        self.collect_defined_names(scope, forms, false);
+        self.lint_statements(scope, forms);
+    }
+
+    /// A lambda (or `let`-style) body: only the leading contiguous run of `define`/`begin`/
+    /// `define-syntax`/`let-syntax`/`letrec-syntax` forms is pre-scanned, mirroring
+    /// `ast::collect_internal_defines` - R7RS only allows internal defines at the head of a body,
+    /// though mutual recursion among them is fine.
+    fn lint_body(&mut self, scope: &Scope, body: &[PoolPtr]) {
+        self.collect_defined_names(scope, body, true);
+        self.lint_statements(scope, body);
+    }
+
+    fn lint_statements(&mut self, scope: &Scope, forms: &[PoolPtr]) {
+        for form in forms {
+            self.lint_expr(scope, *form);
+        }
+    }
+
+    /// Registers every name a `define` (directly, or nested in a `begin`) would introduce into
+    /// `scope.env`, and every name a `define-syntax`/`let-syntax`/`letrec-syntax` would introduce
+    /// into `self.macros`, without evaluating anything. When `stop_at_first_non_define` is set,
+    /// stops at the first statement that isn't one of those forms (see `lint_body`); otherwise
+    /// scans every form regardless of what comes between them (see `lint_toplevel_forms`).
+    fn collect_defined_names(&mut self, scope: &Scope, forms: &[PoolPtr], stop_at_first_non_define: bool) {
+        for form in forms {
+            let (head, rest) = match &*form {
+                Value::Pair(car, cdr) => (car.get(), cdr.get()),
+                _ => {
+                    if stop_at_first_non_define {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            let name = match self.symbol_name(head) {
+                Some(s) => s,
+                None => {
+                    if stop_at_first_non_define {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            match name.as_str() {
+                "define" => {
+                    if let Some(items) = self.proper_list(rest) {
+                        if let Some((name, _)) = items.first().and_then(|t| self.split_define_target(*t)) {
+                            scope.env.borrow_mut().define_if_absent(&name, &scope.afi, true);
+                        }
+                    }
+                }
+                "begin" => {
+                    if let Some(items) = self.proper_list(rest) {
+                        self.collect_defined_names(scope, &items, false);
+                    }
+                }
+                "define-syntax" => {
+                    if let Some(items) = self.proper_list(rest) {
+                        if let Some(name) = items.first().and_then(|t| self.symbol_name(*t)) {
+                            self.macros.insert(name);
+                        }
+                    }
+                }
+                "let-syntax" | "letrec-syntax" => {
+                    if let Some(items) = self.proper_list(rest) {
+                        if let Some((bindings, body)) = items.split_first() {
+                            if let Some(binding_list) = self.proper_list(*bindings) {
+                                for binding in &binding_list {
+                                    if let Some(name) = self
+                                        .proper_list(*binding)
+                                        .and_then(|b| b.first().cloned())
+                                        .and_then(|n| self.symbol_name(n))
+                                    {
+                                        self.macros.insert(name);
+                                    }
+                                }
+                            }
+                            self.collect_defined_names(scope, body, false);
+                        }
+                    }
+                }
+                _ => {
+                    if stop_at_first_non_define {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn lint_expr(&mut self, scope: &Scope, expr: PoolPtr) {
+        match &*expr {
+            Value::Symbol(name) => self.check_reference(scope, name, expr),
+            Value::Pair(car, cdr) => self.lint_pair(scope, car.get(), cdr.get(), expr),
+            _ => {}
+        }
+    }
+
+    fn lint_pair(&mut self, scope: &Scope, head: PoolPtr, rest: PoolPtr, form: PoolPtr) {
+        if let Some(name) = self.symbol_name(head) {
+            if self.macros.contains(&name) {
+                // Can't statically know what this expands to - see the module doc comment.
+                return;
+            }
+            match name.as_str() {
+                "quote" => return,
+                "if" | "set!" => {
+                    self.lint_rest(scope, rest);
+                    return;
+                }
+                "begin" => {
+                    if let Some(body) = self.proper_list(rest) {
+                        self.lint_statements(scope, &body);
+                    }
+                    return;
+                }
+                "lambda" => {
+                    self.lint_lambda(scope, rest);
+                    return;
+                }
+                "define" => {
+                    self.lint_define(scope, rest);
+                    return;
+                }
+                "define-syntax" => return,
+                "let-syntax" | "letrec-syntax" => {
+                    if let Some(items) = self.proper_list(rest) {
+                        if let Some((_, body)) = items.split_first() {
+                            self.lint_statements(scope, body);
+                        }
+                    }
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.lint_application(scope, head, rest, form);
+    }
+
+    fn lint_rest(&mut self, scope: &Scope, rest: PoolPtr) {
+        if let Some(items) = self.proper_list(rest) {
+            self.lint_statements(scope, &items);
+        }
+    }
+
+    fn lint_lambda(&mut self, scope: &Scope, rest: PoolPtr) {
+        if let Some(items) = self.proper_list(rest) {
+            if let Some((formals, body)) = items.split_first() {
+                self.lint_lambda_like(scope, *formals, body);
+            }
+        }
+    }
+
+    /// Shared by a bare `(lambda formals body...)` and `(define (name . formals) body...)`
+    /// sugar: binds `formals` as fresh lexical variables one altitude down, then lints `body`
+    /// exactly as `lint_body` would any other body.
+    fn lint_lambda_like(&mut self, scope: &Scope, formals: PoolPtr, body: &[PoolPtr]) {
+        let afi = environment::extend_af_info(&scope.afi);
+        let env = Rc::new(RefCell::new(Environment::new(Some(scope.env.clone()))));
+        if let Some(names) = self.formals_names(formals) {
+            for name in &names {
+                env.borrow_mut().define(name, &afi, true);
+            }
+        }
+        let inner = Scope { env, afi };
+        self.lint_body(&inner, body);
+    }
+
+    fn lint_define(&mut self, scope: &Scope, rest: PoolPtr) {
+        let items = match self.proper_list(rest) {
+            Some(items) if !items.is_empty() => items,
+            _ => return,
+        };
+        let (name, sugar_formals) = match self.split_define_target(items[0]) {
+            Some(t) => t,
+            None => return,
+        };
+        let var = match scope.env.borrow().get(&name) {
+            Some(EnvironmentValue::Variable(v)) => Some(v),
+            _ => None,
+        };
+
+        if let Some(formals) = sugar_formals {
+            if let (Some(var), Some(arity)) = (&var, self.formals_arity(formals)) {
+                self.arities.insert((var.altitude, var.index), arity);
+            }
+            self.lint_lambda_like(scope, formals, &items[1..]);
+            return;
+        }
+
+        if let Some(value) = items.get(1).cloned() {
+            if let Some(var) = &var {
+                if let Some(arity) = self.lambda_formals(value).and_then(|f| self.formals_arity(f)) {
+                    self.arities.insert((var.altitude, var.index), arity);
+                }
+            }
+            self.lint_expr(scope, value);
+        }
+    }
+
+    fn lint_application(&mut self, scope: &Scope, head: PoolPtr, rest: PoolPtr, form: PoolPtr) {
+        let args = match self.proper_list(rest) {
+            Some(args) => args,
+            None => {
+                // Not a proper call shape (e.g. a dotted application) - outside this pass's scope.
+                self.lint_expr(scope, head);
+                return;
+            }
+        };
+
+        if let Some(name) = self.symbol_name(head) {
+            self.check_reference(scope, &name, head);
+            if let Some(arity) = self.known_arity(scope, &name) {
+                self.check_arity(arity, args.len(), form, &name);
+            }
+        } else if let Some(formals) = self.lambda_formals(head) {
+            // An immediately-invoked lambda: its arity is known exactly, no lookup needed.
+            if let Some(arity) = self.formals_arity(formals) {
+                self.check_arity(arity, args.len(), form, "lambda");
+            }
+            self.lint_expr(scope, head);
+        } else {
+            self.lint_expr(scope, head);
+        }
+
+        for arg in args {
+            self.lint_expr(scope, arg);
+        }
+    }
+
+    fn check_reference(&mut self, scope: &Scope, name: &str, origin: PoolPtr) {
+        if self.macros.contains(name) {
+            return;
+        }
+        if scope.env.borrow().get(name).is_none() {
+            self.diagnostics.push(Diagnostic::new(
+                DiagnosticKind::UnboundReference,
+                self.arena,
+                origin,
+                format!("reference to undefined variable `{}`", name),
+            ));
+        }
+    }
+
+    /// Looks up the statically-known arity for a call to `name`, if any: either a `define` seen
+    /// earlier in this unit whose value was a literal lambda, or - only when `name` resolves to a
+    /// toplevel binding (altitude 0), so a lambda parameter that merely happens to shadow a
+    /// primitive's name is never mistaken for the primitive itself - a curated entry from
+    /// `primitive_arity`.
+    fn known_arity(&self, scope: &Scope, name: &str) -> Option<(usize, Option<usize>)> {
+        match scope.env.borrow().get(name) {
+            Some(EnvironmentValue::Variable(v)) => self
+                .arities
+                .get(&(v.altitude, v.index))
+                .cloned()
+                .or_else(|| if v.altitude == 0 { primitive_arity(name) } else { None }),
+            _ => None,
+        }
+    }
+
+    fn check_arity(&mut self, arity: (usize, Option<usize>), got: usize, form: PoolPtr, name: &str) {
+        let (min, max) = arity;
+        let ok = got >= min && max.map_or(true, |m| got <= m);
+        if !ok {
+            self.diagnostics.push(Diagnostic::new(
+                DiagnosticKind::ArityMismatch,
+                self.arena,
+                form,
+                format!("`{}` expects {}, but got {}", name, describe_arity(min, max), got),
+            ));
+        }
+    }
+
+    fn symbol_name(&self, ptr: PoolPtr) -> Option<String> {
+        match &*ptr {
+            Value::Symbol(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// Walks a proper list, returning its elements - `None` if `ptr` isn't one (an atom, or a
+    /// dotted tail) since the forms this pass understands never legally end any other way.
+    fn proper_list(&self, ptr: PoolPtr) -> Option<Vec<PoolPtr>> {
+        let mut items = Vec::new();
+        let mut cur = ptr;
+        loop {
+            match &*cur {
+                Value::EmptyList => return Some(items),
+                Value::Pair(car, cdr) => {
+                    items.push(car.get());
+                    cur = cdr.get();
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Splits a `define` target into its name and, for `(define (name . formals) ...)` sugar, the
+    /// formals list - `None` for the `(name . formals)` part when `target` is a bare symbol.
+    fn split_define_target(&self, target: PoolPtr) -> Option<(String, Option<PoolPtr>)> {
+        match &*target {
+            Value::Symbol(s) => Some((s.clone(), None)),
+            Value::Pair(car, cdr) => self.symbol_name(car.get()).map(|s| (s, Some(cdr.get()))),
+            _ => None,
+        }
+    }
+
+    /// If `value` is a literal `(lambda formals ...)` form, returns `formals`.
+    fn lambda_formals(&self, value: PoolPtr) -> Option<PoolPtr> {
+        match &*value {
+            Value::Pair(car, cdr) => {
+                if self.symbol_name(car.get())?.as_str() == "lambda" {
+                    self.proper_list(cdr.get())?.first().cloned()
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// All parameter names a formals list binds, fixed and rest alike - used to extend the
+    /// lexical scope. `None` for a malformed shape (a non-symbol in fixed position).
+    fn formals_names(&self, formals: PoolPtr) -> Option<Vec<String>> {
+        let mut names = Vec::new();
+        let mut cur = formals;
+        loop {
+            match &*cur {
+                Value::EmptyList => return Some(names),
+                Value::Symbol(s) => {
+                    names.push(s.clone());
+                    return Some(names);
+                }
+                Value::Pair(car, cdr) => {
+                    names.push(self.symbol_name(car.get())?);
+                    cur = cdr.get();
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// The `(min, max)` arity a formals list requires - `max` is `None` for a dotted/bare-symbol
+    /// (variadic) rest parameter. `None` for a malformed shape.
+    fn formals_arity(&self, formals: PoolPtr) -> Option<(usize, Option<usize>)> {
+        let mut min = 0;
+        let mut cur = formals;
+        loop {
+            match &*cur {
+                Value::EmptyList => return Some((min, Some(min))),
+                Value::Symbol(_) => return Some((min, None)),
+                Value::Pair(car, _) if self.symbol_name(car.get()).is_none() => return None,
+                Value::Pair(_, cdr) => {
+                    min += 1;
+                    cur = cdr.get();
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+fn describe_arity(min: usize, max: Option<usize>) -> String {
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    match max {
+        Some(m) if m == min => format!("{} argument{}", min, plural(min)),
+        Some(m) => format!("between {} and {} arguments", min, m),
+        None => format!("at least {} argument{}", min, plural(min)),
+    }
+}
+
+/// A deliberately partial table of primitive arities - enough to catch the common mistakes
+/// (`cons` with one argument, `car` with two...) without trying to track every primitive in
+/// `primitives::PRIMITIVES`, which carries no arity metadata of its own. A primitive missing from
+/// this table is simply never arity-checked; it's still checked for bound-ness like any other
+/// name, since that comes from `global_environment` rather than this table.
+fn primitive_arity(name: &str) -> Option<(usize, Option<usize>)> {
+    Some(match name {
+        "car" | "cdr" | "not" | "null?" | "pair?" | "zero?" | "abs" | "string-length"
+        | "vector-length" | "symbol->string" | "string->symbol" | "char->integer"
+        | "integer->char" | "string->list"
+        | "list->string" | "vector->list" | "list->vector" => (1, Some(1)),
+        "string->number" | "number->string" => (1, Some(2)),
+        "cons" | "set-car!" | "set-cdr!" | "eq?" | "eqv?" | "equal?" | "vector-ref"
+        | "string-ref" | "expt" | "quotient" | "remainder" | "modulo" => (2, Some(2)),
+        "vector-set!" | "string-set!" => (3, Some(3)),
+        "+" | "*" | "list" | "vector" | "string-append" | "append" => (0, None),
+        "-" | "/" | "apply" | "max" | "min" => (1, None),
+        "=" | "<" | ">" | "<=" | ">=" => (2, None),
+        _ => return None,
+    })
+}
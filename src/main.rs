@@ -34,7 +34,6 @@ mod ast;
 mod compile;
 mod environment;
 mod lex;
-mod macroexpand;
 mod parse;
 mod primitives;
 mod repl;
@@ -0,0 +1,143 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A pluggable hook into `vm::run`'s instruction loop, for tracing, profiling, or coverage
+//! without forking the loop itself. `Interpreter::set_observer` installs one; `vm::run` calls
+//! its methods at the points named below. The default, installed by `Interpreter::new`, is
+//! [`NullObserver`], whose empty methods should inline away entirely.
+//!
+//! Every method takes `&self` rather than `&mut self`, since an `Observer` is called from deep
+//! inside a possibly-nested `vm::run` (see `vm::run_thunk`) holding only a shared `&Interpreter` -
+//! an observer that accumulates state, like [`Profiler`], does so through its own interior
+//! mutability instead.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use heap::PoolPtr;
+use vm::Instruction;
+
+pub trait Observer {
+    /// Called immediately before `instr` - the instruction at `pc` in `code_block` - is
+    /// dispatched.
+    fn on_instruction(&self, _code_block: PoolPtr, _pc: usize, _instr: Instruction) {}
+
+    /// Called when a procedure call begins executing `code_block`'s body, tail call or not.
+    fn on_enter_frame(&self, _code_block: PoolPtr) {}
+
+    /// Called when `Instruction::Return` leaves `code_block`'s body.
+    fn on_leave_frame(&self, _code_block: PoolPtr) {}
+
+    /// Called when `value` is raised, before a handler (if any) is dispatched.
+    fn on_raise(&self, _value: PoolPtr) {}
+}
+
+/// The default [`Observer`]: every hook is a no-op.
+pub struct NullObserver;
+
+impl Observer for NullObserver {}
+
+/// Counts, per code block name, how many instructions ran and how many times the block's body
+/// was entered - enough to tell which Scheme procedures a program spends its cycles in, without
+/// the overhead or platform dependency of a real sampling profiler.
+///
+/// Anonymous code blocks (`code.name == None`, e.g. most `lambda`s not bound by `define`) are all
+/// folded into a single `"[anonymous]"` bucket, matching the fallback `vm::backtrace` already
+/// uses for the same case.
+#[derive(Default)]
+pub struct Profiler {
+    counts: RefCell<HashMap<String, ProfileEntry>>,
+}
+
+#[derive(Default, Clone)]
+struct ProfileEntry {
+    instructions: usize,
+    calls: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    fn frame_name(code_block: PoolPtr) -> String {
+        code_block
+            .get_code_block()
+            .name
+            .clone()
+            .unwrap_or_else(|| "[anonymous]".into())
+    }
+
+    /// Renders accumulated counts as `name: N instructions, M calls` lines, sorted by instruction
+    /// count descending so the hottest code blocks come first.
+    pub fn report(&self) -> String {
+        let counts = self.counts.borrow();
+        let mut entries: Vec<(&String, &ProfileEntry)> = counts.iter().collect();
+        entries.sort_unstable_by(|a, b| b.1.instructions.cmp(&a.1.instructions));
+        let mut out = String::new();
+        for (name, entry) in entries {
+            writeln!(
+                out,
+                "{}: {} instructions, {} calls",
+                name, entry.instructions, entry.calls
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+impl Observer for Profiler {
+    fn on_instruction(&self, code_block: PoolPtr, _pc: usize, _instr: Instruction) {
+        let name = Profiler::frame_name(code_block);
+        self.counts.borrow_mut().entry(name).or_default().instructions += 1;
+    }
+
+    fn on_enter_frame(&self, code_block: PoolPtr) {
+        let name = Profiler::frame_name(code_block);
+        self.counts.borrow_mut().entry(name).or_default().calls += 1;
+    }
+}
+
+/// Dumps every dispatched instruction to an in-memory log - meant for debugging the interpreter
+/// itself (or a runaway Scheme program) by inspecting `Tracer::log` afterwards, rather than
+/// printing directly, since an `Observer` has no output port to write to.
+#[derive(Default)]
+pub struct Tracer {
+    log: RefCell<Vec<String>>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Tracer::default()
+    }
+
+    pub fn log(&self) -> Vec<String> {
+        self.log.borrow().clone()
+    }
+}
+
+impl Observer for Tracer {
+    fn on_instruction(&self, code_block: PoolPtr, pc: usize, instr: Instruction) {
+        let name = code_block
+            .get_code_block()
+            .name
+            .clone()
+            .unwrap_or_else(|| "[anonymous]".into());
+        self.log
+            .borrow_mut()
+            .push(format!("{} pc={} {:?}", name, pc, instr));
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// TODO: deduplicate code between here and vector.rs
+
+use std::cell::RefCell;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use arena::Arena;
+use heap::PoolPtr;
+use primitives::try_get_index;
+use util::check_len;
+use value::Value;
+
+pub fn bytevector_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    Ok(arena.insert(Value::Boolean(args[0].try_get_bytevector().is_some())))
+}
+
+pub fn make_bytevector(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(2))?;
+    let fill = match args.get(1) {
+        Some(v) => v
+            .try_get_integer()
+            .and_then(BigInt::to_u8)
+            .ok_or_else(|| format!("make-bytevector: invalid byte: {}", v.pretty_print()))?,
+        None => 0,
+    };
+    let l = try_get_index(args[0])?;
+    Ok(arena.insert(Value::ByteVector(RefCell::new(vec![fill; l]))))
+}
+
+pub fn bytevector_length(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let l = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("bytevector-length: not a bytevector: {}", args[0].pretty_print()))?
+        .borrow()
+        .len();
+    Ok(arena.insert(Value::Integer(BigInt::from(l))))
+}
+
+pub fn bytevector_u8_ref(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let borrowed = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("bytevector-u8-ref: not a bytevector: {}", args[0].pretty_print()))?
+        .borrow();
+    let idx = try_get_index(args[1])?;
+    borrowed
+        .get(idx)
+        .map(|b| arena.insert(Value::Integer(BigInt::from(*b))))
+        .ok_or_else(|| format!("bytevector-u8-ref: invalid index: {}", idx))
+}
+
+pub fn bytevector_u8_set_b(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(3), Some(3))?;
+    let mut borrowed = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("bytevector-u8-set!: not a bytevector: {}", args[0].pretty_print()))?
+        .borrow_mut();
+    let idx = try_get_index(args[1])?;
+    let byte = args[2]
+        .try_get_integer()
+        .and_then(BigInt::to_u8)
+        .ok_or_else(|| format!("bytevector-u8-set!: invalid byte: {}", args[2].pretty_print()))?;
+    if idx >= borrowed.len() {
+        return Err(format!("bytevector-u8-set!: invalid index: {}", idx));
+    }
+    borrowed[idx] = byte;
+    Ok(arena.unspecific)
+}
+
+pub fn bytevector_copy(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(3))?;
+    let borrowed = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("bytevector-copy: not a bytevector: {}", args[0].pretty_print()))?
+        .borrow();
+    let start = args.get(1).map(|v| try_get_index(*v)).unwrap_or(Ok(0))?;
+    let end = args
+        .get(2)
+        .map(|v| try_get_index(*v))
+        .unwrap_or(Ok(borrowed.len()))?;
+    if start > end || end > borrowed.len() {
+        return Err(format!(
+            "bytevector-copy: invalid indices: {}->{}",
+            start, end
+        ));
+    }
+    Ok(arena.insert(Value::ByteVector(RefCell::new(
+        borrowed[start..end].to_vec(),
+    ))))
+}
+
+pub fn bytevector_append(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    let mut result = Vec::new();
+    for arg in args {
+        let borrowed = arg
+            .try_get_bytevector()
+            .ok_or_else(|| format!("bytevector-append: not a bytevector: {}", arg.pretty_print()))?
+            .borrow();
+        result.extend_from_slice(&borrowed);
+    }
+    Ok(arena.insert(Value::ByteVector(RefCell::new(result))))
+}
@@ -70,6 +70,49 @@ pub fn char_numeric_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String
     Ok(arena.insert(Value::Boolean(arg.is_numeric())))
 }
 
+/// Unicode decimal-digit value of `c`, for digit scripts beyond the ASCII range that
+/// `char::to_digit(10)` understands. Each entry is the zero digit of a contiguous ten-code-point
+/// `Nd` block; the value is the offset of `c` from that zero.
+fn unicode_decimal_digit(c: char) -> Option<u32> {
+    const ZEROES: &[u32] = &[
+        0x0660, // Arabic-Indic
+        0x06f0, // Extended Arabic-Indic
+        0x07c0, // NKo
+        0x0966, // Devanagari
+        0x09e6, // Bengali
+        0x0a66, // Gurmukhi
+        0x0ae6, // Gujarati
+        0x0b66, // Oriya
+        0x0be6, // Tamil
+        0x0c66, // Telugu
+        0x0ce6, // Kannada
+        0x0d66, // Malayalam
+        0x0e50, // Thai
+        0x0ed0, // Lao
+        0x0f20, // Tibetan
+        0x1040, // Myanmar
+        0x17e0, // Khmer
+        0x1810, // Mongolian
+        0xff10, // Fullwidth
+    ];
+    let cp = c as u32;
+    ZEROES
+        .iter()
+        .find(|&&zero| cp >= zero && cp < zero + 10)
+        .map(|&zero| cp - zero)
+}
+
+/// Returns the decimal digit value of `c` as an `Integer`, or `#f` if `c` has no decimal-digit
+/// value. Tries `char::to_digit(10)` first (the ASCII range), then `unicode_decimal_digit` for
+/// other scripts' decimal digits.
+pub fn digit_value(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    let arg = get_char_arg(args, "digit-value")?;
+    match arg.to_digit(10).or_else(|| unicode_decimal_digit(arg)) {
+        Some(d) => Ok(arena.insert(Value::Integer(BigInt::from(d)))),
+        None => Ok(arena.f),
+    }
+}
+
 pub fn char_whitespace_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     let arg = get_char_arg(args, "char-whitespace?")?;
     Ok(arena.insert(Value::Boolean(arg.is_whitespace())))
@@ -108,3 +151,43 @@ pub fn char_downcase_unicode(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr,
     let arg = get_char_arg(args, "char-downcase-unicode")?;
     Ok(arena.insert(Value::String(RefCell::new(arg.to_lowercase().to_string()))))
 }
+
+/// Unicode simple case folding for a single scalar value: if `char::to_lowercase()` yields
+/// exactly one scalar, that's the fold; otherwise (e.g. 'İ', which lowercases to two code
+/// points) the char is left unchanged, since R7RS `char-foldcase` must return a single character.
+fn foldcase(c: char) -> char {
+    let mut lowercase = c.to_lowercase();
+    match (lowercase.next(), lowercase.next()) {
+        (Some(folded), None) => folded,
+        _ => c,
+    }
+}
+
+pub fn char_foldcase(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    let arg = get_char_arg(args, "char-foldcase")?;
+    Ok(arena.insert(Value::Character(foldcase(arg))))
+}
+
+fn to_char_vec(args: &[PoolPtr], prim_name: &str) -> Result<Vec<char>, String> {
+    args.iter()
+        .map(|v| {
+            v.try_get_character()
+                .ok_or_else(|| format!("{}: not a char: {}", prim_name, v.pretty_print()))
+        })
+        .collect()
+}
+
+macro_rules! char_cmp {
+    ($fun:ident, $w:ident, $e:expr) => {
+        pub fn $fun(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+            let chars = to_char_vec(args, stringify!($fun))?;
+            Ok(arena.insert(Value::Boolean(chars.as_slice().windows(2).all(|$w| $e))))
+        }
+    };
+}
+
+char_cmp!(char_ci_equal_p, w, foldcase(w[0]) == foldcase(w[1]));
+char_cmp!(char_ci_less_than_p, w, foldcase(w[0]) < foldcase(w[1]));
+char_cmp!(char_ci_greater_than_p, w, foldcase(w[0]) > foldcase(w[1]));
+char_cmp!(char_ci_less_equal_p, w, foldcase(w[0]) <= foldcase(w[1]));
+char_cmp!(char_ci_greater_equal_p, w, foldcase(w[0]) >= foldcase(w[1]));
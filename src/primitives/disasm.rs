@@ -0,0 +1,43 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `(disassemble proc)` / `(assemble text)` - thin wrappers around the actual codec in
+//! `crate::disasm`, which operates on `CodeBlock`/`&str` directly rather than `PoolPtr`s.
+
+use std::cell::RefCell;
+
+use arena::Arena;
+use disasm;
+use heap::PoolPtr;
+use util::check_len;
+use value::Value;
+
+pub fn disassemble(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let code = match &*args[0] {
+        Value::Lambda { code, .. } => code
+            .try_get_code_block()
+            .expect("a lambda's code is always a code block"),
+        _ => return Err(format!("disassemble: not a procedure: {}", args[0].pretty_print())),
+    };
+    Ok(arena.insert(Value::String(RefCell::new(disasm::disassemble(code)))))
+}
+
+pub fn assemble(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let text = args[0]
+        .try_get_string()
+        .ok_or_else(|| format!("assemble: not a string: {}", args[0].pretty_print()))?;
+    disasm::assemble(arena, &text.borrow())
+}
@@ -58,7 +58,7 @@
 //! eval scheme-report-environment null-environment
 //!
 //! input-port? output-port?
-//! current-input-port current-output-port
+//! current-input-port OK current-output-port
 //! open-input-file open-output-file
 //! close-input-port close-output-port
 //!
@@ -67,17 +67,23 @@
 //! load
 
 use std::fmt::{Debug, Error, Formatter};
+use std::rc::Rc;
 
 use arena::Arena;
 use environment::{RcAfi, RcEnv};
 use heap::{PoolPtr, RootPtr};
 use num_traits::ToPrimitive;
+use primitives::bytevector::*;
 use primitives::char::*;
+use primitives::disasm::*;
 use primitives::numeric::*;
 use primitives::object::*;
 use primitives::pair::*;
-pub use primitives::port::Port;
 use primitives::port::*;
+use primitives::record::*;
+pub use primitives::port::{
+    close_port, get_output_string, new_output_string_port, open_input_file, open_output_file, Port,
+};
 use primitives::string::*;
 use primitives::symbol::*;
 pub use primitives::syntactic_closure::SyntacticClosure;
@@ -85,11 +91,14 @@ use primitives::syntactic_closure::*;
 use primitives::vector::*;
 use value::Value;
 
+mod bytevector;
 mod char;
+mod disasm;
 mod numeric;
 mod object;
 mod pair;
 mod port;
+mod record;
 mod string;
 mod symbol;
 mod syntactic_closure;
@@ -104,7 +113,16 @@ macro_rules! simple_primitive {
     };
 }
 
-static PRIMITIVES: [Primitive; 125] = [
+macro_rules! io_primitive {
+    ($name:expr, $implementation:ident) => {
+        Primitive {
+            name: $name,
+            implementation: PrimitiveImplementation::Io($implementation),
+        }
+    };
+}
+
+static PRIMITIVES: [Primitive; 202] = [
     simple_primitive!("make-syntactic-closure", make_syntactic_closure),
     simple_primitive!("identifier=?", identifier_equal_p),
     simple_primitive!("identifier?", identifier_p),
@@ -123,19 +141,40 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("eqv?", eqv_p),
     simple_primitive!("equal?", equal_p),
     simple_primitive!("number?", number_p),
+    simple_primitive!("complex?", complex_p),
     simple_primitive!("=", equal),
     simple_primitive!("<", less_than),
     simple_primitive!(">", greater_than),
     simple_primitive!("<=", less_than_equal),
     simple_primitive!(">=", greater_than_equal),
+    simple_primitive!("min", min),
+    simple_primitive!("max", max),
+    simple_primitive!("abs", abs),
     simple_primitive!("+", add),
     simple_primitive!("*", mul),
     simple_primitive!("-", sub),
     simple_primitive!("/", div),
     simple_primitive!("modulo", modulo),
     simple_primitive!("remainder", remainder),
+    simple_primitive!("quotient", quotient),
+    // `floor/` and `truncate/` return their quotient and remainder as one `(quotient . remainder)`
+    // pair, not as two values through `values`.
+    simple_primitive!("floor/", floor_div),
+    simple_primitive!("truncate/", truncate_div),
+    simple_primitive!("floor", floor),
+    simple_primitive!("ceiling", ceiling),
+    simple_primitive!("truncate", truncate),
+    simple_primitive!("round", round),
     simple_primitive!("gcd", gcd),
     simple_primitive!("lcm", lcm),
+    simple_primitive!("bitwise-and", bitwise_and),
+    simple_primitive!("bitwise-ior", bitwise_ior),
+    simple_primitive!("bitwise-or", bitwise_ior),
+    simple_primitive!("bitwise-xor", bitwise_xor),
+    simple_primitive!("bitwise-not", bitwise_not),
+    simple_primitive!("arithmetic-shift", arithmetic_shift),
+    simple_primitive!("bit-count", bit_count),
+    simple_primitive!("integer-length", integer_length),
     simple_primitive!("real?", real_p),
     simple_primitive!("rational?", rational_p),
     simple_primitive!("integer?", integer_p),
@@ -154,8 +193,18 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("acos", acos),
     simple_primitive!("asin", asin),
     simple_primitive!("%atan", atan),
+    simple_primitive!("sinh", sinh),
+    simple_primitive!("cosh", cosh),
+    simple_primitive!("tanh", tanh),
+    simple_primitive!("asinh", asinh),
+    simple_primitive!("acosh", acosh),
+    simple_primitive!("atanh", atanh),
     simple_primitive!("sqrt", sqrt),
+    // `exact-integer-sqrt` returns its root and remainder as one `(root . remainder)` pair, not
+    // as two values through `values`.
+    simple_primitive!("exact-integer-sqrt", exact_integer_sqrt),
     simple_primitive!("expt", expt),
+    simple_primitive!("rationalize", rationalize),
     simple_primitive!("magnitude", magnitude),
     simple_primitive!("angle", angle),
     simple_primitive!("make-rectangular", make_rectangular),
@@ -168,9 +217,10 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("cdr", cdr),
     simple_primitive!("set-car!", set_car_b),
     simple_primitive!("set-cdr!", set_cdr_b),
-    simple_primitive!("write", write),
-    simple_primitive!("display", display),
-    simple_primitive!("newline", newline),
+    io_primitive!("write", write),
+    io_primitive!("display", display),
+    io_primitive!("newline", newline),
+    io_primitive!("current-output-port", current_output_port),
     simple_primitive!("symbol?", symbol_p),
     simple_primitive!("symbol->string", symbol_to_string),
     simple_primitive!("string->symbol", string_to_symbol),
@@ -179,6 +229,7 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("integer->char", integer_to_char),
     simple_primitive!("char-alphabetic?", char_alphabetic_p),
     simple_primitive!("char-numeric?", char_numeric_p),
+    simple_primitive!("digit-value", digit_value),
     simple_primitive!("char-whitespace?", char_whitespace_p),
     simple_primitive!("char-lower-case?", char_lower_case_p),
     simple_primitive!("char-upper-case?", char_upper_case_p),
@@ -186,6 +237,12 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("char-downcase", char_downcase),
     simple_primitive!("char-upcase-unicode", char_upcase_unicode),
     simple_primitive!("char-downcase-unicode", char_downcase_unicode),
+    simple_primitive!("char-foldcase", char_foldcase),
+    simple_primitive!("char-ci=?", char_ci_equal_p),
+    simple_primitive!("char-ci<?", char_ci_less_than_p),
+    simple_primitive!("char-ci>?", char_ci_greater_than_p),
+    simple_primitive!("char-ci<=?", char_ci_less_equal_p),
+    simple_primitive!("char-ci>=?", char_ci_greater_equal_p),
     simple_primitive!("string?", string_p),
     simple_primitive!("make-string", make_string),
     simple_primitive!("string-length", string_length),
@@ -212,7 +269,23 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("vector-length", vector_length),
     simple_primitive!("vector-set!", vector_set_b),
     simple_primitive!("vector-ref", vector_ref),
+    simple_primitive!("bytevector?", bytevector_p),
+    simple_primitive!("make-bytevector", make_bytevector),
+    simple_primitive!("bytevector-length", bytevector_length),
+    simple_primitive!("bytevector-u8-ref", bytevector_u8_ref),
+    simple_primitive!("bytevector-u8-set!", bytevector_u8_set_b),
+    simple_primitive!("bytevector-copy", bytevector_copy),
+    simple_primitive!("bytevector-append", bytevector_append),
+    simple_primitive!("utf8->string", utf8_to_string),
+    simple_primitive!("string->utf8", string_to_utf8),
+    simple_primitive!("make-record", make_record),
+    simple_primitive!("record?", record_p),
+    simple_primitive!("record-type", record_type),
+    simple_primitive!("record-ref", record_ref),
+    simple_primitive!("record-set!", record_set_b),
     simple_primitive!("procedure?", procedure_p),
+    simple_primitive!("disassemble", disassemble),
+    simple_primitive!("assemble", assemble),
     simple_primitive!("error", error),
     simple_primitive!("port?", port_p),
     simple_primitive!("input-port?", input_port_p),
@@ -222,6 +295,9 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("close-port", close_port),
     simple_primitive!("port-open?", port_open_p),
     simple_primitive!("open-input-file", open_input_file),
+    simple_primitive!("open-input-string", open_input_string),
+    simple_primitive!("open-binary-input-file", open_binary_input_file),
+    simple_primitive!("open-input-bytevector", open_input_bytevector),
     simple_primitive!("eof-object", eof_object),
     simple_primitive!("eof-object?", eof_object_p),
     simple_primitive!("read-char", read_char),
@@ -229,18 +305,53 @@ static PRIMITIVES: [Primitive; 125] = [
     simple_primitive!("read-line", read_line),
     simple_primitive!("char-ready?", char_ready_p),
     simple_primitive!("read-string", read_string),
+    simple_primitive!("read-u8", read_u8),
+    simple_primitive!("peek-u8", peek_u8),
+    simple_primitive!("u8-ready?", u8_ready_p),
+    simple_primitive!("read-bytevector", read_bytevector),
+    simple_primitive!("read-bytevector!", read_bytevector_b),
+    simple_primitive!("open-output-file", open_output_file),
+    simple_primitive!("open-binary-output-file", open_binary_output_file),
+    simple_primitive!("open-output-bytevector", open_output_bytevector),
+    simple_primitive!("get-output-bytevector", get_output_bytevector),
+    simple_primitive!("write-u8", write_u8),
+    simple_primitive!("write-bytevector", write_bytevector),
+    simple_primitive!("flush-output-port", flush_output_port),
+    simple_primitive!("file-exists?", file_exists_p),
+    simple_primitive!("delete-file", delete_file),
     Primitive {
         name: "apply",
         implementation: PrimitiveImplementation::Apply,
     },
     Primitive {
-        name: "%call/cc", // The actual call/cc handles dynamic-winds, and is written in Scheme.
+        // `%call/cc` is kept as the internal name so a future Scheme-level wrapper can still
+        // layer dynamic-wind unwinding on top of it under that name; until that wrapper exists,
+        // `call/cc`/`call-with-current-continuation` are exposed directly here, since the
+        // underlying capture/resume behavior (snapshotting vm.stack/vm.return_stack on capture,
+        // restoring them and resuming on invocation - see `call_cc`/`invoke`'s
+        // `Value::Continuation` arm) is already complete and re-entrant on its own.
+        name: "%call/cc",
+        implementation: PrimitiveImplementation::CallCC,
+    },
+    Primitive {
+        name: "call/cc",
+        implementation: PrimitiveImplementation::CallCC,
+    },
+    Primitive {
+        name: "call-with-current-continuation",
         implementation: PrimitiveImplementation::CallCC,
     },
     Primitive {
         name: "raise",
         implementation: PrimitiveImplementation::Raise,
     },
+    // `raise-continuable` is registered against the exact same implementation as `raise` - this
+    // VM's handler dispatch can't actually distinguish the two, let alone resume at the raise
+    // site; see `vm::raise`'s doc comment for exactly what that means and why.
+    Primitive {
+        name: "raise-continuable",
+        implementation: PrimitiveImplementation::Raise,
+    },
     Primitive {
         name: "abort",
         implementation: PrimitiveImplementation::Abort,
@@ -257,6 +368,43 @@ static PRIMITIVES: [Primitive; 125] = [
         name: "load",
         implementation: PrimitiveImplementation::Load,
     },
+    Primitive {
+        name: "with-output-to-string",
+        implementation: PrimitiveImplementation::WithOutputToString,
+    },
+    Primitive {
+        name: "with-output-to-file",
+        implementation: PrimitiveImplementation::WithOutputToFile,
+    },
+    Primitive {
+        name: "with-input-from-file",
+        implementation: PrimitiveImplementation::WithInputFromFile,
+    },
+    Primitive {
+        name: "call-with-output-string",
+        implementation: PrimitiveImplementation::CallWithOutputString,
+    },
+    Primitive {
+        name: "call-with-port",
+        implementation: PrimitiveImplementation::CallWithPort,
+    },
+    Primitive {
+        name: "current-environment-bindings",
+        implementation: PrimitiveImplementation::CurrentEnvironmentBindings,
+    },
+    Primitive {
+        name: "dynamic-wind",
+        implementation: PrimitiveImplementation::DynamicWind,
+    },
+    Primitive {
+        name: "with-exception-handler",
+        implementation: PrimitiveImplementation::WithExceptionHandler,
+    },
+    simple_primitive!("values", values),
+    Primitive {
+        name: "call-with-values",
+        implementation: PrimitiveImplementation::CallWithValues,
+    },
 ];
 
 pub struct Primitive {
@@ -264,10 +412,15 @@ pub struct Primitive {
     pub implementation: PrimitiveImplementation,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum PrimitiveImplementation {
     Simple(fn(&Arena, &[PoolPtr]) -> Result<PoolPtr, String>),
     Io(fn(&Arena, PoolPtr, PoolPtr, &[PoolPtr]) -> Result<PoolPtr, String>),
+    /// A primitive registered at runtime by an embedding host (see
+    /// [`Interpreter::register_fn`](::Interpreter::register_fn)), rather than compiled into
+    /// [`PRIMITIVES`]. Holds the closure directly, since there's no `fn` item to point a bare
+    /// function pointer at.
+    Host(Rc<dyn Fn(&Arena, &[PoolPtr]) -> Result<PoolPtr, String>>),
     Eval,
     Apply,
     CallCC,
@@ -275,6 +428,38 @@ pub enum PrimitiveImplementation {
     Abort,
     CurrentJiffy,
     Load,
+    /// Runs a zero-argument thunk with `%current-output-port` rebound to a fresh string port,
+    /// and returns the accumulated output as a string. See `vm::with_output_to_string`.
+    WithOutputToString,
+    /// Opens a file, rebinds `%current-output-port` to it for the duration of a zero-argument
+    /// thunk call, then closes the file and restores the previous port - even if the thunk
+    /// raises. See `vm::with_output_to_file`.
+    WithOutputToFile,
+    /// Same as `WithOutputToFile`, but rebinds `%current-input-port` around a file opened for
+    /// input. See `vm::with_input_from_file`.
+    WithInputFromFile,
+    /// Runs a zero-argument thunk with a fresh string port passed as its single argument, and
+    /// returns the accumulated output as a string. See `vm::call_with_output_string`.
+    CallWithOutputString,
+    /// Invokes a one-argument procedure with a port, guaranteeing the port is closed afterward.
+    /// See `vm::call_with_port`.
+    CallWithPort,
+    /// Dumps the current call's activation frame stack - one frame per lexical scope, each
+    /// listing its slots' variable names, whether they're initialized, and their current values -
+    /// as a printable report. See `vm::current_environment_bindings` and
+    /// `environment::dump_frames`.
+    CurrentEnvironmentBindings,
+    /// Runs `before`, then `thunk`, then `after`, restoring the winder stack correctly if a
+    /// captured continuation later jumps across this call. See `vm::dynamic_wind`.
+    DynamicWind,
+    /// Installs `handler` as the current error handler for the duration of a zero-argument
+    /// `thunk` call, restoring the previous handler afterward - even if `thunk` raises. See
+    /// `vm::with_exception_handler`.
+    WithExceptionHandler,
+    /// Runs a zero-argument `producer` thunk, then applies `consumer` to the values it produced -
+    /// spread out as separate arguments if `producer` returned a `Value::Values`, or as its single
+    /// argument otherwise. See `vm::call_with_values`.
+    CallWithValues,
 }
 
 impl Debug for Primitive {
@@ -289,14 +474,53 @@ impl PartialEq for Primitive {
     }
 }
 
+/// Names of primitives considered unsafe for untrusted/sandboxed evaluation: anything that
+/// touches the filesystem, executes another file, or reads the wall clock. On top of these,
+/// `is_pure` also excludes every [`PrimitiveImplementation::Io`] primitive (`display`, `write`,
+/// `newline`, `current-output-port`), since those always reach into the ambient global port
+/// state. See `Interpreter::new_sandboxed`.
+///
+/// `eval` belongs here too, for a subtler reason than ambient I/O: `vm::run` resets its
+/// instruction/allocation counters and starts a fresh `return_stack` on every call, so a nested
+/// `vm::run` (which `eval` triggers via `Interpreter::parse_compile_run`) gets a brand-new set of
+/// `ExecutionLimits` budgets rather than inheriting what's left of the caller's. Sandboxed code
+/// could otherwise call `(eval expr env)` to reset its own instruction/allocation/recursion
+/// ceilings on demand, and since each nested `eval` is a real Rust-level recursive call into
+/// `vm::run`, deep `eval`-recursion would also exhaust the process stack directly instead of
+/// hitting the graceful `max_recursion_depth` check.
+const IMPURE_PRIMITIVES: &[&str] = &[
+    "open-input-file",
+    "open-binary-input-file",
+    "open-output-file",
+    "open-binary-output-file",
+    "file-exists?",
+    "delete-file",
+    "with-output-to-file",
+    "with-input-from-file",
+    "load",
+    "current-jiffy",
+    "eval",
+];
+
+fn is_pure(prim: &Primitive) -> bool {
+    !matches!(&prim.implementation, PrimitiveImplementation::Io(_))
+        && !IMPURE_PRIMITIVES.contains(&prim.name)
+}
+
+/// Registers every `PRIMITIVES` entry in `global_environment`/`global_frame`, or (if `pure_only`)
+/// only the ones `is_pure` allows - see `Interpreter::new_sandboxed`.
 pub fn register_primitives(
     arena: &Arena,
     global_environment: &RcEnv,
     afi: &RcAfi,
     global_frame: &RootPtr,
+    pure_only: bool,
 ) {
     let frame = global_frame.pp().long_lived().get_activation_frame();
     for prim in PRIMITIVES.iter() {
+        if pure_only && !is_pure(prim) {
+            continue;
+        }
         global_environment.borrow_mut().define(prim.name, afi, true);
         let ptr = arena.insert(Value::Primitive(prim));
         frame.borrow_mut().values.push(ptr);
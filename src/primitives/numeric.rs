@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::convert::TryFrom;
 use std::ops::{Neg, Rem};
 
@@ -23,7 +23,7 @@ use num_complex::Complex;
 use num_integer::Integer;
 use num_rational::BigRational;
 use num_traits::{pow, Float, One, Signed, ToPrimitive, Zero};
-use util::{check_len, is_numeric, rational_to_f64};
+use util::{check_len, is_numeric, rational_to_f64, simplify_numeric};
 use value::Value;
 use {lex, read};
 
@@ -233,6 +233,45 @@ prim_monotonic!(less_than_equal, less_than_equal2);
 compare_op!(greater_than_equal2, >=);
 prim_monotonic!(greater_than_equal, greater_than_equal2);
 
+/// Generates a variadic extremum primitive (`min`/`max`). `$better(a, b)` should report whether
+/// `a` is a strict improvement over `b`. Exactness contagion applies: if any argument is
+/// inexact, the result is coerced to inexact even if the chosen extreme was itself exact.
+macro_rules! extremum {
+    ($name:ident, $better:ident) => {
+        pub fn $name(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+            let values = numeric_vec(args)?;
+            check_len(&values, Some(1), None).map_err(|e| format!("{}: {}", stringify!($name), e))?;
+            let inexact = values.iter().any(|v| is_real(v));
+            let mut best = values[0].clone();
+            for v in &values[1..] {
+                if $better(v, &best) {
+                    best = (*v).clone();
+                }
+            }
+            Ok(arena.insert(if inexact { as_real(&best) } else { best }))
+        }
+    };
+}
+
+extremum!(min, less_than2);
+extremum!(max, greater_than2);
+
+pub fn abs(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let result = match &*args[0] {
+        Value::Integer(i) => Value::Integer(i.abs()),
+        Value::Rational(r) => Value::Rational(Box::new(r.abs())),
+        Value::Real(f) => Value::Real(f.abs()),
+        _ => {
+            return Err(format!(
+                "abs: not a real number, use magnitude instead: {}",
+                args[0].pretty_print()
+            ))
+        }
+    };
+    Ok(arena.insert(result))
+}
+
 pub fn real_part(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(1), Some(1))?;
     Ok(match &*args[0] {
@@ -274,6 +313,14 @@ pub fn number_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     Ok(arena.insert(Value::Boolean(is_numeric(&*args[0]))))
 }
 
+/// Every number is complex in the R7RS tower, so this is just [`number_p`] under another name -
+/// but it's still worth having its own primitive, since `(complex? x)` is how user code asks the
+/// question without committing to "is this specifically a `Value::Complex*`".
+pub fn complex_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    Ok(arena.insert(Value::Boolean(is_numeric(&*args[0]))))
+}
+
 pub fn real_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(1), Some(1))?;
     Ok(match &*args[0] {
@@ -349,16 +396,141 @@ pub fn exact(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     })
 }
 
+/// Raises an exact integer to an integer power: stays an `Integer` for a non-negative exponent
+/// that fits in a `u32` (a larger one gives `+inf.0`, whatever the base), and becomes the exact
+/// `Rational` reciprocal for a negative one.
+fn expt_integer(base: &BigInt, exp: &BigInt) -> Result<Value, String> {
+    if exp.is_negative() {
+        if base.is_zero() {
+            return Err("expt: division by zero".to_string());
+        }
+        let e = (-exp)
+            .to_u32()
+            .ok_or_else(|| "expt: exponent too large".to_string())?;
+        Ok(Value::Rational(Box::new(BigRational::new(
+            BigInt::one(),
+            base.pow(e),
+        ))))
+    } else {
+        match exp.to_u32() {
+            Some(e) => Ok(Value::Integer(base.pow(e))),
+            None => Ok(Value::Real(std::f64::INFINITY)),
+        }
+    }
+}
+
+/// Raises an exact rational to an integer power by raising numerator and denominator
+/// independently, inverting the result for a negative exponent.
+fn expt_rational(base: &BigRational, exp: &BigInt) -> Result<Value, String> {
+    if exp.is_negative() {
+        if base.is_zero() {
+            return Err("expt: division by zero".to_string());
+        }
+        let e = (-exp)
+            .to_u32()
+            .ok_or_else(|| "expt: exponent too large".to_string())?;
+        Ok(Value::Rational(Box::new(BigRational::new(
+            base.denom().pow(e),
+            base.numer().pow(e),
+        ))))
+    } else {
+        let e = exp
+            .to_u32()
+            .ok_or_else(|| "expt: exponent too large".to_string())?;
+        Ok(Value::Rational(Box::new(BigRational::new(
+            base.numer().pow(e),
+            base.denom().pow(e),
+        ))))
+    }
+}
+
+fn complex_integer_to_rational(c: &Complex<BigInt>) -> Complex<BigRational> {
+    Complex::new(bigint_to_rational(&c.re), bigint_to_rational(&c.im))
+}
+
+/// Inverts a (nonzero) exact complex number: `1/(a+bi) = (a-bi)/(a^2+b^2)`.
+fn invert_complex_rational(c: &Complex<BigRational>) -> Complex<BigRational> {
+    let norm_sq = &c.re * &c.re + &c.im * &c.im;
+    Complex::new(&c.re / &norm_sq, -&c.im / &norm_sq)
+}
+
+/// Raises an exact Gaussian integer to an integer power, by repeated complex multiplication;
+/// a negative exponent inverts the (exact) result.
+fn expt_complex_integer(base: &Complex<BigInt>, exp: &BigInt) -> Result<Value, String> {
+    if exp.is_negative() {
+        if base.is_zero() {
+            return Err("expt: division by zero".to_string());
+        }
+        let e = (-exp)
+            .to_u32()
+            .ok_or_else(|| "expt: exponent too large".to_string())?;
+        let powered = complex_integer_to_rational(&pow(base.clone(), e as usize));
+        Ok(Value::ComplexRational(Box::new(invert_complex_rational(
+            &powered,
+        ))))
+    } else {
+        let e = exp
+            .to_u32()
+            .ok_or_else(|| "expt: exponent too large".to_string())?;
+        Ok(Value::ComplexInteger(Box::new(pow(base.clone(), e as usize))))
+    }
+}
+
+/// Raises an exact complex rational to an integer power, analogous to [`expt_complex_integer`].
+fn expt_complex_rational(base: &Complex<BigRational>, exp: &BigInt) -> Result<Value, String> {
+    if exp.is_negative() {
+        if base.is_zero() {
+            return Err("expt: division by zero".to_string());
+        }
+        let e = (-exp)
+            .to_u32()
+            .ok_or_else(|| "expt: exponent too large".to_string())?;
+        let powered = pow(base.clone(), e as usize);
+        Ok(Value::ComplexRational(Box::new(invert_complex_rational(
+            &powered,
+        ))))
+    } else {
+        let e = exp
+            .to_u32()
+            .ok_or_else(|| "expt: exponent too large".to_string())?;
+        Ok(Value::ComplexRational(Box::new(pow(base.clone(), e as usize))))
+    }
+}
+
+/// Falls back to `f64`/`Complex<f64>` `powf`/`powc` for any base/exponent combination that isn't
+/// an exact base raised to an exact integer power (a non-integer or inexact exponent, or an
+/// irrational result such as a negative real base to a fractional exponent).
+fn expt_inexact(base: &Value, exponent: &Value) -> Value {
+    match (as_complex(&as_real(base)), as_complex(&as_real(exponent))) {
+        (Value::ComplexReal(b), Value::ComplexReal(e)) => {
+            if b.im == 0.0 && e.im == 0.0 && (b.re >= 0.0 || e.re.fract() == 0.0) {
+                Value::Real(b.re.powf(e.re))
+            } else {
+                Value::ComplexReal(b.powc(e))
+            }
+        }
+        _ => panic!("conversion to complex failed."),
+    }
+}
+
 pub fn expt(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(2), Some(2))?;
-    let result = match (&*args[0], &*args[1]) {
-        (Value::Integer(base), Value::Integer(exponent)) => {
-            let realistic_exponent = exponent.to_u32();
-            realistic_exponent
-                .map(|re| Value::Integer(base.pow(re)))
-                .unwrap_or(Value::Real(std::f64::INFINITY))
-        }
-        _ => return Err("only integer exponentiation is supported".to_string()),
+    let base = &*args[0];
+    let exponent = &*args[1];
+    if !is_numeric(base) || !is_numeric(exponent) {
+        return Err(format!(
+            "expt: non-numeric argument: {}, {}",
+            args[0].pretty_print(),
+            args[1].pretty_print()
+        ));
+    }
+
+    let result = match (base, exponent) {
+        (Value::Integer(b), Value::Integer(e)) => expt_integer(b, e)?,
+        (Value::Rational(b), Value::Integer(e)) => expt_rational(b, e)?,
+        (Value::ComplexInteger(b), Value::Integer(e)) => expt_complex_integer(b, e)?,
+        (Value::ComplexRational(b), Value::Integer(e)) => expt_complex_rational(b, e)?,
+        _ => expt_inexact(base, exponent),
     };
     Ok(arena.insert(result))
 }
@@ -385,6 +557,189 @@ pub fn remainder(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     Ok(arena.insert(result))
 }
 
+pub fn quotient(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let result = match (&*args[0], &*args[1]) {
+        (Value::Integer(dividend), Value::Integer(divisor)) => Value::Integer(dividend / divisor),
+        _ => return Err("quotient is only supported for integers".to_string()),
+    };
+    Ok(arena.insert(result))
+}
+
+pub fn floor_div(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let (q, r) = match (&*args[0], &*args[1]) {
+        (Value::Integer(dividend), Value::Integer(divisor)) => (
+            Value::Integer(dividend.div_floor(divisor)),
+            Value::Integer(dividend.mod_floor(divisor)),
+        ),
+        _ => return Err("floor/ is only supported for integers".to_string()),
+    };
+    let q = arena.insert(q);
+    let r = arena.insert(r);
+    Ok(arena.insert(Value::Pair(Cell::new(q), Cell::new(r))))
+}
+
+pub fn truncate_div(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let (q, r) = match (&*args[0], &*args[1]) {
+        (Value::Integer(dividend), Value::Integer(divisor)) => (
+            Value::Integer(dividend / divisor),
+            Value::Integer(dividend.rem(divisor)),
+        ),
+        _ => return Err("truncate/ is only supported for integers".to_string()),
+    };
+    let q = arena.insert(q);
+    let r = arena.insert(r);
+    Ok(arena.insert(Value::Pair(Cell::new(q), Cell::new(r))))
+}
+
+/// Rounds a `BigRational` down to the nearest integer (towards negative infinity).
+fn floor_rational(r: &BigRational) -> BigInt {
+    r.numer().div_floor(r.denom())
+}
+
+/// Rounds a `BigRational` up to the nearest integer (towards positive infinity).
+fn ceiling_rational(r: &BigRational) -> BigInt {
+    let q = r.numer().div_floor(r.denom());
+    if r.numer().mod_floor(r.denom()).is_zero() {
+        q
+    } else {
+        q + BigInt::one()
+    }
+}
+
+/// Rounds a `BigRational` towards zero.
+fn truncate_rational(r: &BigRational) -> BigInt {
+    r.numer() / r.denom()
+}
+
+/// Rounds a `BigRational` to the nearest integer, breaking exact ties towards the even integer
+/// (banker's rounding), matching R7RS `round`.
+fn round_rational(r: &BigRational) -> BigInt {
+    let q = r.numer().div_floor(r.denom());
+    let rem = r.numer().mod_floor(r.denom());
+    let twice_rem = &rem * 2;
+    match twice_rem.cmp(r.denom()) {
+        std::cmp::Ordering::Less => q,
+        std::cmp::Ordering::Greater => q + BigInt::one(),
+        std::cmp::Ordering::Equal => {
+            if q.is_even() {
+                q
+            } else {
+                q + BigInt::one()
+            }
+        }
+    }
+}
+
+/// Rounds an `f64` to the nearest integer, breaking exact ties towards the even integer, to match
+/// [`round_rational`] over the inexact domain.
+fn round_f64_half_even(x: f64) -> f64 {
+    let floor = x.floor();
+    let diff = x - floor;
+    if diff < 0.5 {
+        floor
+    } else if diff > 0.5 {
+        floor + 1.0
+    } else if floor.rem_euclid(2.0) == 0.0 {
+        floor
+    } else {
+        floor + 1.0
+    }
+}
+
+/// Generates a rounding primitive (`floor`, `ceiling`, `truncate`, `round`) that works across
+/// `Integer` (identity), `Rational` (via the given rational-rounding function), and `Real` (via
+/// the given float-rounding function), keeping exact inputs exact.
+macro_rules! rounding_op {
+    ($name:ident, $rational_op:ident, $float_op:expr) => {
+        pub fn $name(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+            check_len(args, Some(1), Some(1))?;
+            let result = match &*args[0] {
+                Value::Integer(n) => Value::Integer(n.clone()),
+                Value::Rational(r) => Value::Integer($rational_op(r)),
+                Value::Real(f) => Value::Real($float_op(*f)),
+                _ => {
+                    return Err(format!(
+                        "{}: non-real argument: {}",
+                        stringify!($name),
+                        args[0].pretty_print()
+                    ))
+                }
+            };
+            Ok(arena.insert(result))
+        }
+    };
+}
+
+rounding_op!(floor, floor_rational, f64::floor);
+rounding_op!(ceiling, ceiling_rational, f64::ceil);
+rounding_op!(truncate, truncate_rational, f64::trunc);
+rounding_op!(round, round_rational, round_f64_half_even);
+
+fn as_exact_rational(v: &Value) -> Option<BigRational> {
+    match v {
+        Value::Integer(n) => Some(bigint_to_rational(n)),
+        Value::Rational(r) => Some((**r).clone()),
+        Value::Real(f) => Some(f64_to_rational(*f)),
+        _ => None,
+    }
+}
+
+/// Finds the simplest rational (smallest denominator, then smallest numerator) within the closed
+/// interval `[lo, hi]`, via the classic continued-fraction recursion. Assumes `lo <= hi`.
+fn simplest_rational(lo: &BigRational, hi: &BigRational) -> BigRational {
+    let zero = BigRational::zero();
+    if lo <= &zero && hi >= &zero {
+        return zero;
+    }
+    if hi < &zero {
+        let neg_hi = -hi.clone();
+        let neg_lo = -lo.clone();
+        return -simplest_rational(&neg_hi, &neg_lo);
+    }
+    let fl_int = floor_rational(lo);
+    let fl = BigRational::from_integer(fl_int.clone());
+    if &fl == lo {
+        return fl;
+    }
+    if fl_int == floor_rational(hi) {
+        let hi_frac = hi.clone() - fl.clone();
+        let lo_frac = lo.clone() - fl.clone();
+        let inner = simplest_rational(
+            &(BigRational::one() / hi_frac),
+            &(BigRational::one() / lo_frac),
+        );
+        fl + BigRational::one() / inner
+    } else {
+        fl + BigRational::one()
+    }
+}
+
+/// `(rationalize x tolerance)`: the simplest rational within `tolerance` of `x`. Exact if both
+/// arguments are exact, inexact (and converted back to `Real`) if either is inexact.
+pub fn rationalize(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let inexact = matches!(&*args[0], Value::Real(_)) || matches!(&*args[1], Value::Real(_));
+    let x = as_exact_rational(&args[0])
+        .ok_or_else(|| format!("rationalize: not a real number: {}", args[0].pretty_print()))?;
+    let tolerance = as_exact_rational(&args[1])
+        .ok_or_else(|| format!("rationalize: not a real number: {}", args[1].pretty_print()))?
+        .abs();
+    let lo = &x - &tolerance;
+    let hi = &x + &tolerance;
+    let simplest = simplest_rational(&lo, &hi);
+    let result = if inexact {
+        Value::Real(rational_to_f64(&simplest))
+    } else if simplest.is_integer() {
+        Value::Integer(simplest.to_integer())
+    } else {
+        Value::Rational(Box::new(simplest))
+    };
+    Ok(arena.insert(result))
+}
+
 pub fn gcd(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     let mut acc = BigInt::zero();
     for arg in args {
@@ -409,6 +764,93 @@ pub fn lcm(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     Ok(arena.insert(Value::Integer(acc)))
 }
 
+fn as_bigint<'a>(v: &'a Value, name: &str) -> Result<&'a BigInt, String> {
+    match v {
+        Value::Integer(i) => Ok(i),
+        _ => Err(format!("{}: non-integer argument: {}", name, v.pretty_print())),
+    }
+}
+
+pub fn bitwise_and(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    let mut acc = BigInt::from(-1);
+    for arg in args {
+        acc = &acc & as_bigint(arg, "bitwise-and")?;
+    }
+    Ok(arena.insert(Value::Integer(acc)))
+}
+
+pub fn bitwise_ior(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    let mut acc = BigInt::zero();
+    for arg in args {
+        acc = &acc | as_bigint(arg, "bitwise-ior")?;
+    }
+    Ok(arena.insert(Value::Integer(acc)))
+}
+
+pub fn bitwise_xor(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    let mut acc = BigInt::zero();
+    for arg in args {
+        acc = &acc ^ as_bigint(arg, "bitwise-xor")?;
+    }
+    Ok(arena.insert(Value::Integer(acc)))
+}
+
+pub fn bitwise_not(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let n = as_bigint(&args[0], "bitwise-not")?;
+    Ok(arena.insert(Value::Integer(!n.clone())))
+}
+
+/// `(arithmetic-shift n count)`: shifts `n` left by `count` bits if positive, right
+/// (arithmetically, i.e. sign-extending) by `-count` bits if negative.
+pub fn arithmetic_shift(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let n = as_bigint(&args[0], "arithmetic-shift")?;
+    let count = as_bigint(&args[1], "arithmetic-shift")?.to_isize().ok_or_else(|| {
+        format!(
+            "arithmetic-shift: shift count too large: {}",
+            args[1].pretty_print()
+        )
+    })?;
+    let result = if count >= 0 {
+        n << count as usize
+    } else {
+        n >> (-count) as usize
+    };
+    Ok(arena.insert(Value::Integer(result)))
+}
+
+fn popcount(i: &BigInt) -> u64 {
+    let (_, digits) = i.to_u32_digits();
+    digits.iter().map(|d| u64::from(d.count_ones())).sum()
+}
+
+/// `(bit-count n)`: for non-negative `n`, the number of 1 bits. For negative `n`, the number of
+/// 0 bits in its two's-complement representation (so `(bit-count -1)` is `0`).
+pub fn bit_count(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let n = as_bigint(&args[0], "bit-count")?;
+    let count = if n.is_negative() {
+        popcount(&!n.clone())
+    } else {
+        popcount(n)
+    };
+    Ok(arena.insert(Value::Integer(BigInt::from(count))))
+}
+
+/// `(integer-length n)`: the number of bits necessary to represent `n`, excluding the sign bit,
+/// in two's complement (so `(integer-length -1)` and `(integer-length 0)` are both `0`).
+pub fn integer_length(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let n = as_bigint(&args[0], "integer-length")?;
+    let length = if n.is_negative() {
+        (!n.clone()).bits()
+    } else {
+        n.bits()
+    };
+    Ok(arena.insert(Value::Integer(BigInt::from(length))))
+}
+
 macro_rules! transcendental {
     ($inner_name:ident, $operator:tt) => {
         pub fn $inner_name(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
@@ -427,14 +869,152 @@ macro_rules! transcendental {
 }
 
 transcendental!(exp, exp);
-transcendental!(log, ln);
 transcendental!(cos, cos);
 transcendental!(sin, sin);
 transcendental!(tan, tan);
-transcendental!(acos, acos);
-transcendental!(asin, asin);
 transcendental!(atan, atan);
-transcendental!(sqrt, sqrt);
+
+/// Like [`transcendental!`], but for functions that aren't defined over the whole real line:
+/// a real argument outside `$in_domain` is promoted to `Complex<f64>` first, so e.g. `(log -2)`
+/// and `(asin 2)` return the correct complex result instead of NaN.
+macro_rules! transcendental_branching {
+    ($inner_name:ident, $operator:tt, $in_domain:expr) => {
+        pub fn $inner_name(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+            check_len(args, Some(1), Some(1))?;
+            let arg = &*args[0];
+            if !is_numeric(arg) {
+                return Err(format!("non-numeric value: {}", args[0].pretty_print()));
+            }
+            Ok(arena.insert(match as_real(arg) {
+                Value::ComplexReal(c) => Value::ComplexReal(c.$operator()),
+                Value::Real(x) if $in_domain(x) => Value::Real(x.$operator()),
+                Value::Real(x) => Value::ComplexReal(Complex::new(x, 0.0).$operator()),
+                _ => panic!("conversion to real failed."),
+            }))
+        }
+    };
+}
+
+transcendental_branching!(log, ln, |x: f64| x >= 0.0);
+transcendental_branching!(asin, asin, |x: f64| x.abs() <= 1.0);
+transcendental_branching!(acos, acos, |x: f64| x.abs() <= 1.0);
+
+transcendental!(sinh, sinh);
+transcendental!(cosh, cosh);
+transcendental!(tanh, tanh);
+transcendental!(asinh, asinh);
+transcendental_branching!(acosh, acosh, |x: f64| x >= 1.0);
+transcendental_branching!(atanh, atanh, |x: f64| x.abs() < 1.0);
+
+/// Computes `(s, r)` such that `s = floor(sqrt(n))` and `r = n - s*s`, for non-negative `n`, using
+/// integer Newton iteration: starting from `x = 2^ceil(bits(n)/2)`, repeat `x = (x + n/x) / 2`
+/// until `x*x <= n < (x+1)*(x+1)`.
+fn exact_integer_sqrt_impl(n: &BigInt) -> (BigInt, BigInt) {
+    if n.is_zero() {
+        return (BigInt::zero(), BigInt::zero());
+    }
+    let mut x = pow(BigInt::from(2), ((n.bits() + 1) / 2) as usize);
+    loop {
+        if &x * &x <= *n && (&x + BigInt::one()) * (&x + BigInt::one()) > *n {
+            break;
+        }
+        x = (&x + n / &x) / BigInt::from(2);
+    }
+    let remainder = n - &x * &x;
+    (x, remainder)
+}
+
+pub fn exact_integer_sqrt(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let n = args[0]
+        .try_get_integer()
+        .ok_or_else(|| format!("not an exact integer: {}", args[0].pretty_print()))?;
+    if n.is_negative() {
+        return Err(format!("negative argument to exact-integer-sqrt: {}", n));
+    }
+    let (s, r) = exact_integer_sqrt_impl(n);
+    let s = arena.insert(Value::Integer(s));
+    let r = arena.insert(Value::Integer(r));
+    Ok(arena.insert(Value::Pair(Cell::new(s), Cell::new(r))))
+}
+
+/// Approximates `sqrt(start)` as a `BigRational`, for non-negative `start`, via the Babylonian
+/// method: seeded from the `f64` square root, repeat `approx = (approx + start/approx) / 2` until
+/// successive approximations differ by less than `epsilon`.
+fn rational_sqrt_approx(start: &BigRational) -> BigRational {
+    if start.is_zero() {
+        return BigRational::zero();
+    }
+    let epsilon = BigRational::new(BigInt::one(), pow(BigInt::from(10), 30));
+    let two = BigRational::from_integer(BigInt::from(2));
+    let mut approx = f64_to_rational(rational_to_f64(start).sqrt());
+    loop {
+        let next = (&approx + start / &approx) / &two;
+        let delta = if next >= approx {
+            &next - &approx
+        } else {
+            &approx - &next
+        };
+        approx = next;
+        if delta < epsilon {
+            break;
+        }
+    }
+    approx
+}
+
+/// `sqrt` of a non-negative exact rational (or integer, treated as a rational with denominator
+/// 1): exact if both numerator and denominator are perfect squares, otherwise an inexact
+/// approximation obtained via [`rational_sqrt_approx`] rather than by converting to `f64` up
+/// front, so precision isn't lost for large numerators/denominators before the root is taken.
+fn sqrt_nonnegative_rational(x: &BigRational) -> Value {
+    let (sn, rn) = exact_integer_sqrt_impl(x.numer());
+    let (sd, rd) = exact_integer_sqrt_impl(x.denom());
+    if rn.is_zero() && rd.is_zero() {
+        Value::Rational(Box::new(BigRational::new(sn, sd)))
+    } else {
+        Value::Real(rational_to_f64(&rational_sqrt_approx(x)))
+    }
+}
+
+pub fn sqrt(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let arg = &*args[0];
+    if !is_numeric(arg) {
+        return Err(format!("non-numeric value: {}", args[0].pretty_print()));
+    }
+
+    let result = match arg {
+        Value::Integer(n) if n.is_negative() => {
+            // `bigint_to_rational` always produces a denominator of 1, so the exact branch below
+            // is always an integer too.
+            match sqrt_nonnegative_rational(&bigint_to_rational(&-n)) {
+                Value::Rational(r) => {
+                    Value::ComplexInteger(Box::new(Complex::new(BigInt::zero(), r.to_integer())))
+                }
+                Value::Real(r) => Value::ComplexReal(Complex::new(0.0, r)),
+                _ => unreachable!(),
+            }
+        }
+        Value::Integer(n) => sqrt_nonnegative_rational(&bigint_to_rational(n)),
+        Value::Rational(x) if x.is_negative() => match sqrt_nonnegative_rational(&-(**x).clone()) {
+            Value::Rational(r) => {
+                Value::ComplexRational(Box::new(Complex::new(BigRational::zero(), *r)))
+            }
+            Value::Real(r) => Value::ComplexReal(Complex::new(0.0, r)),
+            _ => unreachable!(),
+        },
+        Value::Rational(x) => sqrt_nonnegative_rational(x),
+        Value::Real(f) if *f < 0.0 => Value::ComplexReal(Complex::new(0.0, (-f).sqrt())),
+        Value::Real(f) => Value::Real(f.sqrt()),
+        _ => match as_complex(&as_real(arg)) {
+            Value::ComplexReal(c) => Value::ComplexReal(c.sqrt()),
+            _ => panic!("conversion to complex failed."),
+        },
+    };
+
+    Ok(arena.insert(simplify_numeric(result)))
+}
 
 pub fn magnitude(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(1), Some(1))?;
@@ -489,46 +1069,10 @@ pub fn string_to_number(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, Stri
 pub fn number_to_string(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(1), Some(2))?;
     let radix = get_radix(args.get(1))? as u32;
-
-    fn format_int(n: &BigInt, r: u32) -> String {
-        n.to_str_radix(r)
-    }
-
-    fn format_real(n: f64, r: u32) -> Result<String, String> {
-        if r != 10 {
-            Err("inexact numbers can only be formatted in radix 10.".to_string())
-        } else {
-            Ok(format!("{}", n))
-        }
-    }
-
-    fn format_rational(n: &BigRational, r: u32) -> String {
-        format!(
-            "{}/{}",
-            n.numer().to_str_radix(r),
-            n.denom().to_str_radix(r)
-        )
+    if !is_numeric(&args[0]) {
+        return Err(format!("converting non-number: {}", args[0].pretty_print()));
     }
-
-    let resp = match &*args[0] {
-        Value::Integer(a) => format_int(a, radix),
-        Value::Real(a) => format_real(*a, radix)?,
-        Value::Rational(a) => format_rational(a, radix),
-        Value::ComplexReal(a) => format!(
-            "{}+{}i",
-            format_real(a.re, radix)?,
-            format_real(a.im, radix)?
-        ),
-        Value::ComplexInteger(a) => {
-            format!("{}+{}i", format_int(&a.re, radix), format_int(&a.im, radix))
-        }
-        Value::ComplexRational(a) => format!(
-            "{}+{}i",
-            format_rational(&a.re, radix),
-            format_rational(&a.im, radix)
-        ),
-        _ => return Err(format!("converting non-number: {}", args[0].pretty_print())),
-    };
+    let resp = args[0].to_string_radix(radix)?;
     Ok(arena.insert(Value::String(RefCell::new(resp))))
 }
 
@@ -16,9 +16,10 @@ use std::fmt::Write;
 
 use arena::Arena;
 use heap::PoolPtr;
+use primitives::port::{write_str, Port};
 use util::check_len;
 use value;
-use value::{Value};
+use value::Value;
 
 pub fn eq_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(2), Some(2))?;
@@ -53,27 +54,96 @@ pub fn display_to_string(args: &[PoolPtr]) -> String {
     result
 }
 
-pub fn write(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
-    print!("{}", display_to_string(args));
+/// Same as [`display_to_string`], but correct on shared/circular structure: each argument is
+/// rendered with [`PoolPtr::write_shared`] instead of `pretty_print`, so a value like `x` from
+/// `(define x (list 1 2)) (set-cdr! (cdr x) x)` prints as `#0=(1 2 . #0#)` instead of looping.
+fn write_to_string(args: &[PoolPtr]) -> String {
+    let mut result = String::new();
+    for a in args.iter() {
+        write!(&mut result, "{}", a.write_shared()).unwrap();
+    }
+    result
+}
+
+/// Splits a trailing port argument off `args`, if present, falling back to `default_port`.
+///
+/// `write`/`display`/`newline` all accept an optional port as their last argument, per R7RS.
+fn resolve_output_port<'a>(
+    default_port: PoolPtr,
+    args: &'a [PoolPtr],
+) -> Result<(&'a Port, &'a [PoolPtr]), String> {
+    let (port, values) = match args.split_last() {
+        Some((last, rest)) if last.try_get_port().is_some() => (*last, rest),
+        _ => (default_port, args),
+    };
+    let port = port
+        .try_get_port()
+        .ok_or_else(|| format!("not a port: {}", port.pretty_print()))?;
+    Ok((port, values))
+}
+
+pub fn write(
+    arena: &Arena,
+    _input_port: PoolPtr,
+    output_port: PoolPtr,
+    args: &[PoolPtr],
+) -> Result<PoolPtr, String> {
+    let (port, values) = resolve_output_port(output_port, args)?;
+    write_str(port, &write_to_string(values))?;
     Ok(arena.unspecific)
 }
 
-pub fn display(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
-    for arg in args {
-        match arena.get(*arg) {
-            Value::String(s) => print!("{}", &s.borrow()),
-            Value::Character(c) => print!("{}", c),
-            _ => print!("{}", arg.pretty_print()),
+pub fn display(
+    arena: &Arena,
+    _input_port: PoolPtr,
+    output_port: PoolPtr,
+    args: &[PoolPtr],
+) -> Result<PoolPtr, String> {
+    let (port, values) = resolve_output_port(output_port, args)?;
+    for arg in values {
+        match arg.try_get_string() {
+            Some(s) => write_str(port, &s.borrow())?,
+            None => match arg.try_get_character() {
+                Some(c) => write_str(port, &c.to_string())?,
+                None => write_str(port, &arg.pretty_print())?,
+            },
         }
     }
     Ok(arena.unspecific)
 }
 
-pub fn newline(arena: &Arena, _args: &[PoolPtr]) -> Result<PoolPtr, String> {
-    println!();
+pub fn newline(
+    arena: &Arena,
+    _input_port: PoolPtr,
+    output_port: PoolPtr,
+    args: &[PoolPtr],
+) -> Result<PoolPtr, String> {
+    check_len(args, Some(0), Some(1))?;
+    let (port, _) = resolve_output_port(output_port, args)?;
+    write_str(port, "\n")?;
     Ok(arena.unspecific)
 }
 
+pub fn current_output_port(
+    _arena: &Arena,
+    _input_port: PoolPtr,
+    output_port: PoolPtr,
+    args: &[PoolPtr],
+) -> Result<PoolPtr, String> {
+    check_len(args, Some(0), Some(0))?;
+    Ok(output_port)
+}
+
 pub fn error(_arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     Err(display_to_string(args))
 }
+
+/// `(values obj ...)`. Exactly one argument is returned as-is, rather than wrapped, so ordinary
+/// single-value code never sees a `Value::Values` - only `call-with-values` (see
+/// `vm::call_with_values`) needs to know the wrapper exists.
+pub fn values(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    match args {
+        [v] => Ok(*v),
+        _ => Ok(arena.insert(Value::Values(args.to_vec()))),
+    }
+}
@@ -30,7 +30,9 @@ pub fn pair_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
 
 pub fn cons(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(2), Some(2))?;
-    Ok(arena.insert(Value::Pair(Cell::new(args[0]), Cell::new(args[1]))))
+    arena
+        .try_insert(Value::Pair(Cell::new(args[0]), Cell::new(args[1])))
+        .map_err(|e| e.to_string())
 }
 
 pub fn car(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
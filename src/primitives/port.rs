@@ -14,8 +14,9 @@
 
 use std::cell::{RefCell, RefMut};
 use std::fmt;
-use std::io::{Error, ErrorKind, Read};
+use std::io::{Error, ErrorKind, Read, Write};
 
+use num_bigint::BigInt;
 use num_traits::ToPrimitive;
 
 use arena::Arena;
@@ -159,6 +160,247 @@ impl TextInputPort for FileTextInputPort {
     }
 }
 
+pub struct FileBinaryInputPort {
+    reader: Option<std::io::BufReader<std::fs::File>>,
+    peek_buffer: Option<u8>,
+}
+
+impl FileBinaryInputPort {
+    fn new(name: &std::path::Path) -> std::io::Result<Self> {
+        let file = std::fs::File::open(name)?;
+        Ok(Self {
+            reader: Some(std::io::BufReader::new(file)),
+            peek_buffer: None,
+        })
+    }
+}
+
+impl BinaryInputPort for FileBinaryInputPort {
+    fn ready(&mut self) -> std::io::Result<bool> {
+        Ok(true)
+    }
+
+    fn peek(&mut self) -> std::io::Result<u8> {
+        if let Some(b) = self.peek_buffer {
+            Ok(b)
+        } else {
+            let b = read_u8_helper(self.reader.as_mut().unwrap())?;
+            self.peek_buffer = Some(b);
+            Ok(b)
+        }
+    }
+
+    fn read_one(&mut self) -> std::io::Result<u8> {
+        if let Some(b) = self.peek_buffer {
+            self.peek_buffer = None;
+            Ok(b)
+        } else {
+            read_u8_helper(self.reader.as_mut().unwrap())
+        }
+    }
+
+    fn read_buf(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut n = 0;
+        if let Some(b) = self.peek_buffer.take() {
+            buf[0] = b;
+            n += 1;
+        }
+        while n < buf.len() {
+            match read_u8_helper(self.reader.as_mut().unwrap()) {
+                Ok(b) => {
+                    buf[n] = b;
+                    n += 1;
+                }
+                Err(e) => {
+                    if e.kind() == ErrorKind::UnexpectedEof {
+                        break;
+                    } else {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        if n == 0 && !buf.is_empty() {
+            Err(std::io::Error::from(ErrorKind::UnexpectedEof))
+        } else {
+            Ok(n)
+        }
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.reader = None;
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.reader.is_none()
+    }
+}
+
+pub struct StringTextInputPort {
+    chars: Vec<char>,
+    pos: usize,
+    closed: bool,
+}
+
+impl StringTextInputPort {
+    fn new(contents: &str) -> Self {
+        StringTextInputPort {
+            chars: contents.chars().collect(),
+            pos: 0,
+            closed: false,
+        }
+    }
+}
+
+impl TextInputPort for StringTextInputPort {
+    fn ready(&mut self) -> std::io::Result<bool> {
+        Ok(true)
+    }
+
+    fn peek(&mut self) -> std::io::Result<char> {
+        self.chars
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))
+    }
+
+    fn read_one(&mut self) -> std::io::Result<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn read_string(&mut self, n: usize) -> std::io::Result<String> {
+        if self.pos >= self.chars.len() {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+        let end = (self.pos + n).min(self.chars.len());
+        let result: String = self.chars[self.pos..end].iter().collect();
+        self.pos = end;
+        Ok(result)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.closed = true;
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// An in-memory binary input port backed by a snapshot of a bytevector's bytes - the
+/// `BinaryInputPort` counterpart to [`StringTextInputPort`], used by `open-input-bytevector`.
+pub struct ByteVectorInputPort {
+    bytes: Vec<u8>,
+    pos: usize,
+    closed: bool,
+}
+
+impl ByteVectorInputPort {
+    fn new(bytes: Vec<u8>) -> Self {
+        ByteVectorInputPort {
+            bytes,
+            pos: 0,
+            closed: false,
+        }
+    }
+}
+
+impl BinaryInputPort for ByteVectorInputPort {
+    fn ready(&mut self) -> std::io::Result<bool> {
+        Ok(true)
+    }
+
+    fn peek(&mut self) -> std::io::Result<u8> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))
+    }
+
+    fn read_one(&mut self) -> std::io::Result<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn read_buf(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos >= self.bytes.len() {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+        let end = (self.pos + buf.len()).min(self.bytes.len());
+        let n = end - self.pos;
+        buf[..n].copy_from_slice(&self.bytes[self.pos..end]);
+        self.pos = end;
+        Ok(n)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.closed = true;
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// A buffered file output port, used both for text (`open-output-file`) and binary
+/// (`open-binary-output-file`) writes: `OutputPort`/`Write` don't distinguish the two, so one
+/// concrete type backs both, the same way `Port::TextInputFile` already holds either a
+/// file-backed or string-backed reader.
+pub struct FileOutputPort {
+    writer: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl FileOutputPort {
+    fn new(file: std::fs::File) -> Self {
+        FileOutputPort {
+            writer: Some(std::io::BufWriter::new(file)),
+        }
+    }
+}
+
+impl std::io::Write for FileOutputPort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.writer.as_mut() {
+            Some(w) => w.write(buf),
+            None => Err(Error::from(ErrorKind::BrokenPipe)),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.writer.as_mut() {
+            Some(w) => w.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl OutputPort for FileOutputPort {
+    // Buffered writes are invisible to anything but the writing process until they're flushed;
+    // silently dropping the `BufWriter` on close would throw that data away, so flush it first.
+    fn close(&mut self) -> std::io::Result<()> {
+        if let Some(mut w) = self.writer.take() {
+            w.flush()?;
+        }
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.writer.is_none()
+    }
+}
+
 pub struct StringOutputPort {
     underlying: String,
 }
@@ -185,11 +427,46 @@ impl OutputPort for StringOutputPort {
     }
 }
 
+/// The binary counterpart to [`StringOutputPort`], used by `open-output-bytevector`.
+pub struct ByteVectorOutputPort {
+    underlying: Vec<u8>,
+}
+
+impl std::io::Write for ByteVectorOutputPort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.underlying.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl OutputPort for ByteVectorOutputPort {
+    fn close(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+/// None of these variants need a bespoke GC finalizer: dropping a `Port` drops its boxed port
+/// trait object with ordinary Rust drop glue, which is run unconditionally on every heap slot by
+/// `heap::Pool::free_ref` - see that method's doc comment. A port manually closed beforehand (via
+/// `close_port`) is unaffected, because the concrete port types (`FileTextInputPort` and friends)
+/// already guard their OS handle behind an `Option` that `close` takes, so a second close - manual
+/// or via this drop - is a no-op.
 pub enum Port {
     BinaryInputFile(RefCell<Box<dyn BinaryInputPort>>),
-    TextInputFile(RefCell<Box<FileTextInputPort>>),
+    TextInputFile(RefCell<Box<dyn TextInputPort>>),
     OutputString(RefCell<StringOutputPort>),
+    OutputBytevector(RefCell<ByteVectorOutputPort>),
     OutputFile(RefCell<Box<dyn OutputPort>>),
+    /// The process' standard output. Used as the default current-output-port.
+    Stdout,
 }
 
 impl fmt::Debug for Port {
@@ -227,21 +504,23 @@ fn is_input_port(arg: PoolPtr) -> bool {
 
 fn is_output_port(arg: PoolPtr) -> bool {
     match arg.try_get_port().expect("not a port") {
-        Port::OutputFile(_) => true,
+        Port::OutputFile(_) | Port::OutputString(_) | Port::OutputBytevector(_) | Port::Stdout => {
+            true
+        }
         _ => false,
     }
 }
 
 fn is_binary_port(arg: PoolPtr) -> bool {
     match arg.try_get_port().expect("not a port") {
-        Port::BinaryInputFile(_) | Port::OutputFile(_) => true,
+        Port::BinaryInputFile(_) | Port::OutputFile(_) | Port::OutputBytevector(_) => true,
         _ => false,
     }
 }
 
 fn is_textual_port(arg: PoolPtr) -> bool {
     match arg.try_get_port().expect("not a port") {
-        Port::TextInputFile(_) | Port::OutputFile(_) => true,
+        Port::TextInputFile(_) | Port::OutputFile(_) | Port::OutputString(_) | Port::Stdout => true,
         _ => false,
     }
 }
@@ -286,6 +565,8 @@ pub fn close_port(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
         Port::TextInputFile(s) => s.borrow_mut().close(),
         Port::OutputFile(s) => s.borrow_mut().close(),
         Port::OutputString(s) => s.borrow_mut().close(),
+        Port::OutputBytevector(s) => s.borrow_mut().close(),
+        Port::Stdout => Ok(()),
     }
     .map_err(|e| e.to_string())?;
     Ok(arena.unspecific)
@@ -301,16 +582,39 @@ pub fn port_open_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
         Port::TextInputFile(s) => s.borrow().is_closed(),
         Port::OutputFile(s) => s.borrow().is_closed(),
         Port::OutputString(s) => s.borrow().is_closed(),
+        Port::OutputBytevector(s) => s.borrow().is_closed(),
+        Port::Stdout => false,
     };
     Ok(arena.insert(Value::Boolean(v)))
 }
 
-// TODO: paths don't have to be strings on most OSes. We should let the user specify arbitrary
-//       bytes. The issue is that I don't think Rust really provides a way to convert arbitrary
-//       bytes to a path?
+/// Builds a path from a string or bytevector argument - used by every `open-*-file` primitive
+/// (and `file-exists?`/`delete-file`), so all of them accept arbitrary-byte paths uniformly.
+///
+/// Paths aren't necessarily valid UTF-8 on most OSes, so a bare `String` argument isn't always
+/// enough to address every file; a bytevector lets the caller supply the raw OS-level bytes. On
+/// Unix those bytes are the path verbatim via `OsStrExt::from_bytes`. Windows paths are UTF-16
+/// internally and have no such raw-bytes constructor, so there we fall back to interpreting the
+/// bytevector as UTF-8 - which covers every path actually reachable from Scheme source, just not
+/// arbitrary ill-formed UTF-16.
 fn get_path(val: PoolPtr) -> Option<std::path::PathBuf> {
     match &*val {
         Value::String(s) => Some(std::path::PathBuf::from(s.borrow().clone())),
+        Value::ByteVector(b) => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::ffi::OsStrExt;
+                Some(std::path::PathBuf::from(std::ffi::OsStr::from_bytes(
+                    &b.borrow(),
+                )))
+            }
+            #[cfg(not(unix))]
+            {
+                std::str::from_utf8(&b.borrow())
+                    .ok()
+                    .map(std::path::PathBuf::from)
+            }
+        }
         _ => None,
     }
 }
@@ -324,6 +628,145 @@ pub fn open_input_file(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, Strin
     Ok(arena.insert(Value::Port(Box::new(port))))
 }
 
+/// Opens `args[0]` (a string) as an in-memory input port, backed by [`StringTextInputPort`]. This
+/// reuses the `Port::TextInputFile` variant rather than adding a dedicated `TextInputString` one:
+/// that variant already just holds a boxed `dyn TextInputPort`, with nothing file-specific about
+/// it, so `is_input_port`/`is_textual_port`/`close_port`/`port_open_p` and every `read-*` primitive
+/// already work on it unchanged - a second variant would be a distinction without a difference.
+pub fn open_input_string(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let contents = args[0]
+        .try_get_string()
+        .ok_or_else(|| format!("not a string: {}", args[0].pretty_print()))?
+        .borrow()
+        .clone();
+    let port = Port::TextInputFile(RefCell::new(Box::new(StringTextInputPort::new(&contents))));
+    Ok(arena.insert(Value::Port(Box::new(port))))
+}
+
+pub fn open_binary_input_file(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let path =
+        get_path(args[0]).ok_or_else(|| format!("not a valid path: {}", args[0].pretty_print()))?;
+    let raw_port = FileBinaryInputPort::new(&path).map_err(|e| e.to_string())?;
+    let port = Port::BinaryInputFile(RefCell::new(Box::new(raw_port)));
+    Ok(arena.insert(Value::Port(Box::new(port))))
+}
+
+/// Opens `args[0]` (a bytevector) as an in-memory binary input port, backed by
+/// [`ByteVectorInputPort`]. Reuses `Port::BinaryInputFile` rather than adding a dedicated variant,
+/// the same way [`open_input_string`] reuses `Port::TextInputFile`.
+pub fn open_input_bytevector(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let contents = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("not a bytevector: {}", args[0].pretty_print()))?
+        .borrow()
+        .clone();
+    let port = Port::BinaryInputFile(RefCell::new(Box::new(ByteVectorInputPort::new(contents))));
+    Ok(arena.insert(Value::Port(Box::new(port))))
+}
+
+/// Opens `args[0]` (a path) for buffered text output. Shares `FileOutputPort`/`Port::OutputFile`
+/// with [`open_binary_output_file`] - see that type's doc comment.
+///
+/// By default (or with `args[1]` the symbol `truncate`) the file is created if needed and
+/// truncated if it already exists. With `args[1]` the symbol `append`, writes are appended to the
+/// end of an existing file (the file is still created if missing) instead.
+pub fn open_output_file(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(2))?;
+    let path =
+        get_path(args[0]).ok_or_else(|| format!("not a valid path: {}", args[0].pretty_print()))?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true);
+    match args.get(1).map(|v| &**v) {
+        None => {
+            options.truncate(true);
+        }
+        Some(Value::Symbol(s)) if s == "truncate" => {
+            options.truncate(true);
+        }
+        Some(Value::Symbol(s)) if s == "append" => {
+            options.append(true);
+        }
+        Some(v) => return Err(format!("invalid file option: {}", v.pretty_print())),
+    };
+    let file = options.open(&path).map_err(|e| e.to_string())?;
+    let port = Port::OutputFile(RefCell::new(Box::new(FileOutputPort::new(file))));
+    Ok(arena.insert(Value::Port(Box::new(port))))
+}
+
+pub fn open_binary_output_file(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    open_output_file(arena, args)
+}
+
+/// Reports whether `args[0]` (a path) refers to a file that currently exists.
+pub fn file_exists_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let path =
+        get_path(args[0]).ok_or_else(|| format!("not a valid path: {}", args[0].pretty_print()))?;
+    Ok(arena.insert(Value::Boolean(path.exists())))
+}
+
+/// Deletes the file at `args[0]` (a path).
+pub fn delete_file(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let path =
+        get_path(args[0]).ok_or_else(|| format!("not a valid path: {}", args[0].pretty_print()))?;
+    std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+    Ok(arena.unspecific)
+}
+
+fn write_bytes(port: &Port, bytes: &[u8]) -> Result<(), String> {
+    match port {
+        Port::Stdout => std::io::stdout().write_all(bytes).map_err(|e| e.to_string()),
+        Port::OutputString(p) => p.borrow_mut().write_all(bytes).map_err(|e| e.to_string()),
+        Port::OutputBytevector(p) => p.borrow_mut().write_all(bytes).map_err(|e| e.to_string()),
+        Port::OutputFile(p) => p.borrow_mut().write_all(bytes).map_err(|e| e.to_string()),
+        _ => Err("not an output port".to_string()),
+    }
+}
+
+pub fn write_u8(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let byte = args[0]
+        .try_get_integer()
+        .and_then(BigInt::to_u8)
+        .ok_or_else(|| format!("not a valid byte: {}", args[0].pretty_print()))?;
+    let port = args[1]
+        .try_get_port()
+        .ok_or_else(|| format!("not a port: {}", args[1].pretty_print()))?;
+    write_bytes(port, &[byte])?;
+    Ok(arena.unspecific)
+}
+
+pub fn write_bytevector(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let bv = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("not a bytevector: {}", args[0].pretty_print()))?;
+    let port = args[1]
+        .try_get_port()
+        .ok_or_else(|| format!("not a port: {}", args[1].pretty_print()))?;
+    write_bytes(port, &bv.borrow())?;
+    Ok(arena.unspecific)
+}
+
+pub fn flush_output_port(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let port = args[0]
+        .try_get_port()
+        .ok_or_else(|| format!("not a port: {}", args[0].pretty_print()))?;
+    match port {
+        Port::Stdout => std::io::stdout().flush().map_err(|e| e.to_string()),
+        Port::OutputString(p) => p.borrow_mut().flush().map_err(|e| e.to_string()),
+        Port::OutputBytevector(p) => p.borrow_mut().flush().map_err(|e| e.to_string()),
+        Port::OutputFile(p) => p.borrow_mut().flush().map_err(|e| e.to_string()),
+        _ => Err("not an output port".to_string()),
+    }?;
+    Ok(arena.unspecific)
+}
+
 pub fn eof_object(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(0), Some(0))?;
     Ok(arena.eof)
@@ -336,7 +779,7 @@ pub fn eof_object_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String>
 
 fn get_open_text_input_port<'a>(
     val: PoolPtr,
-) -> Result<RefMut<'a, Box<FileTextInputPort>>, String> {
+) -> Result<RefMut<'a, Box<dyn TextInputPort>>, String> {
     let port: &'a Port = match val.long_lived() {
         Value::Port(b) => b,
         _ => return Err(format!("not a port: {}", val.pretty_print())),
@@ -353,6 +796,25 @@ fn get_open_text_input_port<'a>(
     }
 }
 
+fn get_open_binary_input_port<'a>(
+    val: PoolPtr,
+) -> Result<RefMut<'a, Box<dyn BinaryInputPort>>, String> {
+    let port: &'a Port = match val.long_lived() {
+        Value::Port(b) => b,
+        _ => return Err(format!("not a port: {}", val.pretty_print())),
+    };
+    if let Port::BinaryInputFile(op) = port {
+        let port = op.borrow_mut();
+        if port.is_closed() {
+            Err(format!("port is closed: {}", val.pretty_print()))
+        } else {
+            Ok(port)
+        }
+    } else {
+        Err(format!("not a binary input port: {}", val.pretty_print()))
+    }
+}
+
 pub fn read_char(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(1), Some(1))?;
     let mut port = get_open_text_input_port(args[0])?;
@@ -448,6 +910,107 @@ pub fn read_string(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     }
 }
 
+pub fn read_u8(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let mut port = get_open_binary_input_port(args[0])?;
+    match port.read_one() {
+        Ok(b) => Ok(arena.insert(Value::Integer(b.into()))),
+        Err(e) => {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                Ok(arena.eof)
+            } else {
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
+pub fn peek_u8(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let mut port = get_open_binary_input_port(args[0])?;
+    match port.peek() {
+        Ok(b) => Ok(arena.insert(Value::Integer(b.into()))),
+        Err(e) => {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                Ok(arena.eof)
+            } else {
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
+pub fn u8_ready_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let mut port = get_open_binary_input_port(args[0])?;
+    match port.ready() {
+        Ok(ready) => Ok(arena.insert(Value::Boolean(ready))),
+        Err(e) => {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                Ok(arena.t)
+            } else {
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
+pub fn read_bytevector(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let k = args[0]
+        .try_get_integer()
+        .ok_or_else(|| format!("not an integer: {}", args[0].pretty_print()))?;
+    let k = k
+        .to_usize()
+        .ok_or_else(|| format!("not a valid count: {}", k))?;
+    let mut port = get_open_binary_input_port(args[1])?;
+    let mut buf = vec![0; k];
+    match port.read_buf(&mut buf) {
+        Ok(n) => {
+            buf.truncate(n);
+            Ok(arena.insert(Value::ByteVector(RefCell::new(buf))))
+        }
+        Err(e) => {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                Ok(arena.eof)
+            } else {
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
+pub fn read_bytevector_b(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(4), Some(4))?;
+    let bv = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("not a bytevector: {}", args[0].pretty_print()))?;
+    let mut port = get_open_binary_input_port(args[1])?;
+    let start = args[2]
+        .try_get_integer()
+        .and_then(|i| i.to_usize())
+        .ok_or_else(|| format!("not a valid index: {}", args[2].pretty_print()))?;
+    let end = args[3]
+        .try_get_integer()
+        .and_then(|i| i.to_usize())
+        .ok_or_else(|| format!("not a valid index: {}", args[3].pretty_print()))?;
+    let mut bv = bv.borrow_mut();
+    if start > end || end > bv.len() {
+        return Err(format!("invalid indices for read-bytevector!: {}->{}", start, end));
+    }
+    match port.read_buf(&mut bv[start..end]) {
+        Ok(0) if start != end => Ok(arena.eof),
+        Ok(n) => Ok(arena.insert(Value::Integer(n.into()))),
+        Err(e) => {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                Ok(arena.eof)
+            } else {
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
 pub fn open_output_string(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     check_len(args, Some(0), Some(0))?;
     Ok(
@@ -471,3 +1034,55 @@ pub fn get_output_string(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, Str
         _ => Err(format!("invalid port type: {}", args[0].pretty_print())),
     }
 }
+
+/// The binary counterpart to [`open_output_string`].
+pub fn open_output_bytevector(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(0), Some(0))?;
+    Ok(arena.insert(Value::Port(Box::new(Port::OutputBytevector(RefCell::new(
+        ByteVectorOutputPort {
+            underlying: Vec::new(),
+        },
+    ))))))
+}
+
+/// The binary counterpart to [`get_output_string`].
+pub fn get_output_bytevector(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    match args[0]
+        .try_get_port()
+        .ok_or_else(|| format!("not a port: {}", args[0].pretty_print()))?
+    {
+        Port::OutputBytevector(s) => Ok(arena.insert(Value::ByteVector(RefCell::new(
+            s.borrow().underlying.clone(),
+        )))),
+        _ => Err(format!("invalid port type: {}", args[0].pretty_print())),
+    }
+}
+
+/// Writes `s` to `port`, which must be an output port (see [`is_output_port`]).
+///
+/// Used by `write`/`display`/`newline` to route their output through the port abstraction
+/// instead of hard-coding stdout.
+pub fn write_str(port: &Port, s: &str) -> Result<(), String> {
+    match port {
+        Port::Stdout => {
+            print!("{}", s);
+            Ok(())
+        }
+        Port::OutputString(p) => p
+            .borrow_mut()
+            .write_all(s.as_bytes())
+            .map_err(|e| e.to_string()),
+        Port::OutputFile(p) => p
+            .borrow_mut()
+            .write_all(s.as_bytes())
+            .map_err(|e| e.to_string()),
+        _ => Err("not an output port".to_string()),
+    }
+}
+
+/// Builds a fresh in-memory output-string port, equivalent to calling the `open-output-string`
+/// primitive. Used by `with-output-to-string` to build a temporary redirect target.
+pub fn new_output_string_port(arena: &Arena) -> PoolPtr {
+    open_output_string(arena, &[]).expect("open_output_string takes no arguments")
+}
@@ -0,0 +1,86 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Low-level runtime support for `define-record-type` (see `ast::parse_define_record_type`),
+//! which expands to calls into these primitives rather than hand-building `Value::Record`s
+//! itself. None of these are meant to be called directly by user code - a malformed/adversarial
+//! call (wrong field count, wrong record type) is still rejected, but the error messages are
+//! written for someone reading an expansion, not a Scheme programmer.
+
+use std::cell::RefCell;
+
+use arena::Arena;
+use heap::PoolPtr;
+use primitives::try_get_index;
+use util::check_len;
+use value::Value;
+
+pub fn make_record(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), None)?;
+    let rtype = args[0]
+        .try_get_record_type()
+        .ok_or_else(|| format!("make-record: not a record type: {}", args[0].pretty_print()))?;
+    let expected = rtype.field_names.len();
+    let got = args.len() - 1;
+    if expected != got {
+        return Err(format!(
+            "make-record: {} expects {} field(s), got {}",
+            rtype.name, expected, got
+        ));
+    }
+    Ok(arena.insert(Value::Record {
+        rtype: args[0],
+        fields: RefCell::new(args[1..].to_vec()),
+    }))
+}
+
+pub fn record_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    Ok(arena.insert(Value::Boolean(args[0].try_get_record().is_some())))
+}
+
+pub fn record_type(_arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(1))?;
+    let (rtype, _) = args[0]
+        .try_get_record()
+        .ok_or_else(|| format!("record-type: not a record: {}", args[0].pretty_print()))?;
+    Ok(rtype)
+}
+
+pub fn record_ref(_arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(2), Some(2))?;
+    let (_, fields) = args[0]
+        .try_get_record()
+        .ok_or_else(|| format!("record-ref: not a record: {}", args[0].pretty_print()))?;
+    let idx = try_get_index(args[1])?;
+    fields
+        .borrow()
+        .get(idx)
+        .cloned()
+        .ok_or_else(|| format!("record-ref: invalid index: {}", idx))
+}
+
+pub fn record_set_b(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(3), Some(3))?;
+    let (_, fields) = args[0]
+        .try_get_record()
+        .ok_or_else(|| format!("record-set!: not a record: {}", args[0].pretty_print()))?;
+    let idx = try_get_index(args[1])?;
+    let mut borrowed = fields.borrow_mut();
+    if idx >= borrowed.len() {
+        return Err(format!("record-set!: invalid index: {}", idx));
+    }
+    borrowed[idx] = args[2];
+    Ok(arena.unspecific)
+}
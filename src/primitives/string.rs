@@ -105,6 +105,50 @@ pub fn substring(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
     Ok(arena.insert(Value::String(RefCell::new(char_iterator.collect()))))
 }
 
+/// `(utf8->string bv [start [end]])`: decodes the bytes of `bv` (or the `start`..`end` slice of
+/// them) as UTF-8. Errors rather than lossily substituting on invalid byte sequences, matching
+/// the rest of this crate's "reject, don't guess" handling of malformed input.
+pub fn utf8_to_string(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(3))?;
+    let borrowed = args[0]
+        .try_get_bytevector()
+        .ok_or_else(|| format!("utf8->string: not a bytevector: {}", args[0].pretty_print()))?
+        .borrow();
+    let start = args.get(1).map(|v| try_get_index(*v)).unwrap_or(Ok(0))?;
+    let end = args
+        .get(2)
+        .map(|v| try_get_index(*v))
+        .unwrap_or(Ok(borrowed.len()))?;
+    if start > end || end > borrowed.len() {
+        return Err(format!("utf8->string: invalid indices: {}->{}", start, end));
+    }
+    let s = std::str::from_utf8(&borrowed[start..end])
+        .map_err(|_| "utf8->string: invalid UTF-8".to_string())?;
+    Ok(arena.insert(Value::String(RefCell::new(s.to_string()))))
+}
+
+/// `(string->utf8 s [start [end]])`: the inverse of [`utf8_to_string`], encoding `s` (or its
+/// `start`..`end` character range) into a fresh bytevector of UTF-8 bytes.
+pub fn string_to_utf8(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, String> {
+    check_len(args, Some(1), Some(3))?;
+    let borrowed = get_borrowed_string(args[0])?;
+    let start = args.get(1).map(|v| try_get_index(*v)).unwrap_or(Ok(0))?;
+    let end = args
+        .get(2)
+        .map(|v| try_get_index(*v))
+        .unwrap_or(Ok(borrowed.chars().count()))?;
+    if start > end || end > borrowed.chars().count() {
+        return Err(format!("string->utf8: invalid indices: {}->{}", start, end));
+    }
+    let bytes: Vec<u8> = borrowed
+        .chars()
+        .skip(start)
+        .take(end - start)
+        .collect::<String>()
+        .into_bytes();
+    Ok(arena.insert(Value::ByteVector(RefCell::new(bytes))))
+}
+
 fn get_borrowed_string<'a>(v: PoolPtr) -> Result<Ref<'a, String>, String> {
     Ok(v.long_lived()
         .try_get_string()
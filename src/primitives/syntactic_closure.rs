@@ -17,7 +17,7 @@ use std::rc::Rc;
 
 use arena::Arena;
 use environment;
-use environment::{Environment, EnvironmentValue, RcEnv};
+use environment::{Environment, EnvironmentValue, MacroTransformer, RcEnv};
 use heap::PoolPtr;
 use util::check_len;
 use value::{list_from_vec, Value};
@@ -138,8 +138,16 @@ pub fn identifier_equal_p(arena: &Arena, args: &[PoolPtr]) -> Result<PoolPtr, St
             v1.altitude == v2.altitude && v1.index == v2.index
         }
         (Some(EnvironmentValue::Macro(m1)), Some(EnvironmentValue::Macro(m2))) => {
-            // Lambdas are unique so no need to check environment equality
-            m1.lambda.pp() == m2.lambda.pp()
+            // Transformers are unique so no need to check environment equality
+            match (&m1.transformer, &m2.transformer) {
+                (MacroTransformer::Procedural(l1), MacroTransformer::Procedural(l2)) => {
+                    l1.pp() == l2.pp()
+                }
+                (MacroTransformer::SyntaxRules(r1), MacroTransformer::SyntaxRules(r2)) => {
+                    std::rc::Rc::ptr_eq(r1, r2)
+                }
+                _ => false,
+            }
         }
         _ => false,
     };
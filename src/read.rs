@@ -15,13 +15,20 @@
 //! Reader system
 //!
 //! This file contains methods to turn a stream of tokens into Lisp objects.
+//!
+//! R7RS datum labels (`#n=`/`#n#`) are already supported here - see the `labels` field on
+//! [`Reader`] and [`Reader::read_datum_label_definition`]/[`Reader::read_datum_label_reference`] -
+//! including the self-referential `#n=(a . #n#)` case, via a pair/vector placeholder that's
+//! back-patched once the labeled datum finishes reading.
 
 use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::iter::Peekable;
 use std::rc::Rc;
 
 use arena::Arena;
-use heap::RootPtr;
+use error::SourceFileLocator;
+use heap::{PoolPtr, RootPtr};
 use lex::{CodeRange, NumValue, PositionedToken, Token};
 use num_complex::Complex;
 use num_traits::cast::ToPrimitive;
@@ -33,6 +40,14 @@ use {lex, File};
 pub enum NoReadResult {
     Nothing,
     ReadError { msg: String, locator: Locator },
+    /// A `#n=` datum label was defined twice within the same datum - see
+    /// [`Reader::read_datum_label_definition`]. Carries both spans so the renderer can point at
+    /// the original definition as well as the conflicting one, rather than just the latter.
+    DuplicateLabel {
+        label: u32,
+        first: Locator,
+        second: Locator,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +62,22 @@ pub struct Reader<'ar> {
     /// If true, insert [`Value::Locator`] objects at each level.
     locate: bool,
     file: Rc<File>,
+
+    /// Maps datum labels (`#n=`/`#n#`) to the value they were bound to, for the datum currently
+    /// being read. Cleared at the start of each top-level [`read_tokens`] call, since labels are
+    /// only meaningful within the datum that defines them.
+    labels: RefCell<HashMap<u32, RootPtr>>,
+
+    /// Maps datum labels to the [`CodeRange`] of their `#n=` definition, so a second `#n=` for the
+    /// same label can report both the original and conflicting spans. Cleared alongside `labels`.
+    label_spans: RefCell<HashMap<u32, CodeRange>>,
+
+    /// `Some` for the duration of one [`read_many_recovering`](Self::read_many_recovering) call:
+    /// instead of aborting on the first unexpected/missing token, `do_read`/`read_list`/`read_vec`
+    /// push the error here and substitute a placeholder so sibling data can still be read. `None`
+    /// the rest of the time, in which case those same call sites propagate the error immediately,
+    /// exactly as they always have.
+    recovery_errors: RefCell<Option<Vec<NoReadResult>>>,
 }
 
 impl<'ar> Reader<'ar> {
@@ -55,6 +86,9 @@ impl<'ar> Reader<'ar> {
             arena,
             locate,
             file,
+            labels: RefCell::new(HashMap::new()),
+            label_spans: RefCell::new(HashMap::new()),
+            recovery_errors: RefCell::new(None),
         }
     }
 
@@ -63,6 +97,8 @@ impl<'ar> Reader<'ar> {
             return Err(NoReadResult::Nothing);
         }
 
+        self.labels.borrow_mut().clear();
+        self.label_spans.borrow_mut().clear();
         let mut it = tokens.iter().peekable();
         let res = self.do_read(&mut it)?;
         if let Some(s) = it.peek() {
@@ -74,7 +110,7 @@ impl<'ar> Reader<'ar> {
 
     pub fn read_many(&self, code: &str) -> Result<Vec<ReadResult>, NoReadResult> {
         let tokens = lex::lex(code).map_err(|e| self.error(e.msg, e.location))?;
-        let segments = lex::segment(tokens).map_err(|e| self.error(e.msg, e.location))?;
+        let segments = lex::segment(tokens, 0).map_err(|e| self.error(e.msg, e.location))?;
         if !segments.remainder.is_empty() {
             return Err(self.error(
                 "unterminated expression: dangling tokens",
@@ -93,6 +129,131 @@ impl<'ar> Reader<'ar> {
             .collect::<Result<Vec<_>, _>>()
     }
 
+    /// Like [`read_tokens`](Self::read_tokens), but doesn't stop at the first malformed datum -
+    /// see [`read_many_recovering`](Self::read_many_recovering). `tokens` must not be empty.
+    pub fn read_tokens_recovering(
+        &self,
+        tokens: &[PositionedToken],
+    ) -> (ReadResult, Vec<NoReadResult>) {
+        *self.recovery_errors.borrow_mut() = Some(Vec::new());
+        self.labels.borrow_mut().clear();
+        self.label_spans.borrow_mut().clear();
+
+        let mut it = tokens.iter().peekable();
+        let res = match self.do_read(&mut it) {
+            Ok(r) => r,
+            Err(e) => {
+                let range = tokens[0].range;
+                self.push_recovery_error(e);
+                self.error_marker(range)
+            }
+        };
+        if let Some(s) = it.peek() {
+            self.push_recovery_error(self.error(format!("unexpected token {:?}", s), s.range));
+        }
+
+        let errors = self.recovery_errors.borrow_mut().take().unwrap_or_default();
+        (res, errors)
+    }
+
+    /// Like [`read_many`](Self::read_many), but doesn't stop at the first malformed datum: an
+    /// unexpected or missing token inside a list/vector is recorded and reading resynchronizes at
+    /// the next balanced closing delimiter (see [`resync`](Self::resync)), so later errors - and
+    /// later, well-formed expressions - in `code` are still found and returned.
+    ///
+    /// Every error encountered is collected into the second element of the returned pair. Callers
+    /// must treat a non-empty error list as fatal: the `ReadResult`s returned alongside it may
+    /// contain placeholder data standing in for whatever couldn't be read, and must never be
+    /// handed to the evaluator.
+    pub fn read_many_recovering(&self, code: &str) -> (Vec<ReadResult>, Vec<NoReadResult>) {
+        *self.recovery_errors.borrow_mut() = Some(Vec::new());
+
+        let mut results = Vec::new();
+        match lex::lex(code).and_then(|tokens| lex::segment(tokens, 0)) {
+            Ok(segments) => {
+                if !segments.remainder.is_empty() {
+                    let range = segments
+                        .remainder
+                        .first()
+                        .unwrap()
+                        .range
+                        .merge(segments.remainder.last().unwrap().range);
+                    self.push_recovery_error(
+                        self.error("unterminated expression: dangling tokens", range),
+                    );
+                }
+                for segment in &segments.segments {
+                    self.labels.borrow_mut().clear();
+                    self.label_spans.borrow_mut().clear();
+                    let mut it = segment.iter().peekable();
+                    match self.do_read(&mut it) {
+                        Ok(r) => results.push(r),
+                        Err(e) => self.push_recovery_error(e),
+                    }
+                }
+            }
+            Err(e) => self.push_recovery_error(self.error(e.msg, e.location)),
+        }
+
+        let errors = self.recovery_errors.borrow_mut().take().unwrap_or_default();
+        (results, errors)
+    }
+
+    fn push_recovery_error(&self, err: NoReadResult) {
+        self.recovery_errors
+            .borrow_mut()
+            .as_mut()
+            .expect("push_recovery_error called outside read_many_recovering")
+            .push(err);
+    }
+
+    /// In non-recovering mode, behaves exactly like propagating `err` immediately. In recovering
+    /// mode (see [`read_many_recovering`](Self::read_many_recovering)), records `err` and returns
+    /// `placeholder` instead, so the caller can splice it in and keep reading siblings.
+    fn recover(
+        &self,
+        err: NoReadResult,
+        placeholder: ReadResult,
+    ) -> Result<ReadResult, NoReadResult> {
+        let mut errors = self.recovery_errors.borrow_mut();
+        match errors.as_mut() {
+            Some(v) => {
+                v.push(err);
+                Ok(placeholder)
+            }
+            None => Err(err),
+        }
+    }
+
+    /// A synthetic stand-in for a datum that couldn't be read, spliced in by [`recover`](Self::recover)
+    /// so the surrounding list/vector keeps its shape. Only ever produced in recovering mode, where
+    /// the accompanying error means the caller must discard the whole result rather than evaluate it.
+    fn error_marker(&self, range: CodeRange) -> ReadResult {
+        ReadResult {
+            ptr: self.arena.root(self.arena.undefined),
+            range,
+        }
+    }
+
+    /// Skips tokens until a `ClosingParen` that balances the nesting level at the point `resync`
+    /// was called is found (consuming it too), or the input runs out. Used after recording a
+    /// structural error, so one malformed element doesn't desynchronize everything that follows
+    /// it in the same list.
+    fn resync<'a, 'b, I>(&self, it: &'a mut Peekable<I>)
+    where
+        I: Iterator<Item = &'b PositionedToken>,
+    {
+        let mut depth: u32 = 0;
+        while let Some(t) = it.next() {
+            match &t.token {
+                Token::OpenParen | Token::OpenVector | Token::OpenByteVector => depth += 1,
+                Token::ClosingParen if depth == 0 => return,
+                Token::ClosingParen => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
     fn do_read<'a, 'b, I>(&self, it: &'a mut Peekable<I>) -> Result<ReadResult, NoReadResult>
     where
         I: Iterator<Item = &'b PositionedToken>,
@@ -115,7 +276,12 @@ impl<'ar> Reader<'ar> {
                 Token::QuasiQuote => self.read_quote(it, "quasiquote", t.range),
                 Token::Unquote => self.read_quote(it, "unquote", t.range),
                 Token::UnquoteSplicing => self.read_quote(it, "unquote-splicing", t.range),
-                _ => Err(self.error(format!("unexpected token {:?}", t), t.range)),
+                Token::DatumLabelDefinition(n) => self.read_datum_label_definition(it, *n, t.range),
+                Token::DatumLabelReference(n) => self.read_datum_label_reference(*n, t.range),
+                _ => self.recover(
+                    self.error(format!("unexpected token {:?}", t), t.range),
+                    self.error_marker(t.range),
+                ),
             }
         } else {
             panic!("do_parse called with no tokens");
@@ -159,11 +325,18 @@ impl<'ar> Reader<'ar> {
                                 token: Token::ClosingParen,
                                 ..
                             }) => ret,
-                            Some(t) => Err(self.error(
-                                format!("unexpected token {:?} after dot", t.token),
-                                t.range,
-                            )),
-                            None => Err(self.error("missing token after dot", *range)),
+                            Some(t) => {
+                                let err = self.error(
+                                    format!("unexpected token {:?} after dot", t.token),
+                                    t.range,
+                                );
+                                self.resync(it);
+                                self.recover(err, self.error_marker(t.range))
+                            }
+                            None => {
+                                let err = self.error("missing token after dot", *range);
+                                self.recover(err, self.error_marker(*range))
+                            }
                         }
                     } else {
                         self.read_list(it, None, first.range)
@@ -176,7 +349,10 @@ impl<'ar> Reader<'ar> {
                 }
             }
         } else {
-            Err(self.error("unexpected end of list", prev))
+            self.recover(
+                self.error("unexpected end of list", prev),
+                self.error_marker(prev),
+            )
         }
     }
 
@@ -235,7 +411,13 @@ impl<'ar> Reader<'ar> {
         let end = loop {
             let t = it.peek();
             match t {
-                None => return Err(self.error("unterminated vector", start.merge(last_pos))),
+                None => {
+                    let err = self.error("unterminated vector", start.merge(last_pos));
+                    match self.recover(err, self.error_marker(last_pos)) {
+                        Ok(_) => break last_pos,
+                        Err(e) => return Err(e),
+                    }
+                }
                 Some(&t) => match &t.token {
                     Token::ClosingParen => {
                         it.next();
@@ -278,6 +460,91 @@ impl<'ar> Reader<'ar> {
         ))
     }
 
+    /// Reads `#n=<datum>`, registering `label` so that `#n#` tokens inside (or after) `<datum>`
+    /// can refer back to it.
+    ///
+    /// If `<datum>` starts with `(` or `#(`, we pre-allocate an empty pair/vector placeholder and
+    /// register it *before* reading the body, so a self-reference like `#0=(a . #0#)` resolves to
+    /// the placeholder; once the body is fully read, we back-patch the placeholder's cells to
+    /// match it, realizing a true cycle. Other data types can't self-reference, since nothing
+    /// inside a number, string, or symbol literal could read `#0#` before `#0=` is done.
+    fn read_datum_label_definition<'a, 'b, I>(
+        &self,
+        it: &'a mut Peekable<I>,
+        label: u32,
+        start: CodeRange,
+    ) -> Result<ReadResult, NoReadResult>
+    where
+        I: Iterator<Item = &'b PositionedToken>,
+    {
+        if let Some(&first) = self.label_spans.borrow().get(&label) {
+            return Err(self.duplicate_label_error(label, first, start));
+        }
+
+        let placeholder = match it.peek().map(|t| &t.token) {
+            Some(Token::OpenParen) => Some(self.insert_positioned(
+                Value::Pair(
+                    Cell::new(self.arena.unspecific),
+                    Cell::new(self.arena.unspecific),
+                ),
+                start,
+            )),
+            Some(Token::OpenVector) => {
+                Some(self.insert_positioned(Value::Vector(RefCell::new(Vec::new())), start))
+            }
+            _ => None,
+        };
+        if let Some(ph) = &placeholder {
+            self.labels.borrow_mut().insert(label, ph.ptr.clone());
+        }
+
+        let result = self.do_read(it)?;
+        let range = start.merge(result.range);
+
+        let chosen = match &placeholder {
+            Some(ph) => match (
+                &*unwrap_located(ph.ptr.pp()),
+                &*unwrap_located(result.ptr.pp()),
+            ) {
+                (Value::Pair(ph_car, ph_cdr), Value::Pair(car, cdr)) => {
+                    ph_car.set(car.get());
+                    ph_cdr.set(cdr.get());
+                    ph.clone()
+                }
+                (Value::Vector(ph_vec), Value::Vector(vec)) => {
+                    *ph_vec.borrow_mut() = vec.borrow().clone();
+                    ph.clone()
+                }
+                // The body didn't turn out to have the shape we guessed (e.g. `#0=()`): nothing
+                // could have referenced the placeholder, so just use the real result.
+                _ => result.clone(),
+            },
+            None => result,
+        };
+        self.labels.borrow_mut().insert(label, chosen.ptr.clone());
+        self.label_spans.borrow_mut().insert(label, start);
+
+        Ok(ReadResult {
+            ptr: chosen.ptr,
+            range,
+        })
+    }
+
+    /// Reads `#n#`, looking up the value previously bound by a `#n=` in the same datum.
+    fn read_datum_label_reference(
+        &self,
+        label: u32,
+        range: CodeRange,
+    ) -> Result<ReadResult, NoReadResult> {
+        let ptr = self.labels.borrow().get(&label).cloned().ok_or_else(|| {
+            self.error(
+                format!("reference to undefined datum label: #{}#", label),
+                range,
+            )
+        })?;
+        Ok(ReadResult { ptr, range })
+    }
+
     fn insert_positioned(&self, v: Value, range: CodeRange) -> ReadResult {
         let inner = self.arena.insert_rooted(v);
         let ptr = if self.locate {
@@ -286,13 +553,23 @@ impl<'ar> Reader<'ar> {
         } else {
             inner
         };
+        // Recorded against whatever pointer actually flows downstream (the `Located` wrapper
+        // when `locate` is set, the bare value otherwise), so `ast::parse` can look up a span for
+        // any `PoolPtr` it gets handed without needing to know about `Value::Located` itself.
+        self.arena.set_location(
+            ptr.pp(),
+            SourceFileLocator {
+                file: self.file.clone(),
+                range,
+            },
+        );
         ReadResult { ptr, range }
     }
 
     /// Convenience method to create a [`Locator`] with the current file name.
     fn locator(&self, range: CodeRange) -> Locator {
         Locator {
-            file: self.file.clone(),
+            file_name: Rc::new(self.file.name.clone()),
             range,
         }
     }
@@ -303,6 +580,27 @@ impl<'ar> Reader<'ar> {
             locator: self.locator(range),
         }
     }
+
+    fn duplicate_label_error(
+        &self,
+        label: u32,
+        first: CodeRange,
+        second: CodeRange,
+    ) -> NoReadResult {
+        NoReadResult::DuplicateLabel {
+            label,
+            first: self.locator(first),
+            second: self.locator(second),
+        }
+    }
+}
+
+/// Strips a [`Value::Located`] wrapper, if present, to get at the underlying value's pointer.
+fn unwrap_located(ptr: PoolPtr) -> PoolPtr {
+    match &*ptr {
+        Value::Located(inner, _) => *inner,
+        _ => ptr,
+    }
 }
 
 pub fn read_num_token(t: &NumValue) -> Value {
@@ -12,11 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Cow::{self, Borrowed, Owned};
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Write};
+use std::rc::Rc;
 
+use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+
+use lex;
+use Interpreter;
 
 #[derive(Debug)]
 pub enum GetLineError {
@@ -31,17 +40,225 @@ pub trait Repl {
     fn save_history(&mut self);
 }
 
+/// Drives rustyline's completion, validation and highlighting for [`ReadlineRepl`]:
+///  * completion offers identifiers from [`Interpreter::completion_candidates`] that match the
+///    word under the cursor;
+///  * the hint is the remainder of the first such identifier, shown inline the way a shell
+///    suggests the rest of a command from history;
+///  * validation reuses `lex::segment`'s depth tracking, so rustyline only submits a form once
+///    its parens/brackets/strings are balanced - this is what lets `ReadlineRepl` return a whole,
+///    possibly multi-line expression from a single `get_line` call, instead of the hand-rolled
+///    continuation loop `handle_one_expr` uses for the other `Repl` backends;
+///  * highlighting colors the open paren matching a close paren just before the cursor, and
+///    colorizes strings, numbers and a handful of syntactic keywords using `lex::lex`.
+pub struct PeroxideHelper {
+    interpreter: Rc<Interpreter>,
+}
+
+impl PeroxideHelper {
+    fn new(interpreter: Rc<Interpreter>) -> Self {
+        PeroxideHelper { interpreter }
+    }
+}
+
+/// Finds the start of the identifier ending at `pos` in `line` - the same notion of "word" for
+/// both completion and hinting.
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .rfind(|c: char| c.is_whitespace() || "()[]'`,\"".contains(c))
+        .map(|i| i + 1)
+        .unwrap_or(0)
+}
+
+impl Completer for PeroxideHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return Ok((start, Vec::new()));
+        }
+
+        let candidates = self
+            .interpreter
+            .completion_candidates()
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: name,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for PeroxideHelper {
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos != line.len() {
+            return None;
+        }
+        let word = &line[word_start(line, pos)..pos];
+        if word.is_empty() {
+            return None;
+        }
+        self.interpreter
+            .completion_candidates()
+            .into_iter()
+            .filter(|name| name.starts_with(word) && name.len() > word.len())
+            .min()
+            .map(|name| name[word.len()..].to_string())
+    }
+}
+
+impl Validator for PeroxideHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+        match lex::lex(input).and_then(|tokens| lex::segment(tokens, 0)) {
+            Ok(res) if res.remainder.is_empty() => Ok(ValidationResult::Valid(None)),
+            // A lex error (e.g. an unterminated string) or leftover tokens both mean the form
+            // isn't finished yet; let `rep`'s diagnostics report the real problem once the user
+            // does submit.
+            _ => Ok(ValidationResult::Incomplete),
+        }
+    }
+}
+
+impl Highlighter for PeroxideHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let match_idx = matching_paren(line, pos);
+        let tokens = lex::lex(line).unwrap_or_default();
+        if match_idx.is_none() && tokens.iter().all(|t| token_color(&t.token).is_none()) {
+            return Borrowed(line);
+        }
+
+        let mut highlighted = String::with_capacity(line.len() + 16);
+        for (i, c) in line.char_indices() {
+            if Some(i) == match_idx {
+                highlighted.push_str("\x1b[1;34m");
+                highlighted.push(c);
+                highlighted.push_str("\x1b[0m");
+                continue;
+            }
+            match token_at(&tokens, i) {
+                Some((start, end, color)) => {
+                    if i == start {
+                        highlighted.push_str(color);
+                    }
+                    highlighted.push(c);
+                    if i == end {
+                        highlighted.push_str("\x1b[0m");
+                    }
+                }
+                None => highlighted.push(c),
+            }
+        }
+        Owned(highlighted)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // Both the paren match and the token colors can change on every keystroke (a single typed
+        // character can turn an in-progress symbol into a keyword, or close a paren), so there's
+        // no cheaper check than just re-highlighting.
+        true
+    }
+}
+
+/// The ANSI color `token` should be rendered in, or `None` to leave it uncolored - parens,
+/// quoting shorthand and plain (non-keyword) symbols fall through uncolored.
+fn token_color(token: &lex::Token) -> Option<&'static str> {
+    const KEYWORDS: &[&str] = &[
+        "define",
+        "define-syntax",
+        "define-record-type",
+        "guard",
+        "lambda",
+        "set!",
+        "if",
+        "cond",
+        "case",
+        "else",
+        "and",
+        "or",
+        "when",
+        "unless",
+        "begin",
+        "let",
+        "let*",
+        "letrec",
+        "letrec*",
+        "let-syntax",
+        "letrec-syntax",
+        "do",
+        "delay",
+        "quote",
+        "quasiquote",
+        "unquote",
+        "unquote-splicing",
+        "syntax-rules",
+    ];
+    match token {
+        lex::Token::String(_) => Some("\x1b[32m"),
+        lex::Token::Num(_) => Some("\x1b[36m"),
+        lex::Token::Boolean(_) | lex::Token::Character(_) => Some("\x1b[35m"),
+        lex::Token::Symbol(s) if KEYWORDS.contains(&s.as_str()) => Some("\x1b[1;33m"),
+        _ => None,
+    }
+}
+
+/// Finds the colored token (if any) covering byte offset `i`, as `(start, end, color)` - `end` is
+/// inclusive, matching `lex::CodeRange`.
+fn token_at(tokens: &[lex::PositionedToken], i: usize) -> Option<(usize, usize, &'static str)> {
+    tokens.iter().find_map(|t| {
+        let color = token_color(&t.token)?;
+        if t.range.start.0 <= i && i <= t.range.end.0 {
+            Some((t.range.start.0, t.range.end.0, color))
+        } else {
+            None
+        }
+    })
+}
+
+impl Helper for PeroxideHelper {}
+
+/// If the cursor in `line` sits right after a `)`, finds the byte offset of the `(` it matches by
+/// scanning backward and tracking nesting depth. Returns `None` if the cursor isn't just after a
+/// close paren, or no balancing open paren is found.
+fn matching_paren(line: &str, pos: usize) -> Option<usize> {
+    if pos == 0 || line.as_bytes().get(pos - 1) != Some(&b')') {
+        return None;
+    }
+
+    let mut depth: u32 = 0;
+    for (i, c) in line[..pos - 1].char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => return Some(i),
+            '(' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
 pub struct ReadlineRepl {
-    editor: Editor<()>,
+    editor: Editor<PeroxideHelper>,
     history_location: Option<String>,
 }
 
 impl ReadlineRepl {
-    pub fn new(history_location: Option<String>) -> ReadlineRepl {
+    pub fn new(history_location: Option<String>, interpreter: Rc<Interpreter>) -> ReadlineRepl {
         let mut ed = ReadlineRepl {
-            editor: Editor::<()>::new(),
+            editor: Editor::<PeroxideHelper>::new(),
             history_location,
         };
+        ed.editor.set_helper(Some(PeroxideHelper::new(interpreter)));
 
         if ed.editor.load_history("history.txt").is_err() {
             println!("No previous history.");
@@ -95,6 +312,44 @@ impl Repl for StdIoRepl {
     fn save_history(&mut self) {}
 }
 
+/// Reads lines from stdin itself, with no prompt and no history - for piping a full program in
+/// (`peroxide -`) rather than driving an interactive session (that's [`StdIoRepl`]).
+pub struct StdinRepl {
+    reader: BufReader<io::Stdin>,
+}
+
+impl StdinRepl {
+    pub fn new() -> StdinRepl {
+        StdinRepl {
+            reader: BufReader::new(io::stdin()),
+        }
+    }
+}
+
+impl Default for StdinRepl {
+    fn default() -> Self {
+        StdinRepl::new()
+    }
+}
+
+impl Repl for StdinRepl {
+    fn get_line(&mut self, _prompt: &str, _prefill: &str) -> Result<String, GetLineError> {
+        let mut line = String::new();
+        let len = self
+            .reader
+            .read_line(&mut line)
+            .map_err(|e| GetLineError::Err(e.to_string()))?;
+        match len {
+            0 => Err(GetLineError::Eof),
+            _ => Ok(line),
+        }
+    }
+
+    fn add_to_history(&mut self, _data: &str) {}
+
+    fn save_history(&mut self) {}
+}
+
 pub struct FileRepl {
     reader: BufReader<File>,
 }
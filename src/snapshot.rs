@@ -0,0 +1,359 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serializes a reachable object graph to bytes and reconstructs it later, so a Scheme image can
+//! be saved and resumed in a different process. `PoolPtr` can't serve as the on-disk handle -
+//! it's a raw pointer into a live heap's pools, meaningless once that process exits - so instead
+//! every reachable value is assigned a dense `u32` index (its position in the flattened graph)
+//! and references between values are encoded as those indices, resolved back into fresh
+//! `PoolPtr`s by [`deserialize`].
+//!
+//! Only a subset of [`Value`] is supported: pairs, vectors, bytevectors, strings, symbols,
+//! exact integers, reals, booleans, characters and the handful of singleton constants. Every
+//! other variant - rationals and complex numbers, and anything that closes over process-local
+//! state: lambdas, environments, continuations, ports, code blocks, syntactic closures, records -
+//! makes [`serialize_reachable`] fail with [`SnapshotError`] rather than silently dropping part
+//! of the graph.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use arena::Arena;
+use heap;
+use heap::{PoolPtr, RootPtr};
+use num_bigint::BigInt;
+use value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotError(&'static str);
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+const TAG_UNDEFINED: u8 = 0;
+const TAG_UNSPECIFIC: u8 = 1;
+const TAG_EOF: u8 = 2;
+const TAG_EMPTY_LIST: u8 = 3;
+const TAG_BOOLEAN: u8 = 4;
+const TAG_CHARACTER: u8 = 5;
+const TAG_REAL: u8 = 6;
+const TAG_INTEGER: u8 = 7;
+const TAG_SYMBOL: u8 = 8;
+const TAG_STRING: u8 = 9;
+const TAG_BYTEVECTOR: u8 = 10;
+const TAG_PAIR: u8 = 11;
+const TAG_VECTOR: u8 = 12;
+
+/// The values a node of the flattened graph references, by their eventual `u32` index. Empty for
+/// leaf values.
+fn children(v: &Value) -> Result<Vec<PoolPtr>, SnapshotError> {
+    match v {
+        Value::Pair(car, cdr) => Ok(vec![car.get(), cdr.get()]),
+        Value::Vector(vals) => Ok(vals.borrow().clone()),
+        Value::Undefined
+        | Value::Unspecific
+        | Value::EofObject
+        | Value::EmptyList
+        | Value::Boolean(_)
+        | Value::Character(_)
+        | Value::Real(_)
+        | Value::Integer(_)
+        | Value::Symbol(_)
+        | Value::String(_)
+        | Value::ByteVector(_) => Ok(vec![]),
+        _ => Err(SnapshotError(
+            "value variant is not supported by serialize_reachable",
+        )),
+    }
+}
+
+fn write_string(s: &str, out: &mut Vec<u8>) {
+    let bytes = s.as_bytes();
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn encode_value(
+    v: &Value,
+    ids: &HashMap<PoolPtr, u32>,
+    out: &mut Vec<u8>,
+) -> Result<(), SnapshotError> {
+    match v {
+        Value::Undefined => out.push(TAG_UNDEFINED),
+        Value::Unspecific => out.push(TAG_UNSPECIFIC),
+        Value::EofObject => out.push(TAG_EOF),
+        Value::EmptyList => out.push(TAG_EMPTY_LIST),
+        Value::Boolean(b) => {
+            out.push(TAG_BOOLEAN);
+            out.push(*b as u8);
+        }
+        Value::Character(c) => {
+            out.push(TAG_CHARACTER);
+            out.extend_from_slice(&(*c as u32).to_le_bytes());
+        }
+        Value::Real(r) => {
+            out.push(TAG_REAL);
+            out.extend_from_slice(&r.to_le_bytes());
+        }
+        Value::Integer(i) => {
+            out.push(TAG_INTEGER);
+            write_string(&i.to_string(), out);
+        }
+        Value::Symbol(s) => {
+            out.push(TAG_SYMBOL);
+            write_string(s, out);
+        }
+        Value::String(s) => {
+            out.push(TAG_STRING);
+            write_string(&s.borrow(), out);
+        }
+        Value::ByteVector(b) => {
+            out.push(TAG_BYTEVECTOR);
+            let b = b.borrow();
+            out.extend_from_slice(&(b.len() as u32).to_le_bytes());
+            out.extend_from_slice(b.as_slice());
+        }
+        Value::Pair(car, cdr) => {
+            out.push(TAG_PAIR);
+            out.extend_from_slice(&ids[&car.get()].to_le_bytes());
+            out.extend_from_slice(&ids[&cdr.get()].to_le_bytes());
+        }
+        Value::Vector(vals) => {
+            out.push(TAG_VECTOR);
+            let vals = vals.borrow();
+            out.extend_from_slice(&(vals.len() as u32).to_le_bytes());
+            for val in vals.iter() {
+                out.extend_from_slice(&ids[val].to_le_bytes());
+            }
+        }
+        _ => {
+            return Err(SnapshotError(
+                "value variant is not supported by serialize_reachable",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Walks every value reachable from `root` through pair and vector cells (see `children`; the
+/// walk does not go through [`heap::Inventory`]) and flattens it into a byte string that
+/// [`deserialize`] can later reconstruct, in a fresh process, as an equivalent object graph -
+/// including one that's cyclic through `Pair`s.
+///
+/// Fails with [`SnapshotError`] if anything reachable from `root` holds process-local state that
+/// has no sensible on-disk form - see the module-level doc comment for exactly which `Value`
+/// variants are supported.
+pub fn serialize_reachable(root: &RootPtr) -> Result<Vec<u8>, SnapshotError> {
+    let root_ptr = root.pp();
+    let mut ids = HashMap::new();
+    let mut order = Vec::new();
+    ids.insert(root_ptr, 0u32);
+    order.push(root_ptr);
+
+    let mut stack = vec![root_ptr];
+    while let Some(ptr) = stack.pop() {
+        for child in children(&*ptr)? {
+            if !ids.contains_key(&child) {
+                let id =
+                    u32::try_from(order.len()).map_err(|_| SnapshotError("graph too large"))?;
+                ids.insert(child, id);
+                order.push(child);
+                stack.push(child);
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&(order.len() as u32).to_le_bytes());
+    for ptr in &order {
+        encode_value(&*ptr, &ids, &mut out)?;
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .ok_or(SnapshotError("unexpected end of snapshot"))?;
+        let slice = self
+            .data
+            .get(self.pos..end)
+            .ok_or(SnapshotError("unexpected end of snapshot"))?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f64(&mut self) -> Result<f64, SnapshotError> {
+        let b = self.bytes(8)?;
+        let mut arr = [0u8; 8];
+        arr.copy_from_slice(b);
+        Ok(f64::from_le_bytes(arr))
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SnapshotError("invalid utf-8 in snapshot"))
+    }
+}
+
+/// A parsed record, not yet turned into a `Value` - composite nodes (`Pair`/`Vector`) still hold
+/// the raw `u32` ids of their children, resolved into real `PoolPtr`s only once every node has
+/// been allocated (see [`deserialize`]), so that forward references and cycles both work.
+enum Node {
+    Undefined,
+    Unspecific,
+    Eof,
+    EmptyList,
+    Boolean(bool),
+    Character(char),
+    Real(f64),
+    Integer(BigInt),
+    Symbol(String),
+    Str(String),
+    ByteVector(Vec<u8>),
+    Pair(u32, u32),
+    Vector(Vec<u32>),
+}
+
+fn read_node(r: &mut Reader<'_>) -> Result<Node, SnapshotError> {
+    Ok(match r.u8()? {
+        TAG_UNDEFINED => Node::Undefined,
+        TAG_UNSPECIFIC => Node::Unspecific,
+        TAG_EOF => Node::Eof,
+        TAG_EMPTY_LIST => Node::EmptyList,
+        TAG_BOOLEAN => Node::Boolean(r.u8()? != 0),
+        TAG_CHARACTER => {
+            let cp = r.u32()?;
+            Node::Character(
+                char::try_from(cp).map_err(|_| SnapshotError("invalid character in snapshot"))?,
+            )
+        }
+        TAG_REAL => Node::Real(r.f64()?),
+        TAG_INTEGER => {
+            let s = r.string()?;
+            Node::Integer(
+                s.parse()
+                    .map_err(|_| SnapshotError("invalid integer in snapshot"))?,
+            )
+        }
+        TAG_SYMBOL => Node::Symbol(r.string()?),
+        TAG_STRING => Node::Str(r.string()?),
+        TAG_BYTEVECTOR => {
+            let len = r.u32()? as usize;
+            Node::ByteVector(r.bytes(len)?.to_vec())
+        }
+        TAG_PAIR => {
+            let car = r.u32()?;
+            let cdr = r.u32()?;
+            Node::Pair(car, cdr)
+        }
+        TAG_VECTOR => {
+            let len = r.u32()? as usize;
+            let mut ids = Vec::with_capacity(len);
+            for _ in 0..len {
+                ids.push(r.u32()?);
+            }
+            Node::Vector(ids)
+        }
+        _ => return Err(SnapshotError("unknown tag in snapshot")),
+    })
+}
+
+/// Reconstructs the graph written by [`serialize_reachable`], returning a fresh root equivalent
+/// to the one that was serialized (cycles and shared structure included).
+///
+/// Composite nodes are allocated with placeholder contents first (so every id has a `PoolPtr`
+/// before any cross-reference needs to be resolved), then patched in place via `Cell::set`/
+/// `RefCell` - the in-process equivalent of the `set-car!`/`set-cdr!` fixup a cyclic `read` does.
+pub fn deserialize(arena: &Arena, bytes: &[u8]) -> Result<RootPtr, SnapshotError> {
+    let mut r = Reader::new(bytes);
+    let count = r.u32()? as usize;
+    let nodes: Vec<Node> = (0..count)
+        .map(|_| read_node(&mut r))
+        .collect::<Result<_, _>>()?;
+
+    let roots: Vec<RootPtr> = nodes
+        .iter()
+        .map(|node| {
+            let value = match node {
+                Node::Undefined => Value::Undefined,
+                Node::Unspecific => Value::Unspecific,
+                Node::Eof => Value::EofObject,
+                Node::EmptyList => Value::EmptyList,
+                Node::Boolean(b) => Value::Boolean(*b),
+                Node::Character(c) => Value::Character(*c),
+                Node::Real(f) => Value::Real(*f),
+                Node::Integer(i) => Value::Integer(i.clone()),
+                Node::Symbol(s) => Value::Symbol(s.clone()),
+                Node::Str(s) => Value::String(RefCell::new(s.clone())),
+                Node::ByteVector(b) => Value::ByteVector(RefCell::new(b.clone())),
+                // Placeholder contents, patched below once every id has a `RootPtr`.
+                Node::Pair(_, _) => {
+                    Value::Pair(Cell::new(arena.empty_list), Cell::new(arena.empty_list))
+                }
+                Node::Vector(_) => Value::Vector(RefCell::new(Vec::new())),
+            };
+            arena.insert_rooted(value)
+        })
+        .collect();
+
+    for (node, root) in nodes.iter().zip(roots.iter()) {
+        match node {
+            Node::Pair(car_id, cdr_id) => match &*root.pp() {
+                Value::Pair(car, cdr) => {
+                    car.set(roots[*car_id as usize].pp());
+                    cdr.set(roots[*cdr_id as usize].pp());
+                }
+                _ => unreachable!("Node::Pair always allocates a Value::Pair"),
+            },
+            Node::Vector(ids) => match &*root.pp() {
+                Value::Vector(vals) => {
+                    *vals.borrow_mut() = ids.iter().map(|&id| roots[id as usize].pp()).collect();
+                }
+                _ => unreachable!("Node::Vector always allocates a Value::Vector"),
+            },
+            _ => (),
+        }
+    }
+
+    Ok(roots[0].clone())
+}
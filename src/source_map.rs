@@ -0,0 +1,117 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracks the files that make up a multi-file (`load`-style) program, so that a [`Span`] produced
+//! while lexing any one of them can be resolved back to the file it came from.
+//!
+//! Each registered file reserves a non-overlapping range of *global* byte offsets; lexing a
+//! registered file through [`SourceMap::lex`] produces tokens whose spans fall inside that range,
+//! and [`SourceMap::resolve`] later maps any such span back to `(file name, line, column)`.
+
+use std::rc::Rc;
+
+use lex::{self, LexError, PositionedToken, Span};
+use File;
+
+/// Identifies a file registered with a [`SourceMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileId(usize);
+
+struct Entry {
+    file: Rc<File>,
+    lo: usize,
+}
+
+/// Assigns each registered file a non-overlapping range of global byte offsets.
+#[derive(Default)]
+pub struct SourceMap {
+    entries: Vec<Entry>,
+    next_offset: usize,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `file`, reserving a range of global byte offsets for its source text, and
+    /// returns the id used to lex it and to resolve spans back to it.
+    pub fn register(&mut self, file: Rc<File>) -> FileId {
+        let lo = self.next_offset;
+        self.next_offset = lo + file.source.len();
+        self.entries.push(Entry { file, lo });
+        FileId(self.entries.len() - 1)
+    }
+
+    /// Lexes the source text of `id`, producing tokens whose spans are global offsets usable with
+    /// [`SourceMap::resolve`].
+    pub fn lex(&self, id: FileId) -> Result<Vec<PositionedToken>, LexError> {
+        let entry = &self.entries[id.0];
+        lex::lex_at(&entry.file.source, entry.lo)
+    }
+
+    /// Resolves a global offset back to the name of the file that contains it and the
+    /// line/column within that file's source text.
+    ///
+    /// Returns `None` if `span` falls before the first registered file or after the last one.
+    // TODO a span exactly at the lo boundary shared between two files resolves to the later one;
+    //      this only matters for a (rare) error reported right at a file's first byte.
+    pub fn resolve(&self, span: Span) -> Option<(&str, u32, u32)> {
+        let entry = self.entries.iter().rev().find(|e| span.0 >= e.lo)?;
+        if span.0 - entry.lo > entry.file.source.len() {
+            return None;
+        }
+        let (line, column) = Span(span.0 - entry.lo).linecol_in(&entry.file.source);
+        Some((&entry.file.name, line, column))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lexes_with_global_offsets() {
+        let mut map = SourceMap::new();
+        let id = map.register(File::new("a.scm", "(+ 1 2)"));
+        let tokens = map.lex(id).unwrap();
+        assert_eq!(tokens[0].range.start, Span(0));
+    }
+
+    #[test]
+    fn resolves_spans_to_their_owning_file() {
+        let mut map = SourceMap::new();
+        let a = map.register(File::new("a.scm", "(foo)"));
+        let b = map.register(File::new("b.scm", "\n(bar)"));
+
+        let a_tokens = map.lex(a).unwrap();
+        let b_tokens = map.lex(b).unwrap();
+
+        assert_eq!(
+            map.resolve(a_tokens[0].range.start),
+            Some(("a.scm", 1, 1))
+        );
+        // `(bar)` is on the second line of b.scm, right after the leading newline.
+        assert_eq!(
+            map.resolve(b_tokens[0].range.start),
+            Some(("b.scm", 2, 1))
+        );
+    }
+
+    #[test]
+    fn resolve_out_of_range_is_none() {
+        let map = SourceMap::new();
+        assert_eq!(map.resolve(Span(0)), None);
+    }
+}
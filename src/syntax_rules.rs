@@ -0,0 +1,493 @@
+// Copyright 2018-2020 Matthieu Felix
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! R7RS `syntax-rules` macro-by-example transformers: a pattern matcher and template expander,
+//! as an alternative to the procedural (lambda-based) macros `make_macro` otherwise builds.
+//!
+//! Matching ignores the pattern's keyword position (the `_` in `(_ a b)`, or the macro name
+//! itself) - R7RS does not require it to name the macro, so both are accepted uniformly by
+//! simply not matching it against anything.
+//!
+//! Hygiene here is scoped rather than a full binding-form-aware renamer: every identifier a
+//! template introduces (i.e. one that is not a pattern variable) is either resolved in the
+//! macro's definition environment - wrapped in a [`SyntacticClosure`], for identifiers bound
+//! there, e.g. a reference to a standard procedure - or replaced with a fresh gensym, for
+//! identifiers with no definition-environment binding, e.g. a template-local helper variable
+//! like `tmp` in `swap!`. Both choices are made consistently within one expansion, so multiple
+//! occurrences of the same template identifier still refer to the same thing. Vector patterns
+//! (`#(...)`) are not supported, matching the scope of the pattern/template grammar actually
+//! exercised by the macros this interpreter can otherwise parse.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
+use arena::Arena;
+use environment::RcEnv;
+use heap::PoolPtr;
+use primitives::SyntacticClosure;
+use util::check_len;
+use value::{equal, Value};
+
+const ELLIPSIS: &str = "...";
+
+/// Head symbols `match_symbol` recognizes directly (by raw string, only once an environment
+/// lookup comes back empty) rather than through macro/variable resolution. A renamed
+/// occurrence of one of these would silently stop being recognized as the special form it
+/// names, so template expansion passes them through unchanged instead of hygienically renaming
+/// them.
+const SPECIAL_FORM_KEYWORDS: &[&str] = &[
+    "quote",
+    "syntax-quote",
+    "if",
+    "begin",
+    "lambda",
+    "set!",
+    "define",
+    "define-syntax",
+    "let-syntax",
+    "letrec-syntax",
+];
+
+#[derive(Debug)]
+pub struct SyntaxRules {
+    pub literals: HashSet<String>,
+    pub rules: Vec<SyntaxRule>,
+}
+
+#[derive(Debug)]
+pub struct SyntaxRule {
+    pub pattern: PoolPtr,
+    pub template: PoolPtr,
+}
+
+/// What a pattern variable is bound to after matching. A variable that does not occur under
+/// any `...` binds to a single input location (`Leaf`); one that occurs under one or more
+/// `...` binds to one sub-binding per repetition (`Seq`), nesting once per ellipsis depth.
+#[derive(Debug, Clone)]
+enum Binding {
+    Leaf(PoolPtr),
+    Seq(Vec<Binding>),
+}
+
+impl Binding {
+    fn as_leaf(&self) -> Option<PoolPtr> {
+        match self {
+            Binding::Leaf(p) => Some(*p),
+            Binding::Seq(_) => None,
+        }
+    }
+}
+
+/// Recognizes a `(syntax-rules (literal ...) (pattern template) ...)` transformer spec. Returns
+/// `Ok(None)` if `val`'s head is not literally `syntax-rules`, so callers can fall back to
+/// treating `val` as a procedural macro expression.
+pub fn try_parse(arena: &Arena, macro_name: &str, val: PoolPtr) -> Result<Option<SyntaxRules>, String> {
+    let rest = match arena.get(val) {
+        Value::Pair(car, cdr) if is_literal_symbol(arena, car.get(), "syntax-rules") => cdr.get(),
+        _ => return Ok(None),
+    };
+    let parts = arena.get(rest).list_to_vec().map_err(|e| {
+        format!("{}: malformed syntax-rules: {}", macro_name, e)
+    })?;
+    check_len(&parts, Some(1), None).map_err(|e| {
+        format!("{}: malformed syntax-rules: {}", macro_name, e)
+    })?;
+
+    let literals: Result<HashSet<_>, _> = arena
+        .get(parts[0])
+        .list_to_vec()
+        .map_err(|e| format!("{}: malformed syntax-rules literal list: {}", macro_name, e))?
+        .iter()
+        .map(|s| match arena.get(*s) {
+            Value::Symbol(s) => Ok(s.clone()),
+            v => Err(format!(
+                "{}: syntax-rules literals must be symbols, got `{}`.",
+                macro_name,
+                v.pretty_print()
+            )),
+        })
+        .collect();
+
+    let rules: Result<Vec<_>, _> = parts[1..]
+        .iter()
+        .map(|r| parse_rule(arena, macro_name, *r))
+        .collect();
+
+    Ok(Some(SyntaxRules {
+        literals: literals?,
+        rules: rules?,
+    }))
+}
+
+fn parse_rule(arena: &Arena, macro_name: &str, rule: PoolPtr) -> Result<SyntaxRule, String> {
+    let parts = arena
+        .get(rule)
+        .list_to_vec()
+        .map_err(|e| format!("{}: invalid syntax rule: {}", macro_name, e))?;
+    check_len(&parts, Some(2), Some(2))
+        .map_err(|e| format!("{}: invalid syntax rule: {}", macro_name, e))?;
+    Ok(SyntaxRule {
+        pattern: parts[0],
+        template: parts[1],
+    })
+}
+
+fn is_literal_symbol(arena: &Arena, v: PoolPtr, s: &str) -> bool {
+    match arena.get(v) {
+        Value::Symbol(sym) => sym == s,
+        _ => false,
+    }
+}
+
+/// Matches `call` against each rule in turn and expands the first one that matches. The
+/// caller (`expand_macro`) is responsible for feeding the result back into `parse`, exactly as
+/// it already does for procedural macros.
+pub fn expand(
+    arena: &Arena,
+    rules: &SyntaxRules,
+    definition_environment: &RcEnv,
+    call: PoolPtr,
+) -> Result<PoolPtr, String> {
+    let call_rest = match arena.get(call) {
+        Value::Pair(_, cdr) => cdr.get(),
+        _ => return Err("syntax-rules: macro use must be a list.".into()),
+    };
+    for rule in &rules.rules {
+        let pattern_rest = match arena.get(rule.pattern) {
+            Value::Pair(_, cdr) => cdr.get(),
+            _ => return Err("syntax-rules: pattern must be a list.".into()),
+        };
+        let mut bindings = HashMap::new();
+        if match_pattern(arena, &rules.literals, pattern_rest, call_rest, &mut bindings)? {
+            let mut expander = Expander {
+                arena,
+                definition_environment,
+                renames: HashMap::new(),
+            };
+            return expander.expand(&bindings, rule.template);
+        }
+    }
+    Err(format!(
+        "syntax-rules: no matching syntax-rules clause for `{}`.",
+        call.pretty_print()
+    ))
+}
+
+/// Walks a (possibly improper) list, returning its elements and final tail (`'()` for a proper
+/// list).
+fn list_elements(arena: &Arena, mut list: PoolPtr) -> (Vec<PoolPtr>, PoolPtr) {
+    let mut elements = Vec::new();
+    loop {
+        match arena.get(list) {
+            Value::Pair(car, cdr) => {
+                elements.push(car.get());
+                list = cdr.get();
+            }
+            _ => return (elements, list),
+        }
+    }
+}
+
+/// If `cdr` is a pair whose car is the literal symbol `...`, returns the tail following it.
+fn ellipsis_follows(arena: &Arena, cdr: PoolPtr) -> Option<PoolPtr> {
+    if let Value::Pair(car, rest) = arena.get(cdr) {
+        if is_literal_symbol(arena, car.get(), ELLIPSIS) {
+            return Some(rest.get());
+        }
+    }
+    None
+}
+
+/// Collects the names of all pattern variables (non-literal, non-`_`, non-`...` symbols)
+/// occurring anywhere in `pattern`.
+fn pattern_vars(arena: &Arena, literals: &HashSet<String>, pattern: PoolPtr, out: &mut Vec<String>) {
+    match arena.get(pattern) {
+        Value::Symbol(s) => {
+            if s != "_" && s != ELLIPSIS && !literals.contains(s) {
+                out.push(s.clone());
+            }
+        }
+        Value::Pair(car, cdr) => {
+            pattern_vars(arena, literals, car.get(), out);
+            pattern_vars(arena, literals, cdr.get(), out);
+        }
+        _ => {}
+    }
+}
+
+fn match_pattern(
+    arena: &Arena,
+    literals: &HashSet<String>,
+    pattern: PoolPtr,
+    input: PoolPtr,
+    bindings: &mut HashMap<String, Binding>,
+) -> Result<bool, String> {
+    match arena.get(pattern) {
+        Value::Symbol(s) if s == "_" => Ok(true),
+        Value::Symbol(s) if literals.contains(s) => Ok(is_literal_symbol(arena, input, s)),
+        Value::Symbol(s) => {
+            if bindings.insert(s.clone(), Binding::Leaf(input)).is_some() {
+                return Err(format!(
+                    "syntax-rules: pattern variable `{}` occurs more than once.",
+                    s
+                ));
+            }
+            Ok(true)
+        }
+        Value::Pair(pcar, pcdr) => {
+            if let Some(after) = ellipsis_follows(arena, pcdr.get()) {
+                match_ellipsis(arena, literals, pcar.get(), after, input, bindings)
+            } else {
+                match arena.get(input) {
+                    Value::Pair(icar, icdr) => Ok(match_pattern(
+                        arena,
+                        literals,
+                        pcar.get(),
+                        icar.get(),
+                        bindings,
+                    )? && match_pattern(
+                        arena,
+                        literals,
+                        pcdr.get(),
+                        icdr.get(),
+                        bindings,
+                    )?),
+                    _ => Ok(false),
+                }
+            }
+        }
+        Value::EmptyList => Ok(matches!(arena.get(input), Value::EmptyList)),
+        _ => Ok(equal(pattern, input)),
+    }
+}
+
+/// Matches `sub_pattern ...` (zero or more repetitions) followed by the fixed pattern
+/// `after_ellipsis`, against the list `input`.
+fn match_ellipsis(
+    arena: &Arena,
+    literals: &HashSet<String>,
+    sub_pattern: PoolPtr,
+    after_ellipsis: PoolPtr,
+    input: PoolPtr,
+    bindings: &mut HashMap<String, Binding>,
+) -> Result<bool, String> {
+    let (input_elements, input_tail) = list_elements(arena, input);
+    let (after_elements, after_tail) = list_elements(arena, after_ellipsis);
+
+    if input_elements.len() < after_elements.len() {
+        return Ok(false);
+    }
+    let rep_count = input_elements.len() - after_elements.len();
+
+    let mut vars = Vec::new();
+    pattern_vars(arena, literals, sub_pattern, &mut vars);
+    let mut seqs: HashMap<String, Vec<Binding>> =
+        vars.iter().map(|v| (v.clone(), Vec::new())).collect();
+
+    for elem in input_elements.iter().take(rep_count) {
+        let mut local = HashMap::new();
+        if !match_pattern(arena, literals, sub_pattern, *elem, &mut local)? {
+            return Ok(false);
+        }
+        for v in &vars {
+            let binding = local.remove(v).ok_or_else(|| {
+                format!("syntax-rules: pattern variable `{}` not bound in repetition.", v)
+            })?;
+            seqs.get_mut(v).unwrap().push(binding);
+        }
+    }
+
+    for (v, seq) in seqs {
+        if bindings.insert(v.clone(), Binding::Seq(seq)).is_some() {
+            return Err(format!(
+                "syntax-rules: pattern variable `{}` occurs more than once.",
+                v
+            ));
+        }
+    }
+
+    for (p, t) in after_elements.iter().zip(input_elements[rep_count..].iter()) {
+        if !match_pattern(arena, literals, *p, *t, bindings)? {
+            return Ok(false);
+        }
+    }
+
+    match_pattern(arena, literals, after_tail, input_tail, bindings)
+}
+
+/// Collects the pattern variables from `bindings` that occur in `template`.
+fn collect_template_vars(
+    bindings: &HashMap<String, Binding>,
+    arena: &Arena,
+    template: PoolPtr,
+    out: &mut Vec<String>,
+) {
+    match arena.get(template) {
+        Value::Symbol(s) => {
+            if bindings.contains_key(s) {
+                out.push(s.clone());
+            }
+        }
+        Value::Pair(car, cdr) => {
+            collect_template_vars(bindings, arena, car.get(), out);
+            collect_template_vars(bindings, arena, cdr.get(), out);
+        }
+        _ => {}
+    }
+}
+
+struct Expander<'a> {
+    arena: &'a Arena,
+    definition_environment: &'a RcEnv,
+    /// Caches the renamed identifier chosen for each template-introduced name, so every
+    /// occurrence of e.g. `tmp` in one expansion resolves to the same fresh symbol (or the
+    /// same syntactic closure, for names resolved in the definition environment).
+    renames: HashMap<String, PoolPtr>,
+}
+
+impl<'a> Expander<'a> {
+    /// Hygienically renames a template-introduced identifier (one that is not a pattern
+    /// variable). If `name` is bound in the macro's definition environment, it's wrapped in a
+    /// syntactic closure over that environment, so it resolves there no matter what's shadowed
+    /// at the use site. Otherwise it's a template-local identifier with no outer meaning (like
+    /// `tmp` in `swap!`), so a fresh gensym is generated for it instead.
+    fn rename(&mut self, name: &str) -> PoolPtr {
+        if let Some(p) = self.renames.get(name) {
+            return *p;
+        }
+        let arena = self.arena;
+        let renamed = if self.definition_environment.borrow().get(name).is_some() {
+            let closed_env = arena.insert(Value::Environment(self.definition_environment.clone()));
+            let expr = arena.insert(Value::Symbol(name.to_string()));
+            arena.insert(Value::SyntacticClosure(SyntacticClosure {
+                closed_env: RefCell::new(closed_env),
+                free_variables: vec![name.to_string()],
+                expr,
+            }))
+        } else {
+            arena.gensym(Some(name))
+        };
+        self.renames.insert(name.to_string(), renamed);
+        renamed
+    }
+
+    fn expand(&mut self, bindings: &HashMap<String, Binding>, template: PoolPtr) -> Result<PoolPtr, String> {
+        match self.arena.get(template) {
+            Value::Symbol(s) => self.expand_symbol(bindings, s, true),
+            Value::Pair(car, cdr) => {
+                // `(... template)` escapes the ellipsis inside `template`, copying it as-is
+                // (modulo pattern variable substitution and hygienic renaming).
+                if is_literal_symbol(self.arena, car.get(), ELLIPSIS) {
+                    if let Value::Pair(escaped, rest) = self.arena.get(cdr.get()) {
+                        if matches!(self.arena.get(rest.get()), Value::EmptyList) {
+                            return self.expand_no_ellipsis(bindings, escaped.get());
+                        }
+                    }
+                }
+                if let Some(after) = ellipsis_follows(self.arena, cdr.get()) {
+                    self.expand_ellipsis(bindings, car.get(), after)
+                } else {
+                    let head = self.expand(bindings, car.get())?;
+                    let tail = self.expand(bindings, cdr.get())?;
+                    Ok(self.arena.insert(Value::Pair(Cell::new(head), Cell::new(tail))))
+                }
+            }
+            _ => Ok(template),
+        }
+    }
+
+    /// Like `expand`, but never treats a following `...` as special; used to implement the
+    /// `(... template)` escape.
+    fn expand_no_ellipsis(
+        &mut self,
+        bindings: &HashMap<String, Binding>,
+        template: PoolPtr,
+    ) -> Result<PoolPtr, String> {
+        match self.arena.get(template) {
+            Value::Symbol(s) => self.expand_symbol(bindings, s, false),
+            Value::Pair(car, cdr) => {
+                let head = self.expand_no_ellipsis(bindings, car.get())?;
+                let tail = self.expand_no_ellipsis(bindings, cdr.get())?;
+                Ok(self.arena.insert(Value::Pair(Cell::new(head), Cell::new(tail))))
+            }
+            _ => Ok(template),
+        }
+    }
+
+    fn expand_symbol(
+        &mut self,
+        bindings: &HashMap<String, Binding>,
+        s: &str,
+        ellipsis_is_special: bool,
+    ) -> Result<PoolPtr, String> {
+        if let Some(binding) = bindings.get(s) {
+            binding.as_leaf().ok_or_else(|| {
+                format!(
+                    "syntax-rules: pattern variable `{}` used without enough following `...`.",
+                    s
+                )
+            })
+        } else if ellipsis_is_special && s == ELLIPSIS {
+            Ok(self.arena.insert(Value::Symbol(s.to_string())))
+        } else if SPECIAL_FORM_KEYWORDS.contains(&s) {
+            Ok(self.arena.insert(Value::Symbol(s.to_string())))
+        } else {
+            Ok(self.rename(s))
+        }
+    }
+
+    fn expand_ellipsis(
+        &mut self,
+        bindings: &HashMap<String, Binding>,
+        sub_template: PoolPtr,
+        after: PoolPtr,
+    ) -> Result<PoolPtr, String> {
+        let mut vars = Vec::new();
+        collect_template_vars(bindings, self.arena, sub_template, &mut vars);
+        let mut len = None;
+        for v in &vars {
+            if let Some(Binding::Seq(seq)) = bindings.get(v) {
+                match len {
+                    None => len = Some(seq.len()),
+                    Some(l) if l == seq.len() => {}
+                    Some(l) => {
+                        return Err(format!(
+                            "syntax-rules: mismatched `...` lengths: `{}` has {} repetitions, expected {}.",
+                            v,
+                            seq.len(),
+                            l
+                        ));
+                    }
+                }
+            }
+        }
+        let len = len.unwrap_or(0);
+
+        let mut expansions = Vec::with_capacity(len);
+        for i in 0..len {
+            let mut sub_bindings = bindings.clone();
+            for v in &vars {
+                if let Some(Binding::Seq(seq)) = bindings.get(v) {
+                    sub_bindings.insert(v.clone(), seq[i].clone());
+                }
+            }
+            expansions.push(self.expand(&sub_bindings, sub_template)?);
+        }
+
+        let tail = self.expand(bindings, after)?;
+        Ok(expansions.into_iter().rev().fold(tail, |acc, e| {
+            self.arena.insert(Value::Pair(Cell::new(e), Cell::new(acc)))
+        }))
+    }
+}
@@ -12,10 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use num_bigint::BigInt;
 use num_complex::Complex;
+use num_integer::Integer;
 use num_rational::BigRational;
-use num_traits::{Signed, ToPrimitive, Zero};
+use num_traits::{One, Signed, ToPrimitive, Zero};
 use value::Value;
 
 /// Checks that a vector has at least `min`, at most `max` entries.
@@ -34,33 +37,6 @@ pub fn check_len<T>(v: &[T], min: Option<usize>, max: Option<usize>) -> Result<(
     Ok(())
 }
 
-pub fn parse_num(s: &str, base: u32) -> Result<i64, String> {
-    if base > 36 {
-        panic!("Invalid base {}.", base);
-    }
-
-    let mut r = 0_i64;
-    let mut it = s.chars().peekable();
-    let reverse = it.peek() == Some(&'-');
-    if reverse {
-        it.next();
-    }
-
-    for d in it {
-        let n = d.to_digit(base);
-        if let Some(n) = n {
-            r = r * i64::from(base) + i64::from(n);
-        } else {
-            return Err(format!("Invalid digit for base {}: {}", base, d));
-        }
-    }
-
-    if reverse {
-        r = -r;
-    }
-    Ok(r)
-}
-
 pub fn str_to_char_vec(s: &str) -> Vec<char> {
     s.chars().collect()
 }
@@ -110,8 +86,217 @@ pub fn escape_symbol(s: &str) -> String {
     }
 }
 
+/// Converts `v` to the nearest `f64`, correctly rounded (ties to even), without the overflow or
+/// precision loss a naive `numer as f64 / denom as f64` would suffer when the numerator or
+/// denominator exceed `f64`'s range. Mirrors the shift-and-round bignum-to-float technique used
+/// by `num-bigint`'s own `ToPrimitive` conversions.
 pub fn rational_to_f64(v: &BigRational) -> f64 {
-    v.to_f64().unwrap()
+    if v.is_zero() {
+        return 0.0;
+    }
+    let negative = v.is_negative();
+    let numer = v.numer().abs();
+    let denom = v.denom().abs();
+
+    // Find `shift` such that `(numer << shift) / denom` has exactly 55 bits: a 53-bit mantissa
+    // (including its implicit leading one), a round bit, and a sticky bit.
+    const TARGET_BITS: i64 = 55;
+    let mut shift = TARGET_BITS + denom.bits() as i64 - numer.bits() as i64;
+    let (quotient, remainder) = loop {
+        let (q, r) = if shift >= 0 {
+            (&numer << shift as usize).div_rem(&denom)
+        } else {
+            numer.div_rem(&(&denom << (-shift) as usize))
+        };
+        let q_bits = q.bits() as i64;
+        if q_bits < TARGET_BITS {
+            shift += 1;
+        } else if q_bits > TARGET_BITS {
+            shift -= 1;
+        } else {
+            break (q, r);
+        }
+    };
+
+    // `numer / denom == quotient * 2^(-shift)`, and `quotient` is in `[2^54, 2^55)`, so the
+    // value's binary exponent (the `e` in `1.xxx * 2^e`) is `54 - shift`.
+    let mut exponent = 54 - shift;
+    let round_bit = (&quotient >> 1) & BigInt::one();
+    let sticky = !(&quotient & BigInt::one()).is_zero() || !remainder.is_zero();
+    let mut mantissa = &quotient >> 2;
+    if !round_bit.is_zero() && (sticky || mantissa.is_odd()) {
+        mantissa += BigInt::one();
+        if mantissa.bits() > 53 {
+            mantissa >>= 1;
+            exponent += 1;
+        }
+    }
+
+    if exponent >= f64::MAX_EXP as i64 {
+        return if negative {
+            std::f64::NEG_INFINITY
+        } else {
+            std::f64::INFINITY
+        };
+    }
+    if exponent < f64::MIN_EXP as i64 - 53 {
+        return if negative { -0.0 } else { 0.0 };
+    }
+
+    let magnitude = mantissa.to_f64().unwrap() * 2f64.powi((exponent - 52) as i32);
+    if negative {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// How many fractional digits [`rational_to_decimal_string`] should emit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Digits {
+    /// The terminating expansion, or the full repeating cycle parenthesized.
+    Shortest,
+    /// Exactly `n` fractional digits, rounded half-to-even.
+    Fixed(usize),
+}
+
+/// Renders `v` as an exact decimal string.
+///
+/// In [`Digits::Shortest`] mode, a terminating expansion is printed as-is (`1/4` -> `"0.25"`),
+/// while a repeating one has its repetend parenthesized (`1/3` -> `"0.(3)"`, `1/7` ->
+/// `"0.(142857)"`), found by recording the position of each remainder seen during long division
+/// and noticing when one recurs. In [`Digits::Fixed`] mode, the expansion is truncated to `n`
+/// fractional digits, rounding half-to-even.
+pub fn rational_to_decimal_string(v: &BigRational, digits: Digits) -> String {
+    let sign = if v.is_negative() { "-" } else { "" };
+    let v = v.abs();
+    let denom = v.denom().clone();
+    let (mut int_part, mut remainder) = v.numer().div_rem(&denom);
+
+    if remainder.is_zero() {
+        return format!("{}{}", sign, int_part);
+    }
+
+    let fractional = match digits {
+        Digits::Shortest => {
+            let mut seen: HashMap<BigInt, usize> = HashMap::new();
+            let mut digits = String::new();
+            let mut repetend_start = None;
+            while !remainder.is_zero() {
+                if let Some(&pos) = seen.get(&remainder) {
+                    repetend_start = Some(pos);
+                    break;
+                }
+                seen.insert(remainder.clone(), digits.len());
+                remainder *= BigInt::from(10);
+                let (digit, rem) = remainder.div_rem(&denom);
+                digits.push_str(&digit.to_string());
+                remainder = rem;
+            }
+            if let Some(pos) = repetend_start {
+                digits.insert(pos, '(');
+                digits.push(')');
+            }
+            digits
+        }
+        Digits::Fixed(n) => {
+            // Compute one extra digit beyond what we keep, to decide how to round.
+            let mut kept: Vec<u8> = Vec::with_capacity(n);
+            let mut extra = 0u8;
+            for i in 0..=n {
+                remainder *= BigInt::from(10);
+                let (digit, rem) = remainder.div_rem(&denom);
+                let digit = digit.to_u8().unwrap();
+                if i < n {
+                    kept.push(digit);
+                } else {
+                    extra = digit;
+                }
+                remainder = rem;
+            }
+            let last_kept_is_odd = kept.last().map_or(int_part.is_odd(), |d| d % 2 == 1);
+            let round_up = extra > 5 || (extra == 5 && (!remainder.is_zero() || last_kept_is_odd));
+            if round_up {
+                let mut carry = true;
+                for d in kept.iter_mut().rev() {
+                    if !carry {
+                        break;
+                    }
+                    if *d == 9 {
+                        *d = 0;
+                    } else {
+                        *d += 1;
+                        carry = false;
+                    }
+                }
+                if carry {
+                    int_part += 1;
+                }
+            }
+            kept.iter().map(|d| d.to_string()).collect()
+        }
+    };
+
+    if fractional.is_empty() {
+        format!("{}{}", sign, int_part)
+    } else {
+        format!("{}{}.{}", sign, int_part, fractional)
+    }
+}
+
+/// Renders `f` per R7RS: `+inf.0`/`-inf.0`/`+nan.0` for the special values, and otherwise the
+/// shortest decimal string that reads back to the exact same `f64`, always with a decimal point
+/// to mark it as inexact (`100.` rather than the ambiguous `100`).
+///
+/// Picks fixed-point or scientific notation by magnitude, the same way Rust's own `{:e}`/`{}`
+/// choose their digits - both are shortest-round-trip, so reformatting `{:e}`'s digits into fixed
+/// notation when the magnitude is unremarkable doesn't cost any precision.
+pub fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        return "+nan.0".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "+inf.0" } else { "-inf.0" }.to_string();
+    }
+    if f == 0.0 {
+        return if f.is_sign_negative() { "-0." } else { "0." }.to_string();
+    }
+
+    let sign = if f.is_sign_negative() { "-" } else { "" };
+    let sci = format!("{:e}", f.abs());
+    let e_pos = sci
+        .find('e')
+        .expect("scientific notation always contains 'e'");
+    let digits: String = sci[..e_pos].chars().filter(|c| *c != '.').collect();
+    let exponent: i32 = sci[e_pos + 1..]
+        .parse()
+        .expect("exponent is always a valid integer");
+
+    if exponent < -10 || exponent >= 21 {
+        let mantissa = if digits.len() == 1 {
+            digits.clone()
+        } else {
+            format!("{}.{}", &digits[..1], &digits[1..])
+        };
+        format!("{}{}e{}", sign, mantissa, exponent)
+    } else {
+        format!("{}{}", sign, fixed_notation(&digits, exponent))
+    }
+}
+
+/// Places the decimal point in `digits` (the significant digits of a number, most significant
+/// first) so that the first digit has place value `10^exponent`.
+fn fixed_notation(digits: &str, exponent: i32) -> String {
+    if exponent >= 0 {
+        let exponent = exponent as usize;
+        if digits.len() > exponent + 1 {
+            format!("{}.{}", &digits[..=exponent], &digits[exponent + 1..])
+        } else {
+            format!("{}{}.", digits, "0".repeat(exponent + 1 - digits.len()))
+        }
+    } else {
+        format!("0.{}{}", "0".repeat((-exponent - 1) as usize), digits)
+    }
 }
 
 pub fn integer_to_float(v: &BigInt) -> f64 {
@@ -175,16 +360,3 @@ pub fn is_numeric(a: &Value) -> bool {
             | Value::ComplexReal(_)
     )
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_num() {
-        assert_eq!(42, parse_num("101010", 2).unwrap());
-        assert_eq!(42, parse_num("2a", 16).unwrap());
-        assert_eq!(42, parse_num("42", 10).unwrap());
-        assert_eq!(-15, parse_num("-F", 16).unwrap());
-    }
-}
@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::any::Any;
 use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::rc::Rc;
 
@@ -24,6 +26,7 @@ use lex::CodeRange;
 use num_bigint::BigInt;
 use num_complex::Complex;
 use num_rational::BigRational;
+use num_traits::Signed;
 use primitives::{Port, Primitive, SyntacticClosure};
 use vm::Continuation;
 use {heap, util};
@@ -60,6 +63,67 @@ pub enum Value {
     Continuation(Continuation),
     CodeBlock(Box<CodeBlock>),
     Located(PoolPtr, Box<Locator>),
+    RecordType(RecordType),
+    Record {
+        rtype: PoolPtr,
+        fields: RefCell<Vec<PoolPtr>>,
+    },
+    /// An opaque native object handed in by an embedding host - see
+    /// `Interpreter::register_fn`. Scheme code can pass it around, store it, and hand it back to
+    /// a host-registered primitive, but can't inspect or construct one itself.
+    Foreign(Foreign),
+    /// The result of `(values ...)` with zero or more than one argument - never produced for
+    /// exactly one, since `values` just returns that argument directly. Only
+    /// `call-with-values` is meant to consume this; everywhere else a `Values` flows through
+    /// like any other value, which in practice means code that forwards a multiple-values
+    /// producer's result anywhere other than `call-with-values` gets this wrapper back instead
+    /// of an error, rather than the single-value coercion R7RS leaves unspecified there anyway.
+    Values(Vec<PoolPtr>),
+}
+
+/// Wraps a host-owned [`Any`] so it can live inside a [`Value`]: equality is pointer identity
+/// (two `Foreign`s are `eqv?` iff they share the same underlying `Rc`), and it prints as an
+/// opaque tag rather than attempting to inspect what's inside.
+#[derive(Clone)]
+pub struct Foreign(pub Rc<dyn Any>);
+
+impl fmt::Debug for Foreign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<foreign>")
+    }
+}
+
+impl PartialEq for Foreign {
+    fn eq(&self, other: &Foreign) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A user-defined record type created by `define-record-type`: carries everything needed to
+/// recognize and print instances of it. Stored in the arena as `Value::RecordType`; every
+/// `Value::Record` built from it points back at that single allocation via its `rtype` field, so
+/// "is this a `point`?" and "are these the same type?" are both just a `PoolPtr` comparison.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordType {
+    pub name: String,
+    /// A monotonically increasing id handed out at definition time (see [`RecordType::new`]).
+    /// Without it, two independently-`define-record-type`'d types that happen to share a name and
+    /// field list would compare equal under the derived `PartialEq` on `Value`, even though
+    /// they're unrelated types.
+    pub id: usize,
+    pub field_names: Vec<String>,
+}
+
+impl RecordType {
+    /// Interns a fresh id via [`Arena::new_mark`] so this type is distinguishable from any other,
+    /// even one defined with an identical name and field list.
+    pub fn new(arena: &Arena, name: String, field_names: Vec<String>) -> RecordType {
+        RecordType {
+            name,
+            id: arena.new_mark(),
+            field_names,
+        }
+    }
 }
 
 impl fmt::Display for Value {
@@ -69,7 +133,7 @@ impl fmt::Display for Value {
             Value::Unspecific => write!(f, "#unspecific"),
             Value::EofObject => write!(f, "#eof-object"),
             Value::EmptyList => write!(f, "()"),
-            Value::Real(r) => write!(f, "{}", r),
+            Value::Real(r) => write!(f, "{}", util::format_float(*r)),
             Value::Integer(i) => write!(f, "{}", i),
             Value::Rational(r) => write!(f, "{}", r),
             Value::ComplexReal(c) => write!(f, "{}", c),
@@ -106,6 +170,57 @@ impl fmt::Display for Value {
     }
 }
 
+impl Value {
+    /// Renders a numeric value in `radix` (2, 8, 10 or 16) rather than the `Display` impl's
+    /// fixed base 10 - the machinery behind `number->string`'s optional radix argument.
+    ///
+    /// Per R7RS, inexact values (`Real`/`ComplexReal`) can only be rendered in radix 10, since
+    /// there's no sensible non-decimal notation for a flonum's fractional part.
+    pub fn to_string_radix(&self, radix: u32) -> Result<String, String> {
+        fn signed_imaginary(re: String, im_negative: bool, im_magnitude: String) -> String {
+            if im_negative {
+                format!("{}-{}i", re, im_magnitude)
+            } else {
+                format!("{}+{}i", re, im_magnitude)
+            }
+        }
+
+        fn rational_radix(n: &BigRational, radix: u32) -> String {
+            format!(
+                "{}/{}",
+                n.numer().to_str_radix(radix),
+                n.denom().to_str_radix(radix)
+            )
+        }
+
+        match self {
+            Value::Integer(n) => Ok(n.to_str_radix(radix)),
+            Value::Rational(n) => Ok(rational_radix(n, radix)),
+            Value::Real(n) if radix == 10 => Ok(util::format_float(*n)),
+            Value::Real(_) => Err("inexact numbers can only be formatted in radix 10.".to_string()),
+            Value::ComplexInteger(c) => Ok(signed_imaginary(
+                c.re.to_str_radix(radix),
+                c.im.is_negative(),
+                c.im.abs().to_str_radix(radix),
+            )),
+            Value::ComplexRational(c) => Ok(signed_imaginary(
+                rational_radix(&c.re, radix),
+                c.im.numer().is_negative(),
+                rational_radix(&c.im.abs(), radix),
+            )),
+            Value::ComplexReal(c) if radix == 10 => Ok(signed_imaginary(
+                util::format_float(c.re),
+                c.im.is_sign_negative(),
+                util::format_float(c.im.abs()),
+            )),
+            Value::ComplexReal(_) => {
+                Err("inexact numbers can only be formatted in radix 10.".to_string())
+            }
+            _ => Err(format!("not a number: {}", self.pretty_print())),
+        }
+    }
+}
+
 impl heap::Inventory for Value {
     fn inventory(&self, v: &mut heap::PtrVec) {
         match self {
@@ -138,16 +253,190 @@ impl heap::Inventory for Value {
             Value::Port(p) => p.inventory(v),
             Value::Continuation(c) => c.inventory(v),
             Value::CodeBlock(c) => c.inventory(v),
+            Value::Record { rtype, fields } => {
+                v.push(*rtype);
+                for val in fields.borrow().iter() {
+                    v.push(*val);
+                }
+            }
+            Value::Values(vals) => {
+                for val in vals.iter() {
+                    v.push(*val);
+                }
+            }
             _ => (),
         }
     }
 }
 
-impl Value {
+impl PoolPtr {
+    /// Pretty-prints the value behind this pointer. Entering this through the pointer (rather
+    /// than a bare [`Value`]) lets us seed the cycle-detection set with the root itself, so a
+    /// value that is *directly* self-referential (e.g. `#0=(a . #0#)`) is also caught.
     pub fn pretty_print(&self) -> String {
+        let mut seen = HashSet::new();
+        match &**self {
+            Value::Pair(_, _) | Value::Vector(_) => {
+                seen.insert(*self);
+            }
+            _ => (),
+        }
+        self.long_lived().pretty_print_seen(&mut seen)
+    }
+
+    /// Same as `pretty_print`, but correct on shared and circular structure: any `Pair`/`Vector`
+    /// node reachable in more than one way is printed with an R7RS datum label (`#N=` the first
+    /// time it's emitted, `#N#` on every later reference, instead of being walked again).
+    ///
+    /// Works in two passes: first [`count_reachable`] walks the graph once to find out how many
+    /// times each node is reached (a node reached a second time - whether that's because it's
+    /// shared or because we looped back into a cycle - is not walked any further, so this always
+    /// terminates); then [`SharedWriter`] walks it again to actually produce output, consulting
+    /// those counts to decide which nodes need a label.
+    pub fn write_shared(&self) -> String {
+        let mut reach_counts = HashMap::new();
+        count_reachable(*self, &mut reach_counts);
+
+        let mut writer = SharedWriter {
+            reach_counts,
+            labels: HashMap::new(),
+            printed: HashSet::new(),
+            next_label: 0,
+        };
+        writer.write(*self)
+    }
+}
+
+/// Counts, in `counts`, how many times each `Pair`/`Vector` node under `ptr` is reached. A node
+/// already at count 1 is not descended into again: reaching it a second time already proves it
+/// needs a datum label, and re-walking it (possibly forever, if that second reach closes a cycle)
+/// wouldn't change that.
+fn count_reachable(ptr: PoolPtr, counts: &mut HashMap<PoolPtr, usize>) {
+    match &*ptr {
+        Value::Pair(car, cdr) => {
+            let count = counts.entry(ptr).or_insert(0);
+            *count += 1;
+            if *count > 1 {
+                return;
+            }
+            count_reachable(car.get(), counts);
+            count_reachable(cdr.get(), counts);
+        }
+        Value::Vector(vals) => {
+            let count = counts.entry(ptr).or_insert(0);
+            *count += 1;
+            if *count > 1 {
+                return;
+            }
+            for val in vals.borrow().iter() {
+                count_reachable(*val, counts);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Drives the second pass of [`PoolPtr::write_shared`]: `reach_counts` (from [`count_reachable`])
+/// says which nodes need a label; `labels` assigns each of those a sequential number the first
+/// time it's printed; `printed` remembers which labeled nodes have already had their `#N=...`
+/// written out, so later references print the bare `#N#` instead of recursing again.
+struct SharedWriter {
+    reach_counts: HashMap<PoolPtr, usize>,
+    labels: HashMap<PoolPtr, usize>,
+    printed: HashSet<PoolPtr>,
+    next_label: usize,
+}
+
+impl SharedWriter {
+    fn is_shared(&self, ptr: PoolPtr) -> bool {
+        self.reach_counts.get(&ptr).copied().unwrap_or(0) > 1
+    }
+
+    fn write(&mut self, ptr: PoolPtr) -> String {
+        if !self.is_shared(ptr) {
+            return self.write_body(ptr);
+        }
+        if self.printed.contains(&ptr) {
+            return format!("#{}#", self.labels[&ptr]);
+        }
+        let label = self.next_label;
+        self.next_label += 1;
+        self.labels.insert(ptr, label);
+        self.printed.insert(ptr);
+        format!("#{}={}", label, self.write_body(ptr))
+    }
+
+    fn write_body(&mut self, ptr: PoolPtr) -> String {
+        match &*ptr {
+            Value::Pair(_, _) => self.write_pair(ptr),
+            Value::Vector(_) => self.write_vector(ptr),
+            v => v.pretty_print(),
+        }
+    }
+
+    fn write_pair(&mut self, ptr: PoolPtr) -> String {
+        let mut s = String::from("(");
+        self.write_pair_tail(ptr, &mut s);
+        s
+    }
+
+    /// Appends the elements of the pair chain starting at `ptr` to `s`, closing with `)`. Stops
+    /// flattening the chain as soon as the cdr is itself a shared/cyclic pair, so that pair gets
+    /// its own `#N=`/`#N#` marker (via `write`) rather than being spliced in as if it were plain
+    /// list tail.
+    fn write_pair_tail(&mut self, ptr: PoolPtr, s: &mut String) {
+        match &*ptr {
+            Value::Pair(car, cdr) => {
+                s.push_str(&self.write(car.get()));
+                let cdr = cdr.get();
+                match &*cdr {
+                    Value::EmptyList => s.push(')'),
+                    Value::Pair(_, _) if !self.is_shared(cdr) => {
+                        s.push(' ');
+                        self.write_pair_tail(cdr, s);
+                    }
+                    _ => {
+                        s.push_str(" . ");
+                        s.push_str(&self.write(cdr));
+                        s.push(')');
+                    }
+                }
+            }
+            _ => unreachable!("write_pair_tail called on a non-pair: {:?}", &*ptr),
+        }
+    }
+
+    fn write_vector(&mut self, ptr: PoolPtr) -> String {
+        match &*ptr {
+            Value::Vector(vals) => {
+                let elems = vals.borrow().clone();
+                let contents = elems
+                    .iter()
+                    .map(|e| self.write(*e))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("#({})", contents)
+            }
+            _ => unreachable!("write_vector called on a non-vector: {:?}", &*ptr),
+        }
+    }
+}
+
+impl Value {
+    fn pretty_print(&self) -> String {
+        let mut seen = HashSet::new();
+        self.pretty_print_seen(&mut seen)
+    }
+
+    /// Same as [`pretty_print`], but tracks the pairs/vectors already entered in `seen` so that
+    /// datum-label cycles (e.g. `#0=(a . #0#)`) print as `#[cycle]` instead of recursing forever.
+    ///
+    /// `seen` only needs to hold pairs/vectors, since those are the only container types whose
+    /// cells can be back-patched by the reader to form a cycle.
+    fn pretty_print_seen(&self, seen: &mut HashSet<PoolPtr>) -> String {
         match self {
-            Value::Pair(_, _) => self.print_pair(),
-            Value::Vector(_) => self.print_vector(),
+            Value::Pair(_, _) => self.print_pair(seen),
+            Value::Vector(_) => self.print_vector(seen),
             Value::SyntacticClosure(SyntacticClosure {
                 closed_env,
                 free_variables,
@@ -164,20 +453,55 @@ impl Value {
                 None => "#<anonymous procedure>".to_string(),
             },
             Value::Primitive(p) => format!("#<primitive {}>", p.name),
+            Value::RecordType(rt) => format!("#<record-type {}>", rt.name),
+            Value::Record { rtype, fields } => {
+                let rt = match &**rtype {
+                    Value::RecordType(rt) => rt,
+                    v => panic!("record's rtype is not a record type: {:?}", v),
+                };
+                let rendered_fields = rt
+                    .field_names
+                    .iter()
+                    .zip(fields.borrow().iter())
+                    .map(|(name, val)| format!("{}={}", name, Value::print_child(*val, seen)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("#<record {} {}>", rt.name, rendered_fields)
+            }
             _ => format!("{}", self),
         }
     }
 
-    fn print_pair(&self) -> String {
-        fn _print_pair(p: &Value, s: &mut String) {
+    /// Prints `ptr`, entering it into `seen` first; if it's already present, we're in a cycle.
+    fn print_child(ptr: PoolPtr, seen: &mut HashSet<PoolPtr>) -> String {
+        match &*ptr {
+            Value::Pair(_, _) | Value::Vector(_) => {
+                if !seen.insert(ptr) {
+                    return "#[cycle]".to_string();
+                }
+                ptr.long_lived().pretty_print_seen(seen)
+            }
+            v => v.pretty_print_seen(seen),
+        }
+    }
+
+    fn print_pair(&self, seen: &mut HashSet<PoolPtr>) -> String {
+        fn _print_pair(p: &Value, s: &mut String, seen: &mut HashSet<PoolPtr>) {
             match p {
                 Value::Pair(a, b) => {
-                    s.push_str(&a.get().pretty_print()[..]);
-                    if let Value::EmptyList = &*b.get() {
-                        s.push(')');
-                    } else {
-                        s.push(' ');
-                        _print_pair(&*b.get(), s);
+                    s.push_str(&Value::print_child(a.get(), seen)[..]);
+                    let cdr = b.get();
+                    match &*cdr {
+                        Value::EmptyList => s.push(')'),
+                        Value::Pair(_, _) => {
+                            if !seen.insert(cdr) {
+                                s.push_str(" . #[cycle])");
+                            } else {
+                                s.push(' ');
+                                _print_pair(&*cdr, s, seen);
+                            }
+                        }
+                        _ => s.push_str(&format!(". {})", Value::print_child(cdr, seen))[..]),
                     }
                 }
                 Value::EmptyList => {
@@ -192,7 +516,7 @@ impl Value {
         match self {
             Value::Pair(_, _) | Value::EmptyList => {
                 let mut s = "(".to_string();
-                _print_pair(self, &mut s);
+                _print_pair(self, &mut s, seen);
                 s
             }
             _ => panic!(
@@ -202,12 +526,12 @@ impl Value {
         }
     }
 
-    fn print_vector(&self) -> String {
+    fn print_vector(&self, seen: &mut HashSet<PoolPtr>) -> String {
         if let Value::Vector(vals) = self {
             let contents = vals
                 .borrow()
                 .iter()
-                .map(|e| e.pretty_print())
+                .map(|e| Value::print_child(*e, seen))
                 .collect::<Vec<_>>()
                 .join(" ");
             format!("#({})", contents)
@@ -291,6 +615,13 @@ impl Value {
         }
     }
 
+    pub fn try_get_bytevector(&self) -> Option<&RefCell<Vec<u8>>> {
+        match self {
+            Value::ByteVector(v) => Some(v),
+            _ => None,
+        }
+    }
+
     pub fn try_get_symbol(&self) -> Option<&str> {
         match self {
             Value::Symbol(s) => Some(s),
@@ -325,6 +656,27 @@ impl Value {
             _ => None,
         }
     }
+
+    pub fn try_get_code_block(&self) -> Option<&CodeBlock> {
+        match self {
+            Value::CodeBlock(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn try_get_record_type(&self) -> Option<&RecordType> {
+        match self {
+            Value::RecordType(rt) => Some(rt),
+            _ => None,
+        }
+    }
+
+    pub fn try_get_record(&self) -> Option<(PoolPtr, &RefCell<Vec<PoolPtr>>)> {
+        match self {
+            Value::Record { rtype, fields } => Some((*rtype, fields)),
+            _ => None,
+        }
+    }
 }
 
 pub fn list_from_vec(arena: &Arena, vals: &[PoolPtr]) -> PoolPtr {
@@ -350,22 +702,74 @@ pub fn eqv(left: PoolPtr, right: PoolPtr) -> bool {
         (Value::Vector(_), Value::Vector(_)) => left == right,
         (Value::String(_), Value::String(_)) => left == right,
         (Value::Lambda { .. }, Value::Lambda { .. }) => left == right,
+        (Value::RecordType(_), Value::RecordType(_)) => left == right,
+        (Value::Record { .. }, Value::Record { .. }) => left == right,
+        (Value::Foreign(a), Value::Foreign(b)) => a == b,
         _ => false,
     }
 }
 
-//TODO should not loop on recursive data (R7RS)
 pub fn equal(left: PoolPtr, right: PoolPtr) -> bool {
+    let mut seen = HashSet::new();
+    equal_seen(left, right, &mut seen)
+}
+
+/// Same as [`equal`], but tracks pairs of pointers already being compared in `seen`, so that
+/// datum-label cycles (e.g. `#0=(a . #0#)`) don't recurse forever: a pair we're already in the
+/// middle of comparing is assumed equal, consistent with R7RS's requirement that `equal?`
+/// terminate on circular structure.
+fn equal_seen(left: PoolPtr, right: PoolPtr, seen: &mut HashSet<(PoolPtr, PoolPtr)>) -> bool {
+    // Short-circuits shared (but not necessarily cyclic) structure: without this, comparing two
+    // graphs that both share a common, deeply-nested substructure would re-walk that substructure
+    // once per reference to it, rather than once total.
+    if left == right {
+        return true;
+    }
     match (&*left, &*right) {
         (Value::Pair(left_car, left_cdr), Value::Pair(right_car, right_cdr)) => {
-            equal(left_car.get(), right_car.get()) && equal(left_cdr.get(), right_cdr.get())
+            if !seen.insert((left, right)) {
+                return true;
+            }
+            equal_seen(left_car.get(), right_car.get(), seen)
+                && equal_seen(left_cdr.get(), right_cdr.get(), seen)
+        }
+        (Value::Vector(left_vec), Value::Vector(right_vec)) => {
+            if !seen.insert((left, right)) {
+                return true;
+            }
+            let left_vec = left_vec.borrow();
+            let right_vec = right_vec.borrow();
+            left_vec.len() == right_vec.len()
+                && left_vec
+                    .iter()
+                    .zip(right_vec.iter())
+                    .all(|(l, r)| equal_seen(*l, *r, seen))
         }
-        (Value::Vector(left_vec), Value::Vector(right_vec)) => left_vec
-            .borrow()
-            .iter()
-            .zip(right_vec.borrow().iter())
-            .all(|(l, r)| equal(*l, *r)),
         (Value::String(left_string), Value::String(right_string)) => left_string == right_string,
+        (
+            Value::Record {
+                rtype: left_rtype,
+                fields: left_fields,
+            },
+            Value::Record {
+                rtype: right_rtype,
+                fields: right_fields,
+            },
+        ) => {
+            if left_rtype != right_rtype {
+                return false;
+            }
+            if !seen.insert((left, right)) {
+                return true;
+            }
+            let left_fields = left_fields.borrow();
+            let right_fields = right_fields.borrow();
+            left_fields.len() == right_fields.len()
+                && left_fields
+                    .iter()
+                    .zip(right_fields.iter())
+                    .all(|(l, r)| equal_seen(*l, *r, seen))
+        }
         _ => eqv(left, right),
     }
 }
@@ -397,6 +801,18 @@ pub fn strip_locators(arena: &Arena, value: PoolPtr) -> RootPtr {
                 roots.iter().map(|v| v.pp()).collect(),
             )))
         }
+        Value::Record { rtype, fields } => {
+            let new_rtype = strip_locators(arena, *rtype);
+            let new_fields = fields
+                .borrow()
+                .iter()
+                .map(|v| strip_locators(arena, *v))
+                .collect::<Vec<_>>();
+            arena.insert_rooted(Value::Record {
+                rtype: new_rtype.pp(),
+                fields: RefCell::new(new_fields.iter().map(|v| v.pp()).collect()),
+            })
+        }
         Value::Located(v, _) => strip_locators(arena, *v),
         _ => arena.root(value),
     }
@@ -406,6 +822,127 @@ pub fn strip_locators(arena: &Arena, value: PoolPtr) -> RootPtr {
 mod tests {
     use super::*;
 
+    #[test]
+    fn equal_terminates_on_cyclic_pairs() {
+        let arena = ::arena::Arena::default();
+
+        // `a` = #0=(1 . #0#), built by hand the way `set-cdr!` would.
+        let a = arena.insert(Value::Pair(
+            Cell::new(arena.insert(Value::Integer(1.into()))),
+            Cell::new(arena.empty_list),
+        ));
+        if let Value::Pair(_, cdr) = &*a {
+            cdr.set(a);
+        }
+
+        // `b` is a separate, structurally identical cycle.
+        let b = arena.insert(Value::Pair(
+            Cell::new(arena.insert(Value::Integer(1.into()))),
+            Cell::new(arena.empty_list),
+        ));
+        if let Value::Pair(_, cdr) = &*b {
+            cdr.set(b);
+        }
+
+        assert!(equal(a, a));
+        assert!(equal(a, b));
+    }
+
+    #[test]
+    fn write_shared_labels_cycles_and_shared_substructure() {
+        let arena = ::arena::Arena::default();
+
+        // `x` = #0=(1 2 . #0#), via `(define x (list 1 2)) (set-cdr! (cdr x) x)`.
+        let two = arena.insert(Value::Pair(
+            Cell::new(arena.insert(Value::Integer(2.into()))),
+            Cell::new(arena.empty_list),
+        ));
+        let x = arena.insert(Value::Pair(
+            Cell::new(arena.insert(Value::Integer(1.into()))),
+            Cell::new(two),
+        ));
+        if let Value::Pair(_, cdr) = &*two {
+            cdr.set(x);
+        }
+        assert_eq!("#0=(1 2 . #0#)", x.write_shared());
+
+        // A non-circular but shared structure: `(list y y)` where `y` = `(9)`.
+        let y = arena.insert(Value::Pair(
+            Cell::new(arena.insert(Value::Integer(9.into()))),
+            Cell::new(arena.empty_list),
+        ));
+        let shared = list_from_vec(&arena, &[y, y]);
+        assert_eq!("(#0=(9) #0#)", shared.write_shared());
+    }
+
+    #[test]
+    fn to_string_radix_formats_exact_numbers_in_other_bases() {
+        assert_eq!(
+            "ff",
+            Value::Integer(255.into()).to_string_radix(16).unwrap()
+        );
+        assert_eq!(
+            "-101",
+            Value::Integer((-5).into()).to_string_radix(2).unwrap()
+        );
+        assert_eq!(
+            "11/100",
+            Value::Rational(Box::new(BigRational::new(3.into(), 4.into())))
+                .to_string_radix(2)
+                .unwrap()
+        );
+        // Negative imaginary parts use `-`, not `+-`.
+        assert_eq!(
+            "3-5i",
+            Value::ComplexInteger(Box::new(Complex::new(3.into(), (-5).into())))
+                .to_string_radix(10)
+                .unwrap()
+        );
+        assert!(Value::Real(1.5).to_string_radix(16).is_err());
+    }
+
+    #[test]
+    fn records_compare_by_type_identity_and_recurse_on_fields() {
+        let arena = ::arena::Arena::default();
+
+        let point_type = arena.insert(Value::RecordType(RecordType::new(
+            &arena,
+            "point".to_string(),
+            vec!["x".to_string(), "y".to_string()],
+        )));
+        // Same name and fields, but a distinct `define-record-type` - must stay distinguishable.
+        let other_point_type = arena.insert(Value::RecordType(RecordType::new(
+            &arena,
+            "point".to_string(),
+            vec!["x".to_string(), "y".to_string()],
+        )));
+        match (&*point_type, &*other_point_type) {
+            (Value::RecordType(a), Value::RecordType(b)) => assert_ne!(a, b),
+            _ => panic!("expected record types"),
+        }
+
+        let make_point = |x: i64, y: i64, rtype: PoolPtr| {
+            arena.insert(Value::Record {
+                rtype,
+                fields: RefCell::new(vec![
+                    arena.insert(Value::Integer(x.into())),
+                    arena.insert(Value::Integer(y.into())),
+                ]),
+            })
+        };
+
+        let p1 = make_point(1, 2, point_type);
+        let p2 = make_point(1, 2, point_type);
+        let p3 = make_point(1, 2, other_point_type);
+
+        assert!(eqv(p1, p1));
+        assert!(!eqv(p1, p2), "eqv? only holds for the same allocation");
+        assert!(equal(p1, p2), "same type, same fields");
+        assert!(!equal(p1, p3), "distinct record types, even if shaped the same");
+
+        assert_eq!("#<record point x=1 y=2>", p1.pretty_print());
+    }
+
     #[test]
     fn format_atoms() {
         assert_eq!("3.45", &format!("{}", Value::Real(3.45)));
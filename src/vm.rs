@@ -16,17 +16,36 @@
 
 use std::cell::{Cell, RefCell};
 use std::fmt::Write;
+use std::rc::Rc;
 use std::sync::atomic::Ordering::Relaxed;
 
 use arena::Arena;
-use environment::ActivationFrame;
+use compile::CodeBlock;
+use environment::{dump_frames, ActivationFrame, Environment};
 use heap::{Inventory, PoolPtr, PtrVec, RootPtr};
-use primitives::PrimitiveImplementation;
+use observer::Observer;
+use primitives::{
+    close_port, get_output_string, new_output_string_port, open_input_file, open_output_file,
+    PrimitiveImplementation,
+};
 use value::{list_from_vec, Value};
-use {heap, Interpreter, INPUT_PORT_INDEX, OUTPUT_PORT_INDEX};
+use {heap, Interpreter, ERROR_HANDLER_INDEX, INPUT_PORT_INDEX, OUTPUT_PORT_INDEX};
 
 static MAX_RECURSION_DEPTH: usize = 1000;
 
+/// Resource ceilings for one [`Interpreter`], checked by [`run`] (instructions, and allocations
+/// as counted by [`Arena::try_insert`](arena::Arena::try_insert)) and by `invoke` (recursion
+/// depth), so untrusted Scheme can be
+/// evaluated deterministically rather than being trusted to terminate on its own - see
+/// [`Interpreter::new_sandboxed`]. `None` means "no limit"; a `None` `max_recursion_depth` still
+/// falls back to [`MAX_RECURSION_DEPTH`], exactly as if no limits were configured at all.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExecutionLimits {
+    pub max_instructions: Option<usize>,
+    pub max_allocations: Option<usize>,
+    pub max_recursion_depth: Option<usize>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Instruction {
     /// Loads a constant to the VM's value register. The attached usize is
@@ -141,6 +160,11 @@ pub struct Vm {
     fun: PoolPtr,
     root_code_block: PoolPtr,
     current_code_block: PoolPtr,
+    /// The `dynamic-wind` calls currently in effect, outermost first, as `(before, after)` thunk
+    /// pairs. Diffed against a continuation's own snapshot of this stack when that continuation is
+    /// invoked, so jumping across a `dynamic-wind` boundary still runs the `after`/`before` thunks
+    /// it's supposed to. See `rewind_to` and `call_cc`.
+    winders: Vec<(PoolPtr, PoolPtr)>,
 }
 
 impl Vm {
@@ -171,6 +195,10 @@ impl Inventory for Vm {
         for rp in self.return_stack.iter() {
             v.push(rp.code_block);
         }
+        for &(before, after) in self.winders.iter() {
+            v.push(before);
+            v.push(after);
+        }
     }
 }
 
@@ -186,15 +214,24 @@ pub fn run(code: RootPtr, pc: usize, env: PoolPtr, int: &Interpreter) -> Result<
         fun: int.arena.unspecific,
         root_code_block: code.pp(),
         current_code_block: code.pp(),
+        winders: Vec::new(),
     };
     int.arena.root_vm(&vm);
+    int.arena.reset_allocation_count();
+    let mut instructions_run: usize = 0;
     let res = loop {
         if int.interruptor.load(Relaxed) {
             int.interruptor.store(false, Relaxed);
-            break Err(int
-                .arena
-                .insert_rooted(Value::String(RefCell::new("interrupted".into()))));
+            break handle_error(
+                int,
+                &mut vm,
+                raise_condition(&int.arena, "interrupted", "execution interrupted".into()),
+            );
         };
+        if let Err(e) = check_budgets(int, instructions_run) {
+            break handle_error(int, &mut vm, e);
+        }
+        instructions_run += 1;
         match run_one_instruction(int, &mut vm) {
             Ok(true) => break Ok(int.arena.root(vm.value)),
             Ok(_) => (),
@@ -205,11 +242,38 @@ pub fn run(code: RootPtr, pc: usize, env: PoolPtr, int: &Interpreter) -> Result<
     res
 }
 
+/// Checks `int.limits` against how much work has been done so far this `run`, aborting with a
+/// distinct message (rather than e.g. panicking or hanging) as soon as a configured ceiling is
+/// crossed. A single pair of counter compares, run once per dispatched [`Instruction`] - see
+/// [`ExecutionLimits`].
+fn check_budgets(int: &Interpreter, instructions_run: usize) -> Result<(), Error> {
+    if let Some(max) = int.limits.max_instructions {
+        if instructions_run >= max {
+            return Err(budget_exceeded(int, "instruction"));
+        }
+    }
+    if let Some(max) = int.limits.max_allocations {
+        if int.arena.allocation_count() >= max {
+            return Err(budget_exceeded(int, "allocation"));
+        }
+    }
+    Ok(())
+}
+
+fn budget_exceeded(int: &Interpreter, kind: &str) -> Error {
+    Error::Abort(int.arena.insert_rooted(Value::String(RefCell::new(format!(
+        "{} budget exceeded",
+        kind
+    )))))
+}
+
 fn run_one_instruction(int: &Interpreter, vm: &mut Vm) -> Result<bool, Error> {
     let arena = &int.arena;
     let code = vm.current_code_block.long_lived().get_code_block();
     let instr = code.instructions[vm.pc];
-    // println!("running {:?}, pc {}", instr, vm.pc);
+    int.observer
+        .borrow()
+        .on_instruction(vm.current_code_block, vm.pc, instr);
     match instr {
         Instruction::Constant(v) => vm.set_value(code.constants[v]),
         Instruction::JumpFalse(offset) => {
@@ -316,6 +380,7 @@ fn run_one_instruction(int: &Interpreter, vm: &mut Vm) -> Result<bool, Error> {
             vm.env = vm.value;
         }
         Instruction::Return => {
+            int.observer.borrow().on_leave_frame(vm.current_code_block);
             let ReturnPoint { code_block, pc } = vm
                 .return_stack
                 .pop()
@@ -415,86 +480,136 @@ fn run_one_instruction(int: &Interpreter, vm: &mut Vm) -> Result<bool, Error> {
     Ok(false)
 }
 
+/// Dispatches a call to whatever `vm.fun` now holds, looping back on itself - rather than
+/// recursing on the Rust stack - whenever `apply`/`call-with-current-continuation` resolve to
+/// another call rather than a value: each just rewrites `vm.fun`/`vm.value` to the flattened
+/// call they amount to and `continue`s, so `(apply f args)` or a `call/cc` invocation costs one
+/// loop iteration here rather than one native stack frame. Only `Value::Lambda` - which hands
+/// control back to the `run` driver loop instead of looping here - and a handful of one-shot
+/// primitives actually return `Ok(())`.
 fn invoke(int: &Interpreter, vm: &mut Vm, tail: bool) -> Result<(), Error> {
     let arena = &int.arena;
-    match vm.fun.long_lived() {
-        Value::Lambda { code, frame } => {
-            if !tail {
-                if vm.return_stack.len() > MAX_RECURSION_DEPTH {
-                    return Err(Error::Abort(arena.insert_rooted(Value::String(
-                        RefCell::new("maximum recursion depth exceeded".into()),
-                    ))));
+    let mut tail = tail;
+    loop {
+        match vm.fun.long_lived() {
+            Value::Lambda { code, frame } => {
+                if !tail {
+                    let max_recursion_depth = int
+                        .limits
+                        .max_recursion_depth
+                        .unwrap_or(MAX_RECURSION_DEPTH);
+                    if vm.return_stack.len() > max_recursion_depth {
+                        return Err(raise_condition(
+                            arena,
+                            "stack-overflow",
+                            "maximum recursion depth exceeded".into(),
+                        ));
+                    }
+                    vm.return_stack.push(vm.get_return_point());
                 }
-                vm.return_stack.push(vm.get_return_point());
+                vm.env = *frame;
+                vm.current_code_block = *code;
+                vm.pc = 0;
+                int.observer.borrow().on_enter_frame(*code);
+                return Ok(());
             }
-            vm.env = *frame;
-            vm.current_code_block = *code;
-            vm.pc = 0;
-        }
-        Value::Primitive(p) => {
-            match p.implementation {
-                PrimitiveImplementation::Simple(i) => {
-                    let af = vm.value.long_lived().get_activation_frame();
-                    let values = &af.borrow().values;
-                    vm.set_value(
-                        i(arena, values)
-                            .map_err(|e| raise_string(arena, format!("In {:?}: {}", p, e)))?,
-                    );
-                }
-                PrimitiveImplementation::Io(i) => {
-                    let af = vm.value.long_lived().get_activation_frame();
-                    let values = &af.borrow().values;
-                    let global_env = vm.global_env.long_lived().get_activation_frame().borrow();
-                    let input_port = global_env.values[INPUT_PORT_INDEX];
-                    let output_port = global_env.values[OUTPUT_PORT_INDEX];
-                    vm.set_value(
-                        i(arena, input_port, output_port, values)
-                            .map_err(|e| raise_string(arena, format!("In {:?}: {}", p, e)))?,
-                    );
+            Value::Primitive(p) => {
+                match &p.implementation {
+                    PrimitiveImplementation::Simple(i) => {
+                        let af = vm.value.long_lived().get_activation_frame();
+                        let values = &af.borrow().values;
+                        vm.set_value(
+                            i(arena, values)
+                                .map_err(|e| raise_string(arena, format!("In {:?}: {}", p, e)))?,
+                        );
+                    }
+                    PrimitiveImplementation::Host(i) => {
+                        let af = vm.value.long_lived().get_activation_frame();
+                        let values = &af.borrow().values;
+                        vm.set_value(
+                            i(arena, values)
+                                .map_err(|e| raise_string(arena, format!("In {:?}: {}", p, e)))?,
+                        );
+                    }
+                    PrimitiveImplementation::Io(i) => {
+                        let af = vm.value.long_lived().get_activation_frame();
+                        let values = &af.borrow().values;
+                        let global_env =
+                            vm.global_env.long_lived().get_activation_frame().borrow();
+                        let input_port = global_env.values[INPUT_PORT_INDEX];
+                        let output_port = global_env.values[OUTPUT_PORT_INDEX];
+                        vm.set_value(
+                            i(arena, input_port, output_port, values)
+                                .map_err(|e| raise_string(arena, format!("In {:?}: {}", p, e)))?,
+                        );
+                    }
+                    PrimitiveImplementation::Apply => {
+                        apply_setup(arena, vm)?;
+                        continue;
+                    }
+                    PrimitiveImplementation::CallCC => {
+                        call_cc_setup(int, vm)?;
+                        tail = true;
+                        continue;
+                    }
+                    PrimitiveImplementation::Abort => return Err(raise(arena, vm, true)),
+                    PrimitiveImplementation::Raise => return Err(raise(arena, vm, false)),
+                    PrimitiveImplementation::Eval => eval(int, vm)?,
+                    PrimitiveImplementation::WithOutputToString => {
+                        with_output_to_string(int, vm)?
+                    }
+                    PrimitiveImplementation::WithOutputToFile => with_output_to_file(int, vm)?,
+                    PrimitiveImplementation::WithInputFromFile => with_input_from_file(int, vm)?,
+                    PrimitiveImplementation::CallWithOutputString => {
+                        call_with_output_string(int, vm)?
+                    }
+                    PrimitiveImplementation::CallWithPort => call_with_port(int, vm)?,
+                    PrimitiveImplementation::CurrentEnvironmentBindings => {
+                        current_environment_bindings(int, vm)?
+                    }
+                    PrimitiveImplementation::DynamicWind => dynamic_wind(int, vm)?,
+                    PrimitiveImplementation::WithExceptionHandler => {
+                        with_exception_handler(int, vm)?
+                    }
+                    PrimitiveImplementation::CallWithValues => call_with_values(int, vm)?,
+                };
+                vm.pc += 1;
+                return Ok(());
+            }
+            Value::Continuation(c) => {
+                let af = vm.value.long_lived().get_activation_frame().borrow();
+                if af.values.len() != 1 {
+                    return Err(raise_string(
+                        arena,
+                        "invoking continuation with more than one argument".into(),
+                    ));
                 }
-                PrimitiveImplementation::Apply => apply(int, vm, tail)?,
-                PrimitiveImplementation::CallCC => call_cc(int, vm)?,
-                PrimitiveImplementation::Abort => return Err(raise(arena, vm, true)),
-                PrimitiveImplementation::Raise => return Err(raise(arena, vm, false)),
-                PrimitiveImplementation::Eval => eval(int, vm)?,
-            };
-            match p.implementation {
-                PrimitiveImplementation::Apply | PrimitiveImplementation::CallCC => {}
-                _ => vm.pc += 1,
-            };
-        }
-        Value::Continuation(c) => {
-            let af = vm.value.long_lived().get_activation_frame().borrow();
-            if af.values.len() != 1 {
+                rewind_to(int, vm, &c.winders)?;
+                vm.stack = c.stack.clone();
+                vm.return_stack = c.return_stack.clone();
+                let ReturnPoint { code_block, pc } = vm
+                    .return_stack
+                    .pop()
+                    .expect("popping continuation with no return address");
+                vm.current_code_block = code_block;
+                vm.pc = pc + 1;
+                vm.set_value(af.values[0]);
+                return Ok(());
+            }
+            _ => {
                 return Err(raise_string(
                     arena,
-                    "invoking continuation with more than one argument".into(),
+                    format!("cannot invoke non-function: {}", vm.fun.pretty_print()),
                 ));
             }
-            vm.stack = c.stack.clone();
-            vm.return_stack = c.return_stack.clone();
-            let ReturnPoint { code_block, pc } = vm
-                .return_stack
-                .pop()
-                .expect("popping continuation with no return address");
-            vm.current_code_block = code_block;
-            vm.pc = pc + 1;
-            vm.set_value(af.values[0]);
-        }
-        _ => {
-            return Err(raise_string(
-                arena,
-                format!("cannot invoke non-function: {}", vm.fun.pretty_print()),
-            ));
         }
     }
-    Ok(())
 }
 
-// TODO apply isn't really tail-recursive, and this could be fixed by returning to the
-//      trampoline here.
-fn apply(int: &Interpreter, vm: &mut Vm, tail: bool) -> Result<(), Error> {
-    let arena = &int.arena;
+/// Rewrites `vm.fun`/`vm.value` to the flattened call `(apply f a b (c d))` amounts to -
+/// `(f a b c d)` - without invoking anything itself; `invoke`'s own loop re-dispatches on the
+/// result, so a chain of `apply`s costs loop iterations rather than Rust stack frames.
+fn apply_setup(arena: &Arena, vm: &mut Vm) -> Result<(), Error> {
     let af = vm.value.long_lived().get_activation_frame().borrow();
     let n_args = af.values.len();
     if n_args < 2 {
@@ -511,15 +626,19 @@ fn apply(int: &Interpreter, vm: &mut Vm, tail: bool) -> Result<(), Error> {
     };
     vm.set_value(arena.insert(Value::ActivationFrame(RefCell::new(new_af))));
     vm.fun = af.values[0];
-    invoke(int, vm, tail)
+    Ok(())
 }
 
-fn call_cc(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+/// Captures the current continuation and rewrites `vm.fun`/`vm.value` to a call of the
+/// procedure `%call/cc` was given, with that continuation as its sole argument - see
+/// `apply_setup` for why this doesn't invoke the procedure itself.
+fn call_cc_setup(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
     let arena = &int.arena;
     vm.return_stack.push(vm.get_return_point());
     let cont = Continuation {
         stack: vm.stack.clone(),
         return_stack: vm.return_stack.clone(),
+        winders: vm.winders.clone(),
     };
     let cont_r = arena.insert(Value::Continuation(cont));
     let af = vm.value.long_lived().get_activation_frame().borrow();
@@ -535,7 +654,114 @@ fn call_cc(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
     };
     vm.set_value(arena.insert(Value::ActivationFrame(RefCell::new(new_af))));
     vm.fun = af.values[0];
-    invoke(int, vm, true)
+    Ok(())
+}
+
+/// Unwinds `vm.winders` down to its common prefix with `target`, running each exited winder's
+/// `after` thunk innermost-first, then runs each entered winder's `before` thunk outermost-first
+/// and leaves `vm.winders` equal to `target`.
+///
+/// Called whenever a continuation jump might cross `dynamic-wind` boundaries - both a plain
+/// `dynamic-wind` call returning/raising normally (trivially, since it never sees any other
+/// winders in between) and invoking a captured continuation (where `target` is that
+/// continuation's own snapshot of the winders in effect when it was captured).
+fn rewind_to(int: &Interpreter, vm: &mut Vm, target: &[(PoolPtr, PoolPtr)]) -> Result<(), Error> {
+    let common = vm
+        .winders
+        .iter()
+        .zip(target.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while vm.winders.len() > common {
+        let (_, after) = vm.winders.pop().expect("just checked len > common");
+        run_thunk(int, after).map_err(Error::Raise)?;
+    }
+    for &winder in &target[common..] {
+        run_thunk(int, winder.0).map_err(Error::Raise)?;
+        vm.winders.push(winder);
+    }
+    Ok(())
+}
+
+/// Runs `before`, then `thunk`, then `after`, in that order, always running `after` even if
+/// `thunk` raises. While `thunk` is running, `(before, after)` sits on `vm.winders`, so invoking -
+/// from within `thunk` - a continuation captured earlier in the same `run_thunk` frame still
+/// triggers `after`/`before` correctly via `rewind_to`.
+///
+/// Note: `thunk` runs as its own nested `run_thunk` (see there for why), so a continuation
+/// captured inside `thunk` and invoked only *after* this call has already returned crosses a
+/// `run_thunk` boundary the VM can't unwind through yet - the same gap `with_output_to_string`
+/// documents. `before`/`after` still run exactly once in that case, just without the VM's normal
+/// instruction stream resuming where the continuation expects.
+fn dynamic_wind(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 3 {
+        return Err(raise_string(
+            arena,
+            "dynamic-wind: expected 3 arguments".into(),
+        ));
+    }
+    let before = af.values[0];
+    let thunk = af.values[1];
+    let after = af.values[2];
+    drop(af);
+
+    run_thunk(int, before).map_err(Error::Raise)?;
+    vm.winders.push((before, after));
+
+    let result = run_thunk(int, thunk);
+
+    // A continuation invoked from inside `thunk` and resumed from outside it may already have
+    // unwound this winder (and run `after`) via `rewind_to`; only pop and re-run `after` here if
+    // it's still the innermost one in effect.
+    if vm.winders.last() == Some(&(before, after)) {
+        vm.winders.pop();
+        run_thunk(int, after).map_err(Error::Raise)?;
+    }
+
+    let v = result.map_err(Error::Raise)?;
+    vm.set_value(v.pp());
+    Ok(())
+}
+
+/// Installs `handler` as the innermost entry of `int`'s dynamic handler stack (see
+/// `Interpreter::handlers`) for the duration of a zero-argument `thunk` call, so a `raise` while
+/// `thunk` is running reaches `handler` - see `handle_error` - before any handler installed
+/// further out. Popped again once `thunk` returns, whether normally or because `handle_error`
+/// already popped and ran it on `thunk`'s behalf.
+///
+/// Note: unlike R7RS `with-exception-handler`, a handler invoked via `raise` (rather than
+/// `raise-continuable`) that returns normally does not itself raise a secondary exception - its
+/// return value is simply used as this call's result. The interpreter does not yet distinguish
+/// continuable from non-continuable raises; see `raise` and `handle_error`.
+fn with_exception_handler(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 2 {
+        return Err(raise_string(
+            arena,
+            "with-exception-handler: expected 2 arguments".into(),
+        ));
+    }
+    let handler = af.values[0];
+    let thunk = af.values[1];
+    drop(af);
+
+    int.handlers.borrow_mut().push(arena.root(handler));
+    let depth = int.handlers.borrow().len();
+
+    let result = run_thunk(int, thunk);
+
+    // `handle_error` pops a handler before invoking it, so ours is only still on the stack here
+    // if `thunk` returned without `handler` ever being consulted.
+    if int.handlers.borrow().len() >= depth {
+        int.handlers.borrow_mut().truncate(depth - 1);
+    }
+
+    let v = result.map_err(Error::Raise)?;
+    vm.set_value(v.pp());
+    Ok(())
 }
 
 // fn eval(arena: &Arena, vm: &mut Vm, env: &RcEnv) -> Result<(), Error> {
@@ -564,11 +790,274 @@ fn eval(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
     Ok(())
 }
 
+/// Runs `thunk` with zero arguments to completion, as a fresh, independent `vm::run`.
+///
+/// This re-enters the VM recursively (mirroring how `eval` recurses into
+/// `Interpreter::parse_compile_run`) rather than tail-calling into the current run loop, so that
+/// the caller regains control as soon as the thunk returns.
+fn run_thunk(int: &Interpreter, thunk: PoolPtr) -> Result<RootPtr, RootPtr> {
+    run_thunk_with_args(int, thunk, &[])
+}
+
+/// Runs `fun` with `args` to completion, as a fresh, independent `vm::run`. Generalizes
+/// `run_thunk` to procedures that take arguments - e.g. `call-with-port`, which must pass the
+/// port to the procedure it invokes.
+///
+/// This builds the same function-call shape the compiler emits for a normal application (see
+/// `compile.rs`'s handling of `SyntaxElement::Application`): the function is pushed first, then
+/// each argument, then `CreateFrame` packages the top `args.len()` stack entries - the arguments,
+/// not the function - into an activation frame.
+fn run_thunk_with_args(
+    int: &Interpreter,
+    fun: PoolPtr,
+    args: &[PoolPtr],
+) -> Result<RootPtr, RootPtr> {
+    let environment = Rc::new(RefCell::new(Environment::new(None)));
+    let mut code = CodeBlock::new(Some("[dynamic-invoke]".into()), 0, false, environment);
+    let fun_idx = code.push_constant(fun);
+    code.push(Instruction::Constant(fun_idx));
+    code.push(Instruction::PushValue);
+    for arg in args {
+        let arg_idx = code.push_constant(*arg);
+        code.push(Instruction::Constant(arg_idx));
+        code.push(Instruction::PushValue);
+    }
+    code.push(Instruction::CreateFrame(args.len()));
+    code.push(Instruction::PopFunction);
+    code.push(Instruction::PreserveEnv);
+    code.push(Instruction::FunctionInvoke { tail: false });
+    code.push(Instruction::RestoreEnv);
+    code.push(Instruction::Finish);
+    let code = int.arena.insert_rooted(Value::CodeBlock(Box::new(code)));
+    run(code, 0, int.global_frame.pp(), int)
+}
+
+/// Runs a zero-argument thunk with `%current-output-port` rebound to a fresh string port for the
+/// duration of the call, returning the output it wrote as a string.
+///
+/// Note: this redirection is not dynamic-wind-safe. If `thunk` escapes via a captured
+/// continuation and is resumed later, the output port will not be restored correctly — the port
+/// swap is not registered on `Vm::winders`, so `rewind_to` never undoes it.
+fn with_output_to_string(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 1 {
+        return Err(raise_string(
+            arena,
+            "with-output-to-string: expected a single argument".into(),
+        ));
+    }
+    let thunk = af.values[0];
+    drop(af);
+
+    let global_af = vm.global_env.long_lived().get_activation_frame();
+    let previous_port = global_af.borrow().values[OUTPUT_PORT_INDEX];
+    let string_port = new_output_string_port(arena);
+    global_af.borrow_mut().values[OUTPUT_PORT_INDEX] = string_port;
+
+    let result = run_thunk(int, thunk);
+
+    global_af.borrow_mut().values[OUTPUT_PORT_INDEX] = previous_port;
+    result.map_err(Error::Raise)?;
+
+    let captured = get_output_string(arena, &[string_port])
+        .map_err(|e| raise_string(arena, format!("with-output-to-string: {}", e)))?;
+    vm.set_value(captured);
+    Ok(())
+}
+
+/// Opens `path` for output, runs a zero-argument thunk with `%current-output-port` rebound to it,
+/// then closes the file and restores the previous port - even if the thunk raises.
+///
+/// See `with_output_to_string`'s note on dynamic-wind safety: the same caveat applies here.
+fn with_output_to_file(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 2 {
+        return Err(raise_string(
+            arena,
+            "with-output-to-file: expected 2 arguments".into(),
+        ));
+    }
+    let path = af.values[0];
+    let thunk = af.values[1];
+    drop(af);
+
+    let port = open_output_file(arena, &[path])
+        .map_err(|e| raise_string(arena, format!("with-output-to-file: {}", e)))?;
+
+    let global_af = vm.global_env.long_lived().get_activation_frame();
+    let previous_port = global_af.borrow().values[OUTPUT_PORT_INDEX];
+    global_af.borrow_mut().values[OUTPUT_PORT_INDEX] = port;
+
+    let result = run_thunk(int, thunk);
+
+    global_af.borrow_mut().values[OUTPUT_PORT_INDEX] = previous_port;
+    let _ = close_port(arena, &[port]);
+    result.map_err(Error::Raise)?;
+
+    vm.set_value(arena.unspecific);
+    Ok(())
+}
+
+/// Opens `path` for input, runs a zero-argument thunk with `%current-input-port` rebound to it,
+/// then closes the file and restores the previous port - even if the thunk raises.
+///
+/// See `with_output_to_string`'s note on dynamic-wind safety: the same caveat applies here.
+fn with_input_from_file(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 2 {
+        return Err(raise_string(
+            arena,
+            "with-input-from-file: expected 2 arguments".into(),
+        ));
+    }
+    let path = af.values[0];
+    let thunk = af.values[1];
+    drop(af);
+
+    let port = open_input_file(arena, &[path])
+        .map_err(|e| raise_string(arena, format!("with-input-from-file: {}", e)))?;
+
+    let global_af = vm.global_env.long_lived().get_activation_frame();
+    let previous_port = global_af.borrow().values[INPUT_PORT_INDEX];
+    global_af.borrow_mut().values[INPUT_PORT_INDEX] = port;
+
+    let result = run_thunk(int, thunk);
+
+    global_af.borrow_mut().values[INPUT_PORT_INDEX] = previous_port;
+    let _ = close_port(arena, &[port]);
+    result.map_err(Error::Raise)?;
+
+    vm.set_value(arena.unspecific);
+    Ok(())
+}
+
+/// Runs a one-argument procedure with a fresh string port passed as its argument, and returns the
+/// output it wrote as a string - the output, not the procedure's own return value, mirroring
+/// `with_output_to_string`.
+fn call_with_output_string(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 1 {
+        return Err(raise_string(
+            arena,
+            "call-with-output-string: expected a single argument".into(),
+        ));
+    }
+    let proc = af.values[0];
+    drop(af);
+
+    let string_port = new_output_string_port(arena);
+    let result = run_thunk_with_args(int, proc, &[string_port]);
+    result.map_err(Error::Raise)?;
+
+    let captured = get_output_string(arena, &[string_port])
+        .map_err(|e| raise_string(arena, format!("call-with-output-string: {}", e)))?;
+    vm.set_value(captured);
+    Ok(())
+}
+
+/// Invokes `proc` with `port` as its single argument, guaranteeing `port` is closed afterward -
+/// even if `proc` raises - and returns whatever `proc` returned.
+fn call_with_port(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 2 {
+        return Err(raise_string(
+            arena,
+            "call-with-port: expected 2 arguments".into(),
+        ));
+    }
+    let port = af.values[0];
+    let proc = af.values[1];
+    drop(af);
+
+    let result = run_thunk_with_args(int, proc, &[port]);
+    let _ = close_port(arena, &[port]);
+    let v = result.map_err(Error::Raise)?;
+
+    vm.set_value(v.pp());
+    Ok(())
+}
+
+/// Runs a zero-argument `producer` thunk, then applies `consumer` to whatever it produced -
+/// spreading a `Value::Values` out into separate arguments, or passing any other value through as
+/// `consumer`'s single argument. Mirrors `call_with_port`'s shape, but there's nothing to clean up
+/// afterward.
+fn call_with_values(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let af = vm.value.long_lived().get_activation_frame().borrow();
+    if af.values.len() != 2 {
+        return Err(raise_string(
+            arena,
+            "call-with-values: expected 2 arguments".into(),
+        ));
+    }
+    let producer = af.values[0];
+    let consumer = af.values[1];
+    drop(af);
+
+    let produced = run_thunk(int, producer).map_err(Error::Raise)?;
+    let args = match &*produced {
+        Value::Values(vals) => vals.clone(),
+        _ => vec![produced.pp()],
+    };
+
+    let v = run_thunk_with_args(int, consumer, &args).map_err(Error::Raise)?;
+    vm.set_value(v.pp());
+    Ok(())
+}
+
+/// Dumps the current call's activation frame stack as a printable report, for a REPL or error
+/// handler to show local bindings at a breakpoint or on error. See `environment::dump_frames`.
+fn current_environment_bindings(int: &Interpreter, vm: &mut Vm) -> Result<(), Error> {
+    let arena = &int.arena;
+    let env = &vm.current_code_block.get_code_block().environment;
+    let frame = vm.env.long_lived().get_activation_frame().borrow();
+    let dumps = dump_frames(arena, &frame, env);
+
+    let mut report = String::new();
+    for dump in &dumps {
+        writeln!(report, "frame {}:", dump.altitude).expect("writing to a String cannot fail");
+        for binding in &dump.bindings {
+            writeln!(
+                report,
+                "  {} = {}{}",
+                binding.name,
+                binding.value,
+                if binding.initialized { "" } else { " (uninitialized)" }
+            )
+            .expect("writing to a String cannot fail");
+        }
+    }
+
+    vm.set_value(arena.insert(Value::String(RefCell::new(report))));
+    Ok(())
+}
+
 fn resolve_variable(vm: &Vm, altitude: usize, index: usize) -> String {
     let env = &vm.current_code_block.get_code_block().environment;
     env.borrow().get_name(altitude, index)
 }
 
+/// Backs `raise`, `abort`, and `raise-continuable` (see `PrimitiveImplementation::Raise`, which
+/// `raise-continuable` is registered against directly - there's no separate code path for it).
+///
+/// R7RS draws a real distinction between the two: if a `with-exception-handler` handler returns
+/// normally from a plain `raise`, that's itself a secondary exception, while returning normally
+/// from `raise-continuable` resumes evaluation at the `raise-continuable` call with the handler's
+/// return value. This VM can't express that distinction, let alone the resumption itself -
+/// `handle_error` always unwinds the whole interrupted `vm::run` down to the handler call and
+/// hands back the handler's return value as that `run`'s own result (see its doc comment), so a
+/// handler returning normally from *either* `raise` or `raise-continuable` just becomes the result
+/// of the nearest `run_thunk`/`run_thunk_with_args` boundary, never the value of the specific
+/// expression the raise occurred in. Genuine resumption - getting `(+ 1 (raise-continuable 'x))`
+/// to add `1` to the handler's return value rather than discarding the `+ 1` entirely - would need
+/// the handler-stack/continuation machinery (`PushHandler`/`PopHandler` bytecode plus explicit
+/// `stack`/`return_stack`/`env` snapshots) that the rest of this exception design was built
+/// without; that's a bigger rewrite than aliasing `raise-continuable` to `raise` here.
 fn raise(arena: &Arena, vm: &Vm, abort: bool) -> Error {
     let af = vm.value.long_lived().get_activation_frame().borrow();
     if af.values.len() != 1 {
@@ -580,46 +1069,64 @@ fn raise(arena: &Arena, vm: &Vm, abort: bool) -> Error {
     }
 }
 
-fn error_stack(arena: &Arena, vm: &Vm, error: Error) -> Error {
-    let mut message = String::new();
-    fn write_code_block(message: &mut String, cb: PoolPtr) {
-        write!(
-            message,
-            "\tat {}",
-            cb.get_code_block().name.as_deref().unwrap_or("[anonymous]")
-        )
-        .unwrap();
-    }
-    write_code_block(&mut message, vm.current_code_block);
-    for ReturnPoint { code_block, .. } in vm.return_stack.iter() {
-        write_code_block(&mut message, *code_block);
+/// Builds a human-readable backtrace by walking `vm`'s call chain from the frame that's about to
+/// raise down to the top level, innermost first - one line per frame, naming the `CodeBlock` that
+/// was running (see `CodeBlock::name`).
+fn backtrace(vm: &Vm) -> Vec<String> {
+    fn frame_name(cb: PoolPtr) -> String {
+        cb.get_code_block()
+            .name
+            .clone()
+            .unwrap_or_else(|| "[anonymous]".into())
     }
+    let mut frames = vec![frame_name(vm.current_code_block)];
+    frames.extend(
+        vm.return_stack
+            .iter()
+            .rev()
+            .map(|rp| frame_name(rp.code_block)),
+    );
+    frames
+}
+
+/// Prepends a backtrace to `error`'s payload: wraps the condition that was raised in a
+/// `(<condition> . <backtrace>)` pair, whose cdr is `backtrace`'s frames rendered as one
+/// `  at <name>` line per frame, so printing the error shows where it came from rather than just
+/// what it was.
+fn error_stack(arena: &Arena, vm: &Vm, error: Error) -> Error {
+    let message = backtrace(vm)
+        .iter()
+        .map(|name| format!("  at {}", name))
+        .collect::<Vec<_>>()
+        .join("\n");
     let msg_r = arena.insert_rooted(Value::String(RefCell::new(message)));
     error.map_error(|e| arena.insert_rooted(Value::Pair(Cell::new(e.pp()), Cell::new(msg_r.pp()))))
 }
 
+/// Dispatches a raised condition to the innermost handler installed by `with_exception_handler`,
+/// falling back to the legacy `%error-handler` global slot - set directly via `(set!
+/// %error-handler ...)`, with no `with-exception-handler` call involved - if `int.handlers` is
+/// empty. The handler is popped before it runs (matching `with_exception_handler`'s own
+/// bookkeeping) and is run to completion as its own nested `vm::run` via `run_thunk_with_args`,
+/// so its return value - not whatever the interrupted `vm` happened to contain - becomes the
+/// result of this call.
 fn handle_error(int: &Interpreter, vm: &mut Vm, e: Error) -> Result<RootPtr, RootPtr> {
     let arena = &int.arena;
     let annotated_e = error_stack(arena, vm, e);
-    match annotated_e {
-        Error::Abort(v) => Err(v),
-        Error::Raise(v) => {
-            let handler = vm.global_env.get_activation_frame().borrow().values[0];
-            match &*handler {
+    let v = match annotated_e {
+        Error::Abort(v) => return Err(v),
+        Error::Raise(v) => v,
+    };
+    int.observer.borrow().on_raise(v.pp());
+
+    let handler = int.handlers.borrow_mut().pop();
+    match handler {
+        Some(handler) => run_thunk_with_args(int, handler.pp(), &[v.pp()]),
+        None => {
+            let global_handler = vm.global_env.get_activation_frame().borrow().values[ERROR_HANDLER_INDEX];
+            match &*global_handler {
                 Value::Boolean(false) => Err(v),
-                Value::Lambda { .. } => {
-                    let frame = ActivationFrame {
-                        parent: None,
-                        values: vec![v.pp()],
-                    };
-                    vm.fun = handler;
-                    vm.set_value(arena.insert(Value::ActivationFrame(RefCell::new(frame))));
-                    invoke(int, vm, false).map_err(|e| e.into_value())?;
-                    Ok(arena.root(arena.unspecific))
-                }
-                _ => {
-                    Err(arena.insert_rooted(Value::String(RefCell::new("invalid handler".into()))))
-                }
+                _ => run_thunk_with_args(int, global_handler, &[v.pp()]),
             }
         }
     }
@@ -629,6 +1136,9 @@ fn handle_error(int: &Interpreter, vm: &mut Vm, e: Error) -> Result<RootPtr, Roo
 pub struct Continuation {
     stack: Vec<PoolPtr>,
     return_stack: Vec<ReturnPoint>,
+    /// A snapshot of `Vm::winders` at capture time, so invoking this continuation can run
+    /// whatever `after`/`before` thunks the jump crosses. See `rewind_to`.
+    winders: Vec<(PoolPtr, PoolPtr)>,
 }
 
 impl heap::Inventory for Continuation {
@@ -636,6 +1146,10 @@ impl heap::Inventory for Continuation {
         for obj in self.stack.iter() {
             v.push(*obj);
         }
+        for &(before, after) in self.winders.iter() {
+            v.push(before);
+            v.push(after);
+        }
     }
 }
 
@@ -666,3 +1180,13 @@ impl Error {
 fn raise_string(arena: &Arena, error: String) -> Error {
     Error::Raise(arena.insert_rooted(Value::String(RefCell::new(error))))
 }
+
+/// Builds a catchable condition: a `(tag . message)` pair, so a `with-exception-handler` handler
+/// can recognize what went wrong with `(eq? (car c) 'tag)` rather than having to pattern-match a
+/// human-readable string - see `error_stack` for the similarly-shaped `(<condition> .
+/// <backtrace>)` wrapping a handler sees on top of this.
+fn raise_condition(arena: &Arena, tag: &str, message: String) -> Error {
+    let tag = arena.insert_rooted(Value::Symbol(tag.to_string()));
+    let message = arena.insert_rooted(Value::String(RefCell::new(message)));
+    Error::Raise(arena.insert_rooted(Value::Pair(Cell::new(tag.pp()), Cell::new(message.pp()))))
+}
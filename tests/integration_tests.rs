@@ -20,6 +20,7 @@ use peroxide::error::locate_message;
 use peroxide::heap::{GcMode, RootPtr};
 use peroxide::read::{NoParseResult, Reader};
 use peroxide::value::Value;
+use peroxide::vm::ExecutionLimits;
 use peroxide::Interpreter;
 
 fn execute(vm_state: &Interpreter, code: &str) -> Result<Value, String> {
@@ -452,3 +453,493 @@ fn check_arity() {
     assert!(magic_execute("(call/cc)", true).is_err());
     assert!(magic_execute("((syntax-rules -1))", true).is_err());
 }
+
+// Builds a bytevector from the given bytes out of `make-bytevector`/`bytevector-u8-set!` rather
+// than a `#u8(...)` literal; there is no `bytevector` constructor primitive.
+fn bytevector_literal(bytes: &[u8]) -> String {
+    let mut code = format!("(let ((bv (make-bytevector {} 0)))", bytes.len());
+    for (i, b) in bytes.iter().enumerate() {
+        code += &format!(" (bytevector-u8-set! bv {} {})", i, b);
+    }
+    code += " bv)";
+    code
+}
+
+#[test]
+fn bytevectors() {
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(bytevector? (make-bytevector 3 0))", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(3.into()),
+        magic_execute("(bytevector-length (make-bytevector 3 0))", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(42.into()),
+        magic_execute(
+            "(let ((bv (make-bytevector 3 0))) (bytevector-u8-set! bv 1 42) (bytevector-u8-ref bv 1))",
+            true
+        )
+        .unwrap()
+    );
+    let appended = format!(
+        "(let ((bv (bytevector-append {} {}))) (list (bytevector-length bv) (bytevector-u8-ref bv 0) (bytevector-u8-ref bv 3)))",
+        bytevector_literal(&[1, 2]),
+        bytevector_literal(&[3, 4])
+    );
+    assert_eq!(
+        vec![
+            Value::Integer(4.into()),
+            Value::Integer(1.into()),
+            Value::Integer(4.into())
+        ],
+        magic_execute_to_vec(&appended, true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute(
+            "(string=? (utf8->string (string->utf8 \"abc\")) \"abc\")",
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn binary_ports() {
+    let three_bytes = bytevector_literal(&[1, 2, 3]);
+    assert_eq!(
+        vec![
+            Value::Integer(1.into()),
+            Value::Integer(2.into()),
+            Value::Integer(3.into())
+        ],
+        magic_execute_to_vec(
+            &format!(
+                "(let ((p (open-input-bytevector {}))) (list (read-u8 p) (read-u8 p) (read-u8 p)))",
+                three_bytes
+            ),
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Integer(1.into()),
+        magic_execute(
+            &format!(
+                "(let ((p (open-input-bytevector {}))) (peek-u8 p) (read-u8 p))",
+                three_bytes
+            ),
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute(
+            "(eof-object? (read-u8 (open-input-bytevector (make-bytevector 0 0))))",
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Integer(3.into()),
+        magic_execute(
+            &format!(
+                "(bytevector-length (read-bytevector 10 (open-input-bytevector {})))",
+                three_bytes
+            ),
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn call_with_current_continuation_escape() {
+    assert_eq!(
+        Value::Integer(6.into()),
+        magic_execute(
+            "(+ 1 (call-with-current-continuation (lambda (k) (+ 2 (k 5)))))",
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn values_and_call_with_values() {
+    assert_eq!(
+        Value::Integer(7.into()),
+        magic_execute("(call-with-values (lambda () (values 3 4)) +)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(5.into()),
+        magic_execute("(call-with-values (lambda () (values 5)) (lambda (x) x))", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(0.into()),
+        magic_execute("(call-with-values (lambda () (values)) +)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(5.into()),
+        magic_execute("(+ 2 (call-with-values (lambda () 3) (lambda (x) x)))", true).unwrap()
+    );
+}
+
+#[test]
+fn call_with_current_continuation_unused() {
+    // Calling the continuation is optional - if it's never invoked, `call/cc` just returns
+    // whatever its thunk returns, like an ordinary procedure call.
+    assert_eq!(
+        Value::Integer(7.into()),
+        magic_execute("(call/cc (lambda (k) (+ 3 4)))", true).unwrap()
+    );
+}
+
+#[test]
+fn complex_tower() {
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(complex? (make-rectangular 1 2))", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(false),
+        magic_execute("(complex? 'not-a-number)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute(
+            "(string=? (number->string (+ (make-rectangular 1 2) (make-rectangular 3 4))) \"4+6i\")",
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute(
+            "(< (abs (- (magnitude (make-rectangular 3 4)) 5)) 1e-9)",
+            true
+        )
+        .unwrap()
+    );
+    // `<`/`>` don't consider any complex value less than or greater than another - they compare
+    // equal via `cast_same` failing to match a real/real pair, not via raising an error.
+    assert_eq!(
+        Value::Boolean(false),
+        magic_execute("(< (make-rectangular 1 2) (make-rectangular 1 2))", true).unwrap()
+    );
+}
+
+#[test]
+fn define_record_type() {
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute(
+            "(define-record-type point (make-point x y) point? (x point-x) (y point-y set-point-y!))\
+             (point? (make-point 1 2))",
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(false),
+        magic_execute(
+            "(define-record-type point (make-point x y) point? (x point-x) (y point-y))\
+             (point? 3)",
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        vec![Value::Integer(1.into()), Value::Integer(2.into())],
+        magic_execute_to_vec(
+            "(define-record-type point (make-point x y) point? (x point-x) (y point-y))\
+             (let ((p (make-point 1 2))) (list (point-x p) (point-y p)))",
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Integer(5.into()),
+        magic_execute(
+            "(define-record-type point (make-point x y) point? (x point-x) (y point-y set-point-y!))\
+             (let ((p (make-point 1 2))) (set-point-y! p 5) (point-y p))",
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn min_max_abs() {
+    assert_eq!(
+        Value::Integer(1.into()),
+        magic_execute("(min 3 1 2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(3.into()),
+        magic_execute("(max 3 1 2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Real(1.0),
+        magic_execute("(min 3 1.0 2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(5.into()),
+        magic_execute("(abs -5)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(5.into()),
+        magic_execute("(abs 5)", true).unwrap()
+    );
+}
+
+#[test]
+fn bitwise_operations() {
+    assert_eq!(
+        Value::Integer(8.into()),
+        magic_execute("(bitwise-and 12 10)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(14.into()),
+        magic_execute("(bitwise-ior 12 10)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(6.into()),
+        magic_execute("(bitwise-xor 12 10)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer((-6).into()),
+        magic_execute("(bitwise-not 5)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(20.into()),
+        magic_execute("(arithmetic-shift 5 2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(1.into()),
+        magic_execute("(arithmetic-shift 5 -2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(3.into()),
+        magic_execute("(bit-count 13)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(4.into()),
+        magic_execute("(integer-length 13)", true).unwrap()
+    );
+}
+
+#[test]
+fn rationalize_simplest_in_tolerance() {
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute(
+            "(string=? (number->string (rationalize 1/3 1/10)) \"1/3\")",
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Integer(0.into()),
+        magic_execute("(rationalize 1/10 1/2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(false),
+        magic_execute("(exact? (rationalize .3 1/10))", true).unwrap()
+    );
+}
+
+#[test]
+fn expt_exactness_preservation() {
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(string=? (number->string (expt 2 -1)) \"1/2\")", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(8.into()),
+        magic_execute("(expt 2 3)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(string=? (number->string (expt 2/3 2)) \"4/9\")", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(< (abs (- (expt 2.0 0.5) (sqrt 2.0))) 1e-9)", true).unwrap()
+    );
+}
+
+#[test]
+fn hyperbolic_functions() {
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(< (abs (- (sinh 0) 0)) 1e-9)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(< (abs (- (cosh 0) 1)) 1e-9)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(< (abs (- (tanh (asinh (sinh 1))) (tanh 1))) 1e-9)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(< (abs (- (acosh (cosh 2)) 2)) 1e-9)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(< (abs (- (atanh (tanh 0.5)) 0.5)) 1e-9)", true).unwrap()
+    );
+}
+
+#[test]
+fn transcendental_functions_branch_into_complex() {
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(string=? (number->string (sqrt -1)) \"0+1i\")", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(complex? (log -2))", true).unwrap()
+    );
+    assert_eq!(
+        Value::Boolean(true),
+        magic_execute("(complex? (asin 2))", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(2.into()),
+        magic_execute("(sqrt 4)", false).unwrap()
+    );
+}
+
+#[test]
+fn integer_division_and_rounding() {
+    assert_eq!(
+        Value::Integer((-3).into()),
+        magic_execute("(quotient -7 2)", true).unwrap()
+    );
+    assert_eq!(
+        vec![Value::Integer((-4).into()), Value::Integer(1.into())],
+        magic_execute_to_vec("(let ((qr (floor/ -7 2))) (list (car qr) (cdr qr)))", true).unwrap()
+    );
+    assert_eq!(
+        vec![Value::Integer((-3).into()), Value::Integer((-1).into())],
+        magic_execute_to_vec(
+            "(let ((qr (truncate/ -7 2))) (list (car qr) (cdr qr)))",
+            true
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        Value::Integer(2.into()),
+        magic_execute("(floor 5/2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(3.into()),
+        magic_execute("(ceiling 5/2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(2.into()),
+        magic_execute("(truncate 5/2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(2.into()),
+        magic_execute("(round 5/2)", true).unwrap()
+    );
+    assert_eq!(
+        Value::Integer(4.into()),
+        magic_execute("(round 7/2)", true).unwrap()
+    );
+}
+
+#[test]
+fn sandboxed_interpreter_has_no_eval() {
+    // `eval` would let sandboxed code reset its own instruction/allocation/recursion budgets by
+    // recursing into a fresh `vm::run` - it must stay unbound in a sandbox, exactly like the
+    // filesystem/clock primitives.
+    let interpreter = Interpreter::new_sandboxed(GcMode::Normal, ExecutionLimits::default());
+    assert!(execute(&interpreter, "(eval '(+ 1 2) \"sandbox\")").is_err());
+}
+
+#[test]
+fn guard_catches_matching_clause() {
+    assert_eq!(
+        Value::Symbol("caught".into()),
+        magic_execute(
+            "(guard (c ((symbol? c) 'caught))
+               (raise 'oops))",
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn guard_else_clause() {
+    assert_eq!(
+        Value::Integer(42.into()),
+        magic_execute(
+            "(guard (c (#f 'unreachable) (else 42))
+               (raise 'oops))",
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn guard_arrow_clause() {
+    assert_eq!(
+        Value::Integer(6.into()),
+        magic_execute(
+            "(guard (c ((assv c '((a . 1) (oops . 6))) => cdr))
+               (raise 'oops))",
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn guard_reraises_when_no_clause_matches() {
+    assert_eq!(
+        Value::Symbol("outer".into()),
+        magic_execute(
+            "(guard (outer-c (#t 'outer))
+               (guard (inner-c (#f 'unreachable))
+                 (raise 'oops)))",
+            true
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn guard_runs_normally_without_raising() {
+    assert_eq!(
+        Value::Integer(5.into()),
+        magic_execute("(guard (c (#t 'unreachable)) (+ 2 3))", true).unwrap()
+    );
+}
+
+#[test]
+fn raise_continuable_resumes_with_handlers_value() {
+    // Only the shape this VM can actually support: `raise-continuable` is the last thing the
+    // protected thunk does, so the handler's return value becomes the result of the whole
+    // `with-exception-handler` call. See `vm::raise`'s doc comment for why a raise buried inside
+    // a pending expression (e.g. `(+ 1 (raise-continuable 'x))`) can't resume the same way.
+    assert_eq!(
+        Value::Integer(42.into()),
+        magic_execute(
+            "(with-exception-handler
+               (lambda (c) 42)
+               (lambda () (raise-continuable 'oops)))",
+            true
+        )
+        .unwrap()
+    );
+}